@@ -0,0 +1,185 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Options that are not given on the command line, but by environment variables or in a config file.
+//!
+//! Every option `--some-option` can also be set by the environment variable `MOPPER_SOME_OPTION`, and by
+//! the key `some-option` in the TOML file given by `--config`. The command line takes precedence over the
+//! environment, which takes precedence over the config file.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use clap::parser::ValueSource;
+use toml::{Table, Value};
+
+const ENV_PREFIX: &str = "MOPPER_";
+const CONFIG_OPTION: &str = "config";
+
+/// Returns the arguments to add to the command line for the options of the given (sub)command that are not
+/// on it, but in the environment or the config file. The command must be built, so its subcommands know the
+/// global options.
+pub fn missing_options(command: &Command, matches: &ArgMatches) -> Result<Vec<OsString>, String> {
+    let (active_command, matches) = given_command(command, matches);
+    let config_file = match matches.get_one::<String>(CONFIG_OPTION) {
+        Some(config_file) => Some(config_file.clone()),
+        None => env::var(env_var(CONFIG_OPTION)).ok()
+    };
+    let mut config = match &config_file {
+        Some(config_file) => read_config(config_file)?,
+        None => Table::new()
+    };
+
+    let mut options = Vec::new();
+    for arg in active_command.get_arguments() {
+        let Some(long) = arg.get_long() else { continue };
+        if matches!(arg.get_action(), ArgAction::Help | ArgAction::HelpShort | ArgAction::HelpLong | ArgAction::Version) {
+            continue;
+        }
+        let config_value = config.remove(long);
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let value = match env::var(env_var(long)) {
+            Ok(env_value) => Some(from_env(arg, env_value)),
+            Err(_) => config_value
+        };
+        if let Some(value) = value {
+            options.extend(to_args(arg, long, &value)?);
+        }
+    }
+
+    // the other keys may be options of other commands; if not, they are probably misspelled
+    if let Some(unknown_key) = config.keys().find(|key| !has_option(command, key)) {
+        return Err(format!("Unknown option '{unknown_key}' in config file {}", config_file.unwrap_or_default()));
+    }
+    Ok(options)
+}
+
+// The subcommand given on the command line with its matches, or the command itself if none is given
+fn given_command<'a>(command: &'a Command, matches: &'a ArgMatches) -> (&'a Command, &'a ArgMatches) {
+    match matches.subcommand() {
+        Some((name, sub_matches)) => match command.find_subcommand(name) {
+            Some(subcommand) => given_command(subcommand, sub_matches),
+            None => (command, matches)
+        },
+        None => (command, matches)
+    }
+}
+
+// Whether the command or one of its subcommands has the option
+fn has_option(command: &Command, long: &str) -> bool {
+    command.get_arguments().any(|arg| arg.get_long() == Some(long))
+        || command.get_subcommands().any(|subcommand| has_option(subcommand, long))
+}
+
+fn read_config(config_file: &str) -> Result<Table, String> {
+    let config = fs::read_to_string(config_file)
+        .map_err(|error| format!("Cannot read config file {config_file}: {error}"))?;
+    config.parse::<Table>()
+        .map_err(|error| format!("Invalid config file {config_file}: {error}"))
+}
+
+// The environment variable of an option, e.g. MOPPER_MESSAGE_BUFFER_CAPACITY for --message-buffer-capacity
+fn env_var(long: &str) -> String {
+    format!("{ENV_PREFIX}{}", long.to_uppercase().replace('-', "_"))
+}
+
+// Environment variables are strings; flags take a boolean and counts a number
+fn from_env(arg: &Arg, env_value: String) -> Value {
+    let value = match arg.get_action() {
+        ArgAction::SetTrue => env_value.parse::<bool>().ok().map(Value::Boolean),
+        ArgAction::Count => env_value.parse::<i64>().ok().map(Value::Integer),
+        _ => None
+    };
+    value.unwrap_or(Value::String(env_value))
+}
+
+// The command line arguments that give the option the value
+fn to_args(arg: &Arg, long: &str, value: &Value) -> Result<Vec<OsString>, String> {
+    let invalid = || format!("Invalid value for option '{long}': {value}");
+    let option = |value: &str| OsString::from(format!("--{long}={value}"));
+    match (arg.get_action(), value) {
+        (ArgAction::SetTrue, Value::Boolean(true)) => Ok(vec![OsString::from(format!("--{long}"))]),
+        (ArgAction::SetTrue, Value::Boolean(false)) => Ok(Vec::new()),
+        (ArgAction::Count, Value::Integer(count)) => {
+            let count = usize::try_from(*count).map_err(|_| invalid())?;
+            Ok(vec![OsString::from(format!("--{long}")); count])
+        },
+        (ArgAction::Append, Value::Array(values)) => values.iter()
+            .map(|value| scalar(value).map(|value| option(&value)).ok_or_else(invalid))
+            .collect(),
+        (ArgAction::Append, Value::Table(values)) => values.iter()
+            .map(|(key, value)| scalar(value).map(|value| option(&format!("{key}={value}"))).ok_or_else(invalid))
+            .collect(),
+        (ArgAction::Set | ArgAction::Append, value) => scalar(value)
+            .map(|value| vec![option(&value)])
+            .ok_or_else(invalid),
+        _ => Err(invalid())
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(string) => Some(string.clone()),
+        Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => Some(value.to_string()),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use clap::CommandFactory;
+    use crate::Args;
+    use super::*;
+
+    // The number of configuration files written so far, so every test writes one of its own
+    static CONFIG_FILES: AtomicUsize = AtomicUsize::new(0);
+
+    fn missing_options_of(command_line: &[&str], config: &str) -> Result<Vec<OsString>, String> {
+        let config_file = env::temp_dir().join(format!("mopper-config-{}-{}.toml", std::process::id(), CONFIG_FILES.fetch_add(1, Ordering::Relaxed)));
+        fs::write(&config_file, config).unwrap();
+        let mut command = Args::command();
+        command.build();
+        let matches = command.clone().ignore_errors(true)
+            .try_get_matches_from(command_line.iter().copied().chain(["--config", config_file.to_str().unwrap()]))
+            .unwrap();
+        let options = missing_options(&command, &matches);
+        fs::remove_file(config_file).unwrap();
+        options
+    }
+
+    #[test]
+    fn options_from_config_file() {
+        let config = r#"
+            message-buffer-capacity = 16
+            deduplicate = true
+            verbose = 2
+            param = { NAME = "value" }
+            every = 10
+        "#;
+        let options = missing_options_of(&["mopper", "validate", "--message-buffer-capacity", "8"], config).unwrap();
+        assert_eq!(options, ["--param=NAME=value", "--deduplicate", "--verbose", "--verbose"]);
+    }
+
+    #[test]
+    fn unknown_option_in_config_file() {
+        let options = missing_options_of(&["mopper", "-m", "mapping.json"], "dedup = true");
+        assert!(options.is_err_and(|error| error.starts_with("Unknown option 'dedup'")));
+    }
+}
@@ -0,0 +1,173 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Running a plan again and again: on a schedule, or when its source files change.
+//! The mapping can be reloaded when it changes, without stopping.
+
+use std::fs;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use ::operator::{IOType, Operator};
+use log::{error, info};
+use crate::{forced_to_one_target, prepare_plan, start_plan};
+use crate::source::location::{find_files, FileLocation};
+use crate::error::MopperError;
+use crate::mopper_options::MopperOptions;
+use crate::plan::NodeMap;
+use crate::stats::RunStats;
+
+// How often the daemon checks if it has to run again
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// When a daemon runs the plan again, after the first run at the start.
+#[derive(Clone, Debug, Default)]
+pub struct Schedule {
+    /// Run again every `interval`, counted from the start of the previous run.
+    pub interval: Option<Duration>,
+
+    /// Run again when one of the source files of the plan changes.
+    pub watch_sources: bool
+}
+
+/// Runs the plan at the start and then according to the `schedule`, and gives the result of every run
+/// to `after_run`. The plan is read, validated and rewritten only once.
+/// Stops when `after_run` returns [ControlFlow::Break], or after the first run if the schedule is empty.
+pub fn run_daemon(algemaploom_plan: &str, options: &MopperOptions, schedule: &Schedule,
+                  after_run: impl FnMut(Result<RunStats, MopperError>) -> ControlFlow<()>) -> Result<(), MopperError> {
+    run_reloading_daemon(&[], || Ok(algemaploom_plan.to_string()), options, schedule, after_run)
+}
+
+/// Like [run_daemon], with the plan `load_plan` creates from the `mapping_files`. When one of these files
+/// changes, the plan is loaded, validated and rewritten again, and used from the next run on; if that fails,
+/// the previous plan is kept. If the schedule watches the sources, the new plan is run right away.
+pub fn run_reloading_daemon(mapping_files: &[PathBuf], mut load_plan: impl FnMut() -> Result<String, MopperError>,
+                            options: &MopperOptions, schedule: &Schedule,
+                            mut after_run: impl FnMut(Result<RunStats, MopperError>) -> ControlFlow<()>) -> Result<(), MopperError> {
+    let mut mappings = FileWatch::new(mapping_files.to_vec());
+    let mut pipeline = Pipeline::load(&mut load_plan, options)?;
+    loop {
+        let run_started = Instant::now();
+        pipeline.sources.reset();
+        if after_run(start_plan(&pipeline.reduced_plan, options)).is_break() {
+            return Ok(());
+        }
+        if schedule.interval.is_none() && !schedule.watch_sources {
+            return Ok(());
+        }
+
+        loop {
+            let sleep = match schedule.interval {
+                Some(interval) => POLL_INTERVAL.min(interval.saturating_sub(run_started.elapsed())),
+                None => POLL_INTERVAL
+            };
+            thread::sleep(sleep);
+            if schedule.interval.is_some_and(|interval| run_started.elapsed() >= interval) {
+                info!("Running the plan again, as scheduled.");
+                break;
+            }
+            if mappings.changed() {
+                match Pipeline::load(&mut load_plan, options) {
+                    Ok(new_pipeline) => {
+                        info!("Reloaded the mapping.");
+                        pipeline = new_pipeline;
+                        if schedule.watch_sources {
+                            break;
+                        }
+                    },
+                    Err(error) => error!("Cannot reload the mapping; keeping the previous one. {error}")
+                }
+            }
+            if schedule.watch_sources && pipeline.sources.changed() {
+                info!("Running the plan again, as its sources changed.");
+                break;
+            }
+        }
+    }
+}
+
+// A rewritten plan, with the files it reads
+struct Pipeline {
+    reduced_plan: NodeMap,
+    sources: FileWatch
+}
+
+impl Pipeline {
+    fn load(load_plan: &mut impl FnMut() -> Result<String, MopperError>, options: &MopperOptions) -> Result<Self, MopperError> {
+        let (_plan_graph, reduced_plan) = prepare_plan(&load_plan()?, options, forced_to_one_target(options))?;
+        let sources = FileWatch::new(source_files(&reduced_plan, options));
+        Ok(Pipeline { reduced_plan, sources })
+    }
+}
+
+// Tells when files change
+struct FileWatch {
+    files: Vec<PathBuf>,
+    modified: Vec<Option<SystemTime>>,
+    changing: Option<Vec<Option<SystemTime>>>
+}
+
+impl FileWatch {
+    fn new(files: Vec<PathBuf>) -> Self {
+        let modified = modification_times(&files);
+        FileWatch { files, modified, changing: None }
+    }
+
+    // Takes the files as they are now as unchanged
+    fn reset(&mut self) {
+        self.modified = modification_times(&self.files);
+        self.changing = None;
+    }
+
+    // Whether the files changed since the last reset or change. A changed file must stay the same
+    // for one poll interval, so it is not read while it is written.
+    fn changed(&mut self) -> bool {
+        let current = modification_times(&self.files);
+        if current != self.modified && self.changing.as_ref() == Some(&current) {
+            self.modified = current;
+            self.changing = None;
+            return true;
+        }
+        self.changing = Some(current);
+        false
+    }
+}
+
+// The files the built-in file sources of the plan read
+fn source_files(reduced_plan: &NodeMap, options: &MopperOptions) -> Vec<PathBuf> {
+    reduced_plan.values()
+        .filter_map(|node| match &node.operator {
+            Operator::SourceOp { config } if config.source_type == IOType::File && options.source_factory(config).is_none() =>
+                config.config.get("path").and_then(|path| find_files(path, options.working_dir_hint()).ok()),
+            _ => None
+        })
+        .flatten()
+        .filter_map(|file_location| match file_location {
+            FileLocation::Local(file_path) => Some(file_path),
+            FileLocation::ZipMember { archive, .. } => Some(archive),
+            // remote files are not watched
+            _ => None
+        })
+        .collect()
+}
+
+// The last modification time of every file, if it exists
+fn modification_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files.iter()
+        .map(|file| fs::metadata(file).and_then(|metadata| metadata.modified()).ok())
+        .collect()
+}
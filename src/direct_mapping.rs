@@ -0,0 +1,143 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Generates plans that apply the [W3C Direct Mapping](https://www.w3.org/TR/rdb-direct-mapping/)
+//! to CSV files, so data can be lifted to RDF before a real mapping exists.
+//!
+//! A CSV file is treated as a table named after the file without extension. As CSV files have no
+//! primary key, all columns together form the key of a row: the subject of a row is
+//! `<base>Table/Col1=val1;Col2=val2`. Every row gets the type `<base>Table`, and every value becomes
+//! a literal object of predicate `<base>Table#Col`.
+
+use std::path::{Path, PathBuf};
+use pct_str::{PctString, URIReserved};
+use serde_json::{json, Map, Value};
+use crate::error::MopperError;
+use crate::source::compression::{SourceCompression, SourceFile};
+use crate::source::csv_file::CsvDialect;
+use crate::source::location::FileLocation;
+
+const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+
+/// Creates an AlgeMapLoom plan in JSON that applies the Direct Mapping to the CSV file,
+/// with IRIs relative to `base_iri`, reading its header in the `dialect`. The plan writes to standard out.
+pub fn to_plan(csv_file: &str, base_iri: &str, dialect: &CsvDialect) -> Result<String, MopperError> {
+    let columns = read_columns(csv_file, dialect)?;
+    let table = Path::new(csv_file).file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(encode)
+        .unwrap_or_default();
+    let table_iri = format!("{base_iri}{table}");
+
+    // the subject: the table name and the values of all columns
+    let key: Vec<String> = columns.iter()
+        .map(|column| format!("{}={{{column}}}", encode(column)))
+        .collect();
+    let variable_function_pairs: Vec<Value> = columns.iter()
+        .map(|column| json!([column, {"type": "UriEncode", "inner_function": {"type": "Reference", "value": column}}]))
+        .collect();
+    let mut extend_pairs = Map::new();
+    extend_pairs.insert("?s".to_string(), json!({
+        "type": "Iri",
+        "base_iri": base_iri,
+        "inner_function": {
+            "type": "TemplateFunctionValue",
+            "template": format!("{table_iri}/{}", key.join(";")),
+            "variable_function_pairs": variable_function_pairs
+        }
+    }));
+
+    // the type and a statement per column
+    let mut statements = vec![format!("?s <{RDF_TYPE}> <{table_iri}> .")];
+    for (index, column) in columns.iter().enumerate() {
+        extend_pairs.insert(format!("?o{index}"), json!({
+            "type": "Literal",
+            "inner_function": {"type": "Reference", "value": column},
+            "dtype_function": null,
+            "langtype_function": null
+        }));
+        statements.push(format!("?s <{table_iri}#{}> ?o{index} .", encode(column)));
+    }
+
+    let plan = json!({
+        "nodes": [
+            {"id": "Source_0", "operator": {"type": "SourceOp", "config": {
+                "path": csv_file,
+                "source_type": "File",
+                "root_iterator": {"reference": null, "reference_formulation": "CSVRows", "fields": [], "alias": null}
+            }}},
+            {"id": "Projection_1", "operator": {"type": "ProjectOp", "config": {"projection_attributes": columns}}},
+            {"id": "ExtendOp_2", "operator": {"type": "ExtendOp", "config": extend_pairs}},
+            {"id": "Serialize_3", "operator": {"type": "SerializerOp", "config": {
+                "template": statements.join("\n"),
+                "format": "NQuads"
+            }}},
+            {"id": "Sink_4", "operator": {"type": "TargetOp", "config": {"target_type": "StdOut", "data_format": "NQuads"}}}
+        ],
+        "node_holes": [],
+        "edge_property": "directed",
+        "edges": [
+            [0, 1, {"fragment": "default"}],
+            [1, 2, {"fragment": "default"}],
+            [2, 3, {"fragment": "default"}],
+            [3, 4, {"fragment": "default"}]
+        ]
+    });
+    Ok(plan.to_string())
+}
+
+// Reads the column names from the header of the CSV file
+fn read_columns(csv_file: &str, dialect: &CsvDialect) -> Result<Vec<String>, MopperError> {
+    let location = FileLocation::Local(PathBuf::from(csv_file));
+    let source_file = SourceFile { compression: SourceCompression::of_file(&location), location };
+    let (file, _size) = source_file.open()
+        .map_err(|err| MopperError::Plan { msg: format!("Cannot read {csv_file}: {err}"), cause: Some(Box::new(err)) })?;
+    let mut reader = dialect.reader_builder(&source_file.location)
+        .from_reader(file);
+    let headers = reader.headers()
+        .map_err(|err| MopperError::Plan { msg: format!("Cannot read the header of {csv_file}: {err}"), cause: Some(Box::new(err)) })?;
+    let columns: Vec<String> = headers.iter().map(str::to_string).collect();
+    if columns.is_empty() {
+        return Err(MopperError::plan(format!("{csv_file} has no columns")));
+    }
+    Ok(columns)
+}
+
+fn encode(name: &str) -> String {
+    PctString::encode(name.chars(), URIReserved).into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plan::PlanGraph;
+    use super::*;
+
+    #[test]
+    fn direct_mapping() {
+        let plan_json = to_plan("test-resources/rml-testcases/RMLTC1036-CSV/sport.csv", "http://example.com/base/", &CsvDialect::default()).unwrap();
+        assert!(PlanGraph::from_json(&plan_json).is_ok());
+
+        let plan: Value = serde_json::from_str(&plan_json).unwrap();
+        assert_eq!("http://example.com/base/sport/ID={ID};Name={Name}",
+            plan.pointer("/nodes/2/operator/config/?s/inner_function/template").unwrap());
+        assert_eq!("?s <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://example.com/base/sport> .\n\
+            ?s <http://example.com/base/sport#ID> ?o0 .\n\
+            ?s <http://example.com/base/sport#Name> ?o1 .",
+            plan.pointer("/nodes/3/operator/config/template").unwrap());
+
+        assert!(to_plan("test-resources/does-not-exist.csv", "http://example.com/base/", &CsvDialect::default()).is_err());
+    }
+}
@@ -16,32 +16,195 @@
 
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use crate::stats::OperatorKind;
 
+/// An error that is the cause of a [MopperError].
+pub type CauseError = Box<dyn Error + Send + Sync>;
+
+/// A problem in an execution plan, found before executing it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanProblem {
+    /// The index of the node in the plan with the problem, if it concerns a node
+    pub node_index: Option<usize>,
+    pub msg: String
+}
+
+impl PlanProblem {
+    pub fn new(node_index: Option<usize>, msg: String) -> Self {
+        PlanProblem { node_index, msg }
+    }
+}
+
+impl Display for PlanProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.node_index {
+            Some(node_index) => write!(f, "node {node_index}: {}", self.msg),
+            None => f.write_str(&self.msg)
+        }
+    }
+}
+
+/// Why a run failed, and where.
 #[derive(Debug)]
-pub struct GeneralError {
-    errors: Vec<(u8, String)>
+pub enum MopperError {
+    /// The mapping could not be translated into an execution plan.
+    Translation {
+        msg: String
+    },
+
+    /// The execution plan could not be read, or contains something that is not valid or not supported.
+    Plan {
+        msg: String,
+        cause: Option<CauseError>
+    },
+
+    /// The execution plan cannot be executed, for all these reasons.
+    Validation(Vec<PlanProblem>),
+
+    /// The options are not valid, or something they refer to cannot be used.
+    Options {
+        msg: String,
+        cause: Option<CauseError>
+    },
+
+    /// A source cannot read its input.
+    Source {
+        node_id: usize,
+        msg: String,
+        cause: Option<CauseError>
+    },
+
+    /// A record cannot be parsed.
+    Parse {
+        node_id: usize,
+        kind: OperatorKind,
+        msg: String,
+        cause: Option<CauseError>
+    },
+
+    /// A record cannot be processed, and the run stops because of the
+    /// [crate::mopper_options::ErrorMode] or [crate::mopper_options::InvalidIriPolicy].
+    Record {
+        node_id: usize,
+        kind: OperatorKind,
+        msg: String
+    },
+
+    /// A join cannot join its input.
+    Join {
+        node_id: usize,
+        msg: String
+    },
+
+    /// A sink cannot write its output.
+    Sink {
+        node_id: usize,
+        msg: String,
+        cause: Option<CauseError>
+    },
+
+    /// An operator gave up on a call that did not return in time, e.g. reading from a remote source.
+    Timeout {
+        node_id: usize,
+        kind: OperatorKind,
+        msg: String
+    },
+
+    /// A record cannot be pushed to an external source, see [crate::external::Engine::push].
+    Push {
+        source_name: String,
+        msg: String
+    },
+
+    /// An operator panicked.
+    Panic {
+        node_id: usize,
+        kind: OperatorKind,
+        msg: String
+    },
+
+    /// Several operators failed.
+    Multiple(Vec<MopperError>)
 }
 
-impl GeneralError {
-    pub fn new(errors: Vec<(u8, String)>) -> Self {
-        GeneralError {errors}
+impl MopperError {
+    pub fn plan(msg: impl Into<String>) -> Self {
+        MopperError::Plan { msg: msg.into(), cause: None }
     }
-    
-    pub fn from_msg(message: String) -> Self {
-        Self::new(vec![(1, message)])
+
+    pub fn options(msg: impl Into<String>) -> Self {
+        MopperError::Options { msg: msg.into(), cause: None }
+    }
+
+    /// The id of the node of the operator that failed, if any.
+    pub fn node_id(&self) -> Option<usize> {
+        match self {
+            MopperError::Source { node_id, .. }
+            | MopperError::Parse { node_id, .. }
+            | MopperError::Record { node_id, .. }
+            | MopperError::Join { node_id, .. }
+            | MopperError::Sink { node_id, .. }
+            | MopperError::Timeout { node_id, .. }
+            | MopperError::Panic { node_id, .. } => Some(*node_id),
+            _ => None
+        }
+    }
+
+    /// The kind of operator that failed, if any.
+    pub fn operator_kind(&self) -> Option<OperatorKind> {
+        match self {
+            MopperError::Source { .. } => Some(OperatorKind::Source),
+            MopperError::Parse { kind, .. }
+            | MopperError::Record { kind, .. }
+            | MopperError::Timeout { kind, .. }
+            | MopperError::Panic { kind, .. } => Some(*kind),
+            MopperError::Join { .. } => Some(OperatorKind::Join),
+            MopperError::Sink { .. } => Some(OperatorKind::Sink),
+            _ => None
+        }
     }
 }
 
-impl Display for GeneralError {
+impl Display for MopperError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let error_strs: Vec<String> = self.errors.iter()
-            .map(|(err_code, msg)| {
-                format!("{} (code {})", msg, err_code)
-            }).collect();
-        f.write_fmt(format_args!("{:?}", error_strs.join("\n")))
+        match self {
+            MopperError::Translation { msg } => write!(f, "Cannot translate mapping: {msg}"),
+            MopperError::Plan { msg, .. } => write!(f, "Invalid plan: {msg}"),
+            MopperError::Validation(problems) => {
+                write!(f, "Invalid plan:")?;
+                for problem in problems {
+                    write!(f, "\n  {problem}")?;
+                }
+                Ok(())
+            },
+            MopperError::Options { msg, .. } => write!(f, "Invalid options: {msg}"),
+            MopperError::Source { node_id, msg, .. } => write!(f, "Source {node_id}: {msg}"),
+            MopperError::Parse { node_id, kind, msg, .. }
+            | MopperError::Record { node_id, kind, msg } => write!(f, "{kind} {node_id}: {msg}"),
+            MopperError::Join { node_id, msg } => write!(f, "Join {node_id}: {msg}"),
+            MopperError::Sink { node_id, msg, .. } => write!(f, "Sink {node_id}: {msg}"),
+            MopperError::Timeout { node_id, kind, msg } => write!(f, "{kind} {node_id} timed out: {msg}"),
+            MopperError::Push { source_name, msg } => write!(f, "External source {source_name}: {msg}"),
+            MopperError::Panic { node_id, kind, msg } => write!(f, "{kind} {node_id} panicked: {msg}"),
+            MopperError::Multiple(errors) => {
+                let error_strs: Vec<String> = errors.iter()
+                    .map(|error| error.to_string())
+                    .collect();
+                f.write_str(&error_strs.join("\n"))
+            }
+        }
     }
 }
 
-unsafe impl Send for GeneralError {}
-
-impl Error for GeneralError {}
\ No newline at end of file
+impl Error for MopperError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MopperError::Plan { cause, .. }
+            | MopperError::Options { cause, .. }
+            | MopperError::Source { cause, .. }
+            | MopperError::Parse { cause, .. }
+            | MopperError::Sink { cause, .. } => cause.as_ref().map(|cause| cause.as_ref() as &(dyn Error + 'static)),
+            _ => None
+        }
+    }
+}
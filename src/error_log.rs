@@ -0,0 +1,144 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::sync::{Arc, Mutex};
+use log::error;
+use serde_json::{Map, Value};
+use crate::error::MopperError;
+use crate::mopper_options::ErrorMode;
+use crate::stats::{OperatorCounters, OperatorKind};
+
+/// A "dead-letter" file: records that could not be processed are written to it as CSV, together
+/// with the reason, so their owners can fix them. The columns are:
+/// - `node_id`: the node of the operator that rejected the record
+/// - `operator`: the kind of that operator
+/// - `error`: the reason why the record was rejected
+/// - `record`: the record as a JSON object of field names to values, if available
+pub struct ErrorLog {
+    writer: Mutex<csv::Writer<BufWriter<File>>>
+}
+
+impl ErrorLog {
+    /// Creates the error log file, overwriting it if it exists.
+    pub fn new(path: &str) -> io::Result<Self> {
+        let mut writer = csv::Writer::from_writer(BufWriter::new(File::create(path)?));
+        writer.write_record(["node_id", "operator", "error", "record"])?;
+        Ok(ErrorLog {
+            writer: Mutex::new(writer)
+        })
+    }
+
+    /// Writes a rejected record. `field_names` and `values` are matched by position.
+    pub fn write(&self, node_id: &str, kind: OperatorKind, error: &str, field_names: &[String], values: &[String]) {
+        let record: Map<String, Value> = field_names.iter()
+            .zip(values)
+            .map(|(field_name, value)| (field_name.clone(), Value::String(value.clone())))
+            .collect();
+        let record = Value::Object(record).to_string();
+        let kind = kind.to_string();
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(err) = writer.write_record([node_id, &kind, error, &record]) {
+            log::warn!("Cannot write to error log: {err}");
+        }
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.writer.lock().unwrap().flush()
+    }
+}
+
+/// Deals with the records an operator cannot process: they are logged, counted and written to
+/// the error log, if any. Whether the operator has to stop depends on the [ErrorMode].
+pub struct RecordErrorHandler {
+    node_id: usize,
+    kind: OperatorKind,
+    error_mode: ErrorMode,
+    error_log: Option<Arc<ErrorLog>>,
+    counters: Arc<OperatorCounters>
+}
+
+impl RecordErrorHandler {
+    pub fn new(node_id: &usize, kind: OperatorKind, error_mode: &ErrorMode, error_log: Option<Arc<ErrorLog>>, counters: Arc<OperatorCounters>) -> Self {
+        RecordErrorHandler {
+            node_id: *node_id,
+            kind,
+            error_mode: error_mode.clone(),
+            error_log,
+            counters
+        }
+    }
+
+    /// Handles a record that cannot be processed because of `msg`. `field_names` and `values`
+    /// describe the record, as far as known. Returns `true` if the operator has to stop,
+    /// `false` if it can skip the record and go on.
+    pub fn handle(&self, msg: &str, field_names: &[String], values: &[String]) -> bool {
+        error!("{msg}");
+        self.counters.add_error();
+        if let Some(error_log) = &self.error_log {
+            error_log.write(&self.node_id.to_string(), self.kind, msg, field_names, values);
+        }
+        self.error_mode == ErrorMode::Strict
+    }
+
+    pub fn node_id(&self) -> usize {
+        self.node_id
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.error_mode == ErrorMode::Strict
+    }
+
+    /// The error to stop the run with, after [Self::handle] returned `true`.
+    pub fn error(&self, msg: String) -> MopperError {
+        MopperError::Record {
+            node_id: self.node_id,
+            kind: self.kind,
+            msg
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+    use std::fs;
+    use super::*;
+
+    #[test]
+    fn write_rejected_records() {
+        let path = temp_dir().join(format!("mopper-error-log-test-{}.csv", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        let error_log = ErrorLog::new(path_str).unwrap();
+        let field_names = vec!["ID".to_string(), "Name".to_string()];
+        error_log.write("7", OperatorKind::Extend, "Invalid IRI: http://ex.com/a b", &field_names,
+                        &["1".to_string(), "a b".to_string()]);
+        error_log.write("0", OperatorKind::Source, "CSV error: record 2 (line 3, byte: 20): found record with 1 fields", &[], &[]);
+        error_log.flush().unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut reader = csv::Reader::from_reader(content.as_bytes());
+        let records: Vec<csv::StringRecord> = reader.records().map(|record| record.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(&records[0][1], "Extend");
+        assert_eq!(&records[0][2], "Invalid IRI: http://ex.com/a b");
+        assert_eq!(&records[0][3], r#"{"ID":"1","Name":"a b"}"#);
+        assert_eq!(&records[1][3], "{}");
+    }
+}
@@ -0,0 +1,163 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Running a plan with records pushed by the caller. A source in the plan with configuration
+//! `"source": "external"` and a `"name"` takes the records that are pushed with [Engine::push] under that name.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use crossbeam_channel::{bounded, Receiver, RecvError, Sender};
+use crate::error::MopperError;
+use crate::Execution;
+use crate::mopper_options::MopperOptions;
+use crate::source::{Source, SourceFactory};
+use crate::stats::RunStats;
+
+/// The value of `source` in the configuration of an external source.
+pub const EXTERNAL_SOURCE: &str = "external";
+
+/// A record pushed to an external source: the values by field name.
+pub type Record = HashMap<String, String>;
+
+/// The handle to push records to the external sources of a run. Dropping it closes all external sources.
+pub struct ExternalSources {
+    senders: HashMap<String, Sender<Record>>
+}
+
+impl ExternalSources {
+    pub(crate) fn new(senders: HashMap<String, Sender<Record>>) -> Self {
+        ExternalSources { senders }
+    }
+
+    /// The names of the external sources that are still open.
+    pub fn source_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.senders.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    /// Pushes a record to the external source with the given name. Blocks while the source has
+    /// no room for it. All records of a source must have the fields of the first record.
+    pub fn push<K: Into<String>, V: Into<String>>(&self, source_name: &str, record: impl IntoIterator<Item = (K, V)>) -> Result<(), MopperError> {
+        let Some(sender) = self.senders.get(source_name) else {
+            return Err(MopperError::Push { source_name: source_name.to_string(), msg: "no such open source".to_string() });
+        };
+        let record: Record = record.into_iter().map(|(field, value)| (field.into(), value.into())).collect();
+        // a source that stops, e.g. because the run fails, drops its receiver
+        sender.send(record)
+            .map_err(|_| MopperError::Push { source_name: source_name.to_string(), msg: "the run has stopped".to_string() })
+    }
+
+    /// Closes the external source with the given name: no more records will be pushed to it.
+    pub fn close(&mut self, source_name: &str) {
+        self.senders.remove(source_name);
+    }
+}
+
+/// A run of a plan whose external sources take records from the caller.
+/// The run goes on until all external sources are closed, see [Engine::close] and [Engine::finish].
+pub struct Engine {
+    sources: ExternalSources,
+    execution: Execution
+}
+
+impl Engine {
+    pub(crate) fn new(sources: ExternalSources, execution: Execution) -> Self {
+        Engine { sources, execution }
+    }
+
+    /// The names of the external sources that are still open.
+    pub fn source_names(&self) -> Vec<&str> {
+        self.sources.source_names()
+    }
+
+    /// Pushes a record to the external source with the given name, see [ExternalSources::push].
+    pub fn push<K: Into<String>, V: Into<String>>(&self, source_name: &str, record: impl IntoIterator<Item = (K, V)>) -> Result<(), MopperError> {
+        self.sources.push(source_name, record)
+    }
+
+    /// Closes the external source with the given name: no more records will be pushed to it.
+    pub fn close(&mut self, source_name: &str) {
+        self.sources.close(source_name);
+    }
+
+    /// Closes all external sources, waits until the run is done, and returns its statistics when successful.
+    pub fn finish(self) -> Result<RunStats, MopperError> {
+        drop(self.sources);
+        self.execution.wait()
+    }
+}
+
+// The senders of the external sources created for a run, by name
+pub(crate) type ExternalSenders = Arc<Mutex<HashMap<String, Sender<Record>>>>;
+
+/// Adds a custom source for the external sources to the options. The senders of the sources that
+/// are created are put in the returned map.
+pub(crate) fn with_external_sources(options: &MopperOptions) -> (MopperOptions, ExternalSenders) {
+    let senders: ExternalSenders = Arc::new(Mutex::new(HashMap::new()));
+    let factory_senders = senders.clone();
+    let capacity = options.message_buffer_capacity();
+    let factory = SourceFactory::new(move |source| {
+        let Some(name) = source.config.get("name") else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "External source without name"));
+        };
+        let mut senders = factory_senders.lock().unwrap();
+        if senders.contains_key(name) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("External source {name} is used more than once")));
+        }
+        let (sender, receiver) = bounded::<Record>(capacity);
+        senders.insert(name.clone(), sender);
+        Ok(Box::new(ExternalSource { name: name.clone(), receiver, fields: Vec::new(), first_record: None }))
+    });
+    (options.clone().with_source(EXTERNAL_SOURCE, factory), senders)
+}
+
+// Takes the records pushed to it. The fields are those of the first record.
+struct ExternalSource {
+    name: String,
+    receiver: Receiver<Record>,
+    fields: Vec<String>,
+    first_record: Option<Record>
+}
+
+impl Source for ExternalSource {
+    fn fields(&mut self) -> io::Result<Vec<String>> {
+        // wait for the first record; if the source is closed before, there are no fields
+        if let Ok(record) = self.receiver.recv() {
+            let mut fields: Vec<String> = record.keys().cloned().collect();
+            fields.sort();
+            self.fields = fields;
+            self.first_record = Some(record);
+        }
+        Ok(self.fields.clone())
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        let record = match self.first_record.take() {
+            Some(record) => record,
+            None => match self.receiver.recv() {
+                Ok(record) => record,
+                Err(RecvError) => return Ok(None)
+            }
+        };
+        self.fields.iter()
+            .map(|field| record.get(field).cloned()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Record pushed to {} has no field {field}", self.name))))
+            .collect::<io::Result<Vec<String>>>()
+            .map(Some)
+    }
+}
@@ -14,6 +14,30 @@
  *    limitations under the License.
  */
 
+use std::fmt::{Display, Formatter};
+
+/// Why a function could not process a record.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FunctionError {
+    /// The function generated a value that is not a valid IRI
+    InvalidIri(String),
+    /// A value the function needs is missing from the record
+    MissingValue(String)
+}
+
+impl Display for FunctionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FunctionError::InvalidIri(iri) => write!(f, "Invalid IRI: {iri}"),
+            FunctionError::MissingValue(msg) => f.write_str(msg)
+        }
+    }
+}
+
+/// The outcome of executing a function on one record: the resulting values, or
+/// the reason why the record could not be processed.
+pub type FunctionResult = Result<Vec<String>, FunctionError>;
+
 pub trait BasicFunction {
     fn variable_names(&mut self, _variable_names: &[String]) {}  // by default ignore the headers
 
@@ -24,5 +48,5 @@ pub trait BasicFunction {
         "str"
     }
 
-    fn exec(&self, input: &[String]) -> Vec<String>;
+    fn exec(&self, input: &[String]) -> FunctionResult;
 }
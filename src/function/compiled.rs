@@ -0,0 +1,367 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use log::{debug, error};
+use operator::Function;
+use pct_str::{PctString, URIReserved};
+use crate::error::MopperError;
+use crate::function::basic_function::{BasicFunction, FunctionError, FunctionResult};
+use crate::function::counter::{Counter, CounterScope};
+use crate::function::iri::check_iri;
+use crate::function::template_parser::parse_template;
+use crate::function::uri_encode::IriSafe;
+use crate::mopper_options::{IriEncoding, MissingReferencePolicy};
+use crate::source::RECORD_NUMBER_REFERENCE;
+use crate::util::remove_join_alias_prefix;
+
+/// An extend function, compiled from its tree of functions into a flat list of instructions when
+/// the plan is built. The instructions run on a stack of values, and references are looked up by
+/// their position in the record, so executing it on a record needs no nested calls or maps.
+pub struct CompiledFunction {
+    instructions: Vec<Instruction>,
+    // The names of the references, with their positions in the records once the schema is known
+    references: Vec<(String, Option<usize>)>,
+    // The most values on the stack at the same time
+    stack_size: usize,
+    result_type: &'static str
+}
+
+enum Instruction {
+    /// Push a value
+    Constant(String),
+    /// Push the value of a reference, or an empty string if it has none and `empty_if_missing`
+    Reference { reference: usize, empty_if_missing: bool, in_template: bool },
+    /// Replace the given number of values on top of the stack by their concatenation
+    Concatenate(usize),
+    /// Percent-encode the value on top of the stack
+    Encode(IriEncoding),
+    /// Make the value on top of the stack an IRI, resolved against the base IRI if it is relative
+    Iri(Option<String>),
+    /// Push the value of a counter of the records of the source, from the record number
+    SourceCounter { counter: Counter, reference: usize },
+    /// Push the next value of a counter of the records the function is executed on
+    FunctionCounter(AtomicU64)
+}
+
+impl CompiledFunction {
+    /// Compiles the function, which percent-encodes values for IRIs with `iri_encoding`, and uses empty strings
+    /// for references without value if `missing_reference_policy` says so.
+    pub fn compile(function: &Function, join_alias: &Option<String>, iri_encoding: &IriEncoding, missing_reference_policy: &MissingReferencePolicy) -> Result<Self, MopperError> {
+        let result_type = match function {
+            Function::Iri { .. } => "iri",
+            Function::Literal { .. } => "lit",
+            Function::BlankNode { .. } => "blank",
+            _ => "str"
+        };
+        let mut compiled = CompiledFunction { instructions: Vec::new(), references: Vec::new(), stack_size: 0, result_type };
+        let mut compiler = Compiler {
+            function: &mut compiled,
+            join_alias,
+            iri_encoding,
+            empty_if_missing: *missing_reference_policy == MissingReferencePolicy::Empty,
+            depth: 0
+        };
+        compiler.compile(function)?;
+        Ok(compiled)
+    }
+
+    /// Looks up the positions of the references in the records with these variable names.
+    pub fn bind(&mut self, variable_names: &[String]) {
+        for (name, index) in &mut self.references {
+            *index = variable_names.iter().position(|variable_name| variable_name == name);
+        }
+    }
+
+    /// Executes the function on a record.
+    pub fn exec_value(&self, input: &[String]) -> Result<String, FunctionError> {
+        let mut stack: Vec<String> = Vec::with_capacity(self.stack_size);
+        for instruction in &self.instructions {
+            match instruction {
+                Instruction::Constant(value) => stack.push(value.clone()),
+                Instruction::Reference { reference, empty_if_missing, in_template } => {
+                    let (name, index) = &self.references[*reference];
+                    match index.and_then(|index| input.get(index)) {
+                        Some(value) => stack.push(value.clone()),
+                        None if *empty_if_missing => stack.push(String::new()),
+                        None if *in_template => return Err(FunctionError::MissingValue(format!("No value found for reference {name} in template"))),
+                        None => return Err(FunctionError::MissingValue(format!("No value found for reference {name}")))
+                    }
+                },
+                Instruction::Concatenate(count) => {
+                    let value = stack.drain(stack.len() - count..).collect();
+                    stack.push(value);
+                },
+                Instruction::Encode(encoding) => {
+                    let value = stack.pop().unwrap();
+                    stack.push(match encoding {
+                        IriEncoding::IriSafe => PctString::encode(value.chars(), IriSafe).into_string(),
+                        IriEncoding::Uri => PctString::encode(value.chars(), URIReserved).into_string(),
+                        IriEncoding::None => value
+                    });
+                },
+                Instruction::Iri(base_iri) => {
+                    let value = stack.pop().unwrap();
+                    stack.push(check_iri(base_iri, value)?);
+                },
+                Instruction::SourceCounter { counter, reference } => {
+                    let number = self.references[*reference].1
+                        .and_then(|index| input.get(index))
+                        .and_then(|number| number.parse().ok())
+                        .ok_or_else(|| FunctionError::MissingValue("No record number found for counter".to_string()))?;
+                    stack.push(counter.value(number).to_string());
+                },
+                Instruction::FunctionCounter(next) => stack.push(next.fetch_add(1, Ordering::Relaxed).to_string())
+            }
+        }
+        Ok(stack.pop().unwrap_or_default())
+    }
+
+    pub fn result_type(&self) -> &'static str {
+        self.result_type
+    }
+}
+
+impl BasicFunction for CompiledFunction {
+    fn variable_names(&mut self, variable_names: &[String]) {
+        self.bind(variable_names);
+    }
+
+    fn get_result_type(&self) -> &str {
+        self.result_type
+    }
+
+    fn exec(&self, input: &[String]) -> FunctionResult {
+        self.exec_value(input).map(|value| vec![value])
+    }
+}
+
+struct Compiler<'a> {
+    function: &'a mut CompiledFunction,
+    join_alias: &'a Option<String>,
+    iri_encoding: &'a IriEncoding,
+    empty_if_missing: bool,
+    // The number of values on the stack
+    depth: usize
+}
+
+impl Compiler<'_> {
+    // Adds the instructions that push the value of the function
+    fn compile(&mut self, function: &Function) -> Result<(), MopperError> {
+        match function {
+            Function::Constant { value } => {
+                debug!(" function 'Constant': [{value}]");
+                self.push(Instruction::Constant(value.clone()));
+            },
+            Function::UriEncode { inner_function } => {
+                debug!(" function 'UriEncode'.");
+                self.compile(inner_function)?;
+                self.function.instructions.push(Instruction::Encode(self.iri_encoding.clone()));
+            },
+            Function::Iri { base_iri, inner_function } => {
+                debug!(" function 'Iri'");
+                self.compile(inner_function)?;
+                self.function.instructions.push(Instruction::Iri(base_iri.clone()));
+            },
+            Function::TemplateString { value } => {
+                debug!(" function 'TemplateString': [{value}]");
+                let parts = parse_template(value, self.join_alias)?;
+                for (is_variable, part) in &parts {
+                    if *is_variable {
+                        let reference = self.reference(part);
+                        self.push(Instruction::Reference { reference, empty_if_missing: false, in_template: true });
+                    } else {
+                        self.push(Instruction::Constant(part.clone()));
+                    }
+                }
+                self.concatenate(parts.len());
+            },
+            Function::TemplateFunctionValue { template, variable_function_pairs } => {
+                let parts = parse_template(template, self.join_alias)?;
+                for (is_variable, part) in &parts {
+                    if *is_variable {
+                        let (_name, function) = variable_function_pairs.iter()
+                            .find(|(name, _function)| remove_join_alias_prefix(name, self.join_alias) == *part)
+                            .ok_or_else(|| MopperError::plan(format!("No function found for {part} in template '{template}'")))?;
+                        self.compile(function)?;
+                    } else {
+                        self.push(Instruction::Constant(part.clone()));
+                    }
+                }
+                self.concatenate(parts.len());
+            },
+            Function::BlankNode { inner_function } => {
+                debug!(" function 'BlankNode'");
+                self.compile(inner_function)?;
+            },
+            Function::Concatenate { .. } => {
+                error!(" function 'Concatenate' not implemented yet.");
+                return Err(MopperError::plan("Function Concatenate is not supported"));
+            },
+            Function::FnO { fno_identifier, .. } => {
+                let Some(counter) = Counter::of(function) else {
+                    error!(" function 'FnO' {fno_identifier} not implemented yet.");
+                    return Err(MopperError::plan(format!("Function {fno_identifier} is not supported")));
+                };
+                let counter = counter?;
+                debug!(" function 'Counter': {counter:?}");
+                match counter.scope {
+                    CounterScope::Source => {
+                        let reference = self.reference(RECORD_NUMBER_REFERENCE);
+                        self.push(Instruction::SourceCounter { counter, reference });
+                    },
+                    CounterScope::Function => self.push(Instruction::FunctionCounter(AtomicU64::new(counter.start)))
+                }
+            },
+            Function::Literal { inner_function, .. } => {
+                debug!(" function 'Literal'");
+                self.compile(inner_function)?;
+            },
+            Function::Lower { .. } => {
+                error!(" function 'Lower' not implemented yet.");
+                return Err(MopperError::plan("Function Lower is not supported"));
+            },
+            Function::Upper { .. } => {
+                error!(" function 'Upper' not implemented yet.");
+                return Err(MopperError::plan("Function Upper is not supported"));
+            },
+            Function::Reference { value } => {
+                debug!(" function 'Reference': [{value}]");
+                let reference = self.reference(&remove_join_alias_prefix(value, self.join_alias));
+                self.push(Instruction::Reference { reference, empty_if_missing: self.empty_if_missing, in_template: false });
+            },
+            Function::Replace { .. } => {
+                error!(" function 'Replace' not implemented yet.");
+                return Err(MopperError::plan("Function Replace is not supported"));
+            }
+        }
+        Ok(())
+    }
+
+    // Adds an instruction that pushes a value
+    fn push(&mut self, instruction: Instruction) {
+        self.function.instructions.push(instruction);
+        self.depth += 1;
+        self.function.stack_size = self.function.stack_size.max(self.depth);
+    }
+
+    // Concatenates the values of the parts of a template
+    fn concatenate(&mut self, count: usize) {
+        match count {
+            0 => self.push(Instruction::Constant(String::new())),
+            1 => {},
+            _ => {
+                self.function.instructions.push(Instruction::Concatenate(count));
+                self.depth -= count - 1;
+            }
+        }
+    }
+
+    // The position of the reference in the references of the function
+    fn reference(&mut self, name: &str) -> usize {
+        let references = &mut self.function.references;
+        references.iter().position(|(reference, _index)| reference == name).unwrap_or_else(|| {
+            references.push((name.to_string(), None));
+            references.len() - 1
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::function::counter::{COUNTER_FUNCTION, COUNTER_SCOPE_PARAMETER, COUNTER_START_PARAMETER};
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn template_compiled() {
+        let template = Function::Iri {
+            base_iri: Some("http://example.com/".to_string()),
+            inner_function: Rc::new(Function::TemplateFunctionValue {
+                template: "person/{name}/{id}".to_string(),
+                variable_function_pairs: vec![
+                    ("name".to_string(), Rc::new(Function::UriEncode { inner_function: Rc::new(Function::Reference { value: "name".to_string() }) })),
+                    ("id".to_string(), Rc::new(Function::TemplateString { value: "n{id}".to_string() }))
+                ]
+            })
+        };
+        let mut function = CompiledFunction::compile(&template, &None, &IriEncoding::IriSafe, &MissingReferencePolicy::default()).unwrap();
+        assert_eq!("iri", function.result_type());
+        assert_eq!(2, function.references.len());
+        function.bind(&names(&["id", "name"]));
+        assert_eq!(Ok("http://example.com/person/Venus%20W/n10".to_string()), function.exec_value(&names(&["10", "Venus W"])));
+        assert!(matches!(function.exec_value(&names(&["1 0", "Venus"])), Err(FunctionError::InvalidIri(_))));
+    }
+
+    #[test]
+    fn missing_references() {
+        let reference = Function::Reference { value: "name".to_string() };
+        let mut function = CompiledFunction::compile(&reference, &None, &IriEncoding::default(), &MissingReferencePolicy::Empty).unwrap();
+        function.bind(&names(&["id"]));
+        assert_eq!(Ok(String::new()), function.exec_value(&names(&["10"])));
+
+        let template = Function::TemplateString { value: "{name}".to_string() };
+        let mut function = CompiledFunction::compile(&template, &None, &IriEncoding::default(), &MissingReferencePolicy::Empty).unwrap();
+        function.bind(&names(&["id"]));
+        assert_eq!(Err(FunctionError::MissingValue("No value found for reference name in template".to_string())), function.exec_value(&names(&["10"])));
+
+        let template = Function::TemplateFunctionValue { template: "{name}".to_string(), variable_function_pairs: Vec::new() };
+        assert!(CompiledFunction::compile(&template, &None, &IriEncoding::default(), &MissingReferencePolicy::default()).is_err());
+    }
+
+    #[test]
+    fn counters() {
+        let counter = |scope: &str| Function::FnO {
+            fno_identifier: COUNTER_FUNCTION.to_string(),
+            parameters: vec![
+                (COUNTER_START_PARAMETER.to_string(), Rc::new(Function::Constant { value: "100".to_string() })),
+                (COUNTER_SCOPE_PARAMETER.to_string(), Rc::new(Function::Constant { value: scope.to_string() }))
+            ]
+        };
+        let mut function = CompiledFunction::compile(&counter("source"), &None, &IriEncoding::default(), &MissingReferencePolicy::default()).unwrap();
+        function.bind(&names(&["id", RECORD_NUMBER_REFERENCE]));
+        assert_eq!(Ok("104".to_string()), function.exec_value(&names(&["a", "5"])));
+        assert_eq!(Ok("104".to_string()), function.exec_value(&names(&["b", "5"])));
+
+        let function = CompiledFunction::compile(&counter("function"), &None, &IriEncoding::default(), &MissingReferencePolicy::default()).unwrap();
+        assert_eq!(Ok("100".to_string()), function.exec_value(&names(&["a"])));
+        assert_eq!(Ok("101".to_string()), function.exec_value(&names(&["a"])));
+
+        let unknown = Function::FnO { fno_identifier: "http://example.com/idlab/function/random".to_string(), parameters: Vec::new() };
+        assert!(CompiledFunction::compile(&unknown, &None, &IriEncoding::default(), &MissingReferencePolicy::default()).is_err());
+    }
+
+    #[test]
+    fn unsupported_functions() {
+        let name = Rc::new(Function::Reference { value: "name".to_string() });
+        let functions = [
+            ("Concatenate", Function::Concatenate { left_value: name.clone(), separator: " ".to_string(), right_value: name.clone() }),
+            ("Lower", Function::Lower { inner_function: name.clone() }),
+            ("Upper", Function::Upper { inner_function: name.clone() }),
+            ("Replace", Function::Replace { replaced_inner_function: name.clone(), with_inner_function: name.clone(), inner_function: name })
+        ];
+        for (function_name, function) in functions {
+            let Err(err) = CompiledFunction::compile(&function, &None, &IriEncoding::default(), &MissingReferencePolicy::default()) else {
+                panic!("Function {function_name} compiled");
+            };
+            assert_eq!(format!("Invalid plan: Function {function_name} is not supported"), err.to_string());
+        }
+    }
+}
@@ -14,7 +14,7 @@
  *    limitations under the License.
  */
 
-use crate::function::basic_function::BasicFunction;
+use crate::function::basic_function::{BasicFunction, FunctionResult};
 
 pub struct ConstantFunction {
     value: Vec<String>
@@ -27,7 +27,7 @@ impl ConstantFunction {
 }
 
 impl BasicFunction for ConstantFunction {
-    fn exec(&self, _input: &[String]) -> Vec<String> {
-        self.value.clone()
+    fn exec(&self, _input: &[String]) -> FunctionResult {
+        Ok(self.value.clone())
     }
 }
\ No newline at end of file
@@ -0,0 +1,103 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+use operator::{Function, RcExtendFunction};
+use crate::error::MopperError;
+
+/// The identifier of the FnO function that counts records, e.g. to mint IRIs for records of sources without a key.
+pub const COUNTER_FUNCTION: &str = "https://github.com/ghsnd/mopper#counter";
+
+/// The parameter with the first value of a counter, 1 by default.
+pub const COUNTER_START_PARAMETER: &str = "https://github.com/ghsnd/mopper#start";
+
+/// The parameter with the [CounterScope] of a counter, `source` by default.
+pub const COUNTER_SCOPE_PARAMETER: &str = "https://github.com/ghsnd/mopper#scope";
+
+/// What a counter counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterScope {
+    /// The records of the source, so all terms of a record get the same value, also in other extend operators
+    Source,
+    /// The records the function is executed on, so every value is unique, also after a join
+    Function
+}
+
+/// A counter, as configured by the parameters of a [COUNTER_FUNCTION].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counter {
+    pub start: u64,
+    pub scope: CounterScope
+}
+
+impl Counter {
+    /// The counter of the function, or `None` if it is not a counter.
+    pub fn of(function: &Function) -> Option<Result<Counter, MopperError>> {
+        match function {
+            Function::FnO { fno_identifier, parameters } if fno_identifier == COUNTER_FUNCTION => Some(Counter::from_parameters(parameters)),
+            _ => None
+        }
+    }
+
+    /// The counter with these parameters, which have to be constants.
+    pub fn from_parameters(parameters: &[(String, RcExtendFunction)]) -> Result<Counter, MopperError> {
+        let mut counter = Counter { start: 1, scope: CounterScope::Source };
+        for (name, function) in parameters {
+            let Function::Constant { value } = function.as_ref() else {
+                return Err(MopperError::plan(format!("Parameter {name} of counter is not a constant")));
+            };
+            match name.as_str() {
+                COUNTER_START_PARAMETER => counter.start = value.parse()
+                    .map_err(|_| MopperError::plan(format!("Start of counter is not a number: {value}")))?,
+                COUNTER_SCOPE_PARAMETER => counter.scope = match value.as_str() {
+                    "source" => CounterScope::Source,
+                    "function" => CounterScope::Function,
+                    _ => return Err(MopperError::plan(format!("Unknown scope of counter: {value}, expected source or function")))
+                },
+                _ => return Err(MopperError::plan(format!("Unknown parameter of counter: {name}")))
+            }
+        }
+        Ok(counter)
+    }
+
+    /// The value of the counter for the record with the given number, starting at 1.
+    pub fn value(&self, number: u64) -> u64 {
+        self.start + number - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+
+    fn counter(parameters: &[(&str, &str)]) -> Result<Counter, MopperError> {
+        let parameters: Vec<(String, RcExtendFunction)> = parameters.iter()
+            .map(|(name, value)| (name.to_string(), Rc::new(Function::Constant { value: value.to_string() })))
+            .collect();
+        Counter::of(&Function::FnO { fno_identifier: COUNTER_FUNCTION.to_string(), parameters }).unwrap()
+    }
+
+    #[test]
+    fn counter_parameters() {
+        assert_eq!(Counter { start: 1, scope: CounterScope::Source }, counter(&[]).unwrap());
+        let function_counter = counter(&[(COUNTER_START_PARAMETER, "1000"), (COUNTER_SCOPE_PARAMETER, "function")]).unwrap();
+        assert_eq!(Counter { start: 1000, scope: CounterScope::Function }, function_counter);
+        assert_eq!(1002, function_counter.value(3));
+        assert!(counter(&[(COUNTER_START_PARAMETER, "-1")]).is_err());
+        assert!(counter(&[(COUNTER_SCOPE_PARAMETER, "run")]).is_err());
+        assert!(counter(&[("https://github.com/ghsnd/mopper#step", "2")]).is_err());
+        assert!(Counter::of(&Function::Constant { value: "1".to_string() }).is_none());
+    }
+}
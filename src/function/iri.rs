@@ -15,8 +15,11 @@
  */
 use iri_string::spec::UriSpec;
 use iri_string::validate::{iri, iri_reference};
-use log::error;
-use crate::function::basic_function::BasicFunction;
+use crate::function::basic_function::{BasicFunction, FunctionError, FunctionResult};
+
+/// Stands in for an invalid IRI when skipping only the statements with invalid IRIs.
+/// It can never be part of a valid IRI, so it cannot be mistaken for one.
+pub const INVALID_IRI: &str = "\u{0}INVALID IRI\u{0}";
 
 pub struct IriFunction {
     base_iri: Option<String>,
@@ -41,30 +44,31 @@ impl BasicFunction for IriFunction {
         "iri"
     }
 
-    fn exec(&self, input: &[String]) -> Vec<String> {
-        let output = self.inner_function.exec(input);
+    fn exec(&self, input: &[String]) -> FunctionResult {
+        let output = self.inner_function.exec(input)?;
 
         output.into_iter()
-            .map(|value| {
-                // check if the value is an absolute IRI
-                let absolute_iri_check = iri::<UriSpec>(&value);
-                if absolute_iri_check.is_ok() {
-                    return value;
-                } else {
-                    let iri = match &self.base_iri {
-                        Some(base_iri) => format!("{base_iri}{value}"),
-                        None => value
-                    };
-                    // check if it's a valid IRI
-                    let valid_iri_check = iri_reference::<UriSpec>(&iri);
-                    if valid_iri_check.is_ok() {
-                        return iri
-                    } else {
-                        error!("Invalid IRI: {iri}");
-                        return "INVALID".to_string();
-                    }
-                }
-            })
+            .map(|value| check_iri(&self.base_iri, value))
             .collect()
     }
 }
+
+/// The value if it is an absolute IRI, otherwise the value resolved against the base IRI, if it is then a valid IRI.
+pub(crate) fn check_iri(base_iri: &Option<String>, value: String) -> Result<String, FunctionError> {
+    // check if the value is an absolute IRI
+    let absolute_iri_check = iri::<UriSpec>(&value);
+    if absolute_iri_check.is_ok() {
+        return Ok(value);
+    }
+    let iri = match base_iri {
+        Some(base_iri) => format!("{base_iri}{value}"),
+        None => value
+    };
+    // check if it's a valid IRI
+    let valid_iri_check = iri_reference::<UriSpec>(&iri);
+    if valid_iri_check.is_ok() {
+        Ok(iri)
+    } else {
+        Err(FunctionError::InvalidIri(iri))
+    }
+}
@@ -14,7 +14,7 @@
  *    limitations under the License.
  */
 
-use crate::function::basic_function::BasicFunction;
+use crate::function::basic_function::{BasicFunction, FunctionResult};
 
 pub struct LiteralFunction {
     inner_function: Box<dyn BasicFunction + Send>
@@ -37,7 +37,7 @@ impl BasicFunction for LiteralFunction {
         "lit"
     }
 
-    fn exec(&self, input: &[String]) -> Vec<String> {
+    fn exec(&self, input: &[String]) -> FunctionResult {
         self.inner_function.exec(input)
     }
 }
\ No newline at end of file
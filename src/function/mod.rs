@@ -22,5 +22,7 @@ pub mod template_string;
 pub mod literal;
 pub mod reference;
 pub mod blank_node;
-mod template_parser;
-pub mod template_function_value;
\ No newline at end of file
+pub(crate) mod template_parser;
+pub mod template_function_value;
+pub mod compiled;
+pub mod counter;
\ No newline at end of file
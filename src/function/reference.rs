@@ -14,19 +14,26 @@
  *    limitations under the License.
  */
 
-use crate::function::basic_function::BasicFunction;
+use crate::function::basic_function::{BasicFunction, FunctionError, FunctionResult};
 use crate::util::remove_join_alias_prefix;
 
+/// Stands in for the value of a term that is left out because a reference has no value,
+/// so the serializer skips the statements with it.
+pub const NO_VALUE: &str = "\u{0}NO VALUE\u{0}";
+
 pub struct ReferenceFunction {
     variable_name: String,
-    index: usize
+    index: Option<usize>,
+    empty_if_missing: bool
 }
 
 impl ReferenceFunction {
-    pub fn new(variable_name: String, join_alias: &Option<String>) -> Self {
+    /// If `empty_if_missing` is set, the value is an empty string if the record has no value for the reference.
+    pub fn new(variable_name: String, join_alias: &Option<String>, empty_if_missing: bool) -> Self {
         ReferenceFunction{
             variable_name: remove_join_alias_prefix(&variable_name, join_alias),
-            index: 0
+            index: None,
+            empty_if_missing
         }
     }
 }
@@ -35,13 +42,17 @@ impl BasicFunction for ReferenceFunction {
     fn variable_names(&mut self, variable_names: &[String]) {
         for (index, name) in variable_names.iter().enumerate() {
             if *name == self.variable_name {
-                self.index = index;
+                self.index = Some(index);
                 break;
             }
         }
     }
 
-    fn exec(&self, input: &[String]) -> Vec<String> {
-        vec![input[self.index].to_string()]
+    fn exec(&self, input: &[String]) -> FunctionResult {
+        match self.index.and_then(|index| input.get(index)) {
+            Some(value) => Ok(vec![value.to_string()]),
+            None if self.empty_if_missing => Ok(vec![String::new()]),
+            None => Err(FunctionError::MissingValue(format!("No value found for reference {}", self.variable_name)))
+        }
     }
 }
\ No newline at end of file
@@ -14,8 +14,8 @@
  *    limitations under the License.
  */
 use std::collections::HashMap;
-use crate::error::GeneralError;
-use crate::function::basic_function::BasicFunction;
+use crate::error::MopperError;
+use crate::function::basic_function::{BasicFunction, FunctionError, FunctionResult};
 use crate::function::template_parser::parse_template;
 
 pub struct TemplateFunctionValueFunction {
@@ -27,7 +27,7 @@ pub struct TemplateFunctionValueFunction {
 }
 
 impl TemplateFunctionValueFunction {
-    pub fn new(template: &str, variable_function_pairs: HashMap<String, Box<dyn BasicFunction + Send>>, join_alias: &Option<String>) -> Result<Self, GeneralError> {
+    pub fn new(template: &str, variable_function_pairs: HashMap<String, Box<dyn BasicFunction + Send>>, join_alias: &Option<String>) -> Result<Self, MopperError> {
         Ok(TemplateFunctionValueFunction {
             template_string_parts: parse_template(template, join_alias)?,
             variable_names: Vec::with_capacity(1),
@@ -43,21 +43,22 @@ impl BasicFunction for TemplateFunctionValueFunction {
             .for_each(|funcion| funcion.variable_names(variable_names))
     }
 
-    fn exec(&self, input: &[String]) -> Vec<String> {
+    fn exec(&self, input: &[String]) -> FunctionResult {
         let mut result_str = String::new();
         let variable_to_function_map = &self.variable_to_function_map;
-        self.template_string_parts.iter()
-            .for_each(|(is_variable, part)| {
-                if *is_variable {
-                    //let value = variable_name_to_value_map[part];
-                    let function = &variable_to_function_map[part];
-                    let output = function.exec(input);
-                    result_str.push_str(&output[0]);
-                } else {
-                    result_str.push_str(part);
-                }
-            });
-        vec![result_str]
+        for (is_variable, part) in &self.template_string_parts {
+            if *is_variable {
+                //let value = variable_name_to_value_map[part];
+                let function = &variable_to_function_map[part];
+                let output = function.exec(input)?;
+                let value = output.first()
+                    .ok_or_else(|| FunctionError::MissingValue(format!("No value found for {part} in template")))?;
+                result_str.push_str(value);
+            } else {
+                result_str.push_str(part);
+            }
+        }
+        Ok(vec![result_str])
     }
 }
 
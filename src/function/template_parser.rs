@@ -13,10 +13,10 @@
  *    See the License for the specific language governing permissions and
  *    limitations under the License.
  */
-use crate::error::GeneralError;
+use crate::error::MopperError;
 use crate::util::remove_join_alias_prefix;
 
-pub fn parse_template(template: &str, join_alias: &Option<String>) -> Result<Vec<(bool, String)>, GeneralError> {
+pub fn parse_template(template: &str, join_alias: &Option<String>) -> Result<Vec<(bool, String)>, MopperError> {
     let mut template_string_parts: Vec<(bool, String)> = Vec::with_capacity(2);
     let mut current_str = String::new();
     let mut between_cb = false;
@@ -31,7 +31,7 @@ pub fn parse_template(template: &str, join_alias: &Option<String>) -> Result<Vec
                 } else {
                     if between_cb {
                         let err_msg = format!("Error parsing template '{template}': Unescaped '{{' found between {{}}.");
-                        return Err(GeneralError::from_msg(err_msg.to_string()))
+                        return Err(MopperError::plan(err_msg.to_string()))
                     } else {
                         if !current_str.is_empty() {
                             template_string_parts.push((false, current_str.to_string()));
@@ -55,7 +55,7 @@ pub fn parse_template(template: &str, join_alias: &Option<String>) -> Result<Vec
                         between_cb = false;
                     } else {
                         let err_msg = format!("Error parsing template '{template}': Unescaped '}}' found between {{}}.");
-                        return Err(GeneralError::from_msg(err_msg.to_string()))
+                        return Err(MopperError::plan(err_msg.to_string()))
                     }
                 }
             },
@@ -70,7 +70,7 @@ pub fn parse_template(template: &str, join_alias: &Option<String>) -> Result<Vec
             _ => {
                 if escape {
                     let err_msg = format!("Error parsing template '{template}': character '{c}' is being escaped, but it doesn't need escaping.");
-                    return Err(GeneralError::from_msg(err_msg.to_string()))
+                    return Err(MopperError::plan(err_msg.to_string()))
                 }
                 current_str.push(c);
             }
@@ -82,11 +82,11 @@ pub fn parse_template(template: &str, join_alias: &Option<String>) -> Result<Vec
 
     if between_cb {
         let err_msg = format!("Error parsing template '{template}': missing '}}'");
-        return Err(GeneralError::from_msg(err_msg.to_string()))
+        return Err(MopperError::plan(err_msg.to_string()))
     }
     if escape {
         let err_msg = format!("Error parsing template '{template}': expecting character to escape after final '\\'");
-        return Err(GeneralError::from_msg(err_msg.to_string()))
+        return Err(MopperError::plan(err_msg.to_string()))
     }
 
     // add last part, if any
@@ -15,8 +15,8 @@
  */
 
 use std::collections::HashMap;
-use crate::error::GeneralError;
-use crate::function::basic_function::BasicFunction;
+use crate::error::MopperError;
+use crate::function::basic_function::{BasicFunction, FunctionError, FunctionResult};
 use crate::function::template_parser::parse_template;
 
 pub struct TemplateStrFunction {
@@ -27,7 +27,7 @@ pub struct TemplateStrFunction {
 }
 
 impl TemplateStrFunction {
-    pub fn new(template: &str, join_alias: &Option<String>) -> Result<Self, GeneralError> {
+    pub fn new(template: &str, join_alias: &Option<String>) -> Result<Self, MopperError> {
         Ok(TemplateStrFunction{
             template_string_parts: parse_template(template, join_alias)?,
             variable_names: Vec::with_capacity(1)
@@ -39,7 +39,7 @@ impl BasicFunction for TemplateStrFunction {
     fn variable_names(&mut self, variable_names: &[String]) {
         self.variable_names = variable_names.to_vec();
     }
-    fn exec(&self, input: &[String]) -> Vec<String> {
+    fn exec(&self, input: &[String]) -> FunctionResult {
         let mut variable_name_to_value_map = HashMap::with_capacity(input.len());
         for (index, value) in input.iter().enumerate() {
             let variable_name = &self.variable_names[index];
@@ -48,15 +48,15 @@ impl BasicFunction for TemplateStrFunction {
 
         let mut result_str = String::new();
 
-        self.template_string_parts.iter()
-            .for_each(|(is_variable, part)| {
-                if *is_variable {
-                    let value = variable_name_to_value_map[part];
-                    result_str.push_str(value);
-                } else {
-                    result_str.push_str(part);
-                }
-            });
-        vec![result_str]
+        for (is_variable, part) in &self.template_string_parts {
+            if *is_variable {
+                let value = variable_name_to_value_map.get(part)
+                    .ok_or_else(|| FunctionError::MissingValue(format!("No value found for reference {part} in template")))?;
+                result_str.push_str(value);
+            } else {
+                result_str.push_str(part);
+            }
+        }
+        Ok(vec![result_str])
     }
 }
\ No newline at end of file
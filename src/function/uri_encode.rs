@@ -14,17 +14,18 @@
  *    limitations under the License.
  */
 
-use pct_str::{PctString, URIReserved};
-use crate::function::basic_function::BasicFunction;
+use pct_str::{Encoder, PctString, URIReserved};
+use crate::function::basic_function::{BasicFunction, FunctionResult};
+use crate::mopper_options::IriEncoding;
 
 pub struct UriEncodeFunction {
-    inner_function: Box<dyn BasicFunction + Send>
+    inner_function: Box<dyn BasicFunction + Send>,
+    encoding: IriEncoding
 }
 
 impl UriEncodeFunction {
-    // Not used at the moment...
-    pub fn new(inner_function: Box<dyn BasicFunction + Send>) -> Self {
-        UriEncodeFunction {inner_function}
+    pub fn new(inner_function: Box<dyn BasicFunction + Send>, encoding: &IriEncoding) -> Self {
+        UriEncodeFunction {inner_function, encoding: encoding.clone()}
     }
 }
 
@@ -33,11 +34,52 @@ impl BasicFunction for UriEncodeFunction {
     fn variable_names(&mut self, variable_names: &[String]) {
         self.inner_function.variable_names(variable_names);
     }
-    fn exec(&self, input: &[String]) -> Vec<String> {
-        let inner_result = self.inner_function.exec(input);
-        inner_result.iter().map(|value| {
-            let pct_str = PctString::encode(value.chars(), URIReserved);
-            pct_str.into_string()
-        }).collect()
+    fn exec(&self, input: &[String]) -> FunctionResult {
+        let inner_result = self.inner_function.exec(input)?;
+        Ok(inner_result.into_iter().map(|value| {
+            match self.encoding {
+                IriEncoding::IriSafe => PctString::encode(value.chars(), IriSafe).into_string(),
+                IriEncoding::Uri => PctString::encode(value.chars(), URIReserved).into_string(),
+                IriEncoding::None => value
+            }
+        }).collect())
+    }
+}
+
+/// Encodes every character that is not in `iunreserved` of RFC 3987, as the IRI-safe version
+/// of R2RML requires.
+pub(crate) struct IriSafe;
+
+impl Encoder for IriSafe {
+    fn encode(&self, c: char) -> bool {
+        !(c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~') || is_ucschar(c))
+    }
+}
+
+fn is_ucschar(c: char) -> bool {
+    matches!(c,
+        '\u{A0}'..='\u{D7FF}' | '\u{F900}'..='\u{FDCF}' | '\u{FDF0}'..='\u{FFEF}'
+        | '\u{10000}'..='\u{1FFFD}' | '\u{20000}'..='\u{2FFFD}' | '\u{30000}'..='\u{3FFFD}'
+        | '\u{40000}'..='\u{4FFFD}' | '\u{50000}'..='\u{5FFFD}' | '\u{60000}'..='\u{6FFFD}'
+        | '\u{70000}'..='\u{7FFFD}' | '\u{80000}'..='\u{8FFFD}' | '\u{90000}'..='\u{9FFFD}'
+        | '\u{A0000}'..='\u{AFFFD}' | '\u{B0000}'..='\u{BFFFD}' | '\u{C0000}'..='\u{CFFFD}'
+        | '\u{D0000}'..='\u{DFFFD}' | '\u{E1000}'..='\u{EFFFD}')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::function::constant::ConstantFunction;
+    use super::*;
+
+    fn encode(value: &str, encoding: &IriEncoding) -> String {
+        let function = UriEncodeFunction::new(Box::new(ConstantFunction::new(value.to_string())), encoding);
+        function.exec(&[]).unwrap().remove(0)
+    }
+
+    #[test]
+    fn encodings() {
+        assert_eq!("Fran%C3%A7ois%2Fa%3Ab", encode("François/a:b", &IriEncoding::Uri));
+        assert_eq!("François%2Fa%3Ab%20c", encode("François/a:b c", &IriEncoding::IriSafe));
+        assert_eq!("François/a:b c", encode("François/a:b c", &IriEncoding::None));
     }
 }
@@ -0,0 +1,202 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! The gRPC service mode: clients send a mapping and stream the records of its sources, and get the
+//! generated quads streamed back. The service is defined in `proto/mopper.proto`.
+
+mod proto {
+    tonic::include_proto!("mopper");
+}
+
+use std::net::SocketAddr;
+use std::thread;
+use log::{info, warn};
+use tokio::runtime::{Handle, Runtime};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+use tonic::transport::Server;
+use crate::{mapping_to_plan, start_external_streaming, MappingLang};
+use crate::error::MopperError;
+use crate::external::ExternalSources;
+use crate::mopper_options::MopperOptions;
+use crate::plan::{externalize_sources, internal_sources};
+use crate::rdf::Statement;
+use crate::streaming::StatementStream;
+use proto::{map_request, MapRequest, Mapping, MappingLanguage, Quad};
+use proto::mapper_server::{Mapper, MapperServer};
+
+/// Serves the gRPC service at `address` until the process stops. Every mapping is executed with `options`,
+/// but as the mappings come from clients, without resolving secrets or reading environment variables in them.
+/// Mappings can only read the sources the client streams, unless `server_sources` is set: then they can also
+/// read files, databases and other sources that the server can reach.
+pub fn serve(address: SocketAddr, options: MopperOptions, server_sources: bool) -> Result<(), MopperError> {
+    let runtime = Runtime::new()
+        .map_err(|err| MopperError::Options { msg: format!("Cannot start the gRPC service: {err}"), cause: Some(Box::new(err)) })?;
+    info!("Serving gRPC at {address}");
+    runtime.block_on(Server::builder()
+        .add_service(MapperServer::new(MapperService { options: options.for_untrusted_plans(), server_sources }))
+        .serve(address))
+        .map_err(|err| MopperError::Options { msg: format!("Cannot serve gRPC at {address}: {err}"), cause: Some(Box::new(err)) })
+}
+
+struct MapperService {
+    options: MopperOptions,
+    server_sources: bool
+}
+
+#[tonic::async_trait]
+impl Mapper for MapperService {
+    type MapStream = ReceiverStream<Result<Quad, Status>>;
+
+    async fn map(&self, request: Request<Streaming<MapRequest>>) -> Result<Response<Self::MapStream>, Status> {
+        let mut requests = request.into_inner();
+        let Some(MapRequest { request: Some(map_request::Request::Mapping(mapping)) }) = requests.message().await? else {
+            return Err(Status::invalid_argument("The first request must contain the mapping"));
+        };
+        let options = self.options.clone();
+        let server_sources = self.server_sources;
+        let (sources, statements) = tokio::task::spawn_blocking(move || start_mapping(&mapping, &options, server_sources))
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+
+        // Pushing records and taking statements block, so they happen on their own threads
+        let (quad_sender, quad_receiver) = mpsc::channel(self.options.message_buffer_capacity().max(1));
+        let runtime = Handle::current();
+        let error_sender = quad_sender.clone();
+        thread::spawn(move || {
+            if let Err(status) = push_records(&runtime, requests, sources) {
+                let _ = error_sender.blocking_send(Err(*status));
+            }
+        });
+        thread::spawn(move || send_quads(statements, quad_sender));
+        Ok(Response::new(ReceiverStream::new(quad_receiver)))
+    }
+}
+
+// Translates the mapping to a plan and starts it, with the streamed sources as external sources.
+// Other sources are read on the server, so they are only allowed with `server_sources`.
+fn start_mapping(mapping: &Mapping, options: &MopperOptions, server_sources: bool) -> Result<(ExternalSources, StatementStream), MopperError> {
+    let plan = match mapping.language() {
+        MappingLanguage::Algemaploom => mapping.mapping.clone(),
+        MappingLanguage::Rml => mapping_to_plan(&mapping.mapping, MappingLang::RML)?,
+        MappingLanguage::Shexml => mapping_to_plan(&mapping.mapping, MappingLang::SHEXML)?
+    };
+    let plan = externalize_sources(&plan, &mapping.streamed_sources)?;
+    if !server_sources {
+        let internal_sources = internal_sources(&plan)?;
+        if !internal_sources.is_empty() {
+            return Err(MopperError::plan(format!("The mapping reads sources that are not streamed: {}; this service only maps streamed sources",
+                                                 internal_sources.join(", "))));
+        }
+    }
+    start_external_streaming(&plan, options)
+}
+
+// Pushes the records of the requests until the client stops sending; then the sources are closed
+fn push_records(runtime: &Handle, mut requests: Streaming<MapRequest>, sources: ExternalSources) -> Result<(), Box<Status>> {
+    while let Some(request) = runtime.block_on(requests.message())? {
+        let Some(map_request::Request::Record(record)) = request.request else {
+            return Err(Box::new(Status::invalid_argument("Only the first request can contain a mapping")));
+        };
+        sources.push(&record.source, record.values)
+            .map_err(|err| Box::new(Status::invalid_argument(err.to_string())))?;
+    }
+    Ok(())
+}
+
+fn send_quads(mut statements: StatementStream, quad_sender: mpsc::Sender<Result<Quad, Status>>) {
+    for statement in statements.by_ref() {
+        if quad_sender.blocking_send(Ok(to_quad(statement))).is_err() {
+            warn!("The client stopped taking quads.");
+            break;
+        }
+    }
+    if let Err(err) = statements.finish() {
+        let _ = quad_sender.blocking_send(Err(Status::aborted(err.to_string())));
+    }
+}
+
+fn to_quad(statement: Statement) -> Quad {
+    Quad {
+        subject: statement.subject.to_string(),
+        predicate: statement.predicate.to_string(),
+        object: statement.object.to_string(),
+        graph: statement.graph.map(|graph| graph.to_string()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::mopper_options::MopperOptionsBuilder;
+    use super::*;
+
+    #[test]
+    fn streamed_sources() {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let mapping = Mapping {
+            mapping: fs::read_to_string(format!("{test_dir}/mapping.json")).unwrap(),
+            language: MappingLanguage::Algemaploom.into(),
+            streamed_sources: vec!["sport.csv".to_string()]
+        };
+        let options = MopperOptionsBuilder::default()
+            .working_dir_hint(test_dir)
+            .build().unwrap()
+            .for_untrusted_plans();
+        // student.csv would be read from the server
+        let Err(MopperError::Plan { msg, .. }) = start_mapping(&mapping, &options, false) else {
+            panic!("Sources that are not streamed should not be read by default");
+        };
+        assert_eq!("The mapping reads sources that are not streamed: student.csv; this service only maps streamed sources", msg);
+
+        let (sources, statements) = start_mapping(&mapping, &options, true).unwrap();
+        sources.push("sport.csv", [("ID", "100"), ("Name", "Tennis")]).unwrap();
+        drop(sources);
+
+        let quads: Vec<Quad> = statements.map(to_quad).collect();
+        let tennis = quads.iter()
+            .find(|quad| quad.object == "\"Tennis\"")
+            .unwrap();
+        assert_eq!("<http://example.com/resource/sport_100>", tennis.subject);
+        assert_eq!("<http://example.com/graph/sports>", tennis.graph);
+    }
+
+    #[test]
+    fn mappings_of_clients_untrusted() {
+        std::env::set_var("MOPPER_TEST_SERVER_VARIABLE", "server");
+        let mut plan: serde_json::Value = serde_json::from_str(&fs::read_to_string("test-resources/rml-testcases/RMLTC1036-CSV/mapping.json").unwrap()).unwrap();
+        plan["nodes"][0]["operator"]["config"]["url"] = serde_json::json!("https://example.com/?leak=${MOPPER_TEST_SERVER_VARIABLE}");
+        let mut mapping = Mapping {
+            mapping: plan.to_string(),
+            language: MappingLanguage::Algemaploom.into(),
+            streamed_sources: vec!["sport.csv".to_string(), "student.csv".to_string()]
+        };
+        let options = MopperOptionsBuilder::default().build().unwrap().for_untrusted_plans();
+        let Err(MopperError::Plan { msg, .. }) = start_mapping(&mapping, &options, false) else {
+            panic!("Environment variables of the server should not be read");
+        };
+        assert_eq!("No parameter or environment variable for placeholder(s) MOPPER_TEST_SERVER_VARIABLE", msg);
+
+        plan["nodes"][0]["operator"]["config"]["url"] = serde_json::json!("https://example.com/?leak=${secret:env:MOPPER_TEST_SERVER_VARIABLE}");
+        mapping.mapping = plan.to_string();
+        let Err(MopperError::Plan { msg, .. }) = start_mapping(&mapping, &options, false) else {
+            panic!("Secrets of the server should not be resolved");
+        };
+        assert_eq!("Unknown credentials provider 'env' in secret reference 'env:MOPPER_TEST_SERVER_VARIABLE'", msg);
+    }
+}
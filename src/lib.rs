@@ -18,44 +18,99 @@
 extern crate derive_builder;
 
 
-mod plan;
+pub mod plan;
 
-mod source;
-mod sink;
-mod plan_rewriter;
+pub mod source;
+pub mod sink;
+mod direct_mapping;
+mod plan_dot;
+mod plan_explain;
+pub mod plan_rewriter;
+mod plan_validator;
 pub mod error;
+pub mod error_log;
+pub mod stats;
+pub mod report;
+pub mod logging;
+pub mod secrets;
+pub mod rdf;
+pub mod shacl;
+pub mod void;
+pub mod provenance;
+pub mod sparql;
+mod timeout;
+pub mod streaming;
+pub mod external;
+pub mod daemon;
+pub mod test_suite;
+pub mod testing;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod mopper_options;
 pub mod function;
 pub mod operator;
+pub mod message;
 
 pub mod util;
 
 #[cfg(test)]
 mod tests;
 
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::error::Error;
+use std::fs;
 use std::fs::File;
+use std::hash::{BuildHasher, Hasher, RandomState};
 use std::io;
-use std::io::BufWriter;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
-use ::operator::{Function, IOType, Operator};
-use ::operator::formats::ReferenceFormulation;
+use std::time::Instant;
+use ::operator::{Function, IOType, Operator, Serializer, Target};
+use ::operator::formats::{DataFormat, ReferenceFormulation};
 use crossbeam_channel::{bounded, Receiver, Sender};
-use log::{error, info};
+use log::{debug, error, info, warn};
 use ltranslator::handler::StringTranslatorHandler;
 use ltranslator::rml::RMLStringHandler;
 use ltranslator::shexml::ShExMLStringHandler;
-use crate::error::GeneralError;
-use crate::mopper_options::{MopperOptions, MopperOptionsBuilder};
+use crate::error::MopperError;
+use crate::error_log::{ErrorLog, RecordErrorHandler};
+use crate::external::{with_external_sources, Engine, ExternalSources, EXTERNAL_SOURCE};
+use crate::message::Message;
+use crate::mopper_options::{DeduplicationStrategy, MopperOptions, MopperOptionsBuilder};
 use crate::operator::extension::ExtendOperator;
 use crate::operator::join::JoinOperator;
 use crate::operator::serializer::SerializeOperator;
-use crate::plan::PlanGraph;
+use crate::plan::{merge_plans_with_ranges, nodes_of_merged_plan, override_sources, set_base_iri, substitute_params, yaml_to_json, NodeMap, PlanGraph};
+use crate::plan_dot::{operator_name, to_dot};
+use crate::plan_explain::explain;
 use crate::plan_rewriter::rewrite;
+use crate::plan_validator::validate;
+use crate::rdf::Statement;
+use crate::shacl::{ShaclValidation, Shapes};
+use crate::provenance::Provenance;
+use crate::sparql::{Query, SparqlQueries};
+use crate::void::DatasetDescription;
+use crate::sink::dedup::{BloomDeduplicator, Deduplicator, ExternalSortDeduplicator, HashSetDeduplicator, PersistentDeduplicator, ShardedDeduplicator, SharedDeduplicator};
+use crate::sink::{sink_key, AtomicFile, Compression, FileSink, OutputLock, Sink, TaggedSink, WriteSink};
+use crate::sink::http::{HttpSink, HTTP_SINK};
+use crate::sink::partition::{graph_path, GraphPartitionedSink, GRAPH_PLACEHOLDER};
 use crate::sink::writer_sink::WriterSink;
-use crate::source::csv_file::CSVFileSource;
+use crate::source::avro::{is_avro, AvroSource};
+use crate::source::compression::{SourceCompression, SourceFile};
+use crate::source::csv_file::{CSVFileSource, CsvDialect};
+use crate::source::custom_source::CustomSource;
+use crate::source::json_lines::JsonLinesSource;
+use crate::source::ldes::{LdesSource, LDES_SOURCE};
+use crate::source::messages::{connect_stream, MessageFormat};
+use crate::source::location::{find_files, local_working_dir, FileLocation};
+use crate::source::rate_limit::RateLimitedSource;
+use crate::timeout::{with_timeout, TimeoutSink, TimeoutSource};
+use crate::source::{source_key, RecordSelection, Source, SourcePermits};
+use crate::stats::{log_throughput, progress, report_progress, OperatorCounters, OperatorKind, OperatorStats, PeriodicTask, ProgressListener, RunStats, TargetCount};
+use crate::streaming::{StatementSink, StatementStream};
 
 #[derive(Clone)]
 pub enum MappingLang {
@@ -63,84 +118,439 @@ pub enum MappingLang {
     SHEXML
 }
 
-type VecSender = Sender<Vec<String>>;
-type VecReceiver = Receiver<Vec<String>>;
+type MessageSender = Sender<Message>;
+type MessageReceiver = Receiver<Message>;
+type OperatorJoinHandle = (usize, OperatorKind, JoinHandle<Result<(), MopperError>>);
+
+// Creates the output of every sink, instead of the targets of the plan
+type OutputFactory = dyn Fn() -> Box<dyn Sink>;
 
 /// Start mopper with the default options
-pub fn start_default(algemaploom_plan: &str) -> Result<(), Box<dyn Error>> {
-    let options = MopperOptionsBuilder::default().build()?;
+pub fn start_default(algemaploom_plan: &str) -> Result<RunStats, MopperError> {
+    let options = MopperOptionsBuilder::default().build()
+        .map_err(|err| MopperError::Options { msg: err.to_string(), cause: Some(Box::new(err)) })?;
     println!();
     start(algemaploom_plan, &options)
 }
 
-/// Start mopper with the given options
-pub fn start(algemaploom_plan: &str, options: &MopperOptions) -> Result<(), Box<dyn Error>> {
-    let plan_graph: PlanGraph = serde_json::from_str(algemaploom_plan).unwrap();
+/// Start mopper with the given options.
+/// Returns statistics of the run when successful.
+pub fn start(algemaploom_plan: &str, options: &MopperOptions) -> Result<RunStats, MopperError> {
+    let (_plan_graph, reduced_plan) = prepare_plan(algemaploom_plan, options, forced_to_one_target(options))?;
+    start_plan(&reduced_plan, options)
+}
+
+/// Executes several plans at the same time, in one run, e.g. the plans of the mappings of one dataset each.
+/// Sources the plans have in common are read once for all of them, and the plans share the budget of sources
+/// that read at the same time, see [MopperOptions::max_concurrent_sources]. Every plan comes with the directory
+/// to resolve its relative source paths against, if any.
+/// Returns the statistics of the run, and those of every plan, in the order of the plans; a source or target
+/// shared by several plans is in the statistics of each of them.
+pub fn start_plans(plans: &[(String, Option<String>)], options: &MopperOptions) -> Result<(RunStats, Vec<RunStats>), MopperError> {
+    let (algemaploom_plan, node_ranges) = merge_plans_with_ranges(plans)?;
+    let (_plan_graph, reduced_plan) = prepare_plan(&algemaploom_plan, options, forced_to_one_target(options))?;
+    let run_stats = start_plan(&reduced_plan, options)?;
+    let plan_stats = node_ranges.iter()
+        .map(|node_range| run_stats.of_nodes(&nodes_of_merged_plan(&reduced_plan, node_range)))
+        .collect();
+    Ok((run_stats, plan_stats))
+}
+
+/// Start mopper with the given options, with the plan in JSON read from `reader`.
+/// Returns statistics of the run when successful.
+pub fn start_from_reader(reader: impl Read, options: &MopperOptions) -> Result<RunStats, MopperError> {
+    let algemaploom_plan = io::read_to_string(reader)
+        .map_err(|err| MopperError::Plan { msg: format!("Cannot read plan: {err}"), cause: Some(Box::new(err)) })?;
+    start(&algemaploom_plan, options)
+}
+
+/// Start mopper with the given options, with the plan in the file at `path`, in YAML if its extension
+/// is `yaml` or `yml` and in JSON otherwise. Like on the command line, relative source paths are looked up
+/// in the directory of the file as well, unless the options have a working directory hint.
+/// Returns statistics of the run when successful.
+pub fn start_from_path(path: impl AsRef<Path>, options: &MopperOptions) -> Result<RunStats, MopperError> {
+    let path = path.as_ref();
+    let plan = fs::read_to_string(path)
+        .map_err(|err| MopperError::Plan { msg: format!("Cannot read plan {}: {err}", path.display()), cause: Some(Box::new(err)) })?;
+    let algemaploom_plan = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml") | Some("yml") => yaml_to_json(&plan)?,
+        _ => plan
+    };
+    let parent_dir = path.parent()
+        .and_then(|parent_dir| parent_dir.to_str())
+        .filter(|parent_dir| !parent_dir.is_empty());
+    match parent_dir {
+        Some(parent_dir) if options.working_dir_hint().is_none() => start(&algemaploom_plan, &options.clone().with_working_dir_hint(parent_dir)),
+        _ => start(&algemaploom_plan, options)
+    }
+}
+
+/// Start mopper with the given options, and stream the generated statements to the caller instead of
+/// writing them to the targets of the plan, which are ignored, as are the forced outputs.
+/// The statements can be taken from the returned stream while the run goes on.
+pub fn start_streaming(algemaploom_plan: &str, options: &MopperOptions) -> Result<StatementStream, MopperError> {
+    let (_plan_graph, reduced_plan) = prepare_plan(algemaploom_plan, options, true)?;
+    let (sender, receiver) = bounded::<Statement>(options.message_buffer_capacity());
+    let output_factory = move || -> Box<dyn Sink> { Box::new(StatementSink::new(sender.clone())) };
+    let execution = launch(&reduced_plan, options, Some(&output_factory))?;
+    Ok(StatementStream::new(receiver, execution))
+}
+
+/// Runs the plan with the given options like [start], but discards the generated statements instead of
+/// writing them. Returns the statistics of the run, and how many statements every target of the plan would
+/// get. The targets need not be writable, and the forced outputs are ignored.
+pub fn count(algemaploom_plan: &str, options: &MopperOptions) -> Result<(RunStats, Vec<TargetCount>), MopperError> {
+    let plan_graph = read_plan(algemaploom_plan, options)?;
+    validate(&plan_graph, true, options)?;
+    let reduced_plan = rewrite(&plan_graph, false)?;
+    let output_factory = || -> Box<dyn Sink> { Box::new(WriteSink::new(io::sink())) };
+    let run_stats = launch(&reduced_plan, options, Some(&output_factory))?.wait()?;
+
+    let mut target_counts: Vec<TargetCount> = reduced_plan.iter()
+        .filter_map(|(id, node)| match &node.operator {
+            Operator::TargetOp { config } => Some((id, config)),
+            _ => None
+        })
+        .filter_map(|(id, config)| run_stats.operators_of_kind(OperatorKind::Sink)
+            .find(|sink_stats| sink_stats.node_id == *id)
+            .map(|sink_stats| TargetCount::new(target_name(config), sink_stats)))
+        .collect();
+    target_counts.sort_by_key(|target_count| target_count.node_id);
+    Ok((run_stats, target_counts))
+}
+
+// The kind of target, and its path if it has one
+fn target_name(config: &Target) -> String {
+    match config.configuration.get("path") {
+        Some(path) => format!("{} {path}", sink_key(config)),
+        None => sink_key(config)
+    }
+}
+
+/// Start mopper with the given options, with records pushed by the caller to the external sources of the plan,
+/// i.e. the sources with configuration `"source": "external"` and a `"name"`. See [Engine].
+pub fn start_external(algemaploom_plan: &str, options: &MopperOptions) -> Result<Engine, MopperError> {
+    let (options, senders) = with_external_sources(options);
+    let (_plan_graph, reduced_plan) = prepare_plan(algemaploom_plan, &options, forced_to_one_target(&options))?;
+    let execution = launch(&reduced_plan, &options, None)?;
+    let sources = ExternalSources::new(std::mem::take(&mut *senders.lock().unwrap()));
+    Ok(Engine::new(sources, execution))
+}
+
+/// Start mopper with the given options, with records pushed by the caller to the external sources of the plan
+/// like [start_external], and stream the generated statements to the caller like [start_streaming].
+/// The run goes on until the returned [ExternalSources] are dropped.
+pub fn start_external_streaming(algemaploom_plan: &str, options: &MopperOptions) -> Result<(ExternalSources, StatementStream), MopperError> {
+    let (options, senders) = with_external_sources(options);
+    let (_plan_graph, reduced_plan) = prepare_plan(algemaploom_plan, &options, true)?;
+    let (sender, receiver) = bounded::<Statement>(options.message_buffer_capacity());
+    let output_factory = move || -> Box<dyn Sink> { Box::new(StatementSink::new(sender.clone())) };
+    let execution = launch(&reduced_plan, &options, Some(&output_factory))?;
+    let sources = ExternalSources::new(std::mem::take(&mut *senders.lock().unwrap()));
+    Ok((sources, StatementStream::new(receiver, execution)))
+}
+
+/// Start mopper with a plan that is already rewritten, e.g. by [plan_rewriter::rewrite] and then
+/// analysed or optimized further.
+/// Returns statistics of the run when successful.
+pub fn start_plan(reduced_plan: &NodeMap, options: &MopperOptions) -> Result<RunStats, MopperError> {
+    launch(reduced_plan, options, None)?.wait()
+}
+
+/// A plan that is being executed.
+pub(crate) struct Execution {
+    started: Instant,
+    join_handles: Vec<OperatorJoinHandle>,
+    operator_counters: Vec<(usize, OperatorKind, Arc<OperatorCounters>)>,
+    source_counters: Vec<Arc<OperatorCounters>>,
+    error_log: Option<Arc<ErrorLog>>,
+    throughput_logger: Option<PeriodicTask>,
+    progress_reporter: Option<PeriodicTask>,
+    progress_listener: Option<ProgressListener>,
+    shacl_validation: Option<ShaclValidation>,
+    dataset_description: Option<DatasetDescription>,
+    provenance: Option<Provenance>,
+    sparql_queries: Option<SparqlQueries>,
+    // shared with the sinks that create their files while writing
+    output_files: Arc<Mutex<OutputFiles>>
+}
+
+/// The files the targets write, which replace the previous ones when the run succeeds.
+#[derive(Default)]
+struct OutputFiles {
+    files: Vec<(usize, AtomicFile)>,
+    // held until the output files are in place
+    locks: Vec<OutputLock>
+}
 
-    // force_std_out takes precedence over force_to_file
-    let to_one_target = options.force_to_std_out() || options.force_to_file().is_some();
-    let reduced_plan = rewrite(&plan_graph, to_one_target);
+// Starts executing the plan. If there is an output factory, the sinks write to its outputs.
+fn launch(reduced_plan: &NodeMap, options: &MopperOptions, output_factory: Option<&OutputFactory>) -> Result<Execution, MopperError> {
+    let started = Instant::now();
 
     info!("Initializing execution engine...");
     // Create map of start node -> `send` channel 
-    let mut sender_map: HashMap<usize, Vec<VecSender>> = HashMap::new();
+    let mut sender_map: HashMap<usize, Vec<MessageSender>> = HashMap::new();
 
     // Create map of end node -> `receive` channel
-    let mut receiver_map: HashMap<usize, VecReceiver> = HashMap::new();
+    let mut receiver_map: HashMap<usize, MessageReceiver> = HashMap::new();
+
+    // Create map of join node -> `receive` channels of its left and right input
+    let mut join_receiver_map: HashMap<usize, (MessageReceiver, MessageReceiver)> = HashMap::new();
 
     for (id, node) in reduced_plan.iter() {
-        // create channel: ONE per node (for incoming messages)
-        // For now, the messages over channels are Vec<String>, where the first message contains the headers (keys)
-        // and subsequent messages contain the values.
-        if !node.from.is_empty() {
-            let (sender, receiver) = bounded::<Vec<String>>(options.message_buffer_capacity());
+        // create channel: ONE per node (for incoming messages), shared by the nodes it receives from.
+        // A join gets one per input instead, so it knows which side a message comes from.
+        // See [Message] for what goes over the channels.
+        if let Operator::JoinOp { .. } = node.operator {
+            let (left_sender, left_receiver) = bounded::<Message>(options.message_buffer_capacity());
+            let (right_sender, right_receiver) = bounded::<Message>(options.message_buffer_capacity());
+            sender_map.entry(node.from[0]).or_default().push(left_sender);
+            sender_map.entry(node.from[1]).or_default().push(right_sender);
+            join_receiver_map.insert(*id, (left_receiver, right_receiver));
+        } else if !node.from.is_empty() {
+            let (sender, receiver) = bounded::<Message>(options.message_buffer_capacity());
             receiver_map.insert(*id, receiver);
 
             // now find the "from" nodes and add this node id as "sender"
             for from_node_id in &node.from {
-                if let Some(senders) = sender_map.get_mut(from_node_id) {
-                    senders.push(sender.clone());
-                } else {
-                    let mut senders: Vec<VecSender> = Vec::with_capacity(1);
-                    senders.push(sender.clone());
-                    sender_map.insert(*from_node_id, senders);
+                sender_map.entry(*from_node_id).or_default().push(sender.clone());
+            }
+        }
+    }
+
+    // The serializers also send their statements to be validated when the run is done, if there are shapes
+    let shacl_validation = match options.shacl_shapes_file() {
+        Some(shapes_file) => {
+            let shapes = fs::read_to_string(shapes_file)
+                .map_err(|err| MopperError::Options { msg: format!("Cannot read SHACL shapes {shapes_file}: {err}"), cause: Some(Box::new(err)) })
+                .and_then(|turtle| Shapes::from_turtle(&turtle)
+                    .map_err(|msg| MopperError::options(format!("Invalid SHACL shapes in {shapes_file}: {msg}"))))?;
+            let mut shacl_validation = ShaclValidation::new(shapes);
+            for (id, node) in reduced_plan.iter() {
+                if let Operator::SerializerOp { .. } = node.operator {
+                    sender_map.entry(*id).or_default().push(shacl_validation.collect_from(*id, options.message_buffer_capacity()));
                 }
             }
+            Some(shacl_validation)
+        },
+        None => None
+    };
+
+    // The serializers also send their statements to be counted for the VoID description or the dataset report,
+    // if one of them is written
+    let dataset_description = if options.void_file().is_some() || options.dataset_report_file().is_some() {
+        let dataset_description = DatasetDescription::new(options.void_file().clone(), options.dataset_report_file().clone(),
+                                                          source_iris(reduced_plan, options), output_iris(reduced_plan, options), options.message_buffer_capacity());
+        for (id, node) in reduced_plan.iter() {
+            if let Operator::SerializerOp { .. } = node.operator {
+                sender_map.entry(*id).or_default().push(dataset_description.collect_from());
+            }
+        }
+        Some(dataset_description)
+    } else {
+        None
+    };
+
+    // The serializers also send their statements to find the named graphs for the provenance, if it is written
+    let provenance = options.provenance_file().as_ref().map(|provenance_file| {
+        let mapping_documents = options.mapping_documents().iter()
+            .map(|mapping_document| file_iri(Path::new(mapping_document)))
+            .collect();
+        let provenance = Provenance::new(provenance_file, source_iris(reduced_plan, options), mapping_documents, output_iris(reduced_plan, options), options.message_buffer_capacity());
+        for (id, node) in reduced_plan.iter() {
+            if let Operator::SerializerOp { .. } = node.operator {
+                sender_map.entry(*id).or_default().push(provenance.collect_from());
+            }
+        }
+        provenance
+    });
+
+    // The serializers also send their statements to run the SPARQL queries over when the run is done, if there are any
+    let sparql_queries = if options.sparql_queries().is_empty() {
+        None
+    } else {
+        let mut queries = Vec::new();
+        for (query_file, output_file) in options.sparql_queries() {
+            let query = fs::read_to_string(query_file)
+                .map_err(|err| MopperError::Options { msg: format!("Cannot read SPARQL query {query_file}: {err}"), cause: Some(Box::new(err)) })
+                .and_then(|query| Query::parse(&query)
+                    .map_err(|msg| MopperError::options(format!("Invalid SPARQL query in {query_file}: {msg}"))))?;
+            queries.push((query, output_file.clone()));
         }
+        let sparql_queries = SparqlQueries::new(queries, options.message_buffer_capacity());
+        for (id, node) in reduced_plan.iter() {
+            if let Operator::SerializerOp { .. } = node.operator {
+                sender_map.entry(*id).or_default().push(sparql_queries.collect_from());
+            }
+        }
+        Some(sparql_queries)
+    };
+
+    // One deduplicator shared by all sinks, if deduplicating globally
+    let nr_of_deduplicated_sinks = reduced_plan.iter()
+        .filter(|(id, node)| matches!(node.operator, Operator::TargetOp { .. }) && options.deduplicates_sink(**id))
+        .count();
+    let global_deduplicator = match options.deduplicate_globally() && nr_of_deduplicated_sinks > 0 {
+        true => Some((Arc::new(Mutex::new(create_deduplicator(options, "global")?)), Arc::new(AtomicUsize::new(nr_of_deduplicated_sinks)))),
+        false => None
+    };
+
+    // Records that cannot be processed are written to the error log, if any
+    let error_log = match options.error_log_file() {
+        Some(error_log_file) => match ErrorLog::new(error_log_file) {
+            Ok(error_log) => Some(Arc::new(error_log)),
+            Err(err) => {
+                let msg = format!("Cannot create error log {error_log_file}: {err}");
+                error!("{msg}");
+                return Err(MopperError::Options { msg, cause: Some(Box::new(err)) });
+            }
+        },
+        None => None
+    };
+
+    // The records every source sends; sources sample independently of each other
+    let sample_seed = options.sample_seed().unwrap_or_else(|| RandomState::new().build_hasher().finish());
+    if let Some(sample_fraction) = options.sample_fraction() {
+        info!("Sampling {sample_fraction} of the records of every source, with seed {sample_seed}");
     }
+    let record_selection = |id: &usize| RecordSelection::new(options.record_limit(), options.sample_fraction(), sample_seed.wrapping_add(*id as u64));
+    let source_permits = SourcePermits::new(options.max_concurrent_sources());
 
-    // Create a vector of the join handles created by the operator threads.
-    let mut join_handles: Vec<JoinHandle<(u8, String)>> = Vec::new();
+    // Only custom sources read from remote systems, so only their rate is limited
+    for node_id in options.source_rate_limits().keys() {
+        let can_be_limited = reduced_plan.get(node_id).is_some_and(|node| matches!(&node.operator,
+            Operator::SourceOp { config } if options.source_factory(config).is_some() || source_key(config) == LDES_SOURCE
+                || matches!(config.source_type, IOType::Kafka | IOType::Websocket)));
+        if !can_be_limited {
+            warn!("Node {node_id} is not a custom, LDES, Kafka or WebSocket source; its rate limit is ignored");
+        }
+    }
+
+    // Create a vector of the join handles created by the operator threads, with the operator they belong to.
+    let mut join_handles: Vec<OperatorJoinHandle> = Vec::new();
+    let output_files: Arc<Mutex<OutputFiles>> = Arc::default();
+
+    // The counters of every operator, to gather statistics
+    let mut operator_counters: Vec<(usize, OperatorKind, Arc<OperatorCounters>)> = Vec::new();
 
     for (id, node) in reduced_plan.iter() {
         let operator = &node.operator;
+        let counters = Arc::new(OperatorCounters::new(options.benchmark()));
+        let record_errors = |kind: OperatorKind| RecordErrorHandler::new(id, kind, options.error_mode(), error_log.clone(), counters.clone());
 
         match operator {
 
             // Create a source
+            // Create a custom source, if one is registered for the source
+            Operator::SourceOp { config } if options.source_factory(config).is_some() => {
+                let source_factory = options.source_factory(config).unwrap().clone();
+                let source_config = config.clone();
+                let source = connect_source(options, *id, move || source_factory.create(&source_config))?;
+                operator_counters.push((*id, OperatorKind::Source, counters.clone()));
+                // the caller decides when an external source is read, and may wait for it
+                let permits = match source_key(config) == EXTERNAL_SOURCE {
+                    true => SourcePermits::new(None),
+                    false => source_permits.clone()
+                };
+                let custom_source = CustomSource::new(source, &node.attributes, id, record_selection(id), counters.clone(), record_errors(OperatorKind::Source), None);
+                let senders = sender_map.remove(id).unwrap();
+                join_handles.push((*id, OperatorKind::Source, custom_source.start(senders, permits)));
+            },
+
+            // Follow a Linked Data Event Stream
+            Operator::SourceOp { config } if source_key(config) == LDES_SOURCE => {
+                let mut fields: Vec<String> = node.attributes.iter().flatten().cloned().collect();
+                fields.sort();
+                let ldes_config = config.config.clone();
+                let ldes_source = connect_source(options, *id, move || Ok(Box::new(LdesSource::connect(&ldes_config, fields)?)))?;
+                operator_counters.push((*id, OperatorKind::Source, counters.clone()));
+                // a stream that is polled does not end, so it would never return a permit
+                let permits = match config.config.contains_key("pollInterval") {
+                    true => SourcePermits::new(None),
+                    false => source_permits.clone()
+                };
+                let custom_source = CustomSource::new(ldes_source, &node.attributes, id, record_selection(id), counters.clone(), record_errors(OperatorKind::Source), None);
+                let senders = sender_map.remove(id).unwrap();
+                join_handles.push((*id, OperatorKind::Source, custom_source.start(senders, permits)));
+            },
+
             Operator::SourceOp { config } => {
                 match config.source_type {
-                    IOType::File => {
-                        let file_path_option = find_file(
-                            &config.config["path"],
-                            options.working_dir_hint()
-                        );
-                        if let Some(file_path) = file_path_option {
-                            let reference_formulation = &config.root_iterator.reference_formulation;
-                            match reference_formulation {
-                                ReferenceFormulation::CSVRows => {
-                                    let csv_file_source = CSVFileSource::new(file_path.to_str().unwrap().to_string(), &node.attributes, id);
-                                    let senders = sender_map.remove(id).unwrap();
-                                    join_handles.push(csv_file_source.start(senders));
-                                },
-                                _ => {}
+                    IOType::File | IOType::StdIn => {
+                        let file_locations_result = match config.source_type {
+                            IOType::StdIn => Ok(vec![FileLocation::StdIn]),
+                            _ => find_files(&config.config["path"], options.working_dir_hint())
+                        };
+                        match file_locations_result {
+                            Ok(file_locations) => {
+                                let reference_formulation = &config.root_iterator.reference_formulation;
+                                // only CSV files are read one after the other
+                                let reads_csv = *reference_formulation == ReferenceFormulation::CSVRows && !file_locations.iter().any(is_avro);
+                                if file_locations.len() > 1 && !reads_csv {
+                                    let msg = format!("{} matches {} files, but only CSV sources can read more than one", &config.config["path"], file_locations.len());
+                                    return Err(MopperError::Source { node_id: *id, msg, cause: None });
+                                }
+                                let mut source_files = file_locations.into_iter()
+                                    .map(|location| SourceCompression::of(&config.config, &location)
+                                        .map(|compression| SourceFile { location: location.with_login(&config.config), compression }))
+                                    .collect::<Result<Vec<SourceFile>, String>>()
+                                    .map_err(|msg| MopperError::Source { node_id: *id, msg, cause: None })?;
+                                match reference_formulation {
+                                    // Avro container files are recognised by their extension
+                                    ReferenceFormulation::CSVRows if !reads_csv => {
+                                        operator_counters.push((*id, OperatorKind::Source, counters.clone()));
+                                        let source_file = source_files.swap_remove(0);
+                                        let file_name = source_file.location.file_name();
+                                        let avro_source = Box::new(AvroSource::new(source_file));
+                                        let custom_source = CustomSource::new(avro_source, &node.attributes, id, record_selection(id), counters.clone(), record_errors(OperatorKind::Source), file_name);
+                                        let senders = sender_map.remove(id).unwrap();
+                                        join_handles.push((*id, OperatorKind::Source, custom_source.start(senders, source_permits.clone())));
+                                    },
+                                    ReferenceFormulation::CSVRows => {
+                                        operator_counters.push((*id, OperatorKind::Source, counters.clone()));
+                                        let dialect = CsvDialect::from_config(&config.config)
+                                            .map_err(|msg| MopperError::Source { node_id: *id, msg, cause: None })?
+                                            .with_overrides(options.csv_dialect());
+                                        let csv_file_source = CSVFileSource::new(source_files, dialect, &node.attributes, id, record_selection(id), counters.clone(), record_errors(OperatorKind::Source));
+                                        let senders = sender_map.remove(id).unwrap();
+                                        join_handles.push((*id, OperatorKind::Source, csv_file_source.start(senders, source_permits.clone(), options.max_concurrent_files())));
+                                    },
+                                    ReferenceFormulation::JSONPath => {
+                                        operator_counters.push((*id, OperatorKind::Source, counters.clone()));
+                                        let array_items = JsonLinesSource::iterates_array_items(&config.root_iterator.reference)
+                                            .map_err(|msg| MopperError::Source { node_id: *id, msg, cause: None })?;
+                                        let mut fields: Vec<String> = node.attributes.iter().flatten().cloned().collect();
+                                        fields.sort();
+                                        let source_file = source_files.swap_remove(0);
+                                        let file_name = source_file.location.file_name();
+                                        let json_source = Box::new(JsonLinesSource::new(source_file, fields, array_items));
+                                        let custom_source = CustomSource::new(json_source, &node.attributes, id, record_selection(id), counters.clone(), record_errors(OperatorKind::Source), file_name);
+                                        let senders = sender_map.remove(id).unwrap();
+                                        join_handles.push((*id, OperatorKind::Source, custom_source.start(senders, source_permits.clone())));
+                                    },
+                                    _ => {}
+                                }
+                            },
+                            Err(attempted) => {
+                                let msg = format!("File not found: {}, looked for it at {}", &config.config["path"], attempted.join(", "));
+                                error!("{msg}");
+                                return Err(MopperError::Source { node_id: *id, msg, cause: None });
                             }
-                        } else {
-                            let msg = format!("File not found:  {}", &config.config["path"]);
-                            error!("{msg}");
-                            return Err(Box::new(GeneralError::from_msg(msg)));
                         }
                     }
+                    IOType::Kafka | IOType::Websocket => {
+                        let format = MessageFormat::of(config, options.csv_dialect())
+                            .map_err(|msg| MopperError::Source { node_id: *id, msg, cause: None })?;
+                        let mut fields: Vec<String> = node.attributes.iter().flatten().cloned().collect();
+                        fields.sort();
+                        let stream_config = config.clone();
+                        let stream_source = connect_source(options, *id, move || connect_stream(&stream_config, format, fields))?;
+                        operator_counters.push((*id, OperatorKind::Source, counters.clone()));
+                        let custom_source = CustomSource::new(stream_source, &node.attributes, id, record_selection(id), counters.clone(), record_errors(OperatorKind::Source), None);
+                        let senders = sender_map.remove(id).unwrap();
+                        // a stream may not end, so it would never return a permit
+                        join_handles.push((*id, OperatorKind::Source, custom_source.start(senders, SourcePermits::new(None))));
+                    },
                     _ => {}
                 }
             },
@@ -148,42 +558,109 @@ pub fn start(algemaploom_plan: &str, options: &MopperOptions) -> Result<(), Box<
             // Create an Extension operator
             Operator::ExtendOp { config } => {
                 let extend_pairs: &HashMap<String, Function> = &config.extend_pairs;
-                let extend_operator = ExtendOperator::new(extend_pairs, id, &node.join_alias)?;
+                operator_counters.push((*id, OperatorKind::Extend, counters.clone()));
+                let extend_operator = ExtendOperator::new(extend_pairs, id, &node.join_alias, counters.clone(), record_errors(OperatorKind::Extend), options)?;
                 let senders = sender_map.remove(id).unwrap();
                 let receiver = receiver_map.remove(id).unwrap();
-                join_handles.push(extend_operator.start(receiver, senders));
+                join_handles.push((*id, OperatorKind::Extend, extend_operator.start(receiver, senders)));
             },
 
             // Create a Serialize operator
             Operator::SerializerOp { config } => {
-                let serialize_operator = SerializeOperator::new(config, id);
+                operator_counters.push((*id, OperatorKind::Serializer, counters.clone()));
+                // The format of the targets is authoritative, unless the output goes elsewhere
+                let config = match output_factory.is_none() && !forced_to_one_target(options) {
+                    true => serializer_for_targets(config, id, reduced_plan),
+                    false => Cow::Borrowed(config)
+                };
+                let serialize_operator = SerializeOperator::new(&config, id, &node.constants, counters.clone(), record_errors(OperatorKind::Serializer));
                 let senders = sender_map.remove(id).unwrap();
                 let receiver = receiver_map.remove(id).unwrap();
-                join_handles.push(serialize_operator.start(receiver, senders));
+                join_handles.push((*id, OperatorKind::Serializer, serialize_operator.start(receiver, senders)));
             },
 
             // Create a Target operator
             Operator::TargetOp { config } => {
                 let receiver = receiver_map.remove(id).unwrap();
+                operator_counters.push((*id, OperatorKind::Sink, counters.clone()));
                 
                 // Forcing output to standard out or to file overrides the target settings
-                if options.force_to_std_out() {
+                let time_limited = |sink: Box<dyn Sink>| -> Box<dyn Sink> {
+                    match options.sink_write_timeout() {
+                        Some(timeout) => Box::new(TimeoutSink::new(sink, *id, timeout)),
+                        None => sink
+                    }
+                };
+                let deduplicator: Option<Box<dyn Deduplicator + Send>> = match &global_deduplicator {
+                    _ if !options.deduplicates_sink(*id) => None,
+                    Some((shared, sharers)) => Some(Box::new(SharedDeduplicator::new(shared.clone(), sharers.clone()))),
+                    None => Some(create_deduplicator(options, &format!("sink-{id}"))?)
+                };
+                if let Some(output_factory) = output_factory {
+                    let writer_sink = WriterSink::new(output_factory(), id, deduplicator, counters);
+                    join_handles.push((*id, OperatorKind::Sink, writer_sink.start(receiver)));
+                } else if options.force_to_std_out() {
                     let stdout = io::stdout();
-                    let writer_sink = WriterSink::new(Box::new(stdout), id, options.deduplicate());
-                    join_handles.push(writer_sink.start(receiver.clone())); // is this a good idea?
+                    let sink: Box<dyn Sink> = if options.tag_targets() {
+                        let target = match config.configuration.get("path") {
+                            Some(path) => format!("target {id}: {path}"),
+                            None => format!("target {id}")
+                        };
+                        Box::new(TaggedSink::new(stdout, &target))
+                    } else {
+                        Box::new(WriteSink::new(stdout))
+                    };
+                    let writer_sink = WriterSink::new(time_limited(sink), id, deduplicator, counters);
+                    join_handles.push((*id, OperatorKind::Sink, writer_sink.start(receiver.clone()))); // is this a good idea?
                 } else if let Some(file_path) = options.force_to_file() {
-                    let file = File::create(file_path).unwrap();
-                    let file_out = BufWriter::new(file);
-                    let writer_sink = WriterSink::new(Box::new(file_out), id, options.deduplicate());
-                    join_handles.push(writer_sink.start(receiver.clone())); // is this a good idea?
+                    let file = output_files.lock().unwrap().create(Path::new(file_path), options.write_in_place(), *id)?;
+                    let file_sink = FileSink::new(file, options.write_buffer_capacity(), options.flush_interval(), options.sync_on_close());
+                    let writer_sink = WriterSink::new(time_limited(Box::new(file_sink)), id, deduplicator, counters);
+                    join_handles.push((*id, OperatorKind::Sink, writer_sink.start(receiver.clone()))); // is this a good idea?
+                } else if let Some(sink_factory) = options.sink_factory(config) {
+                    let sink_factory = sink_factory.clone();
+                    let target_config = config.clone();
+                    let sink = connect(options, *id, move || sink_factory.create(&target_config))
+                        .map_err(|err| match err.kind() {
+                            io::ErrorKind::TimedOut => MopperError::Timeout { node_id: *id, kind: OperatorKind::Sink, msg: format!("Cannot create custom sink: {err}") },
+                            _ => MopperError::Sink { node_id: *id, msg: "Cannot create custom sink".to_string(), cause: Some(Box::new(err)) }
+                        })?;
+                    let writer_sink = WriterSink::new(time_limited(sink), id, deduplicator, counters);
+                    join_handles.push((*id, OperatorKind::Sink, writer_sink.start(receiver)));
+                } else if sink_key(config) == HTTP_SINK {
+                    let http_sink = HttpSink::new(config)
+                        .map_err(|msg| MopperError::Sink { node_id: *id, msg, cause: None })?;
+                    let writer_sink = WriterSink::new(time_limited(Box::new(http_sink)), id, deduplicator, counters);
+                    join_handles.push((*id, OperatorKind::Sink, writer_sink.start(receiver)));
                 } else {
 
-                    // TODO: do something with config, just create a std out sink for now
                     match config.target_type {
                         IOType::StdOut => {
                             let stdout = io::stdout();
-                            let writer_sink = WriterSink::new(Box::new(stdout), id, options.deduplicate());
-                            join_handles.push(writer_sink.start(receiver));
+                            let writer_sink = WriterSink::new(time_limited(Box::new(WriteSink::new(stdout))), id, deduplicator, counters);
+                            join_handles.push((*id, OperatorKind::Sink, writer_sink.start(receiver)));
+                        },
+                        IOType::File => {
+                            let file_path = target_path(config, options)
+                                .ok_or_else(|| MopperError::Sink { node_id: *id, msg: "File target without path".to_string(), cause: None })?;
+                            let compression = Compression::of(config)
+                                .map_err(|msg| MopperError::Sink { node_id: *id, msg, cause: None })?;
+                            let (buffer_capacity, flush_interval, sync_on_close) = (options.write_buffer_capacity(), options.flush_interval(), options.sync_on_close());
+                            let path = file_path.to_string_lossy();
+                            let sink: Box<dyn Sink> = if path.contains(GRAPH_PLACEHOLDER) {
+                                // one file per graph, created when its first statement arrives
+                                let (path, write_in_place, node_id, output_files) = (path.to_string(), options.write_in_place(), *id, output_files.clone());
+                                Box::new(GraphPartitionedSink::new(move |graph| {
+                                    let file = output_files.lock().unwrap().create(Path::new(&graph_path(&path, graph)), write_in_place, node_id)
+                                        .map_err(io::Error::other)?;
+                                    Ok(Box::new(FileSink::compressed(file, compression, buffer_capacity, flush_interval, sync_on_close)))
+                                }))
+                            } else {
+                                let file = output_files.lock().unwrap().create(&file_path, options.write_in_place(), *id)?;
+                                Box::new(FileSink::compressed(file, compression, buffer_capacity, flush_interval, sync_on_close))
+                            };
+                            let writer_sink = WriterSink::new(time_limited(sink), id, deduplicator, counters);
+                            join_handles.push((*id, OperatorKind::Sink, writer_sink.start(receiver)));
                         },
                         _ => {
                             error!("Target type {:?} not implemented yet!", config.target_type);
@@ -199,65 +676,408 @@ pub fn start(algemaploom_plan: &str, options: &MopperOptions) -> Result<(), Box<
                 let left = &node.from[0];
                 let right = &node.from[1];
 
-                let join_operator = JoinOperator::new(config, left, right, id);
+                operator_counters.push((*id, OperatorKind::Join, counters.clone()));
+                let join_operator = JoinOperator::new(config, left, right, id, &node.attributes, counters.clone(), record_errors(OperatorKind::Join));
                 let senders = sender_map.remove(id).unwrap();
-                let receiver = receiver_map.remove(id).unwrap();
-                join_handles.push(join_operator.start(receiver, senders));
+                let (left_receiver, right_receiver) = join_receiver_map.remove(id).unwrap();
+                join_handles.push((*id, OperatorKind::Join, join_operator.start(left_receiver, right_receiver, senders)));
             },
 
-            _ => todo!()
+            other => {
+                let msg = format!("Operator {} of node {id} cannot be executed; is the plan rewritten?", operator_name(other));
+                error!("{msg}");
+                return Err(MopperError::plan(msg));
+            }
         }
 
     }
 
     info!("Up and running!");
 
-    let mut errors: Vec<(u8, String)> = Vec::new();
-    for join_handle in join_handles {
-        let (err_code, msg) = join_handle.join().unwrap();
-        if err_code > 0 {
-            error!("{msg}");
-            errors.push((err_code, msg));
+    let throughput_logger = options.throughput_log_interval()
+        .map(|interval| log_throughput(operator_counters.clone(), interval));
+    let source_counters: Vec<Arc<OperatorCounters>> = operator_counters.iter()
+        .filter(|(_id, kind, _counters)| *kind == OperatorKind::Source)
+        .map(|(_id, _kind, counters)| counters.clone())
+        .collect();
+    let progress_reporter = options.progress_listener().as_ref()
+        .map(|listener| report_progress(source_counters.clone(), listener.clone(), options.progress_interval(), started));
+
+    Ok(Execution {
+        started,
+        join_handles,
+        operator_counters,
+        source_counters,
+        error_log,
+        throughput_logger,
+        progress_reporter,
+        progress_listener: options.progress_listener().clone(),
+        shacl_validation,
+        dataset_description,
+        provenance,
+        sparql_queries,
+        output_files
+    })
+}
+
+impl Execution {
+    /// Waits until all operators are done, and returns the statistics of the run when successful.
+    pub(crate) fn wait(self) -> Result<RunStats, MopperError> {
+        let Execution { started, join_handles, operator_counters, source_counters, error_log, throughput_logger, progress_reporter, progress_listener, shacl_validation, dataset_description, provenance, sparql_queries, output_files } = self;
+        let mut errors: Vec<MopperError> = Vec::new();
+        // An operator that fails or panics drops its channels, so the operators around it stop as well.
+        for (node_id, kind, join_handle) in join_handles {
+            let error = match join_handle.join() {
+                Ok(Ok(())) => continue,
+                Ok(Err(error)) => error,
+                Err(panic) => {
+                    let msg = panic.downcast_ref::<&str>().map(|msg| msg.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "unknown cause".to_string());
+                    MopperError::Panic { node_id, kind, msg }
+                }
+            };
+            error!("{error}");
+            errors.push(error);
+        }
+
+        if let Some(error_log) = &error_log {
+            if let Err(err) = error_log.flush() {
+                error!("Cannot write error log: {err}");
+            }
+        }
+
+        if let Some(throughput_logger) = throughput_logger {
+            throughput_logger.stop();
+        }
+        if let Some(progress_reporter) = progress_reporter {
+            progress_reporter.stop();
+            if let Some(listener) = &progress_listener {
+                listener.report(&progress(&source_counters, started, true));
+            }
+        }
+
+        // Output files only replace the previous ones when the run succeeds; otherwise they are discarded.
+        let OutputFiles { files, locks } = std::mem::take(&mut *output_files.lock().unwrap());
+        for (node_id, output_file) in files {
+            if errors.is_empty() {
+                let path = output_file.path().display().to_string();
+                if let Err(err) = output_file.commit() {
+                    let error = MopperError::Sink { node_id, msg: format!("Cannot move output to {path}"), cause: Some(Box::new(err)) };
+                    error!("{error}");
+                    errors.push(error);
+                }
+            }
+        }
+        drop(locks);
+
+        if errors.is_empty() {
+            info!("Done!");
+            let mut operators: Vec<OperatorStats> = operator_counters.iter()
+                .map(|(id, kind, counters)| OperatorStats::new(*id, *kind, counters))
+                .collect();
+            operators.sort_by_key(|operator_stats| operator_stats.node_id);
+            let validation = shacl_validation.map(|shacl_validation| {
+                let validation = shacl_validation.validate();
+                for violation in &validation.violations {
+                    warn!("SHACL violation of serializers {:?}: {violation}", violation.mapping_rules);
+                }
+                for constraint in &validation.unsupported_constraints {
+                    warn!("SHACL constraint {constraint} is not supported; the validation is inconclusive.");
+                }
+                validation
+            });
+            let dataset = dataset_description.and_then(|dataset_description| dataset_description.write()
+                .inspect_err(|err| error!("Cannot write VoID description or dataset report: {err}"))
+                .ok());
+            if let Some(Err(err)) = provenance.map(Provenance::write) {
+                error!("Cannot write provenance: {err}");
+            }
+            if let Some(Err(err)) = sparql_queries.map(SparqlQueries::run) {
+                error!("Cannot write SPARQL query results: {err}");
+            }
+            Ok(RunStats {
+                elapsed: started.elapsed(),
+                operators,
+                validation,
+                dataset
+            })
+        } else if errors.len() == 1 {
+            Err(errors.remove(0))
+        } else {
+            Err(MopperError::Multiple(errors))
         }
     }
+}
 
-    if errors.is_empty() {
-        info!("Done!");
-        Ok(())
+/// Renders the plan as it will be executed with the given options, i.e. after rewriting,
+/// as a Graphviz DOT graph.
+pub fn plan_to_dot(algemaploom_plan: &str, options: &MopperOptions) -> Result<String, MopperError> {
+    let (_plan_graph, reduced_plan) = prepare_plan(algemaploom_plan, options, forced_to_one_target(options))?;
+    let forced_target = if options.force_to_std_out() {
+        Some("standard out".to_string())
     } else {
-        Err(Box::new(GeneralError::new(errors)))
-    }
+        options.force_to_file().as_ref().map(|file_path| format!("file {file_path}"))
+    };
+    Ok(to_dot(&reduced_plan, forced_target.as_deref()))
+}
 
+/// Describes the operator pipeline `start` would build for the plan with the given options, without running it.
+pub fn explain_plan(algemaploom_plan: &str, options: &MopperOptions) -> Result<String, MopperError> {
+    let (plan_graph, reduced_plan) = prepare_plan(algemaploom_plan, options, forced_to_one_target(options))?;
+    Ok(explain(&plan_graph, &reduced_plan, options))
+}
+
+/// Checks whether the plan can be executed with the given options, without running it.
+/// All problems found in the plan are returned at once, as [MopperError::Validation].
+pub fn validate_plan(algemaploom_plan: &str, options: &MopperOptions) -> Result<(), MopperError> {
+    prepare_plan(algemaploom_plan, options, forced_to_one_target(options))?;
+    Ok(())
+}
+
+// Whether the output is forced to standard out or a file, so the targets in the plan are ignored
+fn forced_to_one_target(options: &MopperOptions) -> bool {
+    options.force_to_std_out() || options.force_to_file().is_some()
+}
 
+// Applies the options to the plan, and validates and rewrites it. If `to_one_target` is set, the
+// targets in the plan are ignored; they are only kept apart to tag their output.
+fn prepare_plan(algemaploom_plan: &str, options: &MopperOptions, to_one_target: bool) -> Result<(PlanGraph, NodeMap), MopperError> {
+    let plan_graph = read_plan(algemaploom_plan, options)?;
+    validate(&plan_graph, to_one_target, options)?;
+    let reduced_plan = rewrite(&plan_graph, to_one_target && !options.tag_targets())?;
+    Ok((plan_graph, reduced_plan))
 }
 
-pub fn mapping_to_plan(mapping: &str, lang: MappingLang) -> Result<String, Box<dyn Error>> {
+// Applies the options to the plan, and reads it
+fn read_plan(algemaploom_plan: &str, options: &MopperOptions) -> Result<PlanGraph, MopperError> {
+    let algemaploom_plan = substitute_params(algemaploom_plan, options.params(), options.params_from_env(), options.credentials())?;
+    let algemaploom_plan = override_sources(&algemaploom_plan, options.source_overrides())?;
+    let algemaploom_plan = match options.base_iri() {
+        Some(base_iri) => set_base_iri(&algemaploom_plan, base_iri)?,
+        None => algemaploom_plan
+    };
+    PlanGraph::from_json(&algemaploom_plan)
+}
+
+/// Creates an AlgeMapLoom plan in JSON that applies the W3C Direct Mapping to a CSV file:
+/// every row becomes a subject with IRIs relative to `base_iri`, and every column a predicate.
+/// The header of the file is read in the `dialect`, which the run should also read the file in.
+pub fn direct_mapping_to_plan(csv_file: &str, base_iri: &str, dialect: &CsvDialect) -> Result<String, MopperError> {
+    direct_mapping::to_plan(csv_file, base_iri, dialect)
+}
+
+pub fn mapping_to_plan(mapping: &str, lang: MappingLang) -> Result<String, MopperError> {
     let handler: Box<dyn StringTranslatorHandler> = match lang {
         MappingLang::RML => Box::new(RMLStringHandler),
         MappingLang::SHEXML => Box::new(ShExMLStringHandler)
     };
     
-    let plan_str = handler.translate(mapping)?.to_string()?;
-    Ok(plan_str)
+    let plan_str = handler.translate(mapping)
+        .and_then(|plan| plan.to_string())
+        .map_err(|err| MopperError::Translation { msg: err.to_string() })?;
+
+    // resolve all relative IRIs against the base IRI of the mapping
+    match lang {
+        MappingLang::RML => match turtle_base_iri(mapping) {
+            Some(base_iri) => Ok(set_base_iri(&plan_str, &base_iri)?.into_owned()),
+            None => Ok(plan_str)
+        },
+        MappingLang::SHEXML => Ok(plan_str)
+    }
 }
 
-fn find_file(file: &str, working_dir_hint: &Option<String>) -> Option<PathBuf> {
-    let file_path = Path::new(file);
-    
-    match file_path.exists() { 
-        true => Some(file_path.to_path_buf()),
-        false => {
-            if let Some(working_dir) = working_dir_hint {
-                let working_dir_path = Path::new(working_dir);
-                let new_path = working_dir_path.join(file);
-                match new_path.exists() { 
-                    true => Some(new_path),
-                    false => None
+// Returns the first base IRI declared in a Turtle document, with `@base <iri> .` or `BASE <iri>`
+fn turtle_base_iri(turtle: &str) -> Option<String> {
+    turtle.lines()
+        .map(str::trim_start)
+        .find_map(|line| {
+            let rest = line.strip_prefix("@base")
+                .or_else(|| line.get(..4).filter(|keyword| keyword.eq_ignore_ascii_case("BASE")).map(|_| &line[4..]))?;
+            let iri = rest.trim_start().strip_prefix('<')?;
+            iri.find('>').map(|end| iri[..end].to_string())
+        })
+}
+
+// Creates a custom source or sink, which is when it connects to a remote system, within the connect timeout if any
+fn connect<T: Send + 'static>(options: &MopperOptions, node_id: usize, create: impl FnOnce() -> io::Result<T> + Send + 'static) -> io::Result<T> {
+    match options.connect_timeout() {
+        Some(timeout) => with_timeout(format!("Connect {node_id}"), timeout, create)?,
+        None => create()
+    }
+}
+
+// Creates a source that reads from a remote system, within the connect timeout, and limits how long it may take to
+// read a record and how fast it reads, as set in the options
+fn connect_source(options: &MopperOptions, node_id: usize, create: impl FnOnce() -> io::Result<Box<dyn Source>> + Send + 'static) -> Result<Box<dyn Source>, MopperError> {
+    let mut source = connect(options, node_id, create)
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::TimedOut => MopperError::Timeout { node_id, kind: OperatorKind::Source, msg: format!("Cannot create source: {err}") },
+            _ => MopperError::Source { node_id, msg: format!("Cannot create source: {err}"), cause: Some(Box::new(err)) }
+        })?;
+    if let Some(timeout) = options.source_read_timeout() {
+        source = Box::new(TimeoutSource::new(source, node_id, timeout));
+    }
+    if let Some(rate_limit) = options.source_rate_limits().get(&node_id) {
+        debug!("Limiting the rate of source {node_id} to {rate_limit:?}");
+        source = Box::new(RateLimitedSource::new(source, *rate_limit));
+    }
+    Ok(source)
+}
+
+impl OutputFiles {
+    // Creates an output file, unless another run is writing it. Unless writing in place, or the path is not
+    // a regular file such as a named pipe, it is a temporary file that replaces the output file when the run succeeds.
+    fn create(&mut self, path: &Path, write_in_place: bool, node_id: usize) -> Result<File, MopperError> {
+        let regular_file = fs::metadata(path).map_or(true, |metadata| metadata.is_file());
+        if regular_file {
+            match OutputLock::acquire(path) {
+                Ok(output_lock) => self.locks.push(output_lock),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    let msg = format!("Output file {} is being written by another run (or another target of this one)", path.display());
+                    return Err(MopperError::Sink { node_id, msg, cause: None });
+                },
+                Err(err) => warn!("Cannot lock output file {}, so other runs writing it at the same time go unnoticed: {err}", path.display())
+            }
+        }
+        let file = if write_in_place || !regular_file {
+            File::create(path)
+        } else {
+            AtomicFile::create(path).map(|(file, output_file)| {
+                self.files.push((node_id, output_file));
+                file
+            })
+        };
+        file.map_err(|err| MopperError::Sink { node_id, msg: format!("Cannot create output file {}", path.display()), cause: Some(Box::new(err)) })
+    }
+}
+
+// Creates the deduplicator of a sink, or of all sinks if deduplicating globally
+fn create_deduplicator(options: &MopperOptions, name: &str) -> Result<Box<dyn Deduplicator + Send>, MopperError> {
+    let capacity = options.deduplication_capacity();
+    let hasher = options.deduplication_hasher();
+    match options.deduplication_strategy() {
+        DeduplicationStrategy::Exact => {},
+        DeduplicationStrategy::Approximate { false_positive_rate, expected_statements } => {
+            warn!("Approximate deduplication: about {false_positive_rate} of the unique statements may be dropped!");
+            return Ok(Box::new(BloomDeduplicator::new(*false_positive_rate, *expected_statements)))
+        },
+        DeduplicationStrategy::Sharded { shards } => {
+            return Ok(Box::new(ShardedDeduplicator::with_capacity(*shards, capacity, hasher)))
+        },
+        DeduplicationStrategy::ExternalSort { max_in_memory, spill_dir } => {
+            return match ExternalSortDeduplicator::new(*max_in_memory, spill_dir) {
+                Ok(dedup) => Ok(Box::new(dedup)),
+                Err(err) => {
+                    let msg = format!("Cannot create spill directory for deduplication: {err}");
+                    error!("{msg}");
+                    Err(MopperError::Options { msg, cause: Some(Box::new(err)) })
                 }
-            } else {
-                None
             }
         }
     }
-    
+    match options.deduplicate_index_dir() {
+        Some(index_dir) => {
+            let dedup_result = PersistentDeduplicator::index_file(index_dir, name)
+                .and_then(|index_file| PersistentDeduplicator::with_capacity(&index_file, capacity, hasher));
+            match dedup_result {
+                Ok(dedup) => Ok(Box::new(dedup)),
+                Err(err) => {
+                    let msg = format!("Cannot open deduplication index in {index_dir}: {err}");
+                    error!("{msg}");
+                    Err(MopperError::Options { msg, cause: Some(Box::new(err)) })
+                }
+            }
+        },
+        None => Ok(Box::new(HashSetDeduplicator::with_capacity(capacity, hasher)))
+    }
 }
+
+// The sources of the plan, as file IRIs if they are files
+fn source_iris(plan: &NodeMap, options: &MopperOptions) -> Vec<String> {
+    let mut sources: Vec<String> = plan.values()
+        .filter_map(|node| match &node.operator {
+            Operator::SourceOp { config } => Some(match config.config.get("path") {
+                Some(path) => match find_files(path, options.working_dir_hint()) {
+                    Ok(file_locations) => file_locations.into_iter()
+                        .map(|file_location| match file_location {
+                            FileLocation::Local(file_path) => file_iri(&file_path),
+                            FileLocation::Remote(url) | FileLocation::Server { url, .. } => url,
+                            FileLocation::ZipMember { archive, member } => format!("{}!{member}", file_iri(&archive)),
+                            FileLocation::StdIn => format!("{:?}", IOType::StdIn)
+                        })
+                        .collect(),
+                    Err(_) => vec![path.clone()]
+                },
+                None => vec![format!("{:?}", config.source_type)]
+            }),
+            _ => None
+        })
+        .flatten()
+        .collect();
+    sources.sort();
+    sources.dedup();
+    sources
+}
+
+// The files the output is written to, as file IRIs. The files of targets partitioned by graph are only
+// known when written, so they are left out.
+fn output_iris(plan: &NodeMap, options: &MopperOptions) -> Vec<String> {
+    if let Some(file_path) = options.force_to_file() {
+        return vec![file_iri(Path::new(file_path))];
+    }
+    if options.force_to_std_out() {
+        return Vec::new();
+    }
+    let mut outputs: Vec<String> = plan.values()
+        .filter_map(|node| match &node.operator {
+            Operator::TargetOp { config } if config.target_type == IOType::File => target_path(config, options),
+            _ => None
+        })
+        .filter(|file_path| !file_path.to_string_lossy().contains(GRAPH_PLACEHOLDER))
+        .map(|file_path| file_iri(&file_path))
+        .collect();
+    outputs.sort();
+    outputs.dedup();
+    outputs
+}
+
+// The path of a file target. A relative path is relative to the working directory hint, if any,
+// like the paths of sources.
+fn target_path(config: &Target, options: &MopperOptions) -> Option<PathBuf> {
+    let path = Path::new(config.configuration.get("path")?);
+    match local_working_dir(options.working_dir_hint()) {
+        Some(working_dir) if path.is_relative() => Some(working_dir.join(path)),
+        _ => Some(path.to_path_buf())
+    }
+}
+
+// The serializer with the format of its targets, if they all have the same format that a serializer supports
+fn serializer_for_targets<'a>(config: &'a Serializer, id: &usize, plan: &NodeMap) -> Cow<'a, Serializer> {
+    let mut target_formats: Vec<&DataFormat> = plan.values()
+        .filter(|node| node.from.contains(id))
+        .filter_map(|node| match &node.operator {
+            Operator::TargetOp { config } => Some(&config.data_format),
+            _ => None
+        })
+        .collect();
+    target_formats.dedup();
+    match target_formats.as_slice() {
+        [format @ (DataFormat::NTriples | DataFormat::NQuads)] if **format != config.format => {
+            debug!("Serializer {id} writes {format:?} for its targets");
+            Cow::Owned(Serializer { format: (*format).clone(), ..config.clone() })
+        },
+        _ => Cow::Borrowed(config)
+    }
+}
+
+fn file_iri(file_path: &Path) -> String {
+    let absolute_path = std::path::absolute(file_path).unwrap_or_else(|_| file_path.to_path_buf());
+    let path = absolute_path.to_string_lossy().replace('\\', "/").replace(' ', "%20");
+    if path.starts_with('/') {
+        format!("file://{path}")
+    } else {
+        format!("file:///{path}")
+    }
+}
+
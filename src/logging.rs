@@ -0,0 +1,289 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! A logger for when the log should not go to standard error as colored text, e.g. when mopper runs
+//! under a scheduler that captures its output: it writes one line per log record to a file or any
+//! other writer, as text or as JSON.
+//!
+//! The log level can also differ per kind of operator, per operator node or per module, so one part
+//! of a large plan can be followed in detail with [LogLevels] and [FilteringLogger].
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{LineWriter, Write};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+
+/// How a log record is written.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    /// `<timestamp> - <LEVEL> - <message>`, like on standard error
+    #[default]
+    Text,
+
+    /// A JSON object with the fields `timestamp`, `level`, `target` (the module that logged) and `message`
+    Json
+}
+
+/// Logs the records of a module and its submodules, up to a level, one line per record.
+pub struct LineLogger {
+    module: String,
+    max_level: LevelFilter,
+    format: LogFormat,
+    writer: Mutex<Box<dyn Write + Send>>
+}
+
+impl LineLogger {
+    pub fn new(module: &str, max_level: LevelFilter, format: LogFormat, writer: Box<dyn Write + Send>) -> Self {
+        LineLogger { module: module.to_string(), max_level, format, writer: Mutex::new(writer) }
+    }
+
+    /// A logger that appends to the file at `path`, creating it if needed.
+    pub fn to_file(module: &str, max_level: LevelFilter, format: LogFormat, path: &str) -> io::Result<Self> {
+        let file: File = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(LineLogger::new(module, max_level, format, Box::new(LineWriter::new(file))))
+    }
+
+    fn format(&self, record: &Record) -> String {
+        let timestamp = utc_timestamp(SystemTime::now());
+        match self.format {
+            LogFormat::Text => format!("{timestamp} - {} - {}", record.level(), record.args()),
+            LogFormat::Json => json!({
+                "timestamp": timestamp,
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string()
+            }).to_string()
+        }
+    }
+}
+
+impl Log for LineLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.max_level && is_in_module(metadata.target(), &self.module)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let line = self.format(record);
+            // a log that cannot be written cannot tell either
+            let _ = writeln!(self.writer.lock().unwrap(), "{line}");
+        }
+    }
+
+    fn flush(&self) {
+        let _ = self.writer.lock().unwrap().flush();
+    }
+}
+
+/// The most verbose log level per kind of operator, operator node or module, and for everything else.
+#[derive(Clone, Debug)]
+pub struct LogLevels {
+    default_level: LevelFilter,
+    module_levels: Vec<(String, LevelFilter)>,
+    node_levels: HashMap<usize, LevelFilter>
+}
+
+impl LogLevels {
+    pub fn new(default_level: LevelFilter) -> Self {
+        LogLevels { default_level, module_levels: Vec::new(), node_levels: HashMap::new() }
+    }
+
+    /// Sets the level of `target`: a kind of operator (`source`, `extend`, `join`, `serializer` or `sink`),
+    /// the id of an operator node in the rewritten plan, or a module path, e.g. `mopper::plan_rewriter`.
+    pub fn set(&mut self, target: &str, level: LevelFilter) {
+        if let Ok(node_id) = target.parse::<usize>() {
+            self.node_levels.insert(node_id, level);
+            return;
+        }
+        let modules: &[&str] = match target.to_lowercase().as_str() {
+            "source" => &["mopper::source"],
+            "extend" => &["mopper::operator::extension", "mopper::function"],
+            "join" => &["mopper::operator::join"],
+            "serializer" => &["mopper::operator::serializer"],
+            "sink" => &["mopper::sink"],
+            _ => &[target]
+        };
+        for module in modules {
+            self.module_levels.retain(|(other_module, _)| other_module != module);
+            self.module_levels.push((module.to_string(), level));
+        }
+    }
+
+    /// The most verbose level of all; more verbose records need not be logged at all.
+    pub fn max_level(&self) -> LevelFilter {
+        self.module_levels.iter().map(|(_, level)| *level)
+            .chain(self.node_levels.values().copied())
+            .fold(self.default_level, |max_level, level| max_level.max(level))
+    }
+
+    /// The level for records of the module `target`, logged by the current thread. The level of
+    /// the node of an operator thread comes first, then the level of the most specific module.
+    pub fn level_of(&self, target: &str) -> LevelFilter {
+        if !self.node_levels.is_empty() {
+            let node_level = thread::current().name()
+                .and_then(node_of_thread)
+                .and_then(|node_id| self.node_levels.get(&node_id));
+            if let Some(level) = node_level {
+                return *level;
+            }
+        }
+        self.module_levels.iter()
+            .filter(|(module, _)| is_in_module(target, module))
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default_level)
+    }
+}
+
+/// Passes the log records that are within the [LogLevels] on to another logger, which should log
+/// everything up to [LogLevels::max_level].
+pub struct FilteringLogger {
+    inner: Box<dyn Log>,
+    levels: LogLevels
+}
+
+impl FilteringLogger {
+    pub fn new(inner: Box<dyn Log>, levels: LogLevels) -> Self {
+        FilteringLogger { inner, levels }
+    }
+}
+
+impl Log for FilteringLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.levels.level_of(metadata.target()) && self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+// Operator threads are named after the operator and its node, e.g. `Join 5`
+fn node_of_thread(thread_name: &str) -> Option<usize> {
+    let (operator, node_id) = thread_name.rsplit_once(' ')?;
+    match operator.contains(' ') {
+        true => None,
+        false => node_id.parse().ok()
+    }
+}
+
+// Whether the target is the module or one of its submodules
+fn is_in_module(target: &str, module: &str) -> bool {
+    target.strip_prefix(module).is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+// The time in RFC 3339 format, in UTC and to the second, e.g. `2024-05-01T12:30:00+00:00`
+pub(crate) fn utc_timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+    let (days, seconds_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // the civil date of the day since 1970-01-01, after Howard Hinnant's `civil_from_days`
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}+00:00",
+            seconds_of_day / 3600, seconds_of_day % 3600 / 60, seconds_of_day % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use log::Level;
+    use serde_json::Value;
+    use super::*;
+
+    // Collects what is written, so the test can read it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn log(logger: &LineLogger, level: Level, target: &str, message: &str) {
+        logger.log(&Record::builder().level(level).target(target).args(format_args!("{message}")).build());
+    }
+
+    #[test]
+    fn json_lines() {
+        let buffer = SharedBuffer::default();
+        let logger = LineLogger::new("mopper", LevelFilter::Info, LogFormat::Json, Box::new(buffer.clone()));
+        log(&logger, Level::Info, "mopper::source::csv_file", "Reading \"sport.csv\"");
+        log(&logger, Level::Debug, "mopper", "Too detailed");
+        log(&logger, Level::Error, "mopperish", "Another crate");
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(1, lines.len());
+        assert_eq!("INFO", lines[0]["level"]);
+        assert_eq!("mopper::source::csv_file", lines[0]["target"]);
+        assert_eq!("Reading \"sport.csv\"", lines[0]["message"]);
+    }
+
+    #[test]
+    fn levels() {
+        let mut levels = LogLevels::new(LevelFilter::Warn);
+        levels.set("join", LevelFilter::Debug);
+        levels.set("mopper::operator", LevelFilter::Info);
+        levels.set("7", LevelFilter::Trace);
+        assert_eq!(LevelFilter::Trace, levels.max_level());
+        assert_eq!(LevelFilter::Debug, levels.level_of("mopper::operator::join"));
+        assert_eq!(LevelFilter::Info, levels.level_of("mopper::operator::extension"));
+        assert_eq!(LevelFilter::Warn, levels.level_of("mopper::operatorx"));
+        assert_eq!(LevelFilter::Warn, levels.level_of("mopper::plan"));
+
+        let level_of_node = |thread_name: &str| thread::Builder::new()
+            .name(thread_name.to_string())
+            .spawn({
+                let levels = levels.clone();
+                move || levels.level_of("mopper::plan")
+            })
+            .unwrap().join().unwrap();
+        assert_eq!(LevelFilter::Trace, level_of_node("Serializer 7"));
+        assert_eq!(LevelFilter::Warn, level_of_node("Serializer 8"));
+        assert_eq!(LevelFilter::Warn, level_of_node("Dedup shard 7"));
+    }
+
+    #[test]
+    fn timestamp() {
+        assert_eq!("1970-01-01T00:00:00+00:00", utc_timestamp(UNIX_EPOCH));
+        assert_eq!("2024-02-29T23:59:59+00:00", utc_timestamp(UNIX_EPOCH + Duration::from_secs(1_709_251_199)));
+    }
+}
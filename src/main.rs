@@ -13,43 +13,308 @@
  *    See the License for the specific language governing permissions and
  *    limitations under the License.
  */
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt::Display;
 use std::fs;
-use std::path::PathBuf;
-use clap::Parser;
-use log::info;
-use mopper::mopper_options::MopperOptionsBuilder;
-use mopper::{mapping_to_plan, start, MappingLang};
+use std::net::SocketAddr;
+use std::ops::ControlFlow;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use clap::{CommandFactory, Parser, ValueEnum};
+use cli_config::missing_options;
+use log::{info, LevelFilter, Log};
+use mopper::plan::{json_to_yaml, merge_plans, pretty_json, yaml_to_json};
+use mopper::mopper_options::{DeduplicationHasher, DeduplicationStrategy, ErrorMode, InvalidIriPolicy, IriEncoding, MissingReferencePolicy, MopperOptions, MopperOptionsBuilder};
+use mopper::{count, direct_mapping_to_plan, explain_plan, mapping_to_plan, plan_to_dot, start, start_plans, validate_plan, MappingLang};
+use mopper::daemon::{run_reloading_daemon, Schedule};
+use mopper::logging::{FilteringLogger, LineLogger, LogFormat, LogLevels};
+use mopper::error::MopperError;
+use mopper::report::{LogCollector, RunReport};
+use mopper::secrets::{CommandProvider, Credentials, FileProvider};
+use mopper::source::csv_file::{parse_csv_char, CsvDialect};
+use mopper::source::rate_limit::RateLimit;
+use mopper::stats::{Progress, ProgressListener, RunStats};
+use mopper::test_suite::{run_test_suite, RML_CORE_TEST_BASE_IRI};
 
+mod cli_config;
+
+// The base IRI of the IRIs of the direct mapping if none is given
+const DEFAULT_DIRECT_BASE_IRI: &str = "http://example.com/base/";
+
+/// Executes a mapping, or checks or shows it, depending on the command.
+/// Without a command, the mapping is executed as with `mopper run`.
 #[derive(Parser)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    
-    //#[options(help = "print help message")]
-    //help: bool,
+    /// Increase log level.
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Be quiet; no logging.
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Write the log to FILE instead of standard error. The log is appended to FILE if it exists.
+    #[arg(long, value_name = "FILE", global = true)]
+    log_file: Option<String>,
+
+    /// Write the log as text, or as one JSON object per line with the fields timestamp, level, target
+    /// and message.
+    #[arg(long, value_name = "FORMAT", global = true, default_value = "text")]
+    log_format: LogFormatArg,
+
+    /// Set the log level of a part of the mapping, overriding -v and -q: TARGET is a kind of operator
+    /// (source, extend, join, serializer or sink), the id of an operator node as shown by `mopper plan`,
+    /// or a module, e.g. `join=debug` or `mopper::plan_rewriter=trace`. Can be given multiple times.
+    #[arg(long, value_name = "TARGET=LEVEL", value_parser = parse_log_level, global = true)]
+    log_level: Vec<(String, LevelFilter)>,
+
+    /// Read the options that are not given on the command line from FILE, in TOML, with the names of the
+    /// options as keys, e.g. `message-buffer-capacity = 256`. Options can also be given as environment
+    /// variables, e.g. MOPPER_MESSAGE_BUFFER_CAPACITY=256, which take precedence over FILE.
+    #[arg(long, value_name = "FILE", global = true)]
+    config: Option<String>,
+
+    #[command(flatten)]
+    run: RunArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Execute the mapping. This is the default command.
+    Run(RunArgs),
+
+    /// Check whether the mapping can be executed with the given options, and report all problems found,
+    /// without executing it.
+    Validate {
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[command(flatten)]
+        processing: ProcessingArgs
+    },
+
+    /// Show the plan as it will be executed, i.e. after merging and removing nodes, without executing it.
+    Plan {
+        /// Show the operators with their inputs and outputs and resolved source paths (text), or a Graphviz DOT graph.
+        #[arg(long, value_name = "FORMAT", default_value = "text")]
+        format: PlanOutputArg,
+
+        /// Write the plan to FILE instead of standard out.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<String>,
+
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[command(flatten)]
+        processing: ProcessingArgs
+    },
+
+    /// Translate the mapping to an AlgeMapLoom plan and write it, without executing it. The options
+    /// that change the plan, like --param, are not applied to it.
+    Translate {
+        /// The format to write the plan in.
+        #[arg(long, value_name = "FORMAT", default_value = "json")]
+        to: PlanFormatArg,
+
+        /// Write the plan to FILE instead of standard out.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<String>,
+
+        #[command(flatten)]
+        input: InputArgs
+    },
+
+    /// Execute the mapping without writing the generated statements, and print how many statements every
+    /// target of the plan would get.
+    Count {
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[command(flatten)]
+        processing: ProcessingArgs
+    },
+
+    /// Execute the mapping without writing the generated statements, and print a summary of the run
+    /// on standard error.
+    Stats {
+        /// Measure how long every operator is busy and how long it is blocked waiting for other operators,
+        /// and print a breakdown on standard error when done. Slows down execution a bit.
+        #[arg(long)]
+        benchmark: bool,
+
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[command(flatten)]
+        processing: ProcessingArgs
+    },
+
+    /// Execute all RML test cases in a directory and its subdirectories, and print which ones pass.
+    ///
+    /// A test case is a directory with its mapping (mapping.json or mapping.ttl), its sources and the statements
+    /// it should generate (output.nq); a test case without output.nq should fail. The generated statements are
+    /// compared with the expected ones regardless of their order, duplicates and blank node labels.
+    TestSuite {
+        /// The directory with the test cases.
+        #[arg(value_name = "DIR")]
+        dir: String,
+
+        /// Also write the outcomes as an EARL report in Turtle to FILE, to publish the conformance of mopper.
+        #[arg(long, value_name = "FILE")]
+        earl: Option<String>,
+
+        /// The IRIs of the test cases in the EARL report are IRI followed by the names of the test cases.
+        #[arg(long, value_name = "IRI", default_value = RML_CORE_TEST_BASE_IRI)]
+        earl_test_base: String,
+
+        #[command(flatten)]
+        processing: ProcessingArgs
+    },
+
+    /// Run as a service that executes the mappings it gets, instead of executing a mapping.
+    Serve {
+        /// Serve the gRPC service defined in proto/mopper.proto at ADDR, e.g. 127.0.0.1:50051.
+        /// Clients send a mapping and stream the records of its sources, and get the quads streamed back.
+        #[arg(long, value_name = "ADDR")]
+        grpc: SocketAddr,
+
+        /// Let the mappings of clients also read sources they do not stream, like files and databases the server
+        /// can reach. By default, a mapping with such sources is refused. Only use it with clients you trust.
+        #[arg(long)]
+        allow_server_sources: bool,
+
+        #[command(flatten)]
+        processing: ProcessingArgs
+    }
+}
+
+// What to execute
+#[derive(clap::Args)]
+#[command(next_help_heading = "Input")]
+struct InputArgs {
+    /// Required, unless --direct is given. The path to the mapping file, or `-` to read the mapping from standard input.
+    /// Relative paths of sources are then resolved against the current directory.
+    /// Can be given more than once, or be a directory, to execute all mappings in one run.
+    #[arg(short, long, value_name = "FILE", required_unless_present = "direct")]
+    mapping_file: Vec<String>,
+
+    /// Instead of executing a mapping, apply the W3C Direct Mapping to the CSV file: every row becomes
+    /// a subject and every column a predicate, with IRIs relative to --base-iri.
+    /// Can be given more than once.
+    #[arg(long, value_name = "CSV_FILE", conflicts_with_all = ["mapping_file", "mapping_lang", "plan_format"])]
+    direct: Vec<String>,
 
-    /// Required. The path to the mapping file.
-    #[arg(short, long, value_name = "FILE")]
-    mapping_file: String,
+    /// The base IRI to resolve relative IRIs against, instead of the base IRI of the mapping.
+    /// With --direct, the base IRI of the generated IRIs, http://example.com/base/ if not given.
+    #[arg(long, value_name = "IRI")]
+    base_iri: Option<String>,
 
     /// The language of the mapping file. If not given, AlgeMapLoom is assumed.
     #[arg(short = 'l', long, value_name = "LANG")]
     mapping_lang: Option<MappingLangArg>,
 
-    /// Increase log level.
-    #[arg(short, long, action = clap::ArgAction::Count)]
-    verbose: u8,
+    /// The format of an AlgeMapLoom plan. If not given, YAML is assumed for files ending in .yaml or .yml,
+    /// and JSON otherwise.
+    #[arg(long, value_name = "FORMAT")]
+    plan_format: Option<PlanFormatArg>,
 
-    /// Be quiet; no logging.
-    #[arg(short, long)]
-    quiet: bool,
+    /// Replace the placeholder ${KEY} in source and target configurations and constants of the plan by VALUE.
+    /// Can be given more than once. Placeholders in source and target configurations without value are
+    /// replaced by the value of the environment variable KEY.
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_param)]
+    param: Vec<(String, String)>,
 
-    /// Force output to standard out, ignoring the targets in the plan. Takes precedence over --force-to-file.
+    /// Also resolve secrets ${secret:PROVIDER:ARGUMENT} with PROVIDER: `file` reads the file ARGUMENT, `cmd` runs
+    /// the command ARGUMENT. Only use it with mappings you trust. By default, only secrets in environment variables
+    /// (`env`) are resolved. Can be given more than once.
+    #[arg(long, value_name = "PROVIDER")]
+    secret_provider: Vec<SecretProviderArg>,
+
+    /// Read the sources whose path is or ends with NAME, a file name or path, from PATH instead.
+    /// Can be given more than once.
+    #[arg(long, value_name = "NAME=PATH", value_parser = parse_source_override)]
+    override_source: Vec<(String, String)>,
+
+    /// Separate the values of CSV files by CHAR, e.g. `;` or `\t`, whatever the mapping says.
+    /// Files ending in .tsv are separated by tabs unless the mapping or this option says otherwise.
+    #[arg(long, value_name = "CHAR", value_parser = parse_csv_char)]
+    csv_delimiter: Option<u8>,
+
+    /// Quote values of CSV files with CHAR instead of `"`, whatever the mapping says.
+    #[arg(long, value_name = "CHAR", value_parser = parse_csv_char, conflicts_with = "csv_no_quoting")]
+    csv_quote: Option<u8>,
+
+    /// Read quotes in CSV files as part of the values.
     #[arg(long)]
+    csv_no_quoting: bool,
+
+    /// Quotes in quoted values of CSV files can be escaped with CHAR, e.g. `\`, besides by doubling them.
+    #[arg(long, value_name = "CHAR", value_parser = parse_csv_char)]
+    csv_escape: Option<u8>,
+
+    /// Skip the lines of CSV files that start with CHAR, e.g. `#`.
+    #[arg(long, value_name = "CHAR", value_parser = parse_csv_char)]
+    csv_comment: Option<u8>,
+}
+
+// How to execute it
+#[derive(clap::Args)]
+#[command(next_help_heading = "Processing")]
+struct ProcessingArgs {
+    /// Force output to standard out, ignoring the targets in the plan.
+    #[arg(long, conflicts_with = "force_to_file")]
     force_std_out: bool,
 
+    /// With --force-std-out, start every batch of statements with a comment line naming the target
+    /// in the plan it belongs to, e.g. `# target 9: sports.nt`.
+    #[arg(long, requires = "force_std_out")]
+    tag_targets: bool,
+
     /// Force output to file, ignoring the targets in the plan.
     #[arg(long, value_name = "FILE")]
     force_to_file: Option<String>,
 
+    /// The size in bytes of the buffer that output to a file goes through. The default is 65536.
+    #[arg(long, value_name = "BYTES")]
+    write_buffer_capacity: Option<usize>,
+
+    /// Flush the output to a file at least every SECONDS, so it can be followed while mopper runs.
+    /// By default, output is only flushed when the buffer is full and at the end of the run.
+    #[arg(long, value_name = "SECONDS")]
+    flush_interval: Option<u64>,
+
+    /// Sync output files to disk at the end of the run.
+    #[arg(long)]
+    sync_on_close: bool,
+
+    /// Write output files directly. By default, output is written to a temporary file that replaces
+    /// the output file only when the run succeeds.
+    #[arg(long)]
+    write_in_place: bool,
+
+    /// Stop the run with an error when a custom, LDES, Kafka or WebSocket source takes more than SECONDS to read
+    /// a record; for a stream, this includes waiting for the next message. By default, sources are waited for
+    /// as long as they take.
+    #[arg(long, value_name = "SECONDS")]
+    source_timeout: Option<u64>,
+
+    /// Stop the run with an error when a sink takes more than SECONDS to write a batch of statements.
+    #[arg(long, value_name = "SECONDS")]
+    sink_timeout: Option<u64>,
+
+    /// Stop the run with an error when a custom, LDES, Kafka or WebSocket source, or a custom sink, takes more
+    /// than SECONDS to connect, i.e. to be created.
+    #[arg(long, value_name = "SECONDS")]
+    connect_timeout: Option<u64>,
+
     /// Set the maximum number of messages each communication channel can hold before blocking the
     /// sender thread.
     /// `0` means no messages are hold: 'send' and 'receive' must happen at the same time.
@@ -57,10 +322,262 @@ struct Args {
     #[arg(long, value_name = "N")]
     message_buffer_capacity: Option<usize>,
 
-    /// Remove duplicate triples or quads. Note that currently deduplication only works on a per-sink basis and
-    /// has a negative impact on speed and memory consumption.
+    /// Remove duplicate triples or quads, per sink or, with --deduplicate-globally, over all sinks.
+    /// Deduplication has a negative impact on speed and memory consumption.
     #[arg(short, long)]
-    deduplicate: bool
+    deduplicate: bool,
+
+    /// Keep the deduplication index in this directory, so duplicates are also removed across runs.
+    /// Implies --deduplicate.
+    #[arg(long, value_name = "DIR")]
+    deduplicate_index: Option<String>,
+
+    /// Remove duplicates over all sinks instead of per sink. Implies --deduplicate.
+    #[arg(long)]
+    deduplicate_globally: bool,
+
+    /// Remove duplicates approximately with a Bloom filter, using much less memory. Some unique triples
+    /// or quads may be dropped too: about RATE of them (e.g. 0.001). Implies --deduplicate.
+    #[arg(long, value_name = "RATE", conflicts_with_all = ["deduplicate_shards", "deduplicate_external"])]
+    deduplicate_approx: Option<f64>,
+
+    /// The number of distinct triples or quads expected when deduplicating approximately.
+    /// If more are generated, more unique ones are dropped. The default is `10000000`.
+    #[arg(long, value_name = "N", default_value_t = 10_000_000, requires = "deduplicate_approx")]
+    deduplicate_expected: usize,
+
+    /// Remove duplicates exactly, divided over N sets that are each handled by their own thread.
+    /// Faster for large outputs. Implies --deduplicate.
+    #[arg(long, value_name = "N", conflicts_with = "deduplicate_external")]
+    deduplicate_shards: Option<usize>,
+
+    /// Remove duplicates exactly with bounded memory: keep at most N triples or quads in memory and spill
+    /// the rest to sorted files on disk. The output is written at the end, sorted. Implies --deduplicate.
+    #[arg(long, value_name = "N")]
+    deduplicate_external: Option<usize>,
+
+    /// The directory to spill to with --deduplicate-external. The default is the system's temporary directory.
+    #[arg(long, value_name = "DIR", requires = "deduplicate_external")]
+    deduplicate_spill_dir: Option<String>,
+
+    /// The number of distinct triples or quads exact deduplication has room for before it has to grow.
+    /// Set it to about the number of distinct triples or quads of a large output. The default is `1024`.
+    #[arg(long, value_name = "N")]
+    deduplicate_capacity: Option<usize>,
+
+    /// How exact deduplication hashes triples and quads: with the standard hash, resistant to input crafted
+    /// to collide (the default), or with the much faster FxHash, for input you trust.
+    #[arg(long, value_name = "HASHER")]
+    deduplicate_hasher: Option<DeduplicationHasherArg>,
+
+    /// Turn deduplication on or off for the sink with node id NODE (see `mopper plan`), e.g. `9=off`,
+    /// whatever --deduplicate says. Can be given more than once.
+    #[arg(long, value_name = "NODE=on|off", value_parser = parse_sink_deduplication)]
+    deduplicate_sink: Vec<(usize, bool)>,
+
+    /// Write records that cannot be processed (e.g. because they yield an invalid IRI or cannot be parsed)
+    /// to FILE as CSV, together with the reason.
+    #[arg(long, value_name = "FILE")]
+    error_log: Option<String>,
+
+    /// Validate the generated triples or quads against the SHACL shapes in this Turtle file when the run is done,
+    /// and print the violations per mapping rule. All generated triples or quads are kept in memory for this.
+    #[arg(long, value_name = "FILE")]
+    shapes: Option<String>,
+
+    /// Write a VoID description of the generated dataset to this Turtle file when the run is done: how many
+    /// triples or quads it has, its classes and properties, and the sources it is generated from.
+    #[arg(long, value_name = "FILE")]
+    void: Option<String>,
+
+    /// Write the statistics of the generated dataset to this file when the run is done, in CSV if its extension
+    /// is csv, otherwise in JSON: triples per class and per property, distinct subjects, and literals per datatype.
+    #[arg(long, value_name = "FILE")]
+    dataset_report: Option<String>,
+
+    /// Write the PROV-O provenance of the run to this Turtle file when it is done: which output files and named
+    /// graphs it generated, from which sources and mapping documents, by which version of mopper, and when.
+    #[arg(long, value_name = "FILE")]
+    provenance: Option<String>,
+
+    /// Run the SPARQL SELECT or CONSTRUCT query in QUERY_FILE over the generated dataset when the run is done,
+    /// and write its results to OUTPUT_FILE: CSV for SELECT, N-Triples for CONSTRUCT. Only a subset of SPARQL
+    /// is supported: basic graph patterns, OPTIONAL, GRAPH, FILTER and solution modifiers. Can be given more than once.
+    #[arg(long, value_name = "QUERY_FILE=OUTPUT_FILE", value_parser = parse_query_output)]
+    query: Vec<(String, String)>,
+
+    /// What to do with a record when an IRI generated from it is invalid: skip only the statements
+    /// with the invalid IRI, skip the record and write it to the error log (the default), or stop.
+    #[arg(long, value_name = "POLICY")]
+    invalid_iri: Option<InvalidIriPolicyArg>,
+
+    /// What to do with a record when a reference has no value in it, e.g. because the source has no field
+    /// with that name: skip only the statements with the term of the reference, skip the record and write it
+    /// to the error log (the default), use an empty string, or stop.
+    #[arg(long, value_name = "POLICY")]
+    missing_reference: Option<MissingReferencePolicyArg>,
+
+    /// How values are percent-encoded when they are put in an IRI: the IRI-safe encoding of R2RML and RML,
+    /// encoding all reserved and non-ASCII characters (the default), or no encoding at all.
+    #[arg(long, value_name = "ENCODING")]
+    iri_encoding: Option<IriEncodingArg>,
+
+    /// Use encoding ENC for the IRIs of the term map with variable TERM in the plan (e.g. tm0_sm; see --explain)
+    /// instead of the one of --iri-encoding. Can be given more than once.
+    #[arg(long, value_name = "TERM=ENC", value_parser = parse_term_iri_encoding)]
+    iri_encoding_of: Vec<(String, IriEncodingArg)>,
+
+    /// Read at most N records from every source, e.g. to check the output of a mapping on a part of
+    /// a large dataset.
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+
+    /// Read only a random sample of about FRACTION (e.g. 0.01) of the records of every source, e.g. to
+    /// estimate the size of the output or find errors in a mapping quickly.
+    #[arg(long, value_name = "FRACTION")]
+    sample: Option<f64>,
+
+    /// Take the random sample of --sample with this seed, to take the same sample again.
+    /// The seed of a run is logged from log level info (-vv) on.
+    #[arg(long, value_name = "N", requires = "sample")]
+    seed: Option<u64>,
+
+    /// Read at most RATE records per second (e.g. `10/s`) or bytes per second (e.g. `500KB/s`, in B, KB or MB)
+    /// from the custom, LDES, Kafka or WebSocket source with node id NODE (see `mopper plan`), so a third-party API it reads from does
+    /// not throttle or block the run. Can be given more than once, also to limit both for the same source.
+    #[arg(long, value_name = "NODE=RATE", value_parser = parse_source_rate_limit)]
+    source_rate_limit: Vec<(usize, RateLimit)>,
+
+    /// Let at most N sources read at the same time, e.g. when executing many mappings in one run;
+    /// the others wait until one is done. By default, all sources read at the same time.
+    #[arg(long, value_name = "N")]
+    max_concurrent_sources: Option<usize>,
+
+    /// Let every source read at most N of its files at the same time, e.g. of a glob pattern; the records
+    /// of the files are then not read in order. By default, the files of a source are read one after the other.
+    #[arg(long, value_name = "N")]
+    max_concurrent_files: Option<usize>,
+
+    /// Stop at the first record that cannot be processed (e.g. because it cannot be parsed, misses values
+    /// or yields an invalid IRI), instead of skipping such records.
+    #[arg(long)]
+    strict: bool,
+}
+
+// The arguments of `mopper run`, which are also those of mopper without command
+#[derive(clap::Args)]
+#[command(next_help_heading = "Running")]
+struct RunArgs {
+    /// Log the number of records processed by every operator, and the throughput, every SECONDS seconds.
+    /// Shown from log level info (-vv) on.
+    #[arg(long, value_name = "SECONDS")]
+    log_throughput: Option<u64>,
+
+    /// Show a progress bar on standard error.
+    #[arg(long)]
+    progress: bool,
+
+    /// Print a summary of the run on standard error when done, like `mopper stats`.
+    #[arg(long)]
+    stats: bool,
+
+    /// Measure how long every operator is busy and how long it is blocked waiting for other operators,
+    /// and print a breakdown on standard error when done. Slows down execution a bit.
+    #[arg(long)]
+    benchmark: bool,
+
+    /// Write a report of the run in JSON to FILE when done: the configuration, statistics per operator,
+    /// warnings and errors.
+    #[arg(long, value_name = "FILE")]
+    report: Option<String>,
+
+    /// Write the plan as it will be executed, i.e. after merging and removing nodes, to FILE in Graphviz
+    /// DOT format before running it, like `mopper plan --format dot`.
+    #[arg(long, value_name = "FILE")]
+    dot: Option<String>,
+
+    /// Print the operators that will be executed after rewriting the plan, with their inputs and outputs
+    /// and resolved source paths, and exit without running them, like `mopper plan`.
+    #[arg(long)]
+    explain: bool,
+
+    /// Keep running, and execute the mapping again every SECONDS seconds. The mapping is read again
+    /// only when it changes.
+    #[arg(long, value_name = "SECONDS", conflicts_with = "explain")]
+    every: Option<u64>,
+
+    /// Keep running, and execute the mapping again when one of its source files changes, or when
+    /// the mapping itself changes.
+    #[arg(long, conflicts_with = "explain")]
+    watch: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    processing: ProcessingArgs
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum InvalidIriPolicyArg {
+    SkipStatement,
+    Quarantine,
+    Fail
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum MissingReferencePolicyArg {
+    SkipStatement,
+    SkipRecord,
+    Empty,
+    Fail
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum SecretProviderArg {
+    File,
+    Cmd
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum DeduplicationHasherArg {
+    Siphash,
+    Fx
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum IriEncodingArg {
+    IriSafe,
+    Uri,
+    None
+}
+
+impl From<&IriEncodingArg> for IriEncoding {
+    fn from(iri_encoding: &IriEncodingArg) -> Self {
+        match iri_encoding {
+            IriEncodingArg::IriSafe => IriEncoding::IriSafe,
+            IriEncodingArg::Uri => IriEncoding::Uri,
+            IriEncodingArg::None => IriEncoding::None
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum PlanFormatArg {
+    Json,
+    Yaml
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum PlanOutputArg {
+    Text,
+    Dot
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum LogFormatArg {
+    Text,
+    Json
 }
 
 #[derive(clap::ValueEnum, Clone, Debug)]
@@ -70,46 +587,634 @@ enum MappingLangArg {
 }
 
 fn main() {
-    let args = Args::parse();
-    
+    let args = parse_args();
+    let command = args.command.unwrap_or(Command::Run(args.run));
+
     // init logging
-    stderrlog::new()
-        .module(module_path!())
-        .quiet(args.quiet)
-        .timestamp(stderrlog::Timestamp::Second)
-        .verbosity(args.verbose as usize)
-        .init()
-        .unwrap();
-
-    // Read the execution plan
+    let default_level = match (args.quiet, args.verbose) {
+        (true, _) => LevelFilter::Off,
+        (false, 0) => LevelFilter::Error,
+        (false, 1) => LevelFilter::Warn,
+        (false, 2) => LevelFilter::Info,
+        (false, 3) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace
+    };
+    let mut log_levels = LogLevels::new(default_level);
+    for (target, level) in &args.log_level {
+        log_levels.set(target, *level);
+    }
+    let max_level = log_levels.max_level();
+    let log_format = match args.log_format {
+        LogFormatArg::Text => LogFormat::Text,
+        LogFormatArg::Json => LogFormat::Json
+    };
+    let logger: Box<dyn Log> = match (&args.log_file, log_format) {
+        (Some(log_file), _) => match LineLogger::to_file(module_path!(), max_level, log_format, log_file) {
+            Ok(logger) => Box::new(logger),
+            Err(error) => exit_with_error(format!("Cannot open log file {log_file}: {error}"))
+        },
+        (None, LogFormat::Json) => Box::new(LineLogger::new(module_path!(), max_level, log_format, Box::new(std::io::stderr()))),
+        (None, LogFormat::Text) => {
+            let color_choice = match std::io::stderr().is_terminal() {
+                true => stderrlog::ColorChoice::Auto,
+                false => stderrlog::ColorChoice::Never
+            };
+            let mut logger = stderrlog::new();
+            logger
+                .module(module_path!())
+                .quiet(max_level == LevelFilter::Off)
+                .timestamp(stderrlog::Timestamp::Second)
+                .verbosity((max_level as usize).saturating_sub(1))
+                .color(color_choice);
+            Box::new(logger)
+        }
+    };
+    let logger: Box<dyn Log> = match args.log_level.is_empty() {
+        true => logger,
+        false => Box::new(FilteringLogger::new(logger, log_levels))
+    };
+    // when writing a report, collect warnings and errors whatever the log level
+    let log_collector = match command {
+        Command::Run(RunArgs { report: Some(_), .. }) => Some(LogCollector::install(logger, max_level).unwrap()),
+        _ => {
+            log::set_boxed_logger(logger).unwrap();
+            log::set_max_level(max_level);
+            None
+        }
+    };
+
+    if let Some(config_file) = &args.config {
+        info!("Options not on the command line are read from {config_file}");
+    }
+
+    match command {
+        Command::Run(run_args) => run(&run_args, log_collector),
+        Command::Validate { input, processing } => validate(&input, &processing),
+        Command::Plan { input, processing, format, output } => show_plan(&input, &processing, &format, &output),
+        Command::Translate { to, output, input } => translate(&input, &to, &output),
+        Command::Count { input, processing } => count_statements(&input, &processing),
+        Command::Stats { input, processing, benchmark } => stats(&input, &processing, benchmark),
+        Command::TestSuite { dir, earl, earl_test_base, processing } => test_suite(&dir, &earl, &earl_test_base, &processing),
+        Command::Serve { processing, grpc, allow_server_sources } => serve_grpc(grpc, build_options(&processing_options(&processing)), allow_server_sources)
+    }
+}
+
+// Parses the command line, completed with the options from the environment and the config file
+fn parse_args() -> Args {
+    let mut command = Args::command();
+    command.build();
+    let command_line: Vec<OsString> = std::env::args_os().collect();
+    // if the command line is wrong, parsing it again tells why
+    let missing_options = match command.clone().ignore_errors(true).try_get_matches_from(&command_line) {
+        Ok(matches) => missing_options(&command, &matches).unwrap_or_else(|error| exit_with_error(error)),
+        Err(_) => Vec::new()
+    };
+    Args::parse_from(command_line.into_iter().chain(missing_options))
+}
+
+// Executes the mapping, once or again and again, and reports on it
+fn run(args: &RunArgs, log_collector: Option<&LogCollector>) {
+    let (mapping_files, mut options_builder) = input_options(&args.input, &args.processing);
+    options_builder.benchmark(args.benchmark);
+    if let Some(seconds) = args.log_throughput {
+        options_builder.throughput_log_interval(Duration::from_secs(seconds));
+    }
+    if args.progress {
+        options_builder.progress_listener(ProgressListener::new(print_progress_bar));
+    }
+    let options = build_options(&options_builder);
+
+    let inputs: Vec<&String> = mapping_files.iter().chain(&args.input.direct).collect();
+    let input_names = inputs.iter().map(|input| input.as_str()).collect::<Vec<&str>>().join(", ");
+    let mut report = RunReport::new(Some(input_names.clone()), &options);
+
+    // Read the execution plans
     info!("Reading mapping plan...");
-    let path_to_plan_serialisation = &args.mapping_file;
-    let mapping = fs::read_to_string(path_to_plan_serialisation)
-        .expect(format!("Mapping file not found: {}", args.mapping_file).as_str());
-    let plan_ser_path = PathBuf::from(path_to_plan_serialisation);
-    let mapping_parent_dir_option = plan_ser_path.parent();
+    let plans_result = read_plan_list(&args.input, &mapping_files)
+        .and_then(|plans| merged_plan(&plans).map(|final_mapping| (plans, final_mapping)));
+    let (plans, final_mapping) = match plans_result {
+        Ok(plans_and_final_mapping) => plans_and_final_mapping,
+        Err(error) => {
+            eprintln!("{}", error);
+            write_report(&mut report, &args.report, &Err(error), log_collector);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(dot_file) = &args.dot {
+        let dot_result = plan_to_dot(&final_mapping, &options)
+            .map_err(|error| error.to_string())
+            .and_then(|dot| fs::write(dot_file, dot).map_err(|error| format!("Cannot write {dot_file}: {error}")));
+        if let Err(error) = dot_result {
+            eprintln!("{}", error);
+            write_report(&mut report, &args.report, &Err(error), log_collector);
+            std::process::exit(1);
+        }
+    }
+
+    if args.explain {
+        match explain_plan(&final_mapping, &options) {
+            Ok(explanation) => {
+                print!("{explanation}");
+                return;
+            },
+            Err(error) => exit_with_error(error)
+        }
+    }
+
+    // Keep running, and report on every run. The mapping files are read again when they change.
+    if args.every.is_some() || args.watch {
+        let schedule = Schedule { interval: args.every.map(Duration::from_secs), watch_sources: args.watch };
+        let watched_mapping_files: Vec<PathBuf> = mapping_files.iter()
+            .filter(|mapping_file| *mapping_file != "-")
+            .map(PathBuf::from)
+            .collect();
+        let mut first_plan = Some(final_mapping);
+        let load_plan = || match first_plan.take() {
+            Some(plan) => Ok(plan),
+            None => read_plans(&args.input, &mapping_files).map_err(MopperError::plan)
+        };
+        let daemon_result = run_reloading_daemon(&watched_mapping_files, load_plan, &options, &schedule, |result| {
+            let result = result.map_err(|error| error.to_string());
+            let mut report = RunReport::new(Some(input_names.clone()), &options);
+            write_report(&mut report, &args.report, &result, log_collector);
+            print_result(&result, args.stats, args.benchmark);
+            ControlFlow::Continue(())
+        });
+        if let Err(error) = daemon_result {
+            exit_with_error(error);
+        }
+        return;
+    }
+
+    // Several plans are executed in one run, with statistics for each of them
+    let mut plan_stats = Vec::new();
+    let result = match plans.len() {
+        1 => start(&final_mapping, &options),
+        _ => start_plans(&plans, &options).map(|(run_stats, stats_per_plan)| {
+            plan_stats = stats_per_plan;
+            run_stats
+        })
+    }.map_err(|error| error.to_string());
+    write_report(&mut report, &args.report, &result, log_collector);
+    print_result(&result, args.stats, args.benchmark);
+    if args.stats && !plan_stats.is_empty() {
+        let plan_names = args.input.direct.iter().chain(&mapping_files);
+        eprintln!("Per mapping");
+        for (plan_name, run_stats) in plan_names.zip(&plan_stats) {
+            eprintln!("  {plan_name}: {} records read, {} statements written, {} errors",
+                      run_stats.records_read(), run_stats.statements_written(), run_stats.errors());
+        }
+    }
+    if result.is_err() {
+        std::process::exit(1);
+    }
+}
+
+// Reads and checks the mapping, and prints all problems found
+fn validate(input: &InputArgs, processing: &ProcessingArgs) {
+    let (mapping_files, options_builder) = input_options(input, processing);
+    let options = build_options(&options_builder);
+    let final_mapping = read_plans(input, &mapping_files).unwrap_or_else(|error| exit_with_error(error));
+    match validate_plan(&final_mapping, &options) {
+        Ok(()) => println!("The mapping is valid."),
+        Err(error) => exit_with_error(error)
+    }
+}
+
+// Prints the plan as it will be executed, or writes it to the output file
+fn show_plan(input: &InputArgs, processing: &ProcessingArgs, format: &PlanOutputArg, output: &Option<String>) {
+    let (mapping_files, options_builder) = input_options(input, processing);
+    let options = build_options(&options_builder);
+    let final_mapping = read_plans(input, &mapping_files).unwrap_or_else(|error| exit_with_error(error));
+    let plan = match format {
+        PlanOutputArg::Text => explain_plan(&final_mapping, &options),
+        PlanOutputArg::Dot => plan_to_dot(&final_mapping, &options)
+    };
+    let plan = plan.unwrap_or_else(|error| exit_with_error(error));
+    write_output(output, &plan);
+}
+
+// Translates the mappings to one plan, and prints it or writes it to the output file
+fn translate(input: &InputArgs, to: &PlanFormatArg, output: &Option<String>) {
+    let mapping_files = find_mapping_files(&input.mapping_file, &input.mapping_lang)
+        .unwrap_or_else(|error| exit_with_error(error));
+    let final_mapping = read_plans(input, &mapping_files).unwrap_or_else(|error| exit_with_error(error));
+    let plan = match to {
+        PlanFormatArg::Json => pretty_json(&final_mapping),
+        PlanFormatArg::Yaml => json_to_yaml(&final_mapping)
+    };
+    let plan = plan.unwrap_or_else(|error| exit_with_error(error));
+    write_output(output, &plan);
+}
+
+fn write_output(output: &Option<String>, content: &str) {
+    match output {
+        Some(output_file) => if let Err(error) = fs::write(output_file, content) {
+            exit_with_error(format!("Cannot write {output_file}: {error}"));
+        },
+        None => print!("{content}")
+    }
+}
+
+// Executes the mapping, discarding the generated statements, and prints how many every target gets
+fn count_statements(input: &InputArgs, processing: &ProcessingArgs) {
+    let (mapping_files, options_builder) = input_options(input, processing);
+    let options = build_options(&options_builder);
+    let final_mapping = read_plans(input, &mapping_files).unwrap_or_else(|error| exit_with_error(error));
+    let (run_stats, target_counts) = count(&final_mapping, &options).unwrap_or_else(|error| exit_with_error(error));
+    for target_count in target_counts {
+        println!("{target_count}");
+    }
+    println!("Total: {} statements", run_stats.statements_written());
+}
+
+// Executes all test cases in the directory, and prints the outcome of every one of them
+fn test_suite(dir: &str, earl: &Option<String>, earl_test_base: &str, processing: &ProcessingArgs) {
+    let options = build_options(&processing_options(processing));
+    let report = run_test_suite(dir, &options).unwrap_or_else(|error| exit_with_error(error));
+    println!("{report}");
+    if let Some(earl_file) = earl {
+        fs::write(earl_file, report.to_earl(earl_test_base))
+            .unwrap_or_else(|error| exit_with_error(format!("Cannot write EARL report to {earl_file}: {error}")));
+    }
+    if !report.all_passed() {
+        std::process::exit(1);
+    }
+}
+
+// Executes the mapping, discarding the generated statements, and prints the summary of the run
+fn stats(input: &InputArgs, processing: &ProcessingArgs, benchmark: bool) {
+    let (mapping_files, mut options_builder) = input_options(input, processing);
+    let options = build_options(options_builder.benchmark(benchmark));
+    let final_mapping = read_plans(input, &mapping_files).unwrap_or_else(|error| exit_with_error(error));
+    let result = count(&final_mapping, &options)
+        .map(|(run_stats, _target_counts)| run_stats)
+        .map_err(|error| error.to_string());
+    print_result(&result, true, benchmark);
+    if result.is_err() {
+        std::process::exit(1);
+    }
+}
+
+fn exit_with_error(error: impl Display) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(1);
+}
+
+fn build_options(options_builder: &MopperOptionsBuilder) -> MopperOptions {
+    options_builder.build()
+        .unwrap_or_else(|error| exit_with_error(format!("Invalid options: {error}")))
+}
+
+// Finds the mapping files, and sets the options for them: directories contain mapping files
+fn input_options(input: &InputArgs, processing: &ProcessingArgs) -> (Vec<String>, MopperOptionsBuilder) {
+    let mapping_files = find_mapping_files(&input.mapping_file, &input.mapping_lang)
+        .unwrap_or_else(|error| exit_with_error(error));
+    let mut options_builder = processing_options(processing);
+    // relative source paths of more than one mapping are resolved when merging them
+    if let [mapping_file] = mapping_files.as_slice() {
+        if let Some(parent_dir) = parent_dir(mapping_file) {
+            options_builder.working_dir_hint(parent_dir);
+        }
+    }
+    options_builder.mapping_documents(mapping_files.iter()
+        .filter(|mapping_file| *mapping_file != "-")
+        .cloned()
+        .collect());
+    options_builder.mapping_from_std_in(mapping_files.iter().any(|mapping_file| mapping_file == "-"));
+    if !input.param.is_empty() {
+        options_builder.params(input.param.iter().cloned().collect());
+    }
+    if !input.secret_provider.is_empty() {
+        options_builder.credentials(input.secret_provider.iter()
+            .fold(Credentials::default(), |credentials, provider| match provider {
+                SecretProviderArg::File => credentials.with_provider("file", FileProvider),
+                SecretProviderArg::Cmd => credentials.with_provider("cmd", CommandProvider)
+            }));
+    }
+    if let Some(base_iri) = &input.base_iri {
+        options_builder.base_iri(base_iri);
+    }
+    if !input.override_source.is_empty() {
+        options_builder.source_overrides(input.override_source.clone());
+    }
+    options_builder.csv_dialect(csv_dialect(input));
+    (mapping_files, options_builder)
+}
 
-    // set options
+// Sets the options on how to execute mappings
+fn processing_options(args: &ProcessingArgs) -> MopperOptionsBuilder {
     let mut options_builder = MopperOptionsBuilder::default();
-    if let Some(forced_output_file) = args.force_to_file {
+    if let Some(forced_output_file) = &args.force_to_file {
         options_builder.force_to_file(forced_output_file);
     }
     options_builder
         .force_to_std_out(args.force_std_out)
-        .deduplicate(args.deduplicate);
-    if let Some(mapping_parent_dir) = mapping_parent_dir_option {
-        let parent_dir = mapping_parent_dir.to_str().unwrap();
-        if !parent_dir.is_empty() {
-            options_builder.working_dir_hint(parent_dir);
-        }
+        .tag_targets(args.tag_targets)
+        .sync_on_close(args.sync_on_close)
+        .write_in_place(args.write_in_place)
+        .error_mode(if args.strict { ErrorMode::Strict } else { ErrorMode::Lenient })
+        .deduplicate(args.deduplicate || args.deduplicate_index.is_some() || args.deduplicate_globally
+            || args.deduplicate_approx.is_some() || args.deduplicate_shards.is_some() || args.deduplicate_external.is_some())
+        .deduplicate_globally(args.deduplicate_globally);
+    if let Some(false_positive_rate) = args.deduplicate_approx {
+        options_builder.deduplication_strategy(DeduplicationStrategy::Approximate {
+            false_positive_rate,
+            expected_statements: args.deduplicate_expected
+        });
+    } else if let Some(shards) = args.deduplicate_shards {
+        options_builder.deduplication_strategy(DeduplicationStrategy::Sharded { shards });
+    } else if let Some(max_in_memory) = args.deduplicate_external {
+        options_builder.deduplication_strategy(DeduplicationStrategy::ExternalSort {
+            max_in_memory,
+            spill_dir: args.deduplicate_spill_dir.clone()
+        });
+    }
+    if let Some(capacity) = args.deduplicate_capacity {
+        options_builder.deduplication_capacity(capacity);
+    }
+    if let Some(hasher) = &args.deduplicate_hasher {
+        options_builder.deduplication_hasher(match hasher {
+            DeduplicationHasherArg::Siphash => DeduplicationHasher::SipHash,
+            DeduplicationHasherArg::Fx => DeduplicationHasher::Fx
+        });
+    }
+    for sink_deduplication in &args.deduplicate_sink {
+        options_builder.deduplicate_sink(*sink_deduplication);
+    }
+    if let Some(invalid_iri) = &args.invalid_iri {
+        options_builder.invalid_iri_policy(match invalid_iri {
+            InvalidIriPolicyArg::SkipStatement => InvalidIriPolicy::SkipStatement,
+            InvalidIriPolicyArg::Quarantine => InvalidIriPolicy::Quarantine,
+            InvalidIriPolicyArg::Fail => InvalidIriPolicy::Fail
+        });
+    }
+    if let Some(missing_reference) = &args.missing_reference {
+        options_builder.missing_reference_policy(match missing_reference {
+            MissingReferencePolicyArg::SkipStatement => MissingReferencePolicy::SkipStatement,
+            MissingReferencePolicyArg::SkipRecord => MissingReferencePolicy::SkipRecord,
+            MissingReferencePolicyArg::Empty => MissingReferencePolicy::Empty,
+            MissingReferencePolicyArg::Fail => MissingReferencePolicy::Fail
+        });
+    }
+    if let Some(iri_encoding) = &args.iri_encoding {
+        options_builder.iri_encoding(iri_encoding.into());
+    }
+    if !args.iri_encoding_of.is_empty() {
+        options_builder.term_iri_encodings(args.iri_encoding_of.iter()
+            .map(|(term, iri_encoding)| (term.trim_start_matches('?').to_string(), iri_encoding.into()))
+            .collect());
+    }
+    if let Some(error_log) = &args.error_log {
+        options_builder.error_log_file(error_log);
+    }
+    if let Some(shapes) = &args.shapes {
+        options_builder.shacl_shapes_file(shapes);
+    }
+    if let Some(void) = &args.void {
+        options_builder.void_file(void);
+    }
+    if let Some(dataset_report) = &args.dataset_report {
+        options_builder.dataset_report_file(dataset_report);
+    }
+    if let Some(provenance) = &args.provenance {
+        options_builder.provenance_file(provenance);
+    }
+    for query_output in &args.query {
+        options_builder.sparql_query(query_output.clone());
+    }
+    if let Some(deduplicate_index) = &args.deduplicate_index {
+        options_builder.deduplicate_index_dir(deduplicate_index);
+    }
+    if let Some(record_limit) = args.limit {
+        options_builder.record_limit(record_limit);
+    }
+    if let Some(sample_fraction) = args.sample {
+        options_builder.sample_fraction(sample_fraction);
+    }
+    if let Some(sample_seed) = args.seed {
+        options_builder.sample_seed(sample_seed);
+    }
+    let mut source_rate_limits: HashMap<usize, RateLimit> = HashMap::new();
+    for (node_id, rate_limit) in &args.source_rate_limit {
+        source_rate_limits.entry(*node_id).or_default().merge(rate_limit);
+    }
+    for source_rate_limit in source_rate_limits {
+        options_builder.source_rate_limit(source_rate_limit);
+    }
+    if let Some(max_concurrent_sources) = args.max_concurrent_sources {
+        options_builder.max_concurrent_sources(max_concurrent_sources);
+    }
+    if let Some(max_concurrent_files) = args.max_concurrent_files {
+        options_builder.max_concurrent_files(max_concurrent_files);
     }
     if let Some(buffer_capacity) = args.message_buffer_capacity {
         options_builder.message_buffer_capacity(buffer_capacity);
     }
-    let options = options_builder.build().unwrap();
+    if let Some(write_buffer_capacity) = args.write_buffer_capacity {
+        options_builder.write_buffer_capacity(write_buffer_capacity);
+    }
+    if let Some(seconds) = args.flush_interval {
+        options_builder.flush_interval(Duration::from_secs(seconds));
+    }
+    if let Some(seconds) = args.source_timeout {
+        options_builder.source_read_timeout(Duration::from_secs(seconds));
+    }
+    if let Some(seconds) = args.sink_timeout {
+        options_builder.sink_write_timeout(Duration::from_secs(seconds));
+    }
+    if let Some(seconds) = args.connect_timeout {
+        options_builder.connect_timeout(Duration::from_secs(seconds));
+    }
+    options_builder
+}
+
+// Prints the error of a run, or its summary and timing if asked for
+fn print_result(result: &Result<RunStats, String>, stats: bool, benchmark: bool) {
+    match result {
+        Ok(run_stats) => {
+            if stats {
+                eprintln!("{run_stats}");
+            }
+            if benchmark {
+                eprintln!("{}", run_stats.timing_report());
+            }
+            if let Some(validation) = &run_stats.validation {
+                eprintln!("{validation}");
+            }
+        },
+        Err(error) => eprintln!("{}", error)
+    }
+}
+
+#[cfg(feature = "grpc")]
+fn serve_grpc(address: SocketAddr, options: MopperOptions, server_sources: bool) {
+    if let Err(error) = mopper::grpc::serve(address, options, server_sources) {
+        exit_with_error(error);
+    }
+}
+
+#[cfg(not(feature = "grpc"))]
+fn serve_grpc(_address: SocketAddr, _options: MopperOptions, _server_sources: bool) {
+    eprintln!("This mopper is built without the gRPC service; build it with `--features grpc`.");
+    std::process::exit(1);
+}
+
+fn parse_param(param: &str) -> Result<(String, String), String> {
+    match param.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("'{param}' is not of the form KEY=VALUE"))
+    }
+}
+
+fn parse_term_iri_encoding(term_iri_encoding: &str) -> Result<(String, IriEncodingArg), String> {
+    match term_iri_encoding.split_once('=') {
+        Some((term, iri_encoding)) if !term.is_empty() => {
+            let iri_encoding = IriEncodingArg::from_str(iri_encoding, true)?;
+            Ok((term.to_string(), iri_encoding))
+        },
+        _ => Err(format!("'{term_iri_encoding}' is not of the form TERM=ENC"))
+    }
+}
+
+fn parse_sink_deduplication(sink_deduplication: &str) -> Result<(usize, bool), String> {
+    let parsed = sink_deduplication.split_once('=').and_then(|(node_id, on_off)| {
+        let deduplicate = match on_off {
+            "on" => true,
+            "off" => false,
+            _ => return None
+        };
+        Some((node_id.parse().ok()?, deduplicate))
+    });
+    parsed.ok_or_else(|| format!("'{sink_deduplication}' is not of the form NODE=on or NODE=off"))
+}
 
+fn parse_source_rate_limit(source_rate_limit: &str) -> Result<(usize, RateLimit), String> {
+    let parsed = source_rate_limit.split_once('=').and_then(|(node_id, rate)| {
+        let node_id = node_id.parse().ok()?;
+        let amount = rate.strip_suffix("/s")?;
+        let units = [("MB", 1_000_000), ("KB", 1_000), ("B", 1)];
+        let rate_limit = match units.iter().find_map(|(unit, factor)| Some((amount.strip_suffix(unit)?, factor))) {
+            Some((bytes, factor)) => RateLimit { records_per_second: None, bytes_per_second: Some(bytes.trim().parse::<u64>().ok()? * factor) },
+            None => RateLimit { records_per_second: Some(amount.trim().parse().ok()?), bytes_per_second: None }
+        };
+        Some((node_id, rate_limit))
+    });
+    parsed.ok_or_else(|| format!("'{source_rate_limit}' is not of the form NODE=N/s, or NODE=NKB/s with B, KB or MB"))
+}
 
-    let final_mapping = match args.mapping_lang {
+fn parse_query_output(query_output: &str) -> Result<(String, String), String> {
+    match query_output.split_once('=') {
+        Some((query_file, output_file)) if !query_file.is_empty() && !output_file.is_empty() =>
+            Ok((query_file.to_string(), output_file.to_string())),
+        _ => Err(format!("'{query_output}' is not of the form QUERY_FILE=OUTPUT_FILE"))
+    }
+}
+
+fn parse_log_level(log_level: &str) -> Result<(String, LevelFilter), String> {
+    match log_level.split_once('=') {
+        Some((target, level)) if !target.is_empty() => {
+            let level = LevelFilter::from_str(level)
+                .map_err(|_| format!("'{level}' is not a log level; use off, error, warn, info, debug or trace"))?;
+            Ok((target.to_string(), level))
+        },
+        _ => Err(format!("'{log_level}' is not of the form TARGET=LEVEL"))
+    }
+}
+
+fn parse_source_override(source_override: &str) -> Result<(String, String), String> {
+    match source_override.split_once('=') {
+        Some((name_or_path, new_path)) if !name_or_path.is_empty() && !new_path.is_empty() =>
+            Ok((name_or_path.to_string(), new_path.to_string())),
+        _ => Err(format!("'{source_override}' is not of the form NAME=PATH"))
+    }
+}
+
+// Returns the given mapping files, with directories replaced by the mapping files in them, sorted by name.
+// The mapping files in a directory are recognized by their extension.
+fn find_mapping_files(paths: &[String], mapping_lang: &Option<MappingLangArg>) -> Result<Vec<String>, String> {
+    let extensions: &[&str] = match mapping_lang {
+        Some(MappingLangArg::RML) => &["ttl", "rml"],
+        Some(MappingLangArg::SHEXML) => &["shexml"],
+        None => &["json", "yaml", "yml"]
+    };
+    let mut mapping_files = Vec::new();
+    for path in paths {
+        if !Path::new(path).is_dir() {
+            mapping_files.push(path.clone());
+            continue;
+        }
+        let entries = fs::read_dir(path)
+            .map_err(|error| format!("Cannot read mapping directory {path}: {error}"))?;
+        let mut dir_mapping_files: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|entry_path| entry_path.is_file())
+            .filter(|entry_path| entry_path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| extensions.contains(&extension)))
+            .map(|entry_path| entry_path.to_string_lossy().to_string())
+            .collect();
+        if dir_mapping_files.is_empty() {
+            return Err(format!("No mapping files found in directory {path}"));
+        }
+        dir_mapping_files.sort();
+        mapping_files.extend(dir_mapping_files);
+    }
+    Ok(mapping_files)
+}
+
+// The CSV dialect the options override
+fn csv_dialect(args: &InputArgs) -> CsvDialect {
+    CsvDialect {
+        delimiter: args.csv_delimiter,
+        quote: args.csv_quote,
+        quoting: args.csv_no_quoting.then_some(false),
+        escape: args.csv_escape,
+        comment: args.csv_comment
+    }
+}
+
+// Reads the mappings and CSV files to map directly as one AlgeMapLoom plan in JSON.
+fn read_plans(args: &InputArgs, mapping_files: &[String]) -> Result<String, String> {
+    read_plan_list(args, mapping_files).and_then(|plans| merged_plan(&plans))
+}
+
+// The plan, or the plans merged into one
+fn merged_plan(plans: &[(String, Option<String>)]) -> Result<String, String> {
+    match plans {
+        [(plan, _)] => Ok(plan.clone()),
+        _ => merge_plans(plans).map_err(|error| error.to_string())
+    }
+}
+
+// Reads the plans of the direct mappings and the mapping files, in that order, with the directory of their mapping file
+fn read_plan_list(args: &InputArgs, mapping_files: &[String]) -> Result<Vec<(String, Option<String>)>, String> {
+    let mut plans: Vec<(String, Option<String>)> = Vec::with_capacity(mapping_files.len() + args.direct.len());
+    for csv_file in &args.direct {
+        let plan = direct_mapping_to_plan(csv_file, args.base_iri.as_deref().unwrap_or(DEFAULT_DIRECT_BASE_IRI), &csv_dialect(args))
+            .map_err(|error| error.to_string())?;
+        plans.push((plan, None));
+    }
+    for mapping_file in mapping_files {
+        let plan = read_plan(mapping_file, &args.mapping_lang, &args.plan_format)?;
+        plans.push((plan, parent_dir(mapping_file)));
+    }
+    Ok(plans)
+}
+
+// Reads the mapping, from standard input if the file is `-`, and returns it as an AlgeMapLoom plan in JSON.
+fn read_plan(mapping_file: &str, mapping_lang: &Option<MappingLangArg>, plan_format: &Option<PlanFormatArg>) -> Result<String, String> {
+    let mapping = if mapping_file == "-" {
+        std::io::read_to_string(std::io::stdin())
+            .map_err(|error| format!("Cannot read mapping from standard input: {error}"))?
+    } else {
+        fs::read_to_string(mapping_file)
+            .map_err(|error| format!("Cannot read mapping file {mapping_file}: {error}"))?
+    };
+
+    match mapping_lang {
 
         // If the mapping language option is set, first translate RML or ShExML to AlgeMapLoom
         Some(mapping_lang_arg) => {
@@ -117,21 +1222,62 @@ fn main() {
                 MappingLangArg::RML => MappingLang::RML,
                 MappingLangArg::SHEXML => MappingLang::SHEXML
             };
-            match mapping_to_plan(&mapping, mapping_lang) {
-                Ok(algemap_loom_plan) => algemap_loom_plan,
-                Err(error) => {
-                    eprintln!("{}", error);
-                    std::process::exit(1);
+            mapping_to_plan(&mapping, mapping_lang)
+                .map_err(|error| error.to_string())
+        }
+
+        // no flag set: an AlgeMapLoom plan, in JSON or YAML
+        None => {
+            let plan_format = plan_format.clone().unwrap_or_else(|| {
+                match Path::new(mapping_file).extension().and_then(|extension| extension.to_str()) {
+                    Some("yaml") | Some("yml") => PlanFormatArg::Yaml,
+                    _ => PlanFormatArg::Json
                 }
+            });
+            match plan_format {
+                PlanFormatArg::Json => Ok(mapping),
+                PlanFormatArg::Yaml => yaml_to_json(&mapping)
+                    .map_err(|error| error.to_string())
             }
         }
+    }
+}
 
-        // no flag set
-        None => mapping
-    };
+// The directory of the mapping file, if it is not the current one.
+fn parent_dir(mapping_file: &str) -> Option<String> {
+    let parent_dir = Path::new(mapping_file).parent()?.to_str()?;
+    match parent_dir.is_empty() {
+        true => None,
+        false => Some(parent_dir.to_string())
+    }
+}
 
-    if let Err(error) = start(&final_mapping, &options) {
-        eprintln!("{}", error);
-        std::process::exit(1);
+fn write_report(report: &mut RunReport, report_file: &Option<String>, result: &Result<RunStats, String>, log_collector: Option<&LogCollector>) {
+    if let Some(report_file) = report_file {
+        report.finish(result, log_collector);
+        if let Err(error) = report.write(report_file) {
+            eprintln!("Cannot write report to {report_file}: {error}");
+        }
+    }
+}
+
+fn print_progress_bar(progress: &Progress) {
+    const WIDTH: usize = 40;
+    let bar = match progress.fraction() {
+        Some(fraction) => {
+            let done = (fraction * WIDTH as f64) as usize;
+            format!("[{}{}] {:>3.0}%", "=".repeat(done), " ".repeat(WIDTH - done), fraction * 100.0)
+        },
+        None => format!("[{}]   ?%", " ".repeat(WIDTH))
+    };
+    let records = match progress.estimated_records_total() {
+        Some(total) => format!("{} / ~{} records", progress.records_read, total),
+        None => format!("{} records", progress.records_read)
+    };
+    let mut stderr = std::io::stderr();
+    let _ = write!(stderr, "\r{bar} {records} ({:.0}s)", progress.elapsed.as_secs_f64());
+    if progress.finished {
+        let _ = writeln!(stderr);
     }
+    let _ = stderr.flush();
 }
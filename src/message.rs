@@ -0,0 +1,47 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! The messages operators send each other over their channels.
+
+/// A message from one operator to the next. An operator first sends a [Message::Schema], then a
+/// [Message::Data] per record, and [Message::End] when it has sent all its records. An operator that
+/// stops early, e.g. because it failed, drops its channels without sending [Message::End].
+///
+/// A channel can be shared by several senders, e.g. the serializers writing to the same sink, so a
+/// receiver is only done when the channel is disconnected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    /// The names of the values in the records that follow, and their types if they are known,
+    /// e.g. `iri` or `lit` for the values an extend operator generates.
+    Schema {
+        names: Vec<String>,
+        types: Option<Vec<String>>
+    },
+
+    /// The values of one record, in the order of the schema. Serializers send the statements
+    /// generated from a record, one per line, as the only value.
+    Data(Vec<String>),
+
+    /// The sender has sent all its records.
+    End
+}
+
+impl Message {
+    /// A schema of values without known types.
+    pub fn schema(names: Vec<String>) -> Self {
+        Message::Schema { names, types: None }
+    }
+}
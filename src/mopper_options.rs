@@ -13,20 +13,182 @@
  *    See the License for the specific language governing permissions and
  *    limitations under the License.
  */
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use operator::{Source, Target};
+use serde::Serialize;
+use crate::secrets::Credentials;
+use crate::sink::{sink_key, SinkFactory};
+use crate::source::{builtin_source_factory, source_key, SourceFactory};
+use crate::source::csv_file::CsvDialect;
+use crate::source::rate_limit::RateLimit;
+use crate::stats::ProgressListener;
 
-#[derive(Default, Builder, Debug)]
+/// How duplicate statements are detected when deduplicating.
+#[derive(Default, Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum DeduplicationStrategy {
+    /// Remember every statement. Exact, but memory grows with the number of distinct statements.
+    #[default]
+    Exact,
+
+    /// Use a Bloom filter with a fixed size. Uses much less memory, but some unique statements
+    /// can be considered duplicates and dropped: about `false_positive_rate` of them, as long as the
+    /// number of distinct statements stays below `expected_statements`.
+    Approximate {
+        false_positive_rate: f64,
+        expected_statements: usize
+    },
+
+    /// Exact, but the statements are divided over `shards` sets, each one handled by its own thread.
+    /// Faster than `Exact` for large outputs.
+    Sharded {
+        shards: usize
+    },
+
+    /// Exact with bounded memory: at most `max_in_memory` statements are kept in memory, the rest is
+    /// sorted and spilled to files in `spill_dir` (or the system's temporary directory). The output is only
+    /// written when all data is processed, in sorted order. Not supported when deduplicating globally.
+    ExternalSort {
+        max_in_memory: usize,
+        spill_dir: Option<String>
+    }
+}
+
+/// How exact deduplication hashes statements to find them in its set.
+#[derive(Default, Clone, Debug, PartialEq, Serialize)]
+pub enum DeduplicationHasher {
+    /// The hash of the standard library (SipHash) with random keys. Resistant to statements crafted
+    /// to collide, which would make deduplication very slow.
+    #[default]
+    SipHash,
+
+    /// FxHash, a much faster hash for short keys such as statements. Use it for data you trust.
+    Fx
+}
+
+/// What to do with a record when a reference has no value in it, e.g. because the source has
+/// no field with that name. Only applies in [ErrorMode::Lenient]; in strict mode a missing value
+/// stops the run.
+#[derive(Default, Clone, Debug, PartialEq, Serialize)]
+pub enum MissingReferencePolicy {
+    /// Leave out the term with the reference, and so the statements with that term; the other
+    /// statements generated from the record are kept.
+    SkipStatement,
+
+    /// Skip the whole record, and write it to the error log, if any.
+    #[default]
+    SkipRecord,
+
+    /// Use an empty string as value of the reference.
+    Empty,
+
+    /// Stop the run with an error.
+    Fail
+}
+
+/// How values are percent-encoded when they are put in an IRI, e.g. by a template.
+#[derive(Default, Clone, Debug, PartialEq, Serialize)]
+pub enum IriEncoding {
+    /// The IRI-safe encoding of R2RML and RML: every character that is not an unreserved IRI
+    /// character is percent-encoded. Non-ASCII letters are kept; `/` and `:` are encoded.
+    IriSafe,
+
+    /// Percent-encode reserved characters and all non-ASCII characters.
+    #[default]
+    Uri,
+
+    /// Keep values as they are, so e.g. `/` and `:` in values end up in the IRI.
+    None
+}
+
+/// How records that cannot be processed are handled, e.g. because they cannot be parsed, miss
+/// values or yield invalid IRIs.
+#[derive(Default, Clone, Debug, PartialEq, Serialize)]
+pub enum ErrorMode {
+    /// Stop the run at the first record that cannot be processed.
+    Strict,
+
+    /// Skip the records that cannot be processed, count them, and write them to the error log, if any.
+    #[default]
+    Lenient
+}
+
+/// What to do with a record when an IRI generated from it is invalid.
+/// Only applies in [ErrorMode::Lenient]; in strict mode an invalid IRI stops the run.
+#[derive(Default, Clone, Debug, PartialEq, Serialize)]
+pub enum InvalidIriPolicy {
+    /// Skip only the statements with the invalid IRI; the other statements generated from
+    /// the record are kept.
+    SkipStatement,
+
+    /// Skip the whole record, and write it to the error log, if any.
+    #[default]
+    Quarantine,
+
+    /// Stop the run with an error.
+    Fail
+}
+
+/// The options of a run. Building them fails if they cannot work together, e.g. if output is forced
+/// both to standard out and to a file, or if the file to force output to cannot be written.
+#[derive(Default, Builder, Debug, Clone)]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct MopperOptions {
     
-    /// Ignore sink configurations and force output to standard out, unless force_to_file is set.
+    /// Ignore sink configurations and force output to standard out. Cannot be combined with force_to_file.
     #[builder(default="false", setter(strip_option))]
     force_to_std_out: bool,
 
-    /// Ignore sink configurations and force output to file. Overrides force_to_std_out.
+    /// When output is forced to standard out, keep the targets apart and put a comment line naming the
+    /// target, `# target <node id>`, in front of every batch of its statements.
+    #[builder(default="false", setter(strip_option))]
+    tag_targets: bool,
+
+    /// Ignore sink configurations and force output to file. Cannot be combined with force_to_std_out.
     #[builder(setter(into, strip_option), default="None")]
     force_to_file: Option<String>,
 
+    /// The size in bytes of the buffer that output to a file goes through. Larger buffers mean fewer writes.
+    #[builder(default="64 * 1024")]
+    write_buffer_capacity: usize,
+
+    /// Flush the buffered output to a file at most this often, so it can be followed while mopper runs.
+    /// If not set, the buffer is only flushed when it is full and at the end of the run.
+    #[builder(setter(strip_option), default="None")]
+    flush_interval: Option<Duration>,
+
+    /// Sync output files to disk at the end of the run, so the output survives a crash of the system
+    /// once the run has finished, at the cost of waiting for the disk.
+    #[builder(default="false", setter(strip_option))]
+    sync_on_close: bool,
+
+    /// Write output files directly instead of to a temporary file that replaces the output file when
+    /// the run succeeds. Useful to follow the output while mopper runs, but a failed run leaves a
+    /// partial output file behind.
+    #[builder(default="false", setter(strip_option))]
+    write_in_place: bool,
+
+    /// Fail a custom, LDES, Kafka or WebSocket source that takes longer than this to read a record, or its fields,
+    /// instead of waiting for it. For a stream, this includes waiting for the next message or poll.
+    #[builder(setter(strip_option), default="None")]
+    source_read_timeout: Option<Duration>,
+
+    /// Fail a sink that takes longer than this to write a batch of statements, or to finish, instead of waiting for it.
+    #[builder(setter(strip_option), default="None")]
+    sink_write_timeout: Option<Duration>,
+
+    /// Fail a custom, LDES, Kafka or WebSocket source, or a custom sink, that takes longer than this to be created,
+    /// which is when it connects to a remote system, instead of waiting for it.
+    #[builder(setter(strip_option), default="None")]
+    connect_timeout: Option<Duration>,
+
     /// Set the working directory virtually to this path.
     /// This is used by file sources to search for files relative to this path. 
+    /// It can also be a `file://` URI, or the `http(s)://` URL of the directory of a remote mapping document,
+    /// against which relative paths of sources are resolved as URLs to read the files from.
     #[builder(setter(into, strip_option), default="None")]
     working_dir_hint: Option<String>,
 
@@ -35,19 +197,331 @@ pub struct MopperOptions {
     #[builder(default="128")]
     message_buffer_capacity: usize,
 
-    /// Remove duplicate triples or quads. Note that currently deduplication only works on a per-sink basis and
-    /// has a negative impact on speed and memory consumption.
+    /// Remove duplicate triples or quads, per sink or, with `deduplicate_globally`, over all sinks.
+    /// Deduplication has a negative impact on speed and memory consumption.
     #[builder(default="false", setter(strip_option))]
     deduplicate: bool,
+
+    /// Keep the deduplication index of every sink in this directory, so duplicates are also removed
+    /// across runs. Only used when `deduplicate` is set, and only supported with [DeduplicationStrategy::Exact].
+    /// The index files are named after the node id of the sink, so the plan should not change between runs.
+    #[builder(setter(into, strip_option), default="None")]
+    deduplicate_index_dir: Option<String>,
+
+    /// Deduplicate over all sinks instead of per sink, so a statement ending up in several sinks
+    /// is only written once. Only used when `deduplicate` is set.
+    #[builder(default="false", setter(strip_option))]
+    deduplicate_globally: bool,
+
+    /// How duplicates are detected. Only used when `deduplicate` is set.
+    #[builder(default="DeduplicationStrategy::Exact")]
+    deduplication_strategy: DeduplicationStrategy,
+
+    /// The number of distinct statements exact deduplication has room for before its set has to grow.
+    /// Setting it to about the number of distinct statements of a large output avoids growing the set
+    /// again and again. Not used by approximate and external sort deduplication.
+    #[builder(default="1024")]
+    deduplication_capacity: usize,
+
+    /// How exact deduplication hashes statements.
+    #[builder(default="DeduplicationHasher::SipHash")]
+    deduplication_hasher: DeduplicationHasher,
+
+    /// Turns deduplication on or off for single sinks, by their node id in the rewritten plan,
+    /// whatever `deduplicate` says.
+    #[builder(default="HashMap::new()", setter(each(name = "deduplicate_sink")))]
+    sink_deduplication: HashMap<usize, bool>,
+
+    /// Log the number of records processed by every operator, and the throughput, at this interval.
+    /// Logged at level `info`.
+    #[builder(setter(strip_option), default="None")]
+    throughput_log_interval: Option<Duration>,
+
+    /// Gets called with the progress of the run every `progress_interval`, and once more when done.
+    #[builder(setter(strip_option), default="None")]
+    progress_listener: Option<ProgressListener>,
+
+    /// How often the progress listener gets called.
+    #[builder(default="Duration::from_secs(1)")]
+    progress_interval: Duration,
+
+    /// Read at most this many records from every source, e.g. to check the output of a mapping on a part
+    /// of a large dataset. The run ends as usual when all sources are done.
+    #[builder(setter(strip_option), default="None")]
+    record_limit: Option<usize>,
+
+    /// Send only this fraction of the records of every source, more than 0 and at most 1, chosen at random,
+    /// e.g. to estimate the size of the output or to find errors in a mapping quickly.
+    #[builder(setter(strip_option), default="None")]
+    sample_fraction: Option<f64>,
+
+    /// The seed of the random choice of records when sampling, so a sample can be taken again.
+    /// If not set, every run takes another sample.
+    #[builder(setter(strip_option), default="None")]
+    sample_seed: Option<u64>,
+
+    /// Limits the rate at which custom, LDES, Kafka and WebSocket sources read records, by their node id in the rewritten plan,
+    /// e.g. so reading from a third-party API does not trip its throttling.
+    #[builder(default="HashMap::new()", setter(each(name = "source_rate_limit")))]
+    source_rate_limits: HashMap<usize, RateLimit>,
+
+    /// Let at most this many sources read at the same time, so a run of many mappings at once does not
+    /// read all their sources in parallel. The other sources wait until one is done.
+    #[builder(setter(strip_option), default="None")]
+    max_concurrent_sources: Option<usize>,
+
+    /// Let every source of more than one file, e.g. of a glob pattern, read at most this many of its files at
+    /// the same time, so a disk is not overwhelmed. With more than one, the records of the files are not read in order.
+    #[builder(default="1")]
+    max_concurrent_files: usize,
+
+    /// Measure the time every operator is blocked, waiting for other operators, besides the total time
+    /// it is running. This makes it possible to tell which operator is the bottleneck, at the cost of
+    /// some overhead. See [crate::stats::RunStats::timing_report].
+    #[builder(default="false", setter(strip_option))]
+    benchmark: bool,
+
+    /// Write records that cannot be processed (e.g. because they yield an invalid IRI or cannot be parsed)
+    /// to this CSV file, together with the reason. See [crate::error_log::ErrorLog].
+    #[builder(setter(into, strip_option), default="None")]
+    error_log_file: Option<String>,
+
+    /// Validate the generated statements against the SHACL shapes in this Turtle file when the run is done,
+    /// and report the violations per mapping rule in [crate::stats::RunStats::validation]. All generated
+    /// statements are kept in memory for this. See [crate::shacl] for the supported constraints.
+    #[builder(setter(into, strip_option), default="None")]
+    shacl_shapes_file: Option<String>,
+
+    /// Write a VoID description of the generated dataset to this Turtle file when the run is done, with
+    /// its statistics and sources. See [crate::void].
+    #[builder(setter(into, strip_option), default="None")]
+    void_file: Option<String>,
+
+    /// Write the statistics of the generated dataset to this file when the run is done: in CSV if its extension
+    /// is `csv`, otherwise in JSON. See [crate::void::DatasetStatistics::write_report].
+    #[builder(setter(into, strip_option), default="None")]
+    dataset_report_file: Option<String>,
+
+    /// Write the PROV-O provenance of the run to this Turtle file when it is done: which output files and
+    /// named graphs it generated, from which sources and mapping documents. See [crate::provenance].
+    #[builder(setter(into, strip_option), default="None")]
+    provenance_file: Option<String>,
+
+    /// Run these SPARQL queries over the generated dataset when the run is done, and write their results to
+    /// the files they are paired with. See [crate::sparql].
+    #[builder(default="Vec::new()", setter(each(name = "sparql_query")))]
+    sparql_queries: Vec<(String, String)>,
+
+    /// The mapping documents the plan is translated from, to refer to in the provenance.
+    #[builder(default="Vec::new()")]
+    mapping_documents: Vec<String>,
+
+    /// Whether the mapping is read from standard input, which then cannot be a source as well.
+    #[builder(default="false")]
+    mapping_from_std_in: bool,
+
+    /// What to do with a record when an IRI generated from it is invalid.
+    #[builder(default="InvalidIriPolicy::Quarantine")]
+    invalid_iri_policy: InvalidIriPolicy,
+
+    /// What to do with a record when a reference has no value in it.
+    #[builder(default="MissingReferencePolicy::SkipRecord")]
+    missing_reference_policy: MissingReferencePolicy,
+
+    /// Stop at the first record that cannot be processed, or skip such records.
+    #[builder(default="ErrorMode::Lenient")]
+    error_mode: ErrorMode,
+
+    /// Values for the placeholders `${name}` in source and target configurations and constants of the plan.
+    /// Placeholders in source and target configurations without value here get the value of the environment
+    /// variable with that name.
+    #[builder(default="HashMap::new()")]
+    params: HashMap<String, String>,
+
+    /// Whether placeholders in source and target configurations without value in `params` get the value of
+    /// the environment variable with that name. Turn it off for plans you do not trust, which could otherwise
+    /// read the environment, e.g. by putting a variable in the URL of a source.
+    #[builder(default="true")]
+    params_from_env: bool,
+
+    /// The providers of the secrets referenced as `${secret:<provider>:<argument>}` in source and target
+    /// configurations of the plan: by default only `env`. See [crate::secrets]. Add `file` or `cmd` only for
+    /// plans you trust, as they let a plan read any file or run any program.
+    #[builder(default="Credentials::default()")]
+    credentials: Credentials,
+
+    /// Replacements of source paths, as pairs of a file name or path and the new path. A source whose
+    /// path ends with the file name or path of a pair is read from the new path of the first such pair.
+    #[builder(default="Vec::new()")]
+    source_overrides: Vec<(String, String)>,
+
+    /// How the values of CSV files are separated, quoted and escaped, overriding what the configurations
+    /// of their sources say. See [CsvDialect].
+    #[builder(default="CsvDialect::default()")]
+    csv_dialect: CsvDialect,
+
+    /// The base IRI to resolve relative IRIs against, for all IRI functions in the plan.
+    /// Overrides the base IRIs of the functions in the plan.
+    #[builder(setter(into, strip_option), default="None")]
+    base_iri: Option<String>,
+
+    /// How values are percent-encoded when they are put in an IRI.
+    #[builder(default="IriEncoding::Uri")]
+    iri_encoding: IriEncoding,
+
+    /// The IRI encoding per term map, i.e. per variable of the plan (e.g. `tm0_sm`), if it differs from
+    /// `iri_encoding`.
+    #[builder(default="HashMap::new()")]
+    term_iri_encodings: HashMap<String, IriEncoding>,
+
+    /// Custom sinks, by the key of the targets they write, i.e. the value of `sink` in the configuration
+    /// of a target or else its target type, e.g. `Kafka`. Custom sinks take precedence over the built-in ones.
+    #[builder(default="HashMap::new()", setter(each(name = "sink")))]
+    sinks: HashMap<String, SinkFactory>,
+
+    /// Custom sources, by the key of the sources they read, i.e. the value of `source` in the configuration
+    /// of a source or else its source type, e.g. `Kafka`. Custom sources take precedence over the built-in ones.
+    #[builder(default="HashMap::new()", setter(each(name = "source")))]
+    sources: HashMap<String, SourceFactory>,
+}
+
+impl MopperOptionsBuilder {
+    // Checks the options that can be wrong on their own or in combination, so a run does not fail
+    // on them halfway through starting
+    fn validate(&self) -> Result<(), String> {
+        let force_to_file = self.force_to_file.clone().flatten();
+        if self.force_to_std_out == Some(true) && force_to_file.is_some() {
+            return Err("Output cannot be forced both to standard out and to a file".to_string());
+        }
+        if self.tag_targets == Some(true) && self.force_to_std_out != Some(true) {
+            return Err("Targets can only be tagged when output is forced to standard out".to_string());
+        }
+        if let Some(file) = &force_to_file {
+            check_writable(file)?;
+        }
+        if let Some(Some(file)) = &self.void_file {
+            check_writable(file)?;
+        }
+        if let Some(Some(file)) = &self.dataset_report_file {
+            check_writable(file)?;
+        }
+        if let Some(Some(file)) = &self.provenance_file {
+            check_writable(file)?;
+        }
+        for (_query_file, output_file) in self.sparql_queries.iter().flatten() {
+            check_writable(output_file)?;
+        }
+        if self.write_buffer_capacity == Some(0) {
+            return Err("The write buffer capacity must be at least 1 byte".to_string());
+        }
+        if self.max_concurrent_sources == Some(Some(0)) {
+            return Err("At least 1 source must be able to read at the same time".to_string());
+        }
+        if self.max_concurrent_files == Some(0) {
+            return Err("A source must be able to read at least 1 file at the same time".to_string());
+        }
+        for (node_id, rate_limit) in self.source_rate_limits.iter().flatten() {
+            let positive = rate_limit.records_per_second.is_none_or(|records_per_second| records_per_second > 0.0)
+                && rate_limit.bytes_per_second != Some(0);
+            if !positive {
+                return Err(format!("The rate limit of source {node_id} must be more than 0"));
+            }
+        }
+        if let Some(Some(sample_fraction)) = self.sample_fraction {
+            if !(sample_fraction > 0.0 && sample_fraction <= 1.0) {
+                return Err(format!("The sample fraction must be more than 0 and at most 1, not {sample_fraction}"));
+            }
+        }
+        if self.throughput_log_interval == Some(Some(Duration::ZERO)) {
+            return Err("The throughput log interval must be longer than 0".to_string());
+        }
+        if let Some(Some(_)) = self.progress_listener {
+            if self.progress_interval == Some(Duration::ZERO) {
+                return Err("The progress interval must be longer than 0".to_string());
+            }
+        }
+        match &self.deduplication_strategy {
+            Some(DeduplicationStrategy::Approximate { false_positive_rate, expected_statements }) => {
+                if !(*false_positive_rate > 0.0 && *false_positive_rate < 1.0) {
+                    return Err(format!("The false positive rate of approximate deduplication must be more than 0 and less than 1, not {false_positive_rate}"));
+                }
+                if *expected_statements == 0 {
+                    return Err("The number of statements expected by approximate deduplication must be at least 1".to_string());
+                }
+            },
+            Some(DeduplicationStrategy::Sharded { shards: 0 }) => {
+                return Err("Sharded deduplication needs at least 1 shard".to_string());
+            },
+            Some(DeduplicationStrategy::ExternalSort { max_in_memory: 0, .. }) => {
+                return Err("External sort deduplication must keep at least 1 statement in memory".to_string());
+            },
+            _ => {}
+        }
+        let exact = matches!(self.deduplication_strategy, None | Some(DeduplicationStrategy::Exact));
+        if matches!(self.deduplicate_index_dir, Some(Some(_))) && !exact {
+            return Err("A deduplication index is only supported with the exact deduplication strategy".to_string());
+        }
+        let external_sort = matches!(self.deduplication_strategy, Some(DeduplicationStrategy::ExternalSort { .. }));
+        if self.deduplicate_globally == Some(true) && external_sort {
+            return Err("Global deduplication is not supported with external sort deduplication".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Checks that the file can be created or overwritten, without touching it
+fn check_writable(file: &str) -> Result<(), String> {
+    let path = Path::new(file);
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => Err(format!("Cannot write output to {file}: it is a directory")),
+        Ok(metadata) if metadata.permissions().readonly() => Err(format!("Cannot write output to {file}: it is read-only")),
+        Ok(_) => Ok(()),
+        Err(_) => {
+            let dir = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new(".")
+            };
+            match fs::metadata(dir) {
+                Ok(metadata) if !metadata.is_dir() => Err(format!("Cannot write output to {file}: {} is not a directory", dir.display())),
+                Ok(metadata) if metadata.permissions().readonly() => Err(format!("Cannot write output to {file}: directory {} is read-only", dir.display())),
+                Ok(_) => Ok(()),
+                Err(err) => Err(format!("Cannot write output to {file}: directory {}: {err}", dir.display()))
+            }
+        }
+    }
 }
 
 impl MopperOptions {
     pub fn force_to_std_out(&self) -> bool {
         self.force_to_std_out
     }
+    pub fn tag_targets(&self) -> bool {
+        self.tag_targets
+    }
     pub fn force_to_file(&self) -> &Option<String> {
         &self.force_to_file
     }
+    pub fn write_buffer_capacity(&self) -> usize {
+        self.write_buffer_capacity
+    }
+    pub fn flush_interval(&self) -> Option<Duration> {
+        self.flush_interval
+    }
+    pub fn source_read_timeout(&self) -> Option<Duration> {
+        self.source_read_timeout
+    }
+    pub fn sink_write_timeout(&self) -> Option<Duration> {
+        self.sink_write_timeout
+    }
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout
+    }
+    pub fn sync_on_close(&self) -> bool {
+        self.sync_on_close
+    }
+    pub fn write_in_place(&self) -> bool {
+        self.write_in_place
+    }
     pub fn working_dir_hint(&self) -> &Option<String> {
         &self.working_dir_hint
     }
@@ -57,4 +531,158 @@ impl MopperOptions {
     pub fn deduplicate(&self) -> bool {
         self.deduplicate
     }
+    pub fn deduplicate_index_dir(&self) -> &Option<String> {
+        &self.deduplicate_index_dir
+    }
+    pub fn deduplicate_globally(&self) -> bool {
+        self.deduplicate_globally
+    }
+    pub fn deduplication_strategy(&self) -> &DeduplicationStrategy {
+        &self.deduplication_strategy
+    }
+    pub fn deduplication_capacity(&self) -> usize {
+        self.deduplication_capacity
+    }
+    pub fn deduplication_hasher(&self) -> &DeduplicationHasher {
+        &self.deduplication_hasher
+    }
+    pub fn sink_deduplication(&self) -> &HashMap<usize, bool> {
+        &self.sink_deduplication
+    }
+    pub fn throughput_log_interval(&self) -> Option<Duration> {
+        self.throughput_log_interval
+    }
+    pub fn progress_listener(&self) -> &Option<ProgressListener> {
+        &self.progress_listener
+    }
+    pub fn progress_interval(&self) -> Duration {
+        self.progress_interval
+    }
+    pub fn record_limit(&self) -> Option<usize> {
+        self.record_limit
+    }
+    pub fn sample_fraction(&self) -> Option<f64> {
+        self.sample_fraction
+    }
+    pub fn sample_seed(&self) -> Option<u64> {
+        self.sample_seed
+    }
+    pub fn max_concurrent_sources(&self) -> Option<usize> {
+        self.max_concurrent_sources
+    }
+    pub fn max_concurrent_files(&self) -> usize {
+        self.max_concurrent_files
+    }
+    pub fn source_rate_limits(&self) -> &HashMap<usize, RateLimit> {
+        &self.source_rate_limits
+    }
+    pub fn benchmark(&self) -> bool {
+        self.benchmark
+    }
+    pub fn error_log_file(&self) -> &Option<String> {
+        &self.error_log_file
+    }
+    pub fn shacl_shapes_file(&self) -> &Option<String> {
+        &self.shacl_shapes_file
+    }
+    pub fn void_file(&self) -> &Option<String> {
+        &self.void_file
+    }
+    pub fn dataset_report_file(&self) -> &Option<String> {
+        &self.dataset_report_file
+    }
+    pub fn provenance_file(&self) -> &Option<String> {
+        &self.provenance_file
+    }
+    pub fn sparql_queries(&self) -> &[(String, String)] {
+        &self.sparql_queries
+    }
+    pub fn mapping_documents(&self) -> &[String] {
+        &self.mapping_documents
+    }
+    pub fn mapping_from_std_in(&self) -> bool {
+        self.mapping_from_std_in
+    }
+    pub fn invalid_iri_policy(&self) -> &InvalidIriPolicy {
+        &self.invalid_iri_policy
+    }
+    pub fn missing_reference_policy(&self) -> &MissingReferencePolicy {
+        &self.missing_reference_policy
+    }
+    pub fn error_mode(&self) -> &ErrorMode {
+        &self.error_mode
+    }
+    pub fn params(&self) -> &HashMap<String, String> {
+        &self.params
+    }
+    pub fn params_from_env(&self) -> bool {
+        self.params_from_env
+    }
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+    pub fn source_overrides(&self) -> &[(String, String)] {
+        &self.source_overrides
+    }
+    pub fn csv_dialect(&self) -> &CsvDialect {
+        &self.csv_dialect
+    }
+    pub fn base_iri(&self) -> &Option<String> {
+        &self.base_iri
+    }
+    pub fn iri_encoding(&self) -> &IriEncoding {
+        &self.iri_encoding
+    }
+    pub fn term_iri_encodings(&self) -> &HashMap<String, IriEncoding> {
+        &self.term_iri_encodings
+    }
+
+    pub fn sinks(&self) -> &HashMap<String, SinkFactory> {
+        &self.sinks
+    }
+
+    /// The custom sink factory for the target, if one is registered.
+    pub fn sink_factory(&self, target: &Target) -> Option<&SinkFactory> {
+        self.sinks.get(&sink_key(target))
+    }
+
+    pub fn sources(&self) -> &HashMap<String, SourceFactory> {
+        &self.sources
+    }
+
+    /// The custom source factory for the source, if one is registered, or else the factory of the built-in
+    /// source for it, e.g. for a PostgreSQL database.
+    pub fn source_factory(&self, source: &Source) -> Option<&SourceFactory> {
+        self.sources.get(&source_key(source)).or_else(|| builtin_source_factory(source))
+    }
+
+    // Uses the working directory hint, whatever the caller set
+    pub(crate) fn with_working_dir_hint(mut self, working_dir_hint: &str) -> Self {
+        self.working_dir_hint = Some(working_dir_hint.to_string());
+        self
+    }
+
+    /// The options without credentials providers and without reading environment variables, for executing
+    /// plans of others, e.g. of the clients of a service.
+    pub fn for_untrusted_plans(mut self) -> Self {
+        self.credentials = Credentials::none();
+        self.params_from_env = false;
+        self
+    }
+
+    // Registers a custom source, in addition to the ones of the caller
+    pub(crate) fn with_source(mut self, key: &str, factory: SourceFactory) -> Self {
+        self.sources.insert(key.to_string(), factory);
+        self
+    }
+
+    /// Whether the sink of the given node deduplicates its statements.
+    pub fn deduplicates_sink(&self, node_id: usize) -> bool {
+        self.sink_deduplication.get(&node_id).copied().unwrap_or(self.deduplicate)
+    }
+
+    /// The IRI encoding of the values of the given variable of the plan, with or without leading `?`.
+    pub fn iri_encoding_of(&self, variable: &str) -> &IriEncoding {
+        self.term_iri_encodings.get(variable.trim_start_matches('?')).unwrap_or(&self.iri_encoding)
+    }
 }
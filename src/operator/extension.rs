@@ -19,34 +19,38 @@ use std::vec::Vec;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::Instant;
 use crossbeam_channel::{Receiver, Sender};
-use log::{debug, error};
+use log::{debug, warn};
 use operator::Function;
-use crate::error::GeneralError;
-use crate::function::basic_function::BasicFunction;
-use crate::function::blank_node::BlankNodeFunction;
-use crate::function::constant::ConstantFunction;
-use crate::function::iri::IriFunction;
-use crate::function::literal::LiteralFunction;
-use crate::function::reference::ReferenceFunction;
-use crate::function::template_function_value::TemplateFunctionValueFunction;
-use crate::function::template_string::TemplateStrFunction;
-use crate::function::uri_encode::UriEncodeFunction;
-use crate::util::remove_join_alias_prefix;
+use crate::error::MopperError;
+use crate::error_log::RecordErrorHandler;
+use crate::function::basic_function::FunctionError;
+use crate::function::compiled::CompiledFunction;
+use crate::function::iri::INVALID_IRI;
+use crate::function::reference::NO_VALUE;
+use crate::message::Message;
+use crate::mopper_options::{InvalidIriPolicy, MissingReferencePolicy, MopperOptions};
+use crate::stats::OperatorCounters;
+use crate::util::send_to_all;
 
 pub struct ExtendOperator {
-    functions_mutex: Arc<Mutex<Vec<(String, Box<dyn BasicFunction + Send>)>>>,
-    node_id: String
+    functions_mutex: Arc<Mutex<Vec<(String, CompiledFunction)>>>,
+    node_id: String,
+    counters: Arc<OperatorCounters>,
+    record_errors: RecordErrorHandler,
+    invalid_iri_policy: InvalidIriPolicy,
+    missing_reference_policy: MissingReferencePolicy
 }
 
 impl ExtendOperator {
-    pub fn new(extend_pairs: &HashMap<String, Function>, node_id: &usize, join_alias: &Option<String>) -> Result<&'static Self, GeneralError> {
+    pub fn new(extend_pairs: &HashMap<String, Function>, node_id: &usize, join_alias: &Option<String>, counters: Arc<OperatorCounters>, record_errors: RecordErrorHandler, options: &MopperOptions) -> Result<&'static Self, MopperError> {
         debug!("Initializing Extend operator {node_id}.");
 
-        let mut functions: Vec<(String, Box<dyn BasicFunction + Send>)> = Vec::new();
+        let mut functions: Vec<(String, CompiledFunction)> = Vec::new();
         
         extend_pairs.iter().try_for_each(|(name, function_description)| {
-            let function = get_function(function_description, join_alias)?;
+            let function = CompiledFunction::compile(function_description, join_alias, options.iri_encoding_of(name), options.missing_reference_policy())?;
             functions.push((name.clone(), function));
             Ok(())
         })?;
@@ -54,11 +58,15 @@ impl ExtendOperator {
         let boxed = Box::new(ExtendOperator{
             functions_mutex: Arc::new(Mutex::new(functions)),
             node_id: node_id.to_string(),
+            counters,
+            record_errors,
+            invalid_iri_policy: options.invalid_iri_policy().clone(),
+            missing_reference_policy: options.missing_reference_policy().clone()
         });
         Ok(Box::leak(boxed))
     }
 
-    pub fn start(&'static self, rx_chan: Receiver<Vec<String>>, tx_channels: Vec<Sender<Vec<String>>>) -> JoinHandle<(u8, String)> {
+    pub fn start(&'static self, rx_chan: Receiver<Message>, tx_channels: Vec<Sender<Message>>) -> JoinHandle<Result<(), MopperError>> {
         debug!("Starting ExtendOperator {}!", self.node_id);
 
         let functions_clone = self.functions_mutex.clone();
@@ -66,125 +74,108 @@ impl ExtendOperator {
             .name(format!("Extend {}", self.node_id))
             .spawn(move ||
             {
+                let started = Instant::now();
                 let mut functions = functions_clone.lock().unwrap();
 
-                // first send headers
-                let mut node_id_plus_function_names = vec![self.node_id.clone()];
-                node_id_plus_function_names.extend(functions.iter()
+                // first send the schema: the names of the variables, and the result types, so the serializer
+                // knows what to do with the string values
+                let function_names: Vec<String> = functions.iter()
                     .map(|(name, _function)| {
                         let minus_first_char = &name[1..];
                         minus_first_char.to_string()
                     })
-                );
-                tx_channels.iter()
-                    .for_each(|tx_chan| tx_chan.send(node_id_plus_function_names.clone()).unwrap());
-
-                // then send result types, so the serializer knows what to do with the string values
-                let mut node_id_plus_result_types = vec![self.node_id.clone()];
-                node_id_plus_result_types.extend(functions.iter()
+                    .collect();
+                let result_types: Vec<String> = functions.iter()
                     .map(|(_name, function)| {
-                        function.get_result_type().to_string()
+                        function.result_type().to_string()
                     })
-                );
-                tx_channels.iter()
-                    .for_each(|tx_chan| tx_chan.send(node_id_plus_result_types.clone()).unwrap());
-
-                // now process values
-                // Set the variable names ("headers") for the functions first
-                let mut iter = rx_chan.iter();
-                let variable_names_option = iter.next();
-                if let Some(variable_names) = variable_names_option {
-                    let variable_names_without_node_id = &variable_names[1..];
-                    functions.iter_mut().for_each(|(_name, function)| {
-                        function.variable_names(variable_names_without_node_id);
-                    });
+                    .collect();
+                if !send_to_all(&tx_channels, Message::Schema { names: function_names, types: Some(result_types) }) {
+                    self.counters.finish(started);
+                    return Ok(());
                 }
 
                 // Let each function process the data
-                for data in iter {
-                    let data_without_node_id = &data[1..];
+                let mut variable_names: Vec<String> = Vec::new();
+                let mut input_ended = false;
+                let mut failure: Option<String> = None;
+                while let Ok(message) = self.counters.blocked(|| rx_chan.recv()) {
+                    let data = match message {
+                        Message::Schema { names, .. } => {
+                            // Set the variable names ("headers") for the functions first
+                            functions.iter_mut().for_each(|(_name, function)| {
+                                function.bind(&names);
+                            });
+                            variable_names = names;
+                            continue;
+                        },
+                        Message::Data(data) => data,
+                        Message::End => {
+                            input_ended = true;
+                            continue;
+                        }
+                    };
+                    self.counters.add_in(1);
 
-                    // prepend node id
-                    let mut node_id_plus_result = vec![self.node_id.clone()];
-                    node_id_plus_result.extend(
-                        functions.iter()
-                            .map(|(_name, function)| { function.exec(data_without_node_id) })
-                            .flatten()
-                    );
+                    // after a fatal error, only drain the input so the upstream operators can finish
+                    if failure.is_some() {
+                        continue;
+                    }
+                    let mut result_values: Vec<String> = Vec::with_capacity(functions.len());
+                    let result = functions.iter()
+                        .try_for_each(|(_name, function)| {
+                            match function.exec_value(&data) {
+                                Ok(value) => result_values.push(value),
+                                Err(FunctionError::InvalidIri(iri)) if self.invalid_iri_policy == InvalidIriPolicy::SkipStatement
+                                    && !self.record_errors.is_strict() => {
+                                    // the serializer skips the statements with this value
+                                    warn!("Invalid IRI: {iri}; skipping the statements with it.");
+                                    self.counters.add_invalid_iri();
+                                    result_values.push(INVALID_IRI.to_string());
+                                },
+                                Err(FunctionError::MissingValue(msg)) if self.missing_reference_policy == MissingReferencePolicy::SkipStatement
+                                    && !self.record_errors.is_strict() => {
+                                    // the serializer skips the statements with this value
+                                    warn!("{msg}; skipping the statements with it.");
+                                    result_values.push(NO_VALUE.to_string());
+                                },
+                                Err(error) => return Err(error)
+                            }
+                            Ok(())
+                        });
 
-                    tx_channels.iter()
-                        .for_each(|tx_chan| tx_chan.send(node_id_plus_result.clone()).unwrap());
+                    // skip records that cannot be processed, or stop
+                    if let Err(error) = result {
+                        let msg = error.to_string();
+                        let mut stop = self.record_errors.handle(&msg, &variable_names, &data);
+                        match error {
+                            FunctionError::InvalidIri(_) => {
+                                self.counters.add_invalid_iri();
+                                stop |= self.invalid_iri_policy == InvalidIriPolicy::Fail;
+                            },
+                            FunctionError::MissingValue(_) => stop |= self.missing_reference_policy == MissingReferencePolicy::Fail
+                        }
+                        if stop {
+                            failure = Some(msg);
+                        }
+                        continue;
+                    }
+
+                    if !self.counters.blocked(|| send_to_all(&tx_channels, Message::Data(result_values))) {
+                        self.counters.finish(started);
+                        return Ok(());
+                    }
+                    self.counters.add_out(1);
                 }
 
-                (0, String::new())
+                if input_ended && failure.is_none() {
+                    send_to_all(&tx_channels, Message::End);
+                }
+                self.counters.finish(started);
+                match failure {
+                    Some(msg) => Err(self.record_errors.error(msg)),
+                    None => Ok(())
+                }
             }).unwrap()
     }
 }
-
-fn get_function(function: &Function, join_alias: &Option<String>) -> Result<Box<dyn BasicFunction + Send>, GeneralError> {
-    match function {
-        Function::Constant { value } => {
-            debug!(" function 'Constant': [{value}]");
-            Ok(Box::new(ConstantFunction::new(value.clone())))
-        },
-        Function::UriEncode { inner_function } => {
-            debug!(" function 'UriEncode'.");
-            let inner = get_function(inner_function, join_alias)?;
-            Ok(Box::new(UriEncodeFunction::new(inner)))
-        },
-        Function::Iri { base_iri, inner_function } => {
-            debug!(" function 'Iri'");
-            let inner = get_function(inner_function, join_alias)?;
-            Ok(Box::new(IriFunction::new(base_iri, inner)))
-        },
-        Function::TemplateString { value } => {
-            debug!(" function 'TemplateString': [{value}]");
-            let function = TemplateStrFunction::new(value, join_alias)?;
-            Ok(Box::new(function))
-        },
-        Function::TemplateFunctionValue { template, variable_function_pairs } => {
-            let variable_to_function_map: HashMap<String, Box<dyn BasicFunction + Send>> = variable_function_pairs.iter()
-                .map(|(name, function)|{
-                    let template_var_name = remove_join_alias_prefix(name, join_alias);
-                    let function_box = get_function(function, join_alias).unwrap();
-                    (template_var_name, function_box)})
-                .collect();
-            let function = TemplateFunctionValueFunction::new(template, variable_to_function_map, join_alias)?;
-            Ok(Box::new(function))
-        },
-        Function::BlankNode { inner_function } => {
-            debug!(" function 'BlankNode'");
-            let inner = get_function(inner_function, join_alias)?;
-            Ok(Box::new(BlankNodeFunction::new(inner)))
-        },
-        Function::Concatenate { .. } => {
-            error!(" function 'Concatenate' not implemented yet.");
-            todo!()
-        },
-        Function::FnO { .. } => {
-            error!(" function 'FnO' not implemented yet.");
-            todo!()
-        },
-        Function::Literal { inner_function, .. } => {
-            debug!(" function 'Literal'");
-            let inner = get_function(inner_function, join_alias)?;
-            Ok(Box::new(LiteralFunction::new(inner)))
-        },
-        Function::Lower { .. } => {
-            error!(" function 'Lower' not implemented yet.");
-            todo!()
-        },
-        Function::Upper { .. } => {
-            error!(" function 'Upper' not implemented yet.");
-            todo!()
-        },
-        Function::Reference { value } => {
-            debug!(" function 'Reference': [{value}]");
-            Ok(Box::new(ReferenceFunction::new(value.to_string(), join_alias)))
-        },
-        Function::Replace { .. } => {
-            error!(" function 'Relace' not implemented yet.");
-            todo!()
-        }
-    }
-}
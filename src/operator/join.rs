@@ -14,15 +14,22 @@
  *    limitations under the License.
  */
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
-use crossbeam_channel::{Receiver, Sender};
+use std::time::Instant;
+use crossbeam_channel::{Receiver, Select, Sender};
 use log::{debug, error, trace};
 use operator::Join;
 use operator::JoinType::InnerJoin;
+use crate::error::MopperError;
+use crate::error_log::RecordErrorHandler;
+use crate::message::Message;
+use crate::stats::OperatorCounters;
+use crate::util::send_to_all;
 
-// TODO: can be optimized when using only attributes of the next operator.
 // TODO: this algorithm assumes every join attribute gets checked against only *1* other join attribute.
 
 pub struct JoinOperator {
@@ -30,11 +37,14 @@ pub struct JoinOperator {
     left_node_id: String,   // in RML: the "child"
     right_node_id: String,  // in RML: the "parent"
     left_right_join_attr_pairs: Vec<(String, String)>,
-    right_node_attr_prefix: String      // = "join alias" in the mapping plan. Prefix to use for attribute names coming from the right node
+    right_node_attr_prefix: String,     // = "join alias" in the mapping plan. Prefix to use for attribute names coming from the right node
+    output_attributes: Option<HashSet<String>>,     // the attributes used by the next operators, if known. Join attributes are always kept.
+    counters: Arc<OperatorCounters>,
+    record_errors: RecordErrorHandler
 }
 
 impl JoinOperator {
-    pub fn new(config: &Join, left_node_id: &usize, right_node_id: &usize, node_id: &usize) -> &'static Self {
+    pub fn new(config: &Join, left_node_id: &usize, right_node_id: &usize, node_id: &usize, output_attributes: &Option<HashSet<String>>, counters: Arc<OperatorCounters>, record_errors: RecordErrorHandler) -> &'static Self {
         debug!("Initializing Join operator {node_id}.");
 
         // Only inner join supported for now.
@@ -48,17 +58,21 @@ impl JoinOperator {
             left_node_id: left_node_id.to_string(),
             right_node_id: right_node_id.to_string(),
             left_right_join_attr_pairs: config.left_right_attr_pairs.clone(),
-            right_node_attr_prefix: format!("{}_", config.join_alias) // use this as prefix to attributes of right node
+            right_node_attr_prefix: format!("{}_", config.join_alias), // use this as prefix to attributes of right node
+            output_attributes: output_attributes.clone(),
+            counters,
+            record_errors
         });
         Box::leak(boxed)
     }
     
-    pub fn start(&'static self, rx_chan: Receiver<Vec<String>>, tx_channels: Vec<Sender<Vec<String>>>) -> JoinHandle<(u8, String)>{
+    pub fn start(&'static self, left_rx_chan: Receiver<Message>, right_rx_chan: Receiver<Message>, tx_channels: Vec<Sender<Message>>) -> JoinHandle<Result<(), MopperError>>{
         debug!("Starting Join operator {}!", self.node_id);
 
         thread::Builder::new()
             .name(format!("Join {}", self.node_id))
             .spawn(move || {
+            let started = Instant::now();
             let mut left_attribute_names: Vec<String> = Vec::with_capacity(self.left_right_join_attr_pairs.len());
             let mut right_attribute_names: Vec<String> = Vec::with_capacity(self.left_right_join_attr_pairs.len());
 
@@ -66,118 +80,219 @@ impl JoinOperator {
             let mut left_join_attribute_indices: Vec<usize> = Vec::with_capacity(self.left_right_join_attr_pairs.len());
             let mut right_join_attribute_indices: Vec<usize> = Vec::with_capacity(self.left_right_join_attr_pairs.len());
 
+            // the positions of the attributes to keep; all of them if None
+            let mut left_kept_positions: Option<Vec<usize>> = None;
+            let mut right_kept_positions: Option<Vec<usize>> = None;
+
             let mut left_join_data = JoinData::new(self.left_right_join_attr_pairs.len());
             let mut right_join_data = JoinData::new(self.left_right_join_attr_pairs.len());
-            
-            for data in rx_chan.iter() {
-                let node_id = &data[0];
-                trace!("Processing join data of node {node_id}");
-                let real_data = &data[1..];
-                
-                if node_id.eq(&self.left_node_id) {
-                    // process left data
 
+            // the attribute names as received, to check records against; None until the schema arrives
+            let mut left_header: Option<Vec<String>> = None;
+            let mut right_header: Option<Vec<String>> = None;
+            let mut failure: Option<String> = None;
+
+            // whether a side has sent all its records; the records of the other side need not be kept then
+            let mut left_ended = false;
+            let mut right_ended = false;
+
+            // receive from both sides as the data comes in, until both are done
+            let mut select = Select::new();
+            let left_index = select.recv(&left_rx_chan);
+            select.recv(&right_rx_chan);
+            let mut nr_of_open_inputs = 2;
+            while nr_of_open_inputs > 0 {
+                let operation = self.counters.blocked(|| select.select());
+                let index = operation.index();
+                let is_left = index == left_index;
+                let message = match operation.recv(if is_left { &left_rx_chan } else { &right_rx_chan }) {
+                    Ok(message) => message,
+                    Err(_) => {
+                        select.remove(index);
+                        nr_of_open_inputs -= 1;
+                        continue;
+                    }
+                };
+
+                // after a fatal error, only drain the input so the upstream operators can finish
+                if failure.is_some() {
+                    continue;
+                }
+                let node_id = if is_left { &self.left_node_id } else { &self.right_node_id };
 
-                    if left_join_attribute_indices.is_empty() {
+                let real_data = match message {
+                    Message::Schema { names, .. } if is_left => {
+                        trace!("Processing the schema of node {node_id}");
                         // get names and positions of attributes
                         let join_attribute_names: Vec<&String> = self.left_right_join_attr_pairs.iter()
                             .map(|(left, _right)| left)
                             .collect();
 
-                        for (position, name) in real_data.iter().enumerate() {
+                        let kept_positions = self.kept_positions(&names, &join_attribute_names, "");
+                        let kept_names = project(&names, &kept_positions);
+                        for (position, name) in kept_names.iter().enumerate() {
                             left_attribute_names.push(name.clone());
                             if join_attribute_names.contains(&name) {
                                 left_join_attribute_indices.push(position);
                             }
                         }
+                        left_kept_positions = kept_positions;
                         left_join_data.set_join_attribute_positions(&left_join_attribute_indices);
-
-                        if !right_attribute_names.is_empty() {
-                            let all_attribute_names: Vec<String> = vec![self.node_id.clone()].iter()
-                                .chain(left_attribute_names.iter())
-                                .chain(right_attribute_names.iter())
-                                .map(|value| value.clone())
-                                .collect();
-                            tx_channels.iter()
-                                .for_each(|tx_chan| tx_chan.send(all_attribute_names.clone()).unwrap());
-                            left_attribute_names.clear();
-                        }
-
-                    } else {
-                        // we have some data!
-                        let join_result_option = process_data_for_one_join_side(real_data, &mut left_join_data, &mut right_join_data);
-                        if let Some(join_result) = join_result_option {
-                            for join_data in join_result {
-                                let data_to_send: Vec<String> = vec![self.node_id.clone()].iter()
-                                    .chain(real_data)
-                                    .chain(join_data)
-                                    .map(|value| value.clone())
-                                    .collect();
-                                tx_channels.iter()
-                                    .for_each(|tx_chan| tx_chan.send(data_to_send.clone()).unwrap());
-                            }
+                        left_header = Some(names);
+                        if right_header.is_some() && !self.send_schema(&tx_channels, &left_attribute_names, &right_attribute_names) {
+                            self.counters.finish(started);
+                            return Ok(());
                         }
-                    }
-
-                } else if node_id.eq(&self.right_node_id) {
-                    // process right data
-
-                    // find the indices of the attributes (first time only)
-                    if right_join_attribute_indices.is_empty() {
+                        continue;
+                    },
+                    Message::Schema { names, .. } => {
+                        trace!("Processing the schema of node {node_id}");
                         // get names and positions of attributes
                         let join_attribute_names: Vec<&String> = self.left_right_join_attr_pairs.iter()
                             .map(|(_left, right)| right)
                             .collect();
 
-                        for (position, name) in real_data.iter().enumerate() {
+                        let kept_positions = self.kept_positions(&names, &join_attribute_names, &self.right_node_attr_prefix);
+                        let kept_names = project(&names, &kept_positions);
+                        for (position, name) in kept_names.iter().enumerate() {
                             let new_name = format!("{}{}", self.right_node_attr_prefix, name);
                             right_attribute_names.push(new_name);
                             if join_attribute_names.contains(&name) {
                                 right_join_attribute_indices.push(position);
                             }
                         }
+                        right_kept_positions = kept_positions;
                         right_join_data.set_join_attribute_positions(&right_join_attribute_indices);
+                        right_header = Some(names);
+                        if left_header.is_some() && !self.send_schema(&tx_channels, &left_attribute_names, &right_attribute_names) {
+                            self.counters.finish(started);
+                            return Ok(());
+                        }
+                        continue;
+                    },
+                    Message::Data(data) => data,
+                    Message::End => {
+                        if is_left {
+                            left_ended = true;
+                        } else {
+                            right_ended = true;
+                        }
+                        continue;
+                    }
+                };
+                trace!("Processing join data of node {node_id}");
+                self.counters.add_in(1);
+
+                // skip records that do not match their header, or stop
+                let Some(header) = (if is_left { &left_header } else { &right_header }) else {
+                    failure = Some(format!("Record of node {node_id} received before its schema"));
+                    continue;
+                };
+                if real_data.len() != header.len() {
+                    let msg = format!("Record of node {node_id} has {} values instead of {}", real_data.len(), header.len());
+                    if self.record_errors.handle(&msg, header, &real_data) {
+                        failure = Some(msg);
+                    }
+                    continue;
+                }
 
-                        if !left_attribute_names.is_empty() {
-                            let all_attribute_names: Vec<String> = vec![self.node_id.clone()].iter()
-                                .chain(left_attribute_names.iter())
-                                .chain(right_attribute_names.iter())
-                                .map(|value| value.clone())
+                if is_left {
+                    // process left data
+                    let kept_data = project(&real_data, &left_kept_positions);
+                    let join_result_option = process_data_for_one_join_side(&kept_data, &mut left_join_data, &mut right_join_data, !right_ended);
+                    if let Some(join_result) = join_result_option {
+                        self.counters.add_out(join_result.len() as u64);
+                        for join_data in join_result {
+                            let data_to_send: Vec<String> = kept_data.iter()
+                                .chain(join_data)
+                                .cloned()
                                 .collect();
-                            tx_channels.iter()
-                                .for_each(|tx_chan| tx_chan.send(all_attribute_names.clone()).unwrap());
-                            right_attribute_names.clear();
+                            if !self.counters.blocked(|| send_to_all(&tx_channels, Message::Data(data_to_send))) {
+                                self.counters.finish(started);
+                                return Ok(());
+                            }
                         }
-                    } else {
-                        // we have some data!
-                        let join_result_option = process_data_for_one_join_side(real_data, &mut right_join_data, &mut left_join_data);
-                        if let Some(join_result) = join_result_option {
-                            for join_data in join_result {
-                                let data_to_send: Vec<String> = vec![self.node_id.clone()].iter()
-                                    .chain(join_data)
-                                    .chain(real_data)
-                                    .map(|value| value.clone())
-                                    .collect();
-                                tx_channels.iter()
-                                    .for_each(|tx_chan| tx_chan.send(data_to_send.clone()).unwrap());
+                    }
+                } else {
+                    // process right data
+                    let kept_data = project(&real_data, &right_kept_positions);
+                    let join_result_option = process_data_for_one_join_side(&kept_data, &mut right_join_data, &mut left_join_data, !left_ended);
+                    if let Some(join_result) = join_result_option {
+                        self.counters.add_out(join_result.len() as u64);
+                        for join_data in join_result {
+                            let data_to_send: Vec<String> = join_data.iter()
+                                .chain(kept_data.iter())
+                                .cloned()
+                                .collect();
+                            if !self.counters.blocked(|| send_to_all(&tx_channels, Message::Data(data_to_send))) {
+                                self.counters.finish(started);
+                                return Ok(());
                             }
                         }
                     }
                 }
             }
 
-            (0, String::new())
+            if left_ended && right_ended && failure.is_none() {
+                send_to_all(&tx_channels, Message::End);
+            }
+            self.counters.finish(started);
+            match failure {
+                Some(msg) => Err(MopperError::Join { node_id: self.record_errors.node_id(), msg }),
+                None => Ok(())
+            }
             
         }).unwrap()
     }
 }
 
+impl JoinOperator {
+    // Sends the names of the attributes of the joined records, once the schemas of both sides are known
+    fn send_schema(&self, tx_channels: &[Sender<Message>], left_attribute_names: &[String], right_attribute_names: &[String]) -> bool {
+        let all_attribute_names: Vec<String> = left_attribute_names.iter()
+            .chain(right_attribute_names.iter())
+            .cloned()
+            .collect();
+        send_to_all(tx_channels, Message::schema(all_attribute_names))
+    }
+
+    // Returns the positions of the attributes in the header that are join attributes or used by
+    // the next operators, or None if all attributes are kept.
+    fn kept_positions(&self, header: &[String], join_attribute_names: &[&String], prefix: &str) -> Option<Vec<usize>> {
+        let output_attributes = self.output_attributes.as_ref()?;
+        let kept_positions: Vec<usize> = header.iter().enumerate()
+            .filter(|(_position, name)| join_attribute_names.contains(name)
+                || output_attributes.contains(&format!("{prefix}{name}")))
+            .map(|(position, _name)| position)
+            .collect();
+        if kept_positions.len() == header.len() {
+            None
+        } else {
+            Some(kept_positions)
+        }
+    }
+}
+
+// Returns the values at the given positions, or all values if there are no positions given.
+fn project<'a>(data: &'a [String], kept_positions: &Option<Vec<usize>>) -> Cow<'a, [String]> {
+    match kept_positions {
+        Some(positions) => Cow::Owned(positions.iter().map(|position| data[*position].clone()).collect()),
+        None => Cow::Borrowed(data)
+    }
+}
+
+// Returns the records of the other side that match the data. The data is only kept if `keep` is set,
+// i.e. if the other side can still send records that match it.
 fn process_data_for_one_join_side<'a> (data:                    &[String],
                                    join_data:               &mut JoinData, 
                                    other_join_data:         &'a mut JoinData,
+                                   keep:                    bool
 ) -> Option<Vec<&'a Vec<String>>>
 {
-    let join_attr_values = join_data.add(data);
+    let join_attr_values = match keep {
+        true => join_data.add(data),
+        false => join_data.join_attribute_values(data)
+    };
     other_join_data.return_values_if_match(&join_attr_values)
 
 }
@@ -222,14 +337,19 @@ impl JoinData {
         self.join_attr_positions.extend(join_attribute_positions);
     }
 
-    fn add(&mut self, data: &[String]) -> Vec<String> { // return join_attr_values
-        
-        // get the values of the join attributes
-        let join_attr_values: Vec<String> = data.iter().enumerate()
+    // Returns the values of the join attributes in the data
+    fn join_attribute_values(&self, data: &[String]) -> Vec<String> {
+        data.iter().enumerate()
             .filter(|(position, _value)| self.join_attr_positions.contains(position))
             .map(|(_position, value)| value)
             .map(|value| value.clone())
-            .collect();
+            .collect()
+    }
+
+    fn add(&mut self, data: &[String]) -> Vec<String> { // return join_attr_values
+        
+        // get the values of the join attributes
+        let join_attr_values = self.join_attribute_values(data);
         
         self.data.push(data.to_vec());
         
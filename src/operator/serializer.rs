@@ -15,115 +15,252 @@
  */
 
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::Instant;
 use crossbeam_channel::{Receiver, Sender};
 use log::{debug, error};
 use operator::formats::DataFormat;
 use operator::Serializer;
+use crate::error::MopperError;
+use crate::error_log::RecordErrorHandler;
+use crate::function::iri::INVALID_IRI;
+use crate::function::reference::NO_VALUE;
+use crate::message::Message;
+use crate::stats::OperatorCounters;
+use crate::util::send_to_all;
+
+/// The graph term of rr:defaultGraph. Statements in this graph are written as triples.
+pub(crate) const DEFAULT_GRAPH: &str = "<http://www.w3.org/ns/r2rml#defaultGraph>";
 
 pub struct SerializeOperator {
     template_string_parts: Vec<(bool, String)>,
-    node_id: String
+    node_id: String,
+    counters: Arc<OperatorCounters>,
+    record_errors: RecordErrorHandler
 }
 impl SerializeOperator {
-    pub fn new(config: &Serializer, node_id: &usize) -> &'static Self {
+    /// `constants` are the formatted values of the variables that are the same for every record.
+    pub fn new(config: &Serializer, node_id: &usize, constants: &HashMap<String, String>, counters: Arc<OperatorCounters>, record_errors: RecordErrorHandler) -> &'static Self {
         debug!("Initializing Serialize operator {node_id}.");
         if config.format != DataFormat::NQuads && config.format != DataFormat::NTriples {
             error!("Serializer: only NQuads / NTriples supported at the moment!");
             todo!()
         }
 
-        let template = config.template.as_str();
+        // N-Triples has no graph position
+        let template = match config.format {
+            DataFormat::NTriples => remove_graph_terms(&config.template),
+            _ => config.template.clone()
+        };
         let boxed = Box::new(SerializeOperator{
-            template_string_parts: create_template_template_string_parts(template),
-            node_id: node_id.to_string()
+            template_string_parts: remove_default_graph(fill_in_constants(create_template_template_string_parts(&template), constants)),
+            node_id: node_id.to_string(),
+            counters,
+            record_errors
         });
         Box::leak(boxed)
     }
     
-    pub fn start(&'static self, rx_chan: Receiver<Vec<String>>, tx_channels: Vec<Sender<Vec<String>>>) -> JoinHandle<(u8, String)> {
+    pub fn start(&'static self, rx_chan: Receiver<Message>, tx_channels: Vec<Sender<Message>>) -> JoinHandle<Result<(), MopperError>> {
         debug!("Starting Serialize {}!", self.node_id);
 
         thread::Builder::new()
             .name(format!("Serializer {}", self.node_id))
             .spawn(move || {
-            
-            // Get the variable names ("headers") in the order they will arrive
-            let mut iter = rx_chan.iter();
-            let variable_names_option = iter.next();
-            if variable_names_option.is_some() {
-                let variable_names = &variable_names_option.unwrap()[1..];
-
-                // Get the data types of the variables
-                let data_types_option = iter.next();
-                if data_types_option.is_some() {
-                    let data_types = &data_types_option.unwrap()[1..];
-
-                    for values in iter {
-                        let mut variable_name_to_value_map = HashMap::with_capacity(variable_names.len());
-                        for (index, value) in values.iter().skip(1).enumerate() {   // skip node id
-                            let variable_name = &variable_names[index];
-                            let data_type = &data_types[index];
-                            variable_name_to_value_map.insert(variable_name, (value, data_type));
+            let started = Instant::now();
+            let mut failure: Option<String> = None;
+
+            // The names and data types of the variables, in the order their values arrive, and where the
+            // value of every variable in the template is in a record; known once the schema arrives
+            let mut variable_names: Vec<String> = Vec::new();
+            let mut data_types: Vec<String> = Vec::new();
+            let mut variable_indexes: Option<Vec<Option<usize>>> = None;
+            let mut input_ended = false;
+
+            // The statements of a record are built in the same buffer every time
+            let mut statements = String::new();
+
+            if !send_to_all(&tx_channels, Message::schema(vec!["statements".to_string()])) {
+                self.counters.finish(started);
+                return Ok(());
+            }
+
+            while let Ok(message) = self.counters.blocked(|| rx_chan.recv()) {
+                let values = match message {
+                    Message::Schema { names, types } => {
+                        match types {
+                            Some(types) => data_types = types,
+                            None => failure = Some(format!("Serializer {} received values without data types", self.node_id))
                         }
+                        variable_indexes = Some(self.template_string_parts.iter()
+                            .map(|(is_variable, part)| match is_variable {
+                                true => names.iter().rposition(|variable_name| variable_name == part),
+                                false => None
+                            })
+                            .collect());
+                        variable_names = names;
+                        continue;
+                    },
+                    Message::Data(values) => values,
+                    Message::End => {
+                        input_ended = true;
+                        continue;
+                    }
+                };
+                self.counters.add_in(1);
+
+                // after a fatal error, only drain the input so the upstream operators can finish
+                if failure.is_some() {
+                    continue;
+                }
+                let Some(variable_indexes) = &variable_indexes else {
+                    failure = Some(format!("Serializer {} received a record before its schema", self.node_id));
+                    continue;
+                };
+
+                statements.clear();
+                let mut has_skipped_term = false;
+                let mut missing_variable: Option<&String> = None;
+
+                for ((is_variable, part), variable_index) in self.template_string_parts.iter().zip(variable_indexes) {
+                    if !*is_variable {
+                        statements.push_str(part);
+                        continue;
+                    }
+                    let Some((value, data_type)) = variable_index.and_then(|index| Some((values.get(index)?, &data_types[index]))) else {
+                        missing_variable = Some(part);
+                        break;
+                    };
 
-                        let mut result_str = String::new();
-
-                        self.template_string_parts.iter()
-                            .for_each(|(is_variable, part)| {
-                                if *is_variable {
-                                    let (value, data_type) = variable_name_to_value_map[part];
-                                    let data_type_str = data_type.to_string();  // TODO: this to_string and later as_str must be avoided!
-                                    
-                                    // TODO: this formatting part should be a separate serialization treat & implementation.
-                                    //       Now it just formats N-Triples / N-quads in a hardcoded way. 
-                                    let value_str = match data_type_str.as_str() {
-                                        "str" => {
-                                            data_type_str
-                                        },
-                                        "iri" => {
-                                            // The format! macro would do fine, but is slower
-                                            let mut str = String::new();
-                                            str.push('<');
-                                            str.push_str(value);
-                                            str.push('>');
-                                            str
-                                        },
-                                        "lit" => {
-                                            // The format! macro would do fine, but is slower
-                                            let mut str = String::new();
-                                            str.push('"');
-                                            str.push_str(value);
-                                            str.push('"');
-                                            str
-                                        },
-                                        "blank" => {
-                                            let mut str = String::from("_:");
-                                            str.push_str(value);
-                                            str
-                                        }
-                                        _ => {
-                                            todo!()
-                                        }
-                                    };
-                                    result_str.push_str(&value_str);
-                                } else {
-                                    result_str.push_str(part);
-                                }
-                            });
-                        tx_channels.iter()
-                            .for_each(|tx_chan| tx_chan.send(vec![self.node_id.clone(), result_str.clone()]).unwrap());
+                    // TODO: this formatting part should be a separate serialization treat & implementation.
+                    //       Now it just formats N-Triples / N-quads in a hardcoded way.
+                    if value == INVALID_IRI || value == NO_VALUE {
+                        has_skipped_term = true;
+                        statements.push_str(value);
+                    } else {
+                        push_value(&mut statements, value, data_type);
                     }
                 }
+
+                // skip records with missing values, or stop
+                if let Some(variable) = missing_variable {
+                    let msg = format!("No value found for variable {variable}");
+                    if self.record_errors.handle(&msg, &variable_names, &values) {
+                        failure = Some(msg);
+                    }
+                    continue;
+                }
+
+                // statements in the default graph are triples
+                if statements.contains(DEFAULT_GRAPH) {
+                    statements = statements.replace(&format!(" {DEFAULT_GRAPH} ."), " .");
+                }
+
+                // skip the statements with an invalid IRI or a term without value
+                if has_skipped_term {
+                    statements = statements.lines()
+                        .filter(|statement| !statement.contains(INVALID_IRI) && !statement.contains(NO_VALUE))
+                        .collect::<Vec<&str>>()
+                        .join("\n");
+                    if statements.is_empty() {
+                        continue;
+                    }
+                }
+                if !self.counters.blocked(|| send_to_all(&tx_channels, Message::Data(vec![statements.clone()]))) {
+                    self.counters.finish(started);
+                    return Ok(());
+                }
+                self.counters.add_out(1);
             }
 
-            (0, String::new())
+            if input_ended && failure.is_none() {
+                send_to_all(&tx_channels, Message::End);
+            }
+            self.counters.finish(started);
+            match failure {
+                Some(msg) => Err(self.record_errors.error(msg)),
+                None => Ok(())
+            }
         }).unwrap()
     }
 }
 
 //// Some helper functions
+
+/// Formats a value of the given data type as a term in N-Triples / N-Quads.
+pub(crate) fn format_value(value: &str, data_type: &str) -> String {
+    let mut term = String::with_capacity(value.len() + 2);
+    push_value(&mut term, value, data_type);
+    term
+}
+
+// Appends a value of the given data type as a term in N-Triples / N-Quads. The format! macro would
+// do fine, but is slower.
+fn push_value(out: &mut String, value: &str, data_type: &str) {
+    match data_type {
+        "str" => out.push_str(data_type),
+        "iri" => {
+            out.push('<');
+            out.push_str(value);
+            out.push('>');
+        },
+        "lit" => {
+            out.push('"');
+            out.push_str(value);
+            out.push('"');
+        },
+        "blank" => {
+            out.push_str("_:");
+            out.push_str(value);
+        }
+        _ => {
+            todo!()
+        }
+    }
+}
+
+// Removes the graph terms, i.e. the fourth term, from the statements in the template
+fn remove_graph_terms(template: &str) -> String {
+    template.lines()
+        .map(|statement| {
+            let terms: Vec<&str> = statement.split_whitespace().collect();
+            match terms.as_slice() {
+                [subject, predicate, object, _graph, "."] => format!("{subject} {predicate} {object} ."),
+                _ => statement.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Removes the constant default graph terms from the template, so the statements become triples
+fn remove_default_graph(template_string_parts: Vec<(bool, String)>) -> Vec<(bool, String)> {
+    template_string_parts.into_iter()
+        .map(|(is_variable, part)| match is_variable {
+            true => (is_variable, part),
+            false => (is_variable, part.replace(&format!(" {DEFAULT_GRAPH} ."), " ."))
+        })
+        .collect()
+}
+
+// Replaces the variables with a constant value by that value, joining it with the surrounding parts
+fn fill_in_constants(template_string_parts: Vec<(bool, String)>, constants: &HashMap<String, String>) -> Vec<(bool, String)> {
+    let mut filled_in_parts: Vec<(bool, String)> = Vec::with_capacity(template_string_parts.len());
+    for (is_variable, part) in template_string_parts {
+        let (is_variable, part) = match constants.get(&part) {
+            Some(value) if is_variable => (false, value.clone()),
+            _ => (is_variable, part)
+        };
+        match filled_in_parts.last_mut() {
+            Some((false, previous_part)) if !is_variable => previous_part.push_str(&part),
+            _ => filled_in_parts.push((is_variable, part))
+        }
+    }
+    filled_in_parts
+}
+
 fn create_template_template_string_parts(template: &str) -> Vec<(bool, String)> {
     let mut template_string_parts: Vec<(bool, String)> = Vec::with_capacity(2);
     let mut current_str = String::new();
@@ -163,4 +300,23 @@ fn create_template_template_string_parts(template: &str) -> Vec<(bool, String)>
     }
     
     template_string_parts
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graph_terms_removed() {
+        let template = "?s <http://example.com/p> ?o ?g .\n?s <http://example.com/q> ?o .";
+        assert_eq!("?s <http://example.com/p> ?o .\n?s <http://example.com/q> ?o .", remove_graph_terms(template));
+    }
+    #[test]
+    fn values_formatted() {
+        let mut statement = String::from("_:b ");
+        push_value(&mut statement, "http://example.com/p", "iri");
+        statement.push(' ');
+        push_value(&mut statement, "1", "lit");
+        assert_eq!("_:b <http://example.com/p> \"1\"", statement);
+        assert_eq!("_:b1", format_value("b1", "blank"));
+    }
+}
@@ -14,25 +14,66 @@
  *    limitations under the License.
  */
 
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::env;
+use std::ops::Range;
+use std::path::Path;
+use log::debug;
 use operator::Operator;
 use serde::Deserialize;
 use serde_json::Value;
+use serde_path_to_error::Segment;
+use crate::error::MopperError;
+use crate::external::EXTERNAL_SOURCE;
+use crate::source::location::{find_file, matching_pattern, FileLocation};
+use crate::secrets::Credentials;
 
+// The maximum length of the snippet of an offending operator in error messages
+const MAX_SNIPPET_LENGTH: usize = 300;
+
+/// Translates a plan of one version of the plan format to the next version.
+type Migration = fn(&mut Value) -> Result<(), MopperError>;
+
+/// The translations of older plan formats, in order: the one at index `i` translates a plan of
+/// version `i + 1` to version `i + 2`. Add one here when the AlgeMapLoom plan format changes.
+const MIGRATIONS: &[Migration] = &[];
+
+/// The version of the plan format mopper executes. Plans declare their version in a top level
+/// `version` field; plans without one are assumed to have this version.
+pub const PLAN_VERSION: u64 = MIGRATIONS.len() as u64 + 1;
+
+/// The nodes of a rewritten plan, by their index in the original plan.
+pub type NodeMap = HashMap<usize, Node>;
+
+/// A node of a plan: an operator with its edges to other nodes.
 #[derive(Deserialize, Clone)]
 pub struct Node {
     pub operator: Operator,
 
-    // The edges
+    /// The nodes sending messages to this node, in order. For a join: first the left, then the right node.
     #[serde(default = "Vec::new")]
     pub from: Vec<usize>,
 
+    /// The nodes this node sends messages to.
     #[serde(default = "HashSet::new")]
     pub to: HashSet<usize>,
 
+    /// The attributes the node has to provide, if known: for a source those of the projections merged
+    /// into it, for a join those used by the next nodes.
     pub attributes: Option<HashSet<String>>,
     
-    pub join_alias: Option<String>
+    /// The join alias to prefix attribute names with, if a self-join is removed in front of this node.
+    pub join_alias: Option<String>,
+
+    /// The nodes of the original plan merged into this one while rewriting.
+    #[serde(skip)]
+    pub merged: Vec<usize>,
+
+    /// For a serializer: the variables that are the same for every record, with their formatted value.
+    /// They are computed once while rewriting.
+    #[serde(skip)]
+    pub constants: HashMap<String, String>
 }
 
 
@@ -73,6 +114,11 @@ impl Node {
         self.to.extend(ids_to_add);
     }
     
+    pub fn add_merged(&mut self, id: usize, merged_ids: &[usize]) {
+        self.merged.push(id);
+        self.merged.extend_from_slice(merged_ids);
+    }
+
     pub fn add_attributes(&mut self, attributes: Option<HashSet<String>>) {
         if self.attributes.is_some() {
             if attributes.is_some() {
@@ -88,8 +134,678 @@ impl Node {
     }
 }
 
+/// A plan as generated by AlgeMapLoom, before rewriting.
 #[derive(Deserialize)]
 pub struct PlanGraph {
     pub nodes: Vec<Node>,
+
+    /// The edges as `[from, to, {"fragment": ...}]`, with `from` and `to` indices in `nodes`.
     pub edges: Vec<Vec<Value>>
-}
\ No newline at end of file
+}
+
+// Only used to find the version of a plan
+#[derive(Deserialize)]
+struct VersionedPlan {
+    version: Option<Value>
+}
+
+impl PlanGraph {
+    /// Reads a plan from its JSON serialisation. Plans of an older version are translated to the
+    /// current version first. If reading fails, the error tells where:
+    /// the line and column, the path in the JSON document and, if the error is in a node,
+    /// (the start of) that node.
+    pub fn from_json(plan_json: &str) -> Result<Self, MopperError> {
+        let version = plan_version(plan_json)?;
+        if version == PLAN_VERSION {
+            return Self::deserialize(plan_json);
+        }
+        let mut plan_value: Value = serde_json::from_str(plan_json)
+            .map_err(|err| MopperError::Plan { msg: err.to_string(), cause: Some(Box::new(err)) })?;
+        migrate(&mut plan_value, version, MIGRATIONS)?;
+        Self::deserialize(&plan_value.to_string()).map_err(|err| match err {
+            MopperError::Plan { msg, cause } => MopperError::Plan { msg: format!("{msg} (in the plan translated from version {version})"), cause },
+            other => other
+        })
+    }
+
+    fn deserialize(plan_json: &str) -> Result<Self, MopperError> {
+        let deserializer = &mut serde_json::Deserializer::from_str(plan_json);
+        serde_path_to_error::deserialize(deserializer).map_err(|err| {
+            let path = err.path().clone();
+            let json_err = err.into_inner();
+            let mut msg = json_err.to_string();
+            if path.iter().next().is_some() {
+                msg.push_str(&format!(", at path {path}"));
+            }
+            if let Some(snippet) = node_snippet(plan_json, &path) {
+                msg.push_str(&format!(", in node {snippet}"));
+            }
+            MopperError::Plan { msg, cause: Some(Box::new(json_err)) }
+        })
+    }
+}
+
+/// Merges plans into one plan, so they are executed in one run. Sources and targets the plans have in
+/// common are merged when rewriting the plan. Every plan comes with the directory to resolve its
+/// relative source paths against, if any.
+pub fn merge_plans(plans: &[(String, Option<String>)]) -> Result<String, MopperError> {
+    merge_plans_with_ranges(plans).map(|(merged_plan, _node_ranges)| merged_plan)
+}
+
+// Merges plans into one plan, and returns it with the range of node ids every plan has in it
+pub(crate) fn merge_plans_with_ranges(plans: &[(String, Option<String>)]) -> Result<(String, Vec<Range<usize>>), MopperError> {
+    let mut nodes: Vec<Value> = Vec::new();
+    let mut edges: Vec<Value> = Vec::new();
+    let mut node_ranges: Vec<Range<usize>> = Vec::with_capacity(plans.len());
+    for (plan_index, (plan_json, working_dir_hint)) in plans.iter().enumerate() {
+        let version = plan_version(plan_json)?;
+        let mut plan_value: Value = serde_json::from_str(plan_json)
+            .map_err(|err| MopperError::Plan { msg: format!("{err}, in plan {}", plan_index + 1), cause: Some(Box::new(err)) })?;
+        migrate(&mut plan_value, version, MIGRATIONS)?;
+
+        let offset = nodes.len() as u64;
+        let (Some(Value::Array(plan_nodes)), Some(Value::Array(plan_edges))) = (plan_value.get_mut("nodes").map(Value::take), plan_value.get_mut("edges").map(Value::take)) else {
+            return Err(MopperError::plan(format!("Plan {} has no nodes or edges", plan_index + 1)));
+        };
+        for mut node in plan_nodes {
+            // the directories of the plans can differ, so resolve relative source paths now
+            if let Some(Value::String(path)) = node.pointer_mut("/operator/config/path") {
+                match find_file(path, working_dir_hint) {
+                    // the URL of a file on a server can have a login, which its location does not show
+                    Ok(FileLocation::Server { .. }) => {},
+                    Ok(file_location) => *path = file_location.to_string(),
+                    Err(_) => if let Some(pattern) = matching_pattern(path, working_dir_hint) {
+                        *path = pattern;
+                    }
+                }
+            }
+            nodes.push(node);
+        }
+        for mut edge in plan_edges {
+            for end in 0..2 {
+                let Some(node_index) = edge.get(end).and_then(Value::as_u64) else {
+                    return Err(MopperError::plan(format!("Edge {edge} of plan {} does not start with two node indices", plan_index + 1)));
+                };
+                edge[end] = Value::from(node_index + offset);
+            }
+            edges.push(edge);
+        }
+        node_ranges.push(offset as usize..nodes.len());
+    }
+    let merged_plan = serde_json::json!({
+        "version": PLAN_VERSION,
+        "nodes": nodes,
+        "node_holes": [],
+        "edge_property": "directed",
+        "edges": edges
+    });
+    Ok((merged_plan.to_string(), node_ranges))
+}
+
+/// The nodes of a reduced plan that one of the merged plans in it needs, given the range of node ids the plan
+/// has in the merged plan: its own nodes, the nodes they receive from, which can be merged with nodes of other
+/// plans, like the sources the plans have in common, and the targets they send to.
+pub(crate) fn nodes_of_merged_plan(node_map: &NodeMap, node_range: &Range<usize>) -> HashSet<usize> {
+    let is_target = |id: &usize| matches!(node_map[id].operator, Operator::TargetOp { .. });
+    // a target can be merged with those of other plans, so it does not tell which nodes a plan has
+    let mut nodes: HashSet<usize> = node_map.keys()
+        .filter(|id| node_range.contains(*id) && !is_target(id))
+        .copied()
+        .collect();
+    let mut to_visit: Vec<usize> = nodes.iter().copied().collect();
+    while let Some(id) = to_visit.pop() {
+        for from in &node_map[&id].from {
+            if nodes.insert(*from) {
+                to_visit.push(*from);
+            }
+        }
+    }
+    let targets: Vec<usize> = nodes.iter()
+        .flat_map(|id| node_map[id].to.iter().copied())
+        .filter(is_target)
+        .collect();
+    nodes.extend(targets);
+    nodes
+}
+
+/// Replaces the placeholders `${name}` in the source and target configurations and in the constants
+/// of the plan by the value of parameter `name`. In source and target configurations, placeholders
+/// without parameter are replaced by the value of environment variable `name` if `from_env` is set, and
+/// secret references `${secret:<provider>:<argument>}` by the secret the credentials provide.
+/// Fails if a placeholder has no value.
+pub fn substitute_params<'a>(plan_json: &'a str, params: &HashMap<String, String>, from_env: bool, credentials: &Credentials) -> Result<Cow<'a, str>, MopperError> {
+    if !plan_json.contains("${") {
+        return Ok(Cow::Borrowed(plan_json));
+    }
+    let mut plan_value: Value = serde_json::from_str(plan_json)
+        .map_err(|err| MopperError::Plan { msg: err.to_string(), cause: Some(Box::new(err)) })?;
+    let mut missing: BTreeSet<String> = BTreeSet::new();
+    let mut secret_errors: Vec<String> = Vec::new();
+    let param = |name: &str| Ok(params.get(name).cloned());
+    let param_or_env_var = |name: &str| match name.strip_prefix("secret:") {
+        Some(reference) => credentials.resolve(reference).map(Some),
+        None => Ok(params.get(name).cloned().or_else(|| env::var(name).ok().filter(|_| from_env)))
+    };
+    if let Some(Value::Array(nodes)) = plan_value.get_mut("nodes") {
+        for node in nodes {
+            let Some(operator) = node.get_mut("operator") else {
+                continue;
+            };
+            let operator_type = operator.get("type").and_then(Value::as_str).unwrap_or_default().to_string();
+            let Some(Value::Object(config)) = operator.get_mut("config") else {
+                continue;
+            };
+            match operator_type.as_str() {
+                "SourceOp" | "TargetOp" => {
+                    for value in config.values_mut() {
+                        if let Value::String(config_value) = value {
+                            *config_value = substitute(config_value, &param_or_env_var, &mut missing, &mut secret_errors);
+                        }
+                    }
+                },
+                "ExtendOp" => {
+                    for function in config.values_mut() {
+                        substitute_in_constants(function, &param, &mut missing, &mut secret_errors);
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+    if !secret_errors.is_empty() {
+        Err(MopperError::plan(secret_errors.join("; ")))
+    } else if missing.is_empty() {
+        Ok(Cow::Owned(plan_value.to_string()))
+    } else {
+        let missing: Vec<String> = missing.into_iter().collect();
+        Err(MopperError::plan(format!("No parameter or environment variable for placeholder(s) {}", missing.join(", "))))
+    }
+}
+
+// Substitutes the placeholders in the values of the constant functions in the (nested) function.
+fn substitute_in_constants(function: &mut Value, lookup: &Lookup, missing: &mut BTreeSet<String>, errors: &mut Vec<String>) {
+    match function {
+        Value::Object(object) => {
+            let is_constant = object.get("type").and_then(Value::as_str) == Some("Constant");
+            for (key, value) in object.iter_mut() {
+                match value {
+                    Value::String(constant) if is_constant && key == "value" => *constant = substitute(constant, lookup, missing, errors),
+                    _ => substitute_in_constants(value, lookup, missing, errors)
+                }
+            }
+        },
+        Value::Array(values) => values.iter_mut()
+            .for_each(|value| substitute_in_constants(value, lookup, missing, errors)),
+        _ => {}
+    }
+}
+
+// Gives the value of a placeholder, if any, or an error if getting it fails
+type Lookup<'a> = dyn Fn(&str) -> Result<Option<String>, String> + 'a;
+
+// Replaces the placeholders in the text by the values `lookup` gives, and adds the names of the
+// placeholders without value to `missing` and the errors of `lookup` to `errors`.
+fn substitute(text: &str, lookup: &Lookup, missing: &mut BTreeSet<String>, errors: &mut Vec<String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let Some(length) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + length];
+        result.push_str(&rest[..start]);
+        match lookup(name) {
+            Ok(Some(value)) => result.push_str(&value),
+            Ok(None) => {
+                missing.insert(name.to_string());
+                result.push_str(&rest[start..start + 3 + length]);
+            },
+            Err(error) => {
+                errors.push(error);
+                result.push_str(&rest[start..start + 3 + length]);
+            }
+        }
+        rest = &rest[start + 3 + length..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Replaces the paths of the sources in the plan according to `overrides`, pairs of a file name or path
+/// and the new path. The path of a source is replaced by the new path of the first pair whose file name
+/// or path the source path ends with, comparing whole path components.
+/// Fails if a pair matches no source, as that is most likely a typo.
+pub fn override_sources<'a>(plan_json: &'a str, overrides: &[(String, String)]) -> Result<Cow<'a, str>, MopperError> {
+    if overrides.is_empty() {
+        return Ok(Cow::Borrowed(plan_json));
+    }
+    let mut plan_value: Value = serde_json::from_str(plan_json)
+        .map_err(|err| MopperError::Plan { msg: err.to_string(), cause: Some(Box::new(err)) })?;
+    let mut used = vec![false; overrides.len()];
+    if let Some(Value::Array(nodes)) = plan_value.get_mut("nodes") {
+        for node in nodes {
+            let Some(operator) = node.get_mut("operator") else {
+                continue;
+            };
+            if operator.get("type").and_then(Value::as_str) != Some("SourceOp") {
+                continue;
+            }
+            let Some(Value::String(path)) = operator.pointer_mut("/config/path") else {
+                continue;
+            };
+            let position = overrides.iter()
+                .position(|(name_or_path, _)| Path::new(path.as_str()).ends_with(name_or_path));
+            if let Some(position) = position {
+                debug!("Reading source {path} from {}", overrides[position].1);
+                path.clone_from(&overrides[position].1);
+                used[position] = true;
+            }
+        }
+    }
+    let unused: Vec<&str> = overrides.iter().zip(used)
+        .filter(|(_, used)| !used)
+        .map(|((name_or_path, _), _)| name_or_path.as_str())
+        .collect();
+    if unused.is_empty() {
+        Ok(Cow::Owned(plan_value.to_string()))
+    } else {
+        Err(MopperError::plan(format!("No source with path or file name {} to override", unused.join(", "))))
+    }
+}
+
+/// Turns the sources in the plan whose path ends with one of `names`, a file name or path, into external
+/// sources with that name, whose records are pushed by the caller (see [crate::external]).
+/// Fails if a name matches no source.
+pub fn externalize_sources<'a>(plan_json: &'a str, names: &[String]) -> Result<Cow<'a, str>, MopperError> {
+    if names.is_empty() {
+        return Ok(Cow::Borrowed(plan_json));
+    }
+    let mut plan_value: Value = serde_json::from_str(plan_json)
+        .map_err(|err| MopperError::Plan { msg: err.to_string(), cause: Some(Box::new(err)) })?;
+    let mut used = vec![false; names.len()];
+    if let Some(Value::Array(nodes)) = plan_value.get_mut("nodes") {
+        for node in nodes {
+            let Some(operator) = node.get_mut("operator") else {
+                continue;
+            };
+            if operator.get("type").and_then(Value::as_str) != Some("SourceOp") {
+                continue;
+            }
+            let Some(Value::Object(config)) = operator.get_mut("config") else {
+                continue;
+            };
+            let Some(path) = config.get("path").and_then(Value::as_str) else {
+                continue;
+            };
+            let position = names.iter()
+                .position(|name| Path::new(path).ends_with(name));
+            if let Some(position) = position {
+                debug!("Taking the records of source {path} from external source {}", names[position]);
+                config.insert("source".to_string(), Value::String(EXTERNAL_SOURCE.to_string()));
+                config.insert("name".to_string(), Value::String(names[position].clone()));
+                used[position] = true;
+            }
+        }
+    }
+    let unused: Vec<&str> = names.iter().zip(used)
+        .filter(|(_, used)| !used)
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if unused.is_empty() {
+        Ok(Cow::Owned(plan_value.to_string()))
+    } else {
+        Err(MopperError::plan(format!("No source with path or file name {} to take records for", unused.join(", "))))
+    }
+}
+
+/// The sources in the plan that are not external sources, i.e. that mopper reads itself, by their path or
+/// URL, or else by their type.
+pub fn internal_sources(plan_json: &str) -> Result<Vec<String>, MopperError> {
+    let plan_value: Value = serde_json::from_str(plan_json)
+        .map_err(|err| MopperError::Plan { msg: err.to_string(), cause: Some(Box::new(err)) })?;
+    let Some(Value::Array(nodes)) = plan_value.get("nodes") else {
+        return Ok(Vec::new());
+    };
+    Ok(nodes.iter()
+        .filter(|node| node.pointer("/operator/type").and_then(Value::as_str) == Some("SourceOp"))
+        .filter_map(|node| node.pointer("/operator/config"))
+        .filter(|config| config.get("source").and_then(Value::as_str) != Some(EXTERNAL_SOURCE))
+        .map(|config| ["path", "url", "source", "source_type"].iter()
+            .find_map(|key| config.get(*key).and_then(Value::as_str))
+            .unwrap_or("unknown")
+            .to_string())
+        .collect())
+}
+
+/// Sets the base IRI of all IRI functions in the plan, so relative IRIs are resolved uniformly.
+pub fn set_base_iri<'a>(plan_json: &'a str, base_iri: &str) -> Result<Cow<'a, str>, MopperError> {
+    let mut plan_value: Value = serde_json::from_str(plan_json)
+        .map_err(|err| MopperError::Plan { msg: err.to_string(), cause: Some(Box::new(err)) })?;
+    if let Some(Value::Array(nodes)) = plan_value.get_mut("nodes") {
+        for node in nodes {
+            let Some(operator) = node.get_mut("operator") else {
+                continue;
+            };
+            if operator.get("type").and_then(Value::as_str) != Some("ExtendOp") {
+                continue;
+            }
+            if let Some(Value::Object(config)) = operator.get_mut("config") {
+                for function in config.values_mut() {
+                    set_base_iri_in_functions(function, base_iri);
+                }
+            }
+        }
+    }
+    Ok(Cow::Owned(plan_value.to_string()))
+}
+
+fn set_base_iri_in_functions(function: &mut Value, base_iri: &str) {
+    match function {
+        Value::Object(object) => {
+            if object.get("type").and_then(Value::as_str) == Some("Iri") {
+                object.insert("base_iri".to_string(), Value::String(base_iri.to_string()));
+            }
+            object.values_mut().for_each(|value| set_base_iri_in_functions(value, base_iri));
+        },
+        Value::Array(values) => values.iter_mut()
+            .for_each(|value| set_base_iri_in_functions(value, base_iri)),
+        _ => {}
+    }
+}
+
+/// Converts a plan in YAML to JSON, so it can be read with [PlanGraph::from_json].
+pub fn yaml_to_json(plan_yaml: &str) -> Result<String, MopperError> {
+    let plan_value: Value = serde_yaml::from_str(plan_yaml)
+        .map_err(|err| MopperError::Plan { msg: err.to_string(), cause: Some(Box::new(err)) })?;
+    Ok(plan_value.to_string())
+}
+
+/// Converts a plan in JSON to YAML, e.g. to write it in the format it can be read in with [yaml_to_json].
+pub fn json_to_yaml(plan_json: &str) -> Result<String, MopperError> {
+    let plan_value: Value = serde_json::from_str(plan_json)
+        .map_err(|err| MopperError::Plan { msg: err.to_string(), cause: Some(Box::new(err)) })?;
+    serde_yaml::to_string(&plan_value)
+        .map_err(|err| MopperError::Plan { msg: err.to_string(), cause: Some(Box::new(err)) })
+}
+
+/// Formats a plan in JSON to be read by humans, e.g. to inspect it or keep it under version control.
+pub fn pretty_json(plan_json: &str) -> Result<String, MopperError> {
+    let plan_value: Value = serde_json::from_str(plan_json)
+        .map_err(|err| MopperError::Plan { msg: err.to_string(), cause: Some(Box::new(err)) })?;
+    let mut pretty_plan = serde_json::to_string_pretty(&plan_value)
+        .map_err(|err| MopperError::Plan { msg: err.to_string(), cause: Some(Box::new(err)) })?;
+    pretty_plan.push('\n');
+    Ok(pretty_plan)
+}
+
+// Returns the version the plan declares, or the current version if it declares none.
+fn plan_version(plan_json: &str) -> Result<u64, MopperError> {
+    // a plan that cannot be read gets a detailed error when deserializing
+    let Ok(VersionedPlan { version: Some(version_value) }) = serde_json::from_str::<VersionedPlan>(plan_json) else {
+        return Ok(PLAN_VERSION);
+    };
+    let version = match &version_value {
+        Value::Number(number) => number.as_u64(),
+        Value::String(version_str) => version_str.trim().parse::<u64>().ok(),
+        _ => None
+    };
+    match version {
+        Some(version) if (1..=PLAN_VERSION).contains(&version) => Ok(version),
+        Some(version) if version > PLAN_VERSION => Err(MopperError::plan(format!("The plan has version {version}, but this version of mopper only supports plan versions up to {PLAN_VERSION}. Please upgrade mopper."))),
+        _ => Err(MopperError::plan(format!("Invalid plan version {version_value}: expected a number from 1 to {PLAN_VERSION}")))
+    }
+}
+
+// Translates the plan from the given version to the latest version the migrations lead to.
+fn migrate(plan_value: &mut Value, version: u64, migrations: &[Migration]) -> Result<(), MopperError> {
+    for migration in &migrations[(version - 1) as usize..] {
+        migration(plan_value)?;
+    }
+    Ok(())
+}
+
+// Returns (the start of) the JSON of the node the path points into, if any.
+fn node_snippet(plan_json: &str, path: &serde_path_to_error::Path) -> Option<String> {
+    let mut segments = path.iter();
+    let (Some(Segment::Map { key }), Some(Segment::Seq { index })) = (segments.next(), segments.next()) else {
+        return None;
+    };
+    if key != "nodes" {
+        return None;
+    }
+    let plan_value: Value = serde_json::from_str(plan_json).ok()?;
+    let node = plan_value.get("nodes")?.get(index)?;
+    let mut snippet = node.to_string();
+    if snippet.len() > MAX_SNIPPET_LENGTH {
+        let mut end = MAX_SNIPPET_LENGTH;
+        while !snippet.is_char_boundary(end) {
+            end -= 1;
+        }
+        snippet.truncate(end);
+        snippet.push_str("...");
+    }
+    Some(snippet)
+}
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    const PLAN: &str = r#"{"nodes": [], "node_holes": [], "edge_property": "directed", "edges": []}"#;
+
+    #[test]
+    fn unversioned_plan() {
+        assert_eq!(PLAN_VERSION, plan_version(PLAN).unwrap());
+    }
+
+    #[test]
+    fn versioned_plan() {
+        let plan = format!(r#"{{"version": "{PLAN_VERSION}", "nodes": [], "edges": []}}"#);
+        assert_eq!(PLAN_VERSION, plan_version(&plan).unwrap());
+        assert!(PlanGraph::from_json(&plan).is_ok());
+    }
+
+    #[test]
+    fn newer_plan() {
+        let plan = format!(r#"{{"version": {}, "nodes": [], "edges": []}}"#, PLAN_VERSION + 1);
+        let Err(MopperError::Plan { msg, .. }) = PlanGraph::from_json(&plan) else {
+            panic!("A newer plan should not be accepted");
+        };
+        assert!(msg.contains("upgrade mopper"), "{msg}");
+    }
+
+    #[test]
+    fn invalid_version() {
+        let plan = r#"{"version": "latest", "nodes": [], "edges": []}"#;
+        assert!(matches!(PlanGraph::from_json(plan), Err(MopperError::Plan { .. })));
+    }
+
+    #[test]
+    fn yaml_plan() {
+        let plan_json = std::fs::read_to_string("test-resources/rml-testcases/RMLTC1036-CSV/mapping.json").unwrap();
+        let plan_value: Value = serde_json::from_str(&plan_json).unwrap();
+        let plan_yaml = serde_yaml::to_string(&plan_value).unwrap();
+        let plan_graph = PlanGraph::from_json(&yaml_to_json(&plan_yaml).unwrap()).unwrap();
+        assert_eq!(16, plan_graph.nodes.len());
+        assert_eq!(15, plan_graph.edges.len());
+    }
+
+    #[test]
+    fn plan_to_yaml_and_back() {
+        let plan_json = std::fs::read_to_string("test-resources/rml-testcases/RMLTC1036-CSV/mapping.json").unwrap();
+        let plan_yaml = json_to_yaml(&pretty_json(&plan_json).unwrap()).unwrap();
+        let plan_value: Value = serde_json::from_str(&yaml_to_json(&plan_yaml).unwrap()).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&plan_json).unwrap(), plan_value);
+    }
+
+    #[test]
+    fn invalid_yaml_plan() {
+        let Err(MopperError::Plan { msg, .. }) = yaml_to_json("nodes: [\n  - {") else {
+            panic!("Invalid YAML should not be accepted");
+        };
+        assert!(msg.contains("line"), "{msg}");
+    }
+
+    #[test]
+    fn merge() {
+        let plan_json = std::fs::read_to_string("test-resources/rml-testcases/RMLTC1036-CSV/mapping.json").unwrap();
+        let test_dir = Some("test-resources/rml-testcases/RMLTC1036-CSV".to_string());
+        let merged_plan = merge_plans(&[(plan_json.clone(), test_dir.clone()), (plan_json, test_dir)]).unwrap();
+        let plan_graph = PlanGraph::from_json(&merged_plan).unwrap();
+        assert_eq!(32, plan_graph.nodes.len());
+        assert_eq!(30, plan_graph.edges.len());
+        assert_eq!(json!([16, 17, {"fragment": "default"}]), json!(plan_graph.edges[15]));
+
+        // the sources of both plans are the same, so they are merged
+        let node_map = crate::plan_rewriter::rewrite(&plan_graph, true).unwrap();
+        let sources = node_map.values()
+            .filter(|node| matches!(node.operator, Operator::SourceOp { .. }))
+            .count();
+        assert_eq!(2, sources);
+    }
+
+    #[test]
+    fn params() {
+        let mut plan = json!({"nodes": [
+            {"operator": {"type": "SourceOp", "config": {"path": "${dir}/sport.csv", "source_type": "File"}}},
+            {"operator": {"type": "ExtendOp", "config": {"?p": {"type": "Iri", "base_iri": "${base}", "inner_function":
+                {"type": "Constant", "value": "http://${host}/ontology/${name}"}}}}}
+        ], "edges": []});
+        let params = HashMap::from([
+            ("dir".to_string(), "data".to_string()),
+            ("host".to_string(), "example.com".to_string()),
+            ("name".to_string(), "practises".to_string())
+        ]);
+        let substituted: Value = serde_json::from_str(&substitute_params(&plan.to_string(), &params, true, &Credentials::default()).unwrap()).unwrap();
+        assert_eq!("data/sport.csv", substituted.pointer("/nodes/0/operator/config/path").unwrap());
+        assert_eq!("http://example.com/ontology/practises", substituted.pointer("/nodes/1/operator/config/?p/inner_function/value").unwrap());
+        // only constants are substituted
+        assert_eq!("${base}", substituted.pointer("/nodes/1/operator/config/?p/base_iri").unwrap());
+
+        plan["nodes"][0]["operator"]["config"]["path"] = json!("${data_dir}/${file}");
+        let Err(MopperError::Plan { msg, .. }) = substitute_params(&plan.to_string(), &params, true, &Credentials::default()) else {
+            panic!("Missing parameters should not be accepted");
+        };
+        assert_eq!("No parameter or environment variable for placeholder(s) data_dir, file", msg);
+    }
+
+    #[test]
+    fn env_vars() {
+        env::set_var("MOPPER_TEST_DATA_DIR", "/data");
+        let plan = json!({"nodes": [
+            {"operator": {"type": "SourceOp", "config": {"path": "${MOPPER_TEST_DATA_DIR}/sport.csv", "source_type": "File"}}},
+            {"operator": {"type": "ExtendOp", "config": {"?o": {"type": "Constant", "value": "${MOPPER_TEST_DATA_DIR}"}}}}
+        ], "edges": []});
+
+        // not in constants
+        let Err(MopperError::Plan { msg, .. }) = substitute_params(&plan.to_string(), &HashMap::new(), true, &Credentials::default()) else {
+            panic!("Environment variables should not be used in constants");
+        };
+        assert_eq!("No parameter or environment variable for placeholder(s) MOPPER_TEST_DATA_DIR", msg);
+
+        // parameters come first
+        let params = HashMap::from([("MOPPER_TEST_DATA_DIR".to_string(), "/test".to_string())]);
+        let substituted: Value = serde_json::from_str(&substitute_params(&plan.to_string(), &params, true, &Credentials::default()).unwrap()).unwrap();
+        assert_eq!("/test/sport.csv", substituted.pointer("/nodes/0/operator/config/path").unwrap());
+
+        let mut plan = plan;
+        plan["nodes"][1]["operator"]["config"]["?o"]["value"] = json!("constant");
+        let substituted: Value = serde_json::from_str(&substitute_params(&plan.to_string(), &HashMap::new(), true, &Credentials::default()).unwrap()).unwrap();
+        assert_eq!("/data/sport.csv", substituted.pointer("/nodes/0/operator/config/path").unwrap());
+
+        // unless they are not to be read
+        let Err(MopperError::Plan { msg, .. }) = substitute_params(&plan.to_string(), &HashMap::new(), false, &Credentials::default()) else {
+            panic!("Environment variables should not be used if they are not to be read");
+        };
+        assert_eq!("No parameter or environment variable for placeholder(s) MOPPER_TEST_DATA_DIR", msg);
+    }
+
+    #[test]
+    fn source_overrides() {
+        let plan = json!({"nodes": [
+            {"operator": {"type": "SourceOp", "config": {"path": "fixtures/student.csv", "source_type": "File"}}},
+            {"operator": {"type": "SourceOp", "config": {"path": "fixtures/sport.csv", "source_type": "File"}}},
+            {"operator": {"type": "SourceOp", "config": {"path": "fixtures/nostudent.csv", "source_type": "File"}}}
+        ], "edges": []});
+        let overrides = vec![
+            ("student.csv".to_string(), "/mnt/data/student.csv".to_string()),
+            ("fixtures/sport.csv".to_string(), "/mnt/data/sport.csv".to_string())
+        ];
+        let overridden: Value = serde_json::from_str(&override_sources(&plan.to_string(), &overrides).unwrap()).unwrap();
+        assert_eq!("/mnt/data/student.csv", overridden.pointer("/nodes/0/operator/config/path").unwrap());
+        assert_eq!("/mnt/data/sport.csv", overridden.pointer("/nodes/1/operator/config/path").unwrap());
+        assert_eq!("fixtures/nostudent.csv", overridden.pointer("/nodes/2/operator/config/path").unwrap());
+
+        let overrides = vec![("course.csv".to_string(), "/mnt/data/course.csv".to_string())];
+        let Err(MopperError::Plan { msg, .. }) = override_sources(&plan.to_string(), &overrides) else {
+            panic!("Overrides that match no source should not be accepted");
+        };
+        assert_eq!("No source with path or file name course.csv to override", msg);
+    }
+
+    #[test]
+    fn external_sources() {
+        let plan = json!({"nodes": [
+            {"operator": {"type": "SourceOp", "config": {"path": "fixtures/student.csv", "source_type": "File"}}},
+            {"operator": {"type": "SourceOp", "config": {"path": "fixtures/sport.csv", "source_type": "File"}}}
+        ], "edges": []});
+        let externalized: Value = serde_json::from_str(&externalize_sources(&plan.to_string(), &["sport.csv".to_string()]).unwrap()).unwrap();
+        assert_eq!(None, externalized.pointer("/nodes/0/operator/config/source"));
+        assert_eq!("external", externalized.pointer("/nodes/1/operator/config/source").unwrap());
+        assert_eq!("sport.csv", externalized.pointer("/nodes/1/operator/config/name").unwrap());
+        assert!(externalize_sources(&plan.to_string(), &["course.csv".to_string()]).is_err());
+        assert_eq!(vec!["fixtures/student.csv"], internal_sources(&externalized.to_string()).unwrap());
+    }
+
+    #[test]
+    fn base_iri() {
+        let plan = json!({"nodes": [
+            {"operator": {"type": "ExtendOp", "config": {
+                "?s": {"type": "Iri", "base_iri": "http://example.com/base/", "inner_function": {"type": "Reference", "value": "ID"}},
+                "?o": {"type": "BlankNode", "inner_function": {"type": "Iri", "base_iri": null, "inner_function": {"type": "Reference", "value": "Name"}}}
+            }}}
+        ], "edges": []});
+        let with_base: Value = serde_json::from_str(&set_base_iri(&plan.to_string(), "http://example.org/").unwrap()).unwrap();
+        assert_eq!("http://example.org/", with_base.pointer("/nodes/0/operator/config/?s/base_iri").unwrap());
+        assert_eq!("http://example.org/", with_base.pointer("/nodes/0/operator/config/?o/inner_function/base_iri").unwrap());
+    }
+
+    #[test]
+    fn secrets() {
+        env::set_var("MOPPER_TEST_PASSWORD", "s3cret");
+        let mut plan = json!({"nodes": [
+            {"operator": {"type": "SourceOp", "config": {"password": "${secret:env:MOPPER_TEST_PASSWORD}", "source_type": "MySQL"}}}
+        ], "edges": []});
+        let substituted: Value = serde_json::from_str(&substitute_params(&plan.to_string(), &HashMap::new(), true, &Credentials::default()).unwrap()).unwrap();
+        assert_eq!("s3cret", substituted.pointer("/nodes/0/operator/config/password").unwrap());
+
+        plan["nodes"][0]["operator"]["config"]["password"] = json!("${secret:vault:db}");
+        let Err(MopperError::Plan { msg, .. }) = substitute_params(&plan.to_string(), &HashMap::new(), true, &Credentials::default()) else {
+            panic!("Unknown credentials providers should not be accepted");
+        };
+        assert_eq!("Unknown credentials provider 'vault' in secret reference 'vault:db'", msg);
+    }
+
+    #[test]
+    fn migrations() {
+        // version 1 calls the edges "links", version 2 has no "node_holes"
+        let rename_links: Migration = |plan| {
+            let links = plan.as_object_mut().unwrap().remove("links").unwrap();
+            plan["edges"] = links;
+            Ok(())
+        };
+        let remove_node_holes: Migration = |plan| {
+            plan.as_object_mut().unwrap().remove("node_holes");
+            Ok(())
+        };
+        let migrations = [rename_links, remove_node_holes];
+
+        let mut plan = json!({"nodes": [], "node_holes": [], "links": [[0, 1]]});
+        migrate(&mut plan, 1, &migrations).unwrap();
+        assert_eq!(json!({"nodes": [], "edges": [[0, 1]]}), plan);
+
+        let mut plan = json!({"nodes": [], "node_holes": [], "edges": []});
+        migrate(&mut plan, 2, &migrations).unwrap();
+        assert_eq!(json!({"nodes": [], "edges": []}), plan);
+    }
+}
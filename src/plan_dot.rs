@@ -0,0 +1,124 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::fmt::Write;
+use operator::Operator;
+use crate::plan::NodeMap;
+
+/// Renders the nodes of a (rewritten) plan as a Graphviz DOT graph.
+/// Every node shows its index, its operator and the most important parts of its configuration.
+/// If the output is forced to one target, `forced_target` describes it.
+pub fn to_dot(node_map: &NodeMap, forced_target: Option<&str>) -> String {
+    let mut ids: Vec<&usize> = node_map.keys().collect();
+    ids.sort();
+
+    let mut dot = String::from("digraph plan {\n    rankdir=LR;\n    node [shape=box];\n");
+    for id in &ids {
+        let node = &node_map[id];
+        let mut lines = vec![format!("{id}: {}", operator_name(&node.operator))];
+        lines.extend(operator_details(&node.operator, forced_target));
+        if let Some(join_alias) = &node.join_alias {
+            lines.push(format!("join alias: {join_alias}"));
+        }
+        if let Some(attributes) = &node.attributes {
+            let mut attributes: Vec<&String> = attributes.iter().collect();
+            attributes.sort();
+            lines.push(format!("attributes: {}", join_strings(attributes)));
+        }
+        let label: Vec<String> = lines.iter().map(|line| escape(line)).collect();
+        let _ = writeln!(dot, "    {id} [label=\"{}\\l\"];", label.join("\\l"));
+    }
+    for id in &ids {
+        let mut to_ids: Vec<&usize> = node_map[id].to.iter().collect();
+        to_ids.sort();
+        for to_id in to_ids {
+            let _ = writeln!(dot, "    {id} -> {to_id};");
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// The name of the operator, e.g. "SourceOp".
+pub fn operator_name(operator: &Operator) -> String {
+    let debug_str = format!("{operator:?}");
+    debug_str.split([' ', '{', '(']).next().unwrap_or_default().to_string()
+}
+
+fn operator_details(operator: &Operator, forced_target: Option<&str>) -> Vec<String> {
+    match operator {
+        Operator::SourceOp { config } => {
+            let mut details = vec![format!("{:?}, {:?}", config.source_type, config.root_iterator.reference_formulation)];
+            if let Some(path) = config.config.get("path") {
+                details.push(format!("path: {path}"));
+            }
+            details
+        },
+        Operator::ExtendOp { config } => {
+            let mut variables: Vec<&String> = config.extend_pairs.keys().collect();
+            variables.sort();
+            vec![format!("variables: {}", join_strings(variables))]
+        },
+        Operator::JoinOp { config } => {
+            let pairs: Vec<String> = config.left_right_attr_pairs.iter()
+                .map(|(left, right)| format!("{left} = {right}"))
+                .collect();
+            vec![format!("{:?} on {}", config.join_type, pairs.join(", "))]
+        },
+        Operator::SerializerOp { config } => vec![format!("{:?}", config.format)],
+        Operator::TargetOp { config } => match forced_target {
+            Some(forced_target) => vec![format!("forced to {forced_target}")],
+            None => {
+                let mut details = vec![format!("{:?}", config.target_type)];
+                if let Some(path) = config.configuration.get("path") {
+                    details.push(format!("path: {path}"));
+                }
+                details
+            }
+        },
+        _ => Vec::new()
+    }
+}
+
+fn join_strings(strings: Vec<&String>) -> String {
+    strings.into_iter().map(|string| string.as_str()).collect::<Vec<&str>>().join(", ")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::plan::PlanGraph;
+    use crate::plan_rewriter::rewrite;
+    use super::*;
+
+    #[test]
+    fn plan_1036_to_dot() {
+        let plan_str = fs::read_to_string("test-resources/rml-testcases/RMLTC1036-CSV/mapping.json").unwrap();
+        let plan_graph = PlanGraph::from_json(&plan_str).unwrap();
+        let node_map = rewrite(&plan_graph, true).unwrap();
+        let dot = to_dot(&node_map, Some("standard out"));
+        assert!(dot.starts_with("digraph plan {\n"));
+        assert!(dot.contains("    6 [label=\"6: JoinOp\\l"));
+        assert!(dot.contains("    9 [label=\"9: TargetOp\\lforced to standard out\\l\"];\n"));
+        assert!(!dot.contains("    12 ["));
+        assert!(dot.contains("    0 -> 6;\n    0 -> 13;\n"));
+        assert!(dot.contains("    14 -> 9;\n}\n"));
+    }
+}
@@ -0,0 +1,173 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use operator::{IOType, Operator};
+use crate::source::location::find_files;
+use crate::mopper_options::MopperOptions;
+use crate::plan::{Node, NodeMap, PlanGraph};
+use crate::plan_dot::operator_name;
+use crate::stats::OperatorKind;
+
+/// Describes the operators `start` builds for the rewritten plan: what every operator reads or writes,
+/// where its messages come from and go to, and which nodes of the original plan were merged or removed.
+pub fn explain(plan: &PlanGraph, node_map: &NodeMap, options: &MopperOptions) -> String {
+    let mut ids: Vec<&usize> = node_map.keys().collect();
+    ids.sort();
+
+    let mut explanation = String::new();
+    let _ = writeln!(explanation, "Execution pipeline: {} operators, message buffer capacity {}", node_map.len(), options.message_buffer_capacity());
+    for &id in &ids {
+        let node = &node_map[id];
+        let kind = operator_kind(&node.operator)
+            .map(|kind| kind.to_string())
+            .unwrap_or_else(|| format!("{} (not supported)", operator_name(&node.operator)));
+        let _ = writeln!(explanation, "  {id:>4}  {kind:<10}  {}", operator_description(*id, node, options));
+        if let Some(attributes) = &node.attributes {
+            let mut attributes: Vec<&str> = attributes.iter().map(|attribute| attribute.as_str()).collect();
+            attributes.sort();
+            let _ = writeln!(explanation, "{:18}attributes: {}", "", attributes.join(", "));
+        }
+        if !node.constants.is_empty() {
+            let mut constants: Vec<&str> = node.constants.keys().map(|variable| variable.as_str()).collect();
+            constants.sort();
+            let _ = writeln!(explanation, "{:18}constants: {}", "", constants.join(", "));
+        }
+        if !node.from.is_empty() {
+            let _ = writeln!(explanation, "{:18}receives from: {}", "", join_ids(node.from.iter()));
+        }
+        if !node.to.is_empty() {
+            let mut to_ids: Vec<&usize> = node.to.iter().collect();
+            to_ids.sort();
+            let _ = writeln!(explanation, "{:18}sends to: {}", "", join_ids(to_ids.into_iter()));
+        }
+        if !node.merged.is_empty() {
+            let mut merged = node.merged.clone();
+            merged.sort();
+            let _ = writeln!(explanation, "{:18}merged: {}", "", join_ids(merged.iter()));
+        }
+    }
+
+    // the nodes that are not in the rewritten plan, and not merged into another node
+    let merged: HashSet<usize> = node_map.values()
+        .flat_map(|node| node.merged.iter().copied())
+        .collect();
+    let removed: Vec<String> = (0..plan.nodes.len())
+        .filter(|index| !node_map.contains_key(index) && !merged.contains(index))
+        .map(|index| format!("{index} ({})", operator_name(&plan.nodes[index].operator)))
+        .collect();
+    if !removed.is_empty() {
+        let _ = writeln!(explanation, "Removed: {}", removed.join(", "));
+    }
+    explanation
+}
+
+/// The kind of operator `start` creates for the given operator of the plan, if any.
+fn operator_kind(operator: &Operator) -> Option<OperatorKind> {
+    match operator {
+        Operator::SourceOp { .. } => Some(OperatorKind::Source),
+        Operator::ExtendOp { .. } => Some(OperatorKind::Extend),
+        Operator::JoinOp { .. } => Some(OperatorKind::Join),
+        Operator::SerializerOp { .. } => Some(OperatorKind::Serializer),
+        Operator::TargetOp { .. } => Some(OperatorKind::Sink),
+        _ => None
+    }
+}
+
+fn operator_description(node_id: usize, node: &Node, options: &MopperOptions) -> String {
+    match &node.operator {
+        Operator::SourceOp { config } => {
+            let reference_formulation = &config.root_iterator.reference_formulation;
+            match config.config.get("path") {
+                Some(path) => match find_files(path, options.working_dir_hint()) {
+                    Ok(file_locations) if file_locations.len() == 1 => format!("{:?}, {reference_formulation:?}: {}", config.source_type, file_locations[0]),
+                    Ok(file_locations) => format!("{:?}, {reference_formulation:?}: {path} ({} files)", config.source_type, file_locations.len()),
+                    Err(_) => format!("{:?}, {reference_formulation:?}: {path} (not found)", config.source_type)
+                },
+                None => format!("{:?}, {reference_formulation:?}", config.source_type)
+            }
+        },
+        Operator::ExtendOp { config } => {
+            let mut variables: Vec<&str> = config.extend_pairs.keys().map(|variable| variable.as_str()).collect();
+            variables.sort();
+            let mut description = format!("variables: {}", variables.join(", "));
+            if let Some(join_alias) = &node.join_alias {
+                let _ = write!(description, "; join alias {join_alias}");
+            }
+            description
+        },
+        Operator::JoinOp { config } => {
+            let pairs: Vec<String> = config.left_right_attr_pairs.iter()
+                .map(|(left, right)| format!("{left} = {right}"))
+                .collect();
+            let mut description = format!("{:?} on {}", config.join_type, pairs.join(", "));
+            if let [left, right, ..] = node.from.as_slice() {
+                let _ = write!(description, "; left {left}, right {right}");
+            }
+            description
+        },
+        Operator::SerializerOp { config } => format!("{:?}", config.format),
+        Operator::TargetOp { config } => {
+            let mut description = if options.force_to_std_out() {
+                "standard out (forced)".to_string()
+            } else if let Some(file_path) = options.force_to_file() {
+                format!("file {file_path} (forced)")
+            } else if config.target_type == IOType::StdOut {
+                "standard out".to_string()
+            } else {
+                format!("{:?} (not supported)", config.target_type)
+            };
+            if options.deduplicates_sink(node_id) {
+                description.push_str(", deduplicated");
+                if options.deduplicate_globally() {
+                    description.push_str(" globally");
+                }
+            }
+            description
+        },
+        _ => String::new()
+    }
+}
+
+fn join_ids<'a>(ids: impl Iterator<Item = &'a usize>) -> String {
+    ids.map(|id| id.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::mopper_options::MopperOptionsBuilder;
+    use crate::plan_rewriter::rewrite;
+    use super::*;
+
+    #[test]
+    fn explain_1036() {
+        let plan_str = fs::read_to_string("test-resources/rml-testcases/RMLTC1036-CSV/mapping.json").unwrap();
+        let plan_graph = PlanGraph::from_json(&plan_str).unwrap();
+        let node_map = rewrite(&plan_graph, true).unwrap();
+        let options = MopperOptionsBuilder::default()
+            .force_to_std_out(true)
+            .working_dir_hint("test-resources/rml-testcases/RMLTC1036-CSV")
+            .build().unwrap();
+        let explanation = explain(&plan_graph, &node_map, &options);
+        assert!(explanation.starts_with("Execution pipeline: 10 operators, message buffer capacity 128\n"));
+        assert!(explanation.contains("     0  Source      File, CSVRows: test-resources/rml-testcases/RMLTC1036-CSV/sport.csv\n"));
+        assert!(explanation.contains("     6  Join        InnerJoin on Sport = ID; left 2, right 0\n"));
+        assert!(explanation.contains("     9  Sink        standard out (forced)\n                  receives from: 8, 11, 14\n                  merged: 12, 15\n"));
+        assert!(explanation.ends_with("Removed: 4 (FragmentOp), 5 (FragmentOp)\n"));
+    }
+}
@@ -14,11 +14,21 @@
  *    limitations under the License.
  */
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Component, Path, PathBuf};
 use log::{debug, info};
-use operator::Operator;
-use crate::plan::{Node, PlanGraph};
+use operator::{Function, IOType, Join, Operator, Source};
+use operator::formats::ReferenceFormulation;
+use serde_json::Value;
+use crate::error::MopperError;
+use crate::function::compiled::CompiledFunction;
+use crate::function::counter::{Counter, CounterScope};
+use crate::function::template_parser::parse_template;
+use crate::mopper_options::{IriEncoding, MissingReferencePolicy};
+use crate::operator::serializer::format_value;
+use crate::plan::{Node, NodeMap, PlanGraph};
+use crate::source::RECORD_NUMBER_REFERENCE;
 
 // Add destination(s) to node
 // Merge Projection operator into source
@@ -27,9 +37,13 @@ use crate::plan::{Node, PlanGraph};
 
 // TODO: if output is forced to std out and/or file, don't hash and put everything to e.g. 0 (and 1)
 
-pub fn rewrite(plan: &PlanGraph, to_one_target: bool) -> HashMap<usize, Node> {
+/// Rewrites the plan into the nodes the execution engine runs: Fragment, Projection and self-join nodes
+/// are removed, and sources or targets with the same configuration, and identical extend nodes, are merged.
+/// If `to_one_target` is set, all targets are merged into one.
+/// Fails if the edges do not connect existing nodes, or if they form a cycle.
+pub fn rewrite(plan: &PlanGraph, to_one_target: bool) -> Result<NodeMap, MopperError> {
     info!("Optimizing AlgeMapLoom plan a bit.");
-    let mut node_map: HashMap<usize, Node> = HashMap::new();
+    let mut node_map: NodeMap = HashMap::new();
     
     let mut fragment_indices = Vec::new();
     let mut projection_indices = Vec::new();
@@ -45,7 +59,7 @@ pub fn rewrite(plan: &PlanGraph, to_one_target: bool) -> HashMap<usize, Node> {
                 projection_indices.push(id);
             },
             Operator::SourceOp { config} => {
-                add_to_hash_map(&mut io_hash_to_node_index, config, id, false);
+                add_to_hash_map(&mut io_hash_to_node_index, scan_key(config), id, false);
             },
             Operator::TargetOp { config } => {
                 add_to_hash_map(&mut io_hash_to_node_index, config, id, to_one_target);
@@ -61,61 +75,48 @@ pub fn rewrite(plan: &PlanGraph, to_one_target: bool) -> HashMap<usize, Node> {
    
     // Set "edges" into node objects
     for edge in &plan.edges {
-        let from = edge[0].as_u64().unwrap() as usize;
-        let to = edge[1].as_u64().unwrap() as usize;
+        let (Some(from), Some(to)) = (edge.first().and_then(Value::as_u64), edge.get(1).and_then(Value::as_u64)) else {
+            return Err(MopperError::plan(format!("Edge {edge:?} does not start with two node indices")));
+        };
+        let (from, to) = (from as usize, to as usize);
+        if !node_map.contains_key(&from) || !node_map.contains_key(&to) {
+            return Err(MopperError::plan(format!("Edge {from} -> {to} refers to a node that does not exist")));
+        }
         let from_node = node_map.get_mut(&from).unwrap();
         from_node.add_to(to);
         let to_node = node_map.get_mut(&to).unwrap();
         to_node.add_from(from);
     }
+
+    // A cycle would make the operators wait for each other forever
+    check_for_cycles(&node_map)?;
     
     debug!("Merging nodes with same source or sink.");
-    let mut merged_io_ids_to_remove: Vec<usize> = Vec::new();
-    let mut changed_nodes: Vec<(usize, Node)> = Vec::new();   // these sources remain after merge with updated "to" edges
-    io_hash_to_node_index.values()
-        .filter(|nodes| nodes.len() > 1)
-        .for_each(|same_io_node_ids| {
-            // "io" stands for source or sink here
-            let mut io_iter = same_io_node_ids.iter();
-            
-            // get first source node
-            let first_io_id = io_iter.next().unwrap();
-            let mut first_io = node_map[first_io_id].clone();
-            
-            // now add the "to" edges from the other nodes to the first one
-            // and remove the other nodes from the map
-            for other_io_id in io_iter {
-                let other_io = &node_map[other_io_id];
-                first_io.add_all_to(&other_io.to);
-                first_io.add_all_from(&other_io.from);
-                merged_io_ids_to_remove.push(*other_io_id);
-                
-                //let other_source = &node_map[other_io_id];
-                // update the "to" nodes, because their "from"s still point to other sources
-                for to_node_id in &other_io.to {
-                    let mut to_node_to_update = node_map[to_node_id].clone();
-                    to_node_to_update.replace_from(*other_io_id, *first_io_id);
-                    changed_nodes.push((*to_node_id, to_node_to_update));
-                }
-                // update the "from" nodes, because their "to"s still point to other sinks
-                for from_node_id in &other_io.from {
-                    let mut from_node_to_update = node_map[from_node_id].clone();
-                    from_node_to_update.replace_to(*other_io_id, *first_io_id);
-                    changed_nodes.push((*from_node_id, from_node_to_update));
-                }
+    // The nodes around them are updated in place, so a node connected to several merged nodes,
+    // e.g. a join reading from two sources that turn out to be the same, keeps all updates.
+    for same_io_node_ids in io_hash_to_node_index.values().filter(|nodes| nodes.len() > 1) {
+        // "io" stands for source or sink here
+        let (first_io_id, other_io_ids) = same_io_node_ids.split_first().unwrap();
+
+        // now add the "to" edges from the other nodes to the first one
+        // and remove the other nodes from the map
+        for other_io_id in other_io_ids {
+            debug!("Merging source or sink node {other_io_id} into {first_io_id}");
+            let other_io = node_map.remove(other_io_id).unwrap();
+            let first_io = node_map.get_mut(first_io_id).unwrap();
+            first_io.add_all_to(&other_io.to);
+            first_io.add_all_from(&other_io.from);
+            first_io.add_merged(*other_io_id, &other_io.merged);
+
+            // update the "to" nodes, because their "from"s still point to other sources
+            for to_node_id in &other_io.to {
+                node_map.get_mut(to_node_id).unwrap().replace_from(*other_io_id, *first_io_id);
             }
-            changed_nodes.push((*first_io_id, first_io));
-        });
-    // Remove duplicate source nodes update merged source nodes
-    for merged_source_id in merged_io_ids_to_remove {
-        debug!("Removing source or sink node {merged_source_id}");
-        node_map.remove(&merged_source_id);
-    }
-    
-    // Update changed source nodes
-    for (node_id, updated_node) in changed_nodes {
-        debug!("Updating node {node_id}");
-        node_map.insert(node_id, updated_node);
+            // update the "from" nodes, because their "to"s still point to other sinks
+            for from_node_id in &other_io.from {
+                node_map.get_mut(from_node_id).unwrap().replace_to(*other_io_id, *first_io_id);
+            }
+        }
     }
     
     // Remove Fragment operators by setting their edges to involved nodes
@@ -157,6 +158,7 @@ pub fn rewrite(plan: &PlanGraph, to_one_target: bool) -> HashMap<usize, Node> {
             let start_node = node_map.get_mut(&start_node_index).unwrap();
             start_node.change_to_ids(&projection_node.to, projection_index);
             start_node.add_attributes(attributes.clone());
+            start_node.add_merged(projection_index, &projection_node.merged);
 
             // move "from" edges to "to" node
             for end_node_index in &projection_node.to {
@@ -167,46 +169,389 @@ pub fn rewrite(plan: &PlanGraph, to_one_target: bool) -> HashMap<usize, Node> {
     }
     
     debug!("Removing self-join nodes from plan.");
-    let mut self_join_nodes_to_remove: Vec<usize> = Vec::new();
-    let mut changed_join_connected_nodes: Vec<(usize, Node)> = Vec::new();
-    
+    // the self-join each join alias comes from, to report collisions
+    let mut join_alias_origins: HashMap<usize, usize> = HashMap::new();
     for join_index in join_indices {
         let join_node = &node_map[&join_index];
-        if join_node.from[0] == join_node.from[1] {
-            self_join_nodes_to_remove.push(join_index);
-            // make sure the renamed attributes also get passed
-            match &join_node.operator {
-                Operator::JoinOp { config } => {
-                    let join_alias = &config.join_alias;
-                    for to_node_id in &join_node.to {
-                        let mut to_node = node_map[to_node_id].clone();
-                        to_node.replace_from(join_index, join_node.from[0]);
-                        to_node.join_alias = Some(join_alias.to_string());
-                        changed_join_connected_nodes.push((*to_node_id, to_node));
-                    }
-                    let from_node_id = &join_node.from[0];
-                    let mut from_node = node_map[from_node_id].clone();
-                    from_node.change_to_ids(&join_node.to, join_index);
-                    changed_join_connected_nodes.push((*from_node_id, from_node));
-                },
-                _ => {}
-            }
+        let Operator::JoinOp { config } = &join_node.operator else {
+            continue;
+        };
+        if !is_removable_self_join(&node_map, join_index, config) {
+            continue;
+        }
+        let join_node = join_node.clone();
+        let join_alias = config.join_alias.clone();
+        let from_node_id = join_node.from[0];
+        check_join_alias(&node_map, &join_alias_origins, join_index, &join_node, &join_alias)?;
+
+        // make sure the renamed attributes also get passed
+        debug!("Removing self-join {join_index}");
+        for to_node_id in &join_node.to {
+            let to_node = node_map.get_mut(to_node_id).unwrap();
+            to_node.replace_from(join_index, from_node_id);
+            to_node.join_alias = Some(join_alias.clone());
+            join_alias_origins.insert(*to_node_id, join_index);
         }
+        node_map.get_mut(&from_node_id).unwrap().change_to_ids(&join_node.to, join_index);
+        node_map.remove(&join_index);
     }
-    for (node_id, updated_node) in changed_join_connected_nodes {
-        debug!("Updating node {node_id} affected by removing self-join node");
-        node_map.insert(node_id, updated_node);
+
+    // Evaluate the same functions only once per record
+    debug!("Merging identical extend nodes.");
+    merge_identical_extends(&mut node_map);
+
+    // Counters of source records need the record numbers, which are not in the projections of the plan
+    debug!("Passing record numbers to counters.");
+    pass_record_numbers(&mut node_map);
+
+    // Let joins only keep and pass the attributes used by the next operators
+    debug!("Pushing attribute requirements down to joins.");
+    let join_ids: Vec<usize> = node_map.iter()
+        .filter(|(_id, node)| matches!(node.operator, Operator::JoinOp { .. }))
+        .map(|(id, _node)| *id)
+        .collect();
+    for join_id in join_ids {
+        if let Some(attributes) = attributes_used_after(&node_map, join_id) {
+            debug!("Join {join_id} only needs attributes {attributes:?}");
+            node_map.get_mut(&join_id).unwrap().attributes = Some(attributes);
+        }
     }
     
-    for id in self_join_nodes_to_remove {
-        debug!("Removing self-join {id}");
-        node_map.remove(&id);
+    // Compute constant values once instead of for every record
+    debug!("Folding constants into serializers.");
+    let extend_ids: Vec<usize> = node_map.iter()
+        .filter(|(_id, node)| matches!(node.operator, Operator::ExtendOp { .. }))
+        .map(|(id, _node)| *id)
+        .collect();
+    for extend_id in extend_ids {
+        fold_constants(&mut node_map, extend_id);
     }
-    
+
     let final_nr_of_nodes = node_map.len();
     info!("Reduced number of nodes in the plan from {initial_nr_of_nodes} to {final_nr_of_nodes}");
+
+    // Merging sources or sinks should not introduce cycles, but better safe than deadlocked
+    check_for_cycles(&node_map)?;
     
-    node_map
+    Ok(node_map)
+}
+
+/// Whether the join joins the records of one node with themselves, so it can be removed and the records
+/// passed on as they are: both its inputs are the same node (possibly after merging sources), and it either
+/// has no join condition, or only conditions comparing an attribute with itself while the next nodes only
+/// use those attributes of the right side. A record then yields the same statements as the records it is
+/// joined with, which include itself.
+fn is_removable_self_join(node_map: &NodeMap, join_id: usize, config: &Join) -> bool {
+    let join_node = &node_map[&join_id];
+    if join_node.from[0] != join_node.from[1] {
+        return false;
+    }
+    if config.left_right_attr_pairs.is_empty() {
+        return true;
+    }
+    if config.left_right_attr_pairs.iter().any(|(left, right)| left != right) {
+        return false;
+    }
+    let Some(used_attributes) = attributes_used_after(node_map, join_id) else {
+        return false;
+    };
+    let prefix = format!("{}_", config.join_alias);
+    used_attributes.iter()
+        .filter_map(|attribute| attribute.strip_prefix(&prefix))
+        .all(|attribute| config.left_right_attr_pairs.iter().any(|(_left, right)| right == attribute))
+}
+
+/// Checks that the alias of a self-join that is removed resolves references unambiguously in the nodes
+/// after it: they may not have another join alias already, and no attribute of the joined node may look
+/// like one prefixed with the alias.
+fn check_join_alias(node_map: &NodeMap, join_alias_origins: &HashMap<usize, usize>, join_id: usize, join_node: &Node, join_alias: &str) -> Result<(), MopperError> {
+    for to_node_id in sorted_to_ids(join_node) {
+        if let Some(other_alias) = node_map[&to_node_id].join_alias.as_ref().filter(|other_alias| *other_alias != join_alias) {
+            let origin = match join_alias_origins.get(&to_node_id) {
+                Some(other_join_id) => format!("self-join {other_join_id}"),
+                None => "the plan".to_string()
+            };
+            return Err(MopperError::plan(format!("Join alias {join_alias} of self-join {join_id} collides with join alias {other_alias} of node {to_node_id} from {origin}")));
+        }
+    }
+    let from_node_id = join_node.from[0];
+    let prefix = format!("{join_alias}_");
+    let mut attributes: Vec<&String> = node_map[&from_node_id].attributes.iter().flatten()
+        .filter(|attribute| attribute.starts_with(&prefix))
+        .collect();
+    attributes.sort();
+    if let Some(attribute) = attributes.first() {
+        return Err(MopperError::plan(format!("Join alias {join_alias} of self-join {join_id} is ambiguous: attribute {attribute} of node {from_node_id} already starts with {prefix}")));
+    }
+    Ok(())
+}
+
+/// Merges extend nodes that compute the same variables from the same records, i.e. with the same functions,
+/// input and join alias, into the first of them, which then sends to the nodes of all of them.
+fn merge_identical_extends(node_map: &mut NodeMap) {
+    let mut extend_ids: Vec<usize> = node_map.iter()
+        .filter(|(_id, node)| matches!(node.operator, Operator::ExtendOp { .. }))
+        .map(|(id, _node)| *id)
+        .collect();
+    extend_ids.sort();
+
+    let mut kept_ids: Vec<usize> = Vec::new();
+    for extend_id in extend_ids {
+        let Some(kept_id) = kept_ids.iter().copied().find(|kept_id| same_extend(&node_map[kept_id], &node_map[&extend_id])) else {
+            kept_ids.push(extend_id);
+            continue;
+        };
+        debug!("Merging extend node {extend_id} into {kept_id}");
+        let extend_node = node_map.remove(&extend_id).unwrap();
+        for from_id in &extend_node.from {
+            node_map.get_mut(from_id).unwrap().to.remove(&extend_id);
+        }
+        for to_id in &extend_node.to {
+            let to_node = node_map.get_mut(to_id).unwrap();
+            if to_node.from.contains(&kept_id) {
+                // it gets the records once instead of twice
+                to_node.from.retain(|from_id| *from_id != extend_id);
+            } else {
+                to_node.replace_from(extend_id, kept_id);
+            }
+        }
+        let kept_node = node_map.get_mut(&kept_id).unwrap();
+        kept_node.add_all_to(&extend_node.to);
+        kept_node.add_attributes(extend_node.attributes);
+        kept_node.add_merged(extend_id, &extend_node.merged);
+    }
+}
+
+fn same_extend(node: &Node, other_node: &Node) -> bool {
+    match (&node.operator, &other_node.operator) {
+        (Operator::ExtendOp { config }, Operator::ExtendOp { config: other_config }) =>
+            config == other_config && node.from == other_node.from && node.join_alias == other_node.join_alias,
+        _ => false
+    }
+}
+
+/// Returns the (output) attributes of the node used by the nodes it sends to, or `None` if
+/// it is not known which ones are used.
+fn attributes_used_after(node_map: &NodeMap, id: usize) -> Option<HashSet<String>> {
+    let mut attributes = HashSet::new();
+    for to_id in &node_map[&id].to {
+        let Operator::ExtendOp { config } = &node_map[to_id].operator else {
+            return None;
+        };
+        for function in config.extend_pairs.values() {
+            if !add_referenced_attributes(function, &mut attributes) {
+                return None;
+            }
+        }
+    }
+    Some(attributes)
+}
+
+/// Lets the sources send the record numbers that the extend nodes after them count with. References without
+/// join alias prefix after a join are to the attributes of its left side, so the record numbers come from there.
+fn pass_record_numbers(node_map: &mut NodeMap) {
+    let counting_extend_ids: Vec<usize> = node_map.iter()
+        .filter(|(_id, node)| match &node.operator {
+            Operator::ExtendOp { config } => config.extend_pairs.values().any(|function| {
+                let mut attributes = HashSet::new();
+                add_referenced_attributes(function, &mut attributes) && attributes.contains(RECORD_NUMBER_REFERENCE)
+            }),
+            _ => false
+        })
+        .map(|(id, _node)| *id)
+        .collect();
+    for extend_id in counting_extend_ids {
+        let mut from_id = node_map[&extend_id].from.first().copied();
+        while let Some(id) = from_id {
+            let node = node_map.get_mut(&id).unwrap();
+            match node.operator {
+                Operator::SourceOp { .. } => {
+                    debug!("Source {id} sends record numbers to extend node {extend_id}");
+                    node.add_attributes(Some(HashSet::from([RECORD_NUMBER_REFERENCE.to_string()])));
+                    from_id = None;
+                },
+                Operator::JoinOp { .. } => from_id = node.from.first().copied(),
+                _ => from_id = None
+            }
+        }
+    }
+}
+
+/// Adds the attributes the function refers to. Returns `false` if they cannot be determined.
+fn add_referenced_attributes(function: &Function, attributes: &mut HashSet<String>) -> bool {
+    match function {
+        Function::Reference { value } => {
+            attributes.insert(value.clone());
+            true
+        },
+        Function::Constant { .. } => true,
+        Function::TemplateString { value } => match parse_template(value, &None) {
+            Ok(parts) => {
+                attributes.extend(parts.into_iter()
+                    .filter(|(is_variable, _part)| *is_variable)
+                    .map(|(_is_variable, part)| part));
+                true
+            },
+            Err(_) => false
+        },
+        Function::TemplateFunctionValue { variable_function_pairs, .. } => variable_function_pairs.iter()
+            .all(|(_name, function)| add_referenced_attributes(function, attributes)),
+        Function::UriEncode { inner_function }
+        | Function::Iri { inner_function, .. }
+        | Function::BlankNode { inner_function }
+        | Function::Literal { inner_function, dtype_function: None, langtype_function: None } => add_referenced_attributes(inner_function, attributes),
+        // counters of source records count with the record numbers
+        Function::FnO { .. } => match Counter::of(function) {
+            Some(Ok(counter)) => {
+                if counter.scope == CounterScope::Source {
+                    attributes.insert(RECORD_NUMBER_REFERENCE.to_string());
+                }
+                true
+            },
+            _ => false
+        },
+        // other functions are not supported by the extend operator (yet)
+        _ => false
+    }
+}
+
+/// Computes the values of the extend functions of the node that do not depend on the record, removes
+/// them from the node and gives them to the serializers it sends to. Only if it sends to serializers only.
+fn fold_constants(node_map: &mut NodeMap, extend_id: usize) {
+    let extend_node = &node_map[&extend_id];
+    let Operator::ExtendOp { config } = &extend_node.operator else {
+        return;
+    };
+    let sends_to_serializers_only = extend_node.to.iter()
+        .all(|to_id| matches!(node_map[to_id].operator, Operator::SerializerOp { .. }));
+    if !sends_to_serializers_only {
+        return;
+    }
+
+    let mut constants: HashMap<String, String> = HashMap::new();
+    for (name, function) in &config.extend_pairs {
+        if !is_constant(function) {
+            continue;
+        }
+        // invalid values are left to the extend operator, which knows how to deal with them
+        // constants contain no encoding or references, so these options do not matter
+        let Ok(compiled_function) = CompiledFunction::compile(function, &extend_node.join_alias, &IriEncoding::default(), &MissingReferencePolicy::default()) else {
+            continue;
+        };
+        if let Ok(value) = compiled_function.exec_value(&[]) {
+            // the variables in the templates of the serializers have no '?' prefix
+            constants.insert(name[1..].to_string(), format_value(&value, compiled_function.result_type()));
+        }
+    }
+    if constants.is_empty() {
+        return;
+    }
+    debug!("Folding constants {:?} of extend node {extend_id}", constants.keys());
+
+    let mut folded_config = config.clone();
+    folded_config.extend_pairs.retain(|name, _function| !constants.contains_key(&name[1..]));
+    let to_ids: Vec<usize> = extend_node.to.iter().copied().collect();
+    node_map.get_mut(&extend_id).unwrap().operator = Operator::ExtendOp { config: folded_config };
+    for to_id in to_ids {
+        node_map.get_mut(&to_id).unwrap().constants.extend(constants.clone());
+    }
+}
+
+/// Checks if the function only consists of constants.
+fn is_constant(function: &Function) -> bool {
+    match function {
+        Function::Constant { .. } => true,
+        Function::TemplateString { value } => matches!(parse_template(value, &None),
+            Ok(parts) if parts.iter().all(|(is_variable, _part)| !is_variable)),
+        Function::TemplateFunctionValue { variable_function_pairs, .. } => variable_function_pairs.iter()
+            .all(|(_name, function)| is_constant(function)),
+        // the encoding depends on the options, so it is left to the extend
+        Function::UriEncode { .. } => false,
+        Function::Iri { inner_function, .. }
+        | Function::BlankNode { inner_function }
+        | Function::Literal { inner_function, .. } => is_constant(inner_function),
+        _ => false
+    }
+}
+
+/// Checks that the nodes form a directed acyclic graph, by visiting them depth first.
+/// Returns an error with the chain of nodes forming the cycle if there is one.
+fn check_for_cycles(node_map: &NodeMap) -> Result<(), MopperError> {
+    let mut finished: HashSet<usize> = HashSet::new();
+    let mut start_ids: Vec<&usize> = node_map.keys().collect();
+    start_ids.sort();
+
+    for start_id in start_ids {
+        if finished.contains(start_id) {
+            continue;
+        }
+        // the current path from the start node, with the "to" nodes not visited yet
+        let mut path: Vec<(usize, Vec<usize>)> = vec![(*start_id, sorted_to_ids(&node_map[start_id]))];
+        while let Some((node_id, to_ids)) = path.last_mut() {
+            let node_id = *node_id;
+            match to_ids.pop() {
+                Some(to_id) if finished.contains(&to_id) => {},
+                Some(to_id) => {
+                    if let Some(cycle_start) = path.iter().position(|(id, _)| *id == to_id) {
+                        let chain: Vec<String> = path[cycle_start..].iter()
+                            .map(|(id, _)| id.to_string())
+                            .chain(std::iter::once(to_id.to_string()))
+                            .collect();
+                        return Err(MopperError::plan(format!("Cycle in the plan: {}", chain.join(" -> "))));
+                    }
+                    if let Some(to_node) = node_map.get(&to_id) {
+                        path.push((to_id, sorted_to_ids(to_node)));
+                    }
+                },
+                None => {
+                    finished.insert(node_id);
+                    path.pop();
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn sorted_to_ids(node: &Node) -> Vec<usize> {
+    let mut to_ids: Vec<usize> = node.to.iter().copied().collect();
+    // reversed, so they are popped in ascending order
+    to_ids.sort_by(|a, b| b.cmp(a));
+    to_ids
+}
+
+/// The configuration of what the source reads, to merge sources that read the same records even if their
+/// configurations differ in ways that do not change the records. For a CSV file these are how its path is
+/// written, e.g. `./people.csv` or `people.csv`, and the alias and fields of its iterator, which are not
+/// used to read rows. The merged source sends the union of the attributes projected from all of them.
+pub(crate) fn scan_key(config: &Source) -> Source {
+    let mut key = config.clone();
+    let reads_csv_file = config.source_type == IOType::File
+        && config.root_iterator.reference_formulation == ReferenceFormulation::CSVRows
+        && !config.config.contains_key("source");
+    if reads_csv_file {
+        if let Some(path) = key.config.get_mut("path") {
+            *path = normalize_path(path);
+        }
+        key.root_iterator.alias = None;
+        key.root_iterator.fields.clear();
+    }
+    key
+}
+
+// The path without `.` and with `dir/..` removed, without looking at the file system
+fn normalize_path(path: &str) -> String {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            },
+            other => normalized.push(other)
+        }
+    }
+    normalized.to_string_lossy().to_string()
 }
 
 fn add_to_hash_map<T: Hash>(io_hash_to_node_index: &mut HashMap<u64, Vec<usize>>, config: T, id: usize, constant_hash: bool) {
@@ -227,4 +572,201 @@ fn add_to_hash_map<T: Hash>(io_hash_to_node_index: &mut HashMap<u64, Vec<usize>>
         let node_ids = vec![id];
         io_hash_to_node_index.insert(hash, node_ids);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use serde_json::{json, Value};
+    use super::*;
+
+    fn plan_1036() -> Value {
+        let plan_str = fs::read_to_string("test-resources/rml-testcases/RMLTC1036-CSV/mapping.json").unwrap();
+        serde_json::from_str(&plan_str).unwrap()
+    }
+
+    #[test]
+    fn no_cycle() {
+        let plan_graph = PlanGraph::from_json(&plan_1036().to_string()).unwrap();
+        assert!(rewrite(&plan_graph, false).is_ok());
+    }
+
+    #[test]
+    fn attributes_pushed_down_to_join() {
+        let plan_graph = PlanGraph::from_json(&plan_1036().to_string()).unwrap();
+        let node_map = rewrite(&plan_graph, false).unwrap();
+        // the extend operator after join 6 only uses ID of the left node and ID of the right node
+        let expected = HashSet::from(["ID".to_string(), "join_0_ID".to_string()]);
+        assert_eq!(Some(expected), node_map[&6].attributes);
+    }
+
+    #[test]
+    fn record_numbers_passed_to_counters() {
+        // the students after join 6 are numbered instead of identified by ID
+        let mut plan = plan_1036();
+        plan["nodes"][7]["operator"]["config"]["?tm1_sm"]["inner_function"] = json!({
+            "type": "TemplateFunctionValue", "template": "http://example.com/resource/student_{n}",
+            "variable_function_pairs": [["n", {"type": "FnO", "fno_identifier": "https://github.com/ghsnd/mopper#counter", "parameters": []}]]
+        });
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        let node_map = rewrite(&plan_graph, false).unwrap();
+        let expected = HashSet::from(["_rownum".to_string(), "join_0_ID".to_string()]);
+        assert_eq!(Some(expected), node_map[&6].attributes);
+        // from the left side of the join only
+        assert!(node_map[&2].attributes.as_ref().unwrap().contains("_rownum"));
+        assert!(!node_map[&0].attributes.as_ref().unwrap().contains("_rownum"));
+    }
+
+    #[test]
+    fn constants_folded() {
+        let plan_graph = PlanGraph::from_json(&plan_1036().to_string()).unwrap();
+        let node_map = rewrite(&plan_graph, false).unwrap();
+        let Operator::ExtendOp { config } = &node_map[&7].operator else {
+            panic!("Node 7 should be an extend operator");
+        };
+        let mut remaining: Vec<&String> = config.extend_pairs.keys().collect();
+        remaining.sort();
+        assert_eq!(vec!["?tm1_o1_0", "?tm1_sm"], remaining);
+        assert_eq!(Some(&"<http://example.com/ontology/practises>".to_string()), node_map[&8].constants.get("tm1_p1_0"));
+        assert_eq!(3, node_map[&8].constants.len());
+    }
+
+    fn plan_0008b() -> Value {
+        let plan_str = fs::read_to_string("test-resources/rml-testcases/RMLTC0008b-CSV/mapping.json").unwrap();
+        serde_json::from_str(&plan_str).unwrap()
+    }
+
+    #[test]
+    fn sources_reading_the_same_file_merged() {
+        // the same file, written differently and with another iterator alias
+        let mut plan = plan_0008b();
+        plan["nodes"][2]["operator"]["config"]["path"] = json!("./student.csv");
+        plan["nodes"][2]["operator"]["config"]["root_iterator"]["alias"] = json!("students");
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        let node_map = rewrite(&plan_graph, false).unwrap();
+        assert!(!node_map.contains_key(&2));
+        let expected = HashSet::from(["Sport".to_string(), "Name".to_string(), "ID".to_string()]);
+        assert_eq!(Some(expected), node_map[&0].attributes);
+
+        // another file
+        plan["nodes"][2]["operator"]["config"]["path"] = json!("../student.csv");
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        let node_map = rewrite(&plan_graph, false).unwrap();
+        assert!(node_map.contains_key(&2));
+    }
+
+    #[test]
+    fn paths_normalized() {
+        assert_eq!("people.csv", normalize_path("./people.csv"));
+        assert_eq!("data/people.csv", normalize_path("data/./raw/../people.csv"));
+        assert_eq!("../people.csv", normalize_path("../people.csv"));
+        assert_eq!("/data/people.csv", normalize_path("/data/raw/../people.csv"));
+    }
+
+    #[test]
+    fn self_join_removed() {
+        let plan_graph = PlanGraph::from_json(&plan_0008b().to_string()).unwrap();
+        let node_map = rewrite(&plan_graph, false).unwrap();
+        assert!(!node_map.contains_key(&6));
+        assert_eq!(Some("join_0".to_string()), node_map[&7].join_alias);
+        assert_eq!(vec![0], node_map[&7].from);
+    }
+
+    #[test]
+    fn self_join_on_equal_references_removed() {
+        // the next nodes only use the join attribute of the right side
+        let mut plan = plan_0008b();
+        plan["nodes"][6]["operator"]["config"]["left_right_attr_pairs"] = json!([["Sport", "Sport"]]);
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        let node_map = rewrite(&plan_graph, false).unwrap();
+        assert!(!node_map.contains_key(&6));
+        assert_eq!(vec![0], node_map[&7].from);
+
+        // they use another attribute of the right side, which may come from another record
+        plan["nodes"][6]["operator"]["config"]["left_right_attr_pairs"] = json!([["ID", "ID"]]);
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        let node_map = rewrite(&plan_graph, false).unwrap();
+        assert_eq!(vec![0, 0], node_map[&6].from);
+
+        // a real join of the node with itself
+        plan["nodes"][6]["operator"]["config"]["left_right_attr_pairs"] = json!([["Sport", "ID"]]);
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        let node_map = rewrite(&plan_graph, false).unwrap();
+        assert_eq!(vec![0, 0], node_map[&6].from);
+    }
+
+    #[test]
+    fn self_join_of_merged_sources_removed() {
+        // the join reads straight from two sources that are merged with a third one
+        let mut plan = plan_0008b();
+        let source = plan["nodes"][0].clone();
+        plan["nodes"].as_array_mut().unwrap().push(source);
+        let edges = plan["edges"].as_array_mut().unwrap();
+        edges.retain(|edge| edge[1] != json!(6));
+        edges.push(json!([2, 6, {"fragment": "join_0"}]));
+        edges.push(json!([16, 6, {"fragment": "join_0"}]));
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        let node_map = rewrite(&plan_graph, false).unwrap();
+        assert!(!node_map.contains_key(&2) && !node_map.contains_key(&16));
+        assert!(!node_map.contains_key(&6));
+        assert_eq!(vec![0], node_map[&7].from);
+        assert!(node_map[&0].to.contains(&7));
+    }
+
+    #[test]
+    fn identical_extends_merged() {
+        // a copy of the triples map of extend node 13, to another serializer
+        let mut plan = plan_0008b();
+        let extend = plan["nodes"][13].clone();
+        let serializer = plan["nodes"][14].clone();
+        plan["nodes"].as_array_mut().unwrap().extend([extend, serializer]);
+        plan["edges"].as_array_mut().unwrap().extend([
+            json!([4, 16, {"fragment": "default"}]), json!([16, 17, {"fragment": "default"}]), json!([17, 15, {"fragment": "default"}])
+        ]);
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        let node_map = rewrite(&plan_graph, false).unwrap();
+        assert!(!node_map.contains_key(&16));
+        assert_eq!(vec![16], node_map[&13].merged);
+        assert_eq!(HashSet::from([14, 17]), node_map[&13].to);
+        assert_eq!(vec![13], node_map[&17].from);
+        assert!(!node_map[&0].to.contains(&16));
+
+        // the other extend nodes differ
+        assert!(node_map.contains_key(&7) && node_map.contains_key(&10));
+    }
+
+    #[test]
+    fn join_alias_collisions() {
+        // an attribute of the source that looks like one of the other side of the self-join
+        let mut plan = plan_0008b();
+        plan["nodes"][1]["operator"]["config"]["projection_attributes"] = json!(["Sport", "join_0_Sport"]);
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        match rewrite(&plan_graph, false) {
+            Err(MopperError::Plan { msg, .. }) => assert_eq!(msg, "Join alias join_0 of self-join 6 is ambiguous: attribute join_0_Sport of node 0 already starts with join_0_"),
+            Err(other) => panic!("Unexpected error {other}"),
+            Ok(_) => panic!("Ambiguous join alias not detected")
+        }
+
+        // a node after the self-join that has a join alias already
+        let mut plan = plan_0008b();
+        plan["nodes"][7]["join_alias"] = json!("join_1");
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        match rewrite(&plan_graph, false) {
+            Err(MopperError::Plan { msg, .. }) => assert_eq!(msg, "Join alias join_0 of self-join 6 collides with join alias join_1 of node 7 from the plan"),
+            Err(other) => panic!("Unexpected error {other}"),
+            Ok(_) => panic!("Join alias collision not detected")
+        }
+    }
+
+    #[test]
+    fn cycle() {
+        let mut plan = plan_1036();
+        // 7 -> 8 -> 9 already exists
+        plan["edges"].as_array_mut().unwrap().push(json!([9, 7, {"fragment": "default"}]));
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        match rewrite(&plan_graph, false) {
+            Err(MopperError::Plan { msg, .. }) => assert_eq!(msg, "Cycle in the plan: 7 -> 8 -> 9 -> 7"),
+            Err(other) => panic!("Unexpected error {other}"),
+            Ok(_) => panic!("Cycle not detected")
+        }
+    }
+}
@@ -0,0 +1,424 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use operator::{Function, IOType, Operator};
+use operator::formats::{DataFormat, ReferenceFormulation};
+use crate::error::{MopperError, PlanProblem};
+use crate::plan::PlanGraph;
+use crate::plan_dot::operator_name;
+use crate::plan_rewriter::scan_key;
+use crate::mopper_options::MopperOptions;
+use crate::sink::{sink_key, Compression};
+use crate::sink::http::{HttpSink, HTTP_SINK};
+use crate::source::source_key;
+use crate::source::json_lines::JsonLinesSource;
+#[cfg(feature = "kafka")]
+use crate::source::kafka::client_config;
+use crate::source::ldes::{ldes_url, poll_interval, LDES_SOURCE};
+use crate::source::location::STD_IN_PATH;
+use crate::source::websocket::websocket_url;
+
+/// Checks if the plan can be executed, before anything is started. All problems found are
+/// reported at once. If `to_one_target` is set, the targets in the plan are ignored.
+/// Sources and targets with a custom source or sink in the `options` are supported, whatever their type.
+pub fn validate(plan: &PlanGraph, to_one_target: bool, options: &MopperOptions) -> Result<(), MopperError> {
+    let mut problems: Vec<PlanProblem> = Vec::new();
+    let nr_of_nodes = plan.nodes.len();
+
+    // count the incoming and outgoing edges of every node
+    let mut nr_of_inputs = vec![0usize; nr_of_nodes];
+    let mut nr_of_outputs = vec![0usize; nr_of_nodes];
+    for (edge_index, edge) in plan.edges.iter().enumerate() {
+        let from = edge.first().and_then(|value| value.as_u64());
+        let to = edge.get(1).and_then(|value| value.as_u64());
+        match (from, to) {
+            (Some(from), Some(to)) => {
+                for node_index in [from, to] {
+                    if node_index as usize >= nr_of_nodes {
+                        problems.push(PlanProblem::new(None, format!("edge {edge_index} refers to node {node_index}, which does not exist")));
+                    }
+                }
+                if let Some(nr) = nr_of_outputs.get_mut(from as usize) {
+                    *nr += 1;
+                }
+                if let Some(nr) = nr_of_inputs.get_mut(to as usize) {
+                    *nr += 1;
+                }
+            },
+            _ => problems.push(PlanProblem::new(None, format!("edge {edge_index} does not start with two node indices")))
+        }
+    }
+
+    // standard input can only be read by one source, or by several sources that are merged into one
+    let mut std_in_sources = Vec::new();
+
+    for (index, node) in plan.nodes.iter().enumerate() {
+        let node_problem = |msg: String| PlanProblem::new(Some(index), msg);
+        match &node.operator {
+            Operator::SourceOp { config } if options.source_factory(config).is_some() => {},
+            Operator::SourceOp { config } if source_key(config) == LDES_SOURCE => {
+                if let Err(msg) = ldes_url(&config.config) {
+                    problems.push(node_problem(msg));
+                }
+                if let Err(msg) = poll_interval(&config.config) {
+                    problems.push(node_problem(msg));
+                }
+            },
+            Operator::SourceOp { config } => {
+                let is_kafka = cfg!(feature = "kafka") && config.source_type == IOType::Kafka;
+                if !matches!(config.source_type, IOType::File | IOType::StdIn | IOType::Websocket) && !is_kafka {
+                    problems.push(node_problem(format!("source type {:?} is not supported", config.source_type)));
+                } else {
+                    match &config.root_iterator.reference_formulation {
+                        ReferenceFormulation::CSVRows => {},
+                        ReferenceFormulation::JSONPath => if let Err(msg) = JsonLinesSource::iterates_array_items(&config.root_iterator.reference) {
+                            problems.push(node_problem(msg));
+                        },
+                        other => problems.push(node_problem(format!("reference formulation {other:?} is not supported")))
+                    }
+                }
+                if config.source_type == IOType::File && !config.config.contains_key("path") {
+                    problems.push(node_problem("file source without path".to_string()));
+                }
+                let reads_std_in = config.source_type == IOType::StdIn
+                    || (config.source_type == IOType::File && config.config.get("path").map(String::as_str) == Some(STD_IN_PATH));
+                if reads_std_in {
+                    if options.mapping_from_std_in() {
+                        problems.push(node_problem("source reads standard input, from which the mapping is read".to_string()));
+                    }
+                    let key = scan_key(config);
+                    if !std_in_sources.iter().any(|(_, std_in_key)| *std_in_key == key) {
+                        if let Some((first_index, _)) = std_in_sources.first() {
+                            problems.push(node_problem(format!("source reads standard input, which node {first_index} reads already")));
+                        }
+                        std_in_sources.push((index, key));
+                    }
+                }
+                if config.source_type == IOType::Websocket {
+                    if let Err(msg) = websocket_url(&config.config) {
+                        problems.push(node_problem(msg));
+                    }
+                }
+                #[cfg(feature = "kafka")]
+                if is_kafka {
+                    if let Err(msg) = client_config(&config.config) {
+                        problems.push(node_problem(msg));
+                    }
+                }
+            },
+            Operator::JoinOp { .. } => {
+                if nr_of_inputs[index] < 2 {
+                    problems.push(node_problem(format!("join has {} input(s) instead of 2", nr_of_inputs[index])));
+                }
+            },
+            Operator::SerializerOp { config } => {
+                if nr_of_outputs[index] == 0 {
+                    problems.push(node_problem("serializer without target".to_string()));
+                }
+                if config.format != DataFormat::NQuads && config.format != DataFormat::NTriples {
+                    problems.push(node_problem(format!("serialization format {:?} is not supported", config.format)));
+                }
+            },
+            Operator::TargetOp { config } if to_one_target || options.sink_factory(config).is_some() => {},
+            Operator::TargetOp { config } if sink_key(config) == HTTP_SINK => {
+                if let Err(msg) = HttpSink::new(config) {
+                    problems.push(node_problem(msg));
+                }
+                if config.data_format != DataFormat::NQuads && config.data_format != DataFormat::NTriples {
+                    problems.push(node_problem(format!("target format {:?} is not supported", config.data_format)));
+                }
+            },
+            Operator::TargetOp { config } => {
+                if config.target_type != IOType::StdOut && config.target_type != IOType::File {
+                    problems.push(node_problem(format!("target type {:?} is not supported; output can be forced to standard out or a file", config.target_type)));
+                }
+                if config.target_type == IOType::File && !config.configuration.contains_key("path") {
+                    problems.push(node_problem("file target without path".to_string()));
+                }
+                if config.data_format != DataFormat::NQuads && config.data_format != DataFormat::NTriples {
+                    problems.push(node_problem(format!("target format {:?} is not supported", config.data_format)));
+                }
+                if let Err(msg) = Compression::of(config) {
+                    problems.push(node_problem(msg));
+                }
+            },
+            Operator::ExtendOp { config } => {
+                let mut variables: Vec<(&String, &Function)> = config.extend_pairs.iter().collect();
+                variables.sort_by_key(|(variable, _)| *variable);
+                for (variable, function) in variables {
+                    if let Some(msg) = function_term_type_problem(function) {
+                        problems.push(node_problem(format!("{variable}: {msg}")));
+                    }
+                }
+            },
+            Operator::ProjectOp { .. } | Operator::FragmentOp { .. } => {},
+            other => problems.push(node_problem(format!("operator {} is not supported", operator_name(other))))
+        }
+        if nr_of_inputs[index] == 0 && !matches!(node.operator, Operator::SourceOp { .. }) {
+            problems.push(node_problem("operator without input".to_string()));
+        }
+    }
+
+    problems.extend(position_term_type_problems(plan));
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(MopperError::Validation(problems))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TermType {
+    Iri,
+    BlankNode,
+    Literal
+}
+
+impl TermType {
+    fn of(function: &Function) -> Option<TermType> {
+        match function {
+            Function::Iri { .. } => Some(TermType::Iri),
+            Function::BlankNode { .. } => Some(TermType::BlankNode),
+            Function::Literal { .. } => Some(TermType::Literal),
+            _ => None
+        }
+    }
+}
+
+impl Display for TermType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TermType::Iri => write!(f, "an IRI"),
+            TermType::BlankNode => write!(f, "a blank node"),
+            TermType::Literal => write!(f, "a literal")
+        }
+    }
+}
+
+// Checks that a term is not made from another term, and that the datatype of a literal is an IRI
+fn function_term_type_problem(function: &Function) -> Option<String> {
+    let term_type = TermType::of(function)?;
+    let (inner_function, dtype_function, langtype_function) = match function {
+        Function::Iri { inner_function, .. } | Function::BlankNode { inner_function } => (inner_function, &None, &None),
+        Function::Literal { inner_function, dtype_function, langtype_function } => (inner_function, dtype_function, langtype_function),
+        _ => return None
+    };
+    if let Some(inner_term_type) = TermType::of(inner_function) {
+        return Some(format!("{term_type} cannot be made from {inner_term_type}"));
+    }
+    if dtype_function.is_some() && langtype_function.is_some() {
+        return Some("a literal cannot have both a datatype and a language tag".to_string());
+    }
+    match dtype_function.as_ref().and_then(|dtype_function| TermType::of(dtype_function)) {
+        Some(TermType::Iri) | None => None,
+        Some(dtype_term_type) => Some(format!("the datatype of a literal must be an IRI, not {dtype_term_type}"))
+    }
+}
+
+// Checks that the terms the extends generate can take their position in the statements of the
+// serializers they send to: subjects cannot be literals, and predicates and graphs must be IRIs.
+fn position_term_type_problems(plan: &PlanGraph) -> Vec<PlanProblem> {
+    let mut problems = Vec::new();
+    for edge in &plan.edges {
+        let (Some(from), Some(to)) = (edge.first().and_then(|value| value.as_u64()), edge.get(1).and_then(|value| value.as_u64())) else {
+            continue;
+        };
+        let (Some(from_node), Some(to_node)) = (plan.nodes.get(from as usize), plan.nodes.get(to as usize)) else {
+            continue;
+        };
+        let (Operator::ExtendOp { config: extend }, Operator::SerializerOp { config: serializer }) = (&from_node.operator, &to_node.operator) else {
+            continue;
+        };
+        let term_types: HashMap<&str, TermType> = extend.extend_pairs.iter()
+            .filter_map(|(variable, function)| TermType::of(function).map(|term_type| (variable.as_str(), term_type)))
+            .collect();
+        for statement in serializer.template.lines() {
+            let terms: Vec<&str> = statement.split_whitespace().filter(|term| *term != ".").collect();
+            for (position, term) in terms.iter().enumerate() {
+                let Some(&term_type) = term_types.get(term) else {
+                    continue;
+                };
+                let problem = match (position, term_type) {
+                    (0, TermType::Literal) => Some("subject"),
+                    (1, TermType::BlankNode | TermType::Literal) => Some("predicate"),
+                    (3, TermType::BlankNode | TermType::Literal) => Some("graph"),
+                    _ => None
+                };
+                if let Some(position_name) = problem {
+                    let msg = format!("{term}: {term_type} cannot be the {position_name} of a statement");
+                    let problem = PlanProblem::new(Some(from as usize), msg);
+                    if !problems.contains(&problem) {
+                        problems.push(problem);
+                    }
+                }
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use serde_json::{json, Value};
+    use super::*;
+    use crate::mopper_options::MopperOptionsBuilder;
+
+    fn plan_1036() -> Value {
+        let plan_str = fs::read_to_string("test-resources/rml-testcases/RMLTC1036-CSV/mapping.json").unwrap();
+        serde_json::from_str(&plan_str).unwrap()
+    }
+
+    fn problems(plan: &Value, to_one_target: bool) -> Vec<PlanProblem> {
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        match validate(&plan_graph, to_one_target, &MopperOptionsBuilder::default().build().unwrap()) {
+            Ok(()) => Vec::new(),
+            Err(MopperError::Validation(problems)) => problems,
+            Err(other) => panic!("Unexpected error {other}")
+        }
+    }
+
+    #[test]
+    fn valid_plan() {
+        assert!(problems(&plan_1036(), true).is_empty());
+    }
+
+    #[test]
+    fn all_problems_reported() {
+        let mut plan = plan_1036();
+        let edges = plan["edges"].as_array_mut().unwrap();
+        // remove one input of the join (node 6) and the target of serializer 8
+        edges.retain(|edge| !(edge[0] == 5 && edge[1] == 6 || edge[0] == 8 && edge[1] == 9));
+        edges.push(json!([2, 42, {"fragment": "default"}]));
+
+        let problems = problems(&plan, true);
+        let problem_strs: Vec<String> = problems.iter().map(|problem| problem.to_string()).collect();
+        assert!(problem_strs.contains(&"edge 13 refers to node 42, which does not exist".to_string()), "{problem_strs:?}");
+        assert!(problem_strs.contains(&"node 6: join has 1 input(s) instead of 2".to_string()), "{problem_strs:?}");
+        assert!(problem_strs.contains(&"node 8: serializer without target".to_string()), "{problem_strs:?}");
+        assert!(problem_strs.contains(&"node 9: operator without input".to_string()), "{problem_strs:?}");
+    }
+
+    #[test]
+    fn target_problems() {
+        let mut plan = plan_1036();
+        plan["nodes"][9]["operator"]["config"] = json!({"target_type": "File", "data_format": "JSONLD", "compression": "http://w3id.org/rml/zip"});
+        plan["nodes"][12]["operator"]["config"] = json!({"target_type": "File", "data_format": "NTriples", "path": "out.nt.gz", "compression": "gzip"});
+        plan["nodes"][15]["operator"]["config"] = json!({"target_type": "StdOut", "data_format": "NQuads", "sink": "http"});
+
+        let problem_strs: Vec<String> = problems(&plan, false).iter().map(|problem| problem.to_string()).collect();
+        assert_eq!(vec![
+            "node 9: file target without path".to_string(),
+            "node 9: target format JSONLD is not supported".to_string(),
+            "node 9: compression http://w3id.org/rml/zip is not supported".to_string(),
+            "node 15: HTTP target without url".to_string()
+        ], problem_strs);
+        assert!(problems(&plan, true).is_empty());
+    }
+
+    #[test]
+    fn source_problems() {
+        let mut plan = plan_1036();
+        plan["nodes"][0]["operator"]["config"] = json!({"source_type": "StdIn",
+            "root_iterator": {"reference": "$.sports[*]", "reference_formulation": "JSONPath", "fields": [], "alias": null}});
+        plan["nodes"][2]["operator"]["config"]["source_type"] = json!("SPARQLEndpoint");
+
+        let problem_strs: Vec<String> = problems(&plan, true).iter().map(|problem| problem.to_string()).collect();
+        assert_eq!(vec![
+            "node 0: JSONPath iterator $.sports[*] is not supported; records are read from JSON values with $, or from their array items with $[*]".to_string(),
+            "node 2: source type SPARQLEndpoint is not supported".to_string()
+        ], problem_strs);
+
+        // standard input, in CSV or JSON, needs no path, but can only be read by one source
+        plan["nodes"][0]["operator"]["config"]["root_iterator"]["reference"] = json!("$");
+        plan["nodes"][2]["operator"]["config"] = json!({"source_type": "StdIn",
+            "root_iterator": {"reference": null, "reference_formulation": "CSVRows", "fields": [], "alias": null}});
+        let problem_strs: Vec<String> = problems(&plan, true).iter().map(|problem| problem.to_string()).collect();
+        assert_eq!(vec!["node 2: source reads standard input, which node 0 reads already".to_string()], problem_strs);
+        plan["nodes"][2]["operator"]["config"] = plan["nodes"][0]["operator"]["config"].clone();
+        assert!(problems(&plan, true).is_empty());
+
+        // not when the mapping is read from standard input
+        let plan_graph = PlanGraph::from_json(&plan.to_string()).unwrap();
+        let options = MopperOptionsBuilder::default().mapping_from_std_in(true).build().unwrap();
+        let Err(MopperError::Validation(mapping_problems)) = validate(&plan_graph, true, &options) else { panic!("Standard input read twice") };
+        let problem_strs: Vec<String> = mapping_problems.iter().map(|problem| problem.to_string()).collect();
+        assert_eq!(vec![
+            "node 0: source reads standard input, from which the mapping is read".to_string(),
+            "node 2: source reads standard input, from which the mapping is read".to_string()
+        ], problem_strs);
+
+        // a WebSocket needs a url
+        plan["nodes"][2]["operator"]["config"]["source_type"] = json!("Websocket");
+        let problem_strs: Vec<String> = problems(&plan, true).iter().map(|problem| problem.to_string()).collect();
+        assert_eq!(vec!["node 2: WebSocket source without url".to_string()], problem_strs);
+        plan["nodes"][2]["operator"]["config"]["url"] = json!("wss://feed.example.com/sports");
+        assert!(problems(&plan, true).is_empty());
+    }
+
+    #[cfg(feature = "kafka")]
+    #[test]
+    fn kafka_source_problems() {
+        let mut plan = plan_1036();
+        plan["nodes"][0]["operator"]["config"] = json!({"source_type": "Kafka", "brokers": "localhost:9092", "topic": "sports",
+            "root_iterator": {"reference": "$", "reference_formulation": "JSONPath", "fields": [], "alias": null}});
+        plan["nodes"][2]["operator"]["config"] = json!({"source_type": "Kafka", "brokers": "localhost:9092", "groupId": "mapper",
+            "root_iterator": {"reference": null, "reference_formulation": "XMLPath", "fields": [], "alias": null}});
+
+        let problem_strs: Vec<String> = problems(&plan, true).iter().map(|problem| problem.to_string()).collect();
+        assert_eq!(vec![
+            "node 0: Kafka source without groupId".to_string(),
+            "node 2: reference formulation XMLPath is not supported".to_string(),
+            "node 2: Kafka source without topic".to_string()
+        ], problem_strs);
+    }
+
+    #[test]
+    fn ldes_source_problems() {
+        let mut plan = plan_1036();
+        // the fields of members are named by their properties, whatever the reference formulation
+        plan["nodes"][0]["operator"]["config"] = json!({"source_type": "File", "source": "LDES", "pollInterval": "soon",
+            "root_iterator": {"reference": null, "reference_formulation": "XMLPath", "fields": [], "alias": null}});
+        plan["nodes"][2]["operator"]["config"] = json!({"source_type": "File", "source": "LDES", "url": "https://example.com/sports",
+            "root_iterator": {"reference": null, "reference_formulation": "CSVRows", "fields": [], "alias": null}});
+
+        let problem_strs: Vec<String> = problems(&plan, true).iter().map(|problem| problem.to_string()).collect();
+        assert_eq!(vec![
+            "node 0: LDES source without url".to_string(),
+            "node 0: The pollInterval of an LDES source must be a positive number of seconds, not soon".to_string()
+        ], problem_strs);
+    }
+
+    #[test]
+    fn term_type_problems() {
+        let mut plan = plan_1036();
+        // extend 13 makes the subject ?tm0_sm and object ?tm0_o0_0 of serializer 14
+        let subject = plan.pointer("/nodes/13/operator/config/?tm0_sm").unwrap().clone();
+        plan["nodes"][13]["operator"]["config"]["?tm0_sm"] = json!({
+            "type": "Literal", "inner_function": {"type": "Reference", "value": "ID"}, "dtype_function": null, "langtype_function": null
+        });
+        plan["nodes"][13]["operator"]["config"]["?tm0_o0_0"] = json!({"type": "Iri", "base_iri": null, "inner_function": subject});
+
+        let problem_strs: Vec<String> = problems(&plan, true).iter().map(|problem| problem.to_string()).collect();
+        assert_eq!(vec![
+            "node 13: ?tm0_o0_0: an IRI cannot be made from an IRI".to_string(),
+            "node 13: ?tm0_sm: a literal cannot be the subject of a statement".to_string()
+        ], problem_strs);
+    }
+}
@@ -0,0 +1,173 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! [PROV-O](https://www.w3.org/TR/prov-o/) provenance of a run: which files and named graphs it generated,
+//! from which sources and mapping documents, by which version of mopper, and when.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::SystemTime;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use crate::logging::utc_timestamp;
+use crate::message::Message;
+use crate::rdf::{Statement, Term};
+use crate::test_suite::MOPPER_IRI;
+use crate::void::turtle_resource;
+
+/// The provenance of the output of a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProvenanceRecord {
+    pub started: SystemTime,
+    pub ended: SystemTime,
+    /// The sources read, as file IRIs if they are files
+    pub sources: Vec<String>,
+    /// The mapping documents executed, as file IRIs
+    pub mapping_documents: Vec<String>,
+    /// The files written, as file IRIs
+    pub output_files: Vec<String>,
+    /// The IRIs of the named graphs generated
+    pub graphs: BTreeSet<String>
+}
+
+impl ProvenanceRecord {
+    /// The provenance in Turtle. The run is the activity `<#run>`, mopper its agent `<#mopper>`, and every
+    /// output file and named graph an entity generated by the run and derived from all sources.
+    pub fn to_prov(&self) -> String {
+        let mut prov = String::from("\
+@prefix prov: <http://www.w3.org/ns/prov#> .
+@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+
+");
+        prov.push_str(&format!("\
+<#mopper> a prov:SoftwareAgent ;
+    rdfs:label \"mopper {}\" ;
+    rdfs:seeAlso <{MOPPER_IRI}> .
+
+", env!("CARGO_PKG_VERSION")));
+
+        let ended = utc_timestamp(self.ended);
+        prov.push_str(&format!("\
+<#run> a prov:Activity ;
+    prov:startedAtTime \"{}\"^^xsd:dateTime ;
+    prov:endedAtTime \"{ended}\"^^xsd:dateTime ;
+    prov:wasAssociatedWith <#mopper>", utc_timestamp(self.started)));
+        for mapping_document in &self.mapping_documents {
+            prov.push_str(&format!(" ;\n    prov:qualifiedAssociation [ a prov:Association ; prov:agent <#mopper> ; prov:hadPlan {} ]",
+                                   turtle_resource(mapping_document)));
+        }
+        for used in self.sources.iter().chain(&self.mapping_documents) {
+            prov.push_str(&format!(" ;\n    prov:used {}", turtle_resource(used)));
+        }
+        prov.push_str(" .\n");
+
+        for mapping_document in &self.mapping_documents {
+            prov.push_str(&format!("\n{} a prov:Plan, prov:Entity .\n", turtle_resource(mapping_document)));
+        }
+        for source in &self.sources {
+            prov.push_str(&format!("\n{} a prov:Entity .\n", turtle_resource(source)));
+        }
+        for generated in self.output_files.iter().chain(&self.graphs) {
+            prov.push_str(&format!("\n{} a prov:Entity ;\n    prov:wasGeneratedBy <#run> ;\n    prov:generatedAtTime \"{ended}\"^^xsd:dateTime",
+                                   turtle_resource(generated)));
+            for source in &self.sources {
+                prov.push_str(&format!(" ;\n    prov:wasDerivedFrom {}", turtle_resource(source)));
+            }
+            prov.push_str(" .\n");
+        }
+        prov
+    }
+}
+
+/// Collects the named graphs the serializers generate while the plan executes, and writes the provenance
+/// of the run when it is done.
+pub(crate) struct Provenance {
+    file: String,
+    started: SystemTime,
+    sources: Vec<String>,
+    mapping_documents: Vec<String>,
+    output_files: Vec<String>,
+    sender: Sender<Message>,
+    collector: JoinHandle<BTreeSet<String>>
+}
+
+impl Provenance {
+    pub(crate) fn new(file: &str, sources: Vec<String>, mapping_documents: Vec<String>, output_files: Vec<String>, capacity: usize) -> Self {
+        let (sender, receiver) = bounded::<Message>(capacity);
+        let collector = thread::Builder::new()
+            .name("Provenance collector".to_string())
+            .spawn(move || collect_graphs(receiver)).unwrap();
+        Provenance { file: file.to_string(), started: SystemTime::now(), sources, mapping_documents, output_files, sender, collector }
+    }
+
+    /// A channel to send the messages of a serializer to, like to a sink.
+    pub(crate) fn collect_from(&self) -> Sender<Message> {
+        self.sender.clone()
+    }
+
+    /// Waits until all named graphs are collected, and writes the provenance.
+    pub(crate) fn write(self) -> io::Result<ProvenanceRecord> {
+        let Provenance { file, started, sources, mapping_documents, output_files, sender, collector } = self;
+        drop(sender);
+        let graphs = collector.join().map_err(|_| io::Error::other("The provenance collector panicked"))?;
+        let record = ProvenanceRecord { started, ended: SystemTime::now(), sources, mapping_documents, output_files, graphs };
+        fs::write(file, record.to_prov())?;
+        Ok(record)
+    }
+}
+
+fn collect_graphs(receiver: Receiver<Message>) -> BTreeSet<String> {
+    let mut graphs = BTreeSet::new();
+    for message in receiver {
+        if let Message::Data(values) = message {
+            for statement in values.iter().flat_map(|value| value.lines()).filter_map(Statement::from_nquads) {
+                if let Some(Term::Iri(graph)) = statement.graph {
+                    graphs.insert(graph);
+                }
+            }
+        }
+    }
+    graphs
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn provenance_written() {
+        let started = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let record = ProvenanceRecord {
+            started,
+            ended: started + Duration::from_secs(90),
+            sources: vec!["file:///data/people.csv".to_string()],
+            mapping_documents: vec!["file:///data/mapping.ttl".to_string()],
+            output_files: vec!["file:///data/output.nq".to_string()],
+            graphs: BTreeSet::from(["http://example.com/people".to_string()])
+        };
+        let prov = record.to_prov();
+        assert!(prov.contains("prov:startedAtTime \"2023-11-14T22:13:20+00:00\"^^xsd:dateTime ;"));
+        assert!(prov.contains("prov:endedAtTime \"2023-11-14T22:14:50+00:00\"^^xsd:dateTime ;"));
+        assert!(prov.contains("prov:hadPlan <file:///data/mapping.ttl> ]"));
+        assert!(prov.contains("\n<file:///data/output.nq> a prov:Entity ;\n    prov:wasGeneratedBy <#run> ;"));
+        assert!(prov.contains("\n<http://example.com/people> a prov:Entity ;\n    prov:wasGeneratedBy <#run> ;"));
+        assert_eq!(2, prov.matches("prov:wasDerivedFrom <file:///data/people.csv> .").count());
+    }
+}
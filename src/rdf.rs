@@ -0,0 +1,145 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! RDF terms and statements, as mopper generates them.
+
+use std::fmt::{Display, Formatter};
+
+/// An RDF term.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Term {
+    Iri(String),
+    BlankNode(String),
+    Literal {
+        value: String,
+        datatype: Option<String>,
+        language: Option<String>
+    }
+}
+
+impl Display for Term {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Term::Iri(iri) => write!(f, "<{iri}>"),
+            Term::BlankNode(label) => write!(f, "_:{label}"),
+            Term::Literal { value, datatype: Some(datatype), .. } => write!(f, "\"{value}\"^^<{datatype}>"),
+            Term::Literal { value, language: Some(language), .. } => write!(f, "\"{value}\"@{language}"),
+            Term::Literal { value, .. } => write!(f, "\"{value}\"")
+        }
+    }
+}
+
+impl Term {
+    // Parses a term that is not a literal
+    fn from_nquads_resource(term: &str) -> Option<Term> {
+        if let Some(iri) = term.strip_prefix('<').and_then(|term| term.strip_suffix('>')) {
+            Some(Term::Iri(iri.to_string()))
+        } else {
+            term.strip_prefix("_:").map(|label| Term::BlankNode(label.to_string()))
+        }
+    }
+
+    // Parses a literal, as the serializer writes it
+    fn from_nquads_literal(term: &str) -> Option<Term> {
+        let term = term.strip_prefix('"')?;
+        if let Some(value) = term.strip_suffix('"') {
+            return Some(Term::Literal { value: value.to_string(), datatype: None, language: None });
+        }
+        if let Some((value, datatype)) = term.strip_suffix('>').and_then(|term| term.rsplit_once("\"^^<")) {
+            return Some(Term::Literal { value: value.to_string(), datatype: Some(datatype.to_string()), language: None });
+        }
+        let (value, language) = term.rsplit_once("\"@")?;
+        if language.is_empty() || !language.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return None;
+        }
+        Some(Term::Literal { value: value.to_string(), datatype: None, language: Some(language.to_string()) })
+    }
+}
+
+/// An RDF statement: a triple, or a quad if it is in a named graph.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Statement {
+    pub subject: Term,
+    pub predicate: Term,
+    pub object: Term,
+    pub graph: Option<Term>
+}
+
+impl Statement {
+    /// Parses a statement in N-Triples or N-Quads, as the serializer writes it.
+    /// Returns `None` if it is not a statement.
+    pub fn from_nquads(line: &str) -> Option<Statement> {
+        let line = line.trim().strip_suffix('.')?.trim_end();
+        let (subject, rest) = line.split_once(' ')?;
+        let (predicate, rest) = rest.trim_start().split_once(' ')?;
+        let rest = rest.trim_start();
+        let subject = Term::from_nquads_resource(subject)?;
+        let predicate = Term::from_nquads_resource(predicate)?;
+
+        let (object, graph) = if rest.starts_with('"') {
+            // the value of a literal can contain spaces, so look for a graph at the end
+            let with_graph = rest.rsplit_once(' ')
+                .and_then(|(object, graph)| Some((Term::from_nquads_literal(object.trim_end())?, Some(Term::from_nquads_resource(graph)?))));
+            match with_graph {
+                Some(object_and_graph) => object_and_graph,
+                None => (Term::from_nquads_literal(rest)?, None)
+            }
+        } else {
+            match rest.split_once(' ') {
+                Some((object, graph)) => (Term::from_nquads_resource(object)?, Some(Term::from_nquads_resource(graph.trim())?)),
+                None => (Term::from_nquads_resource(rest)?, None)
+            }
+        };
+        Some(Statement { subject, predicate, object, graph })
+    }
+}
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.subject, self.predicate, self.object)?;
+        if let Some(graph) = &self.graph {
+            write!(f, " {graph}")?;
+        }
+        write!(f, " .")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_statements() {
+        let statements = [
+            "<http://example.com/s> <http://example.com/p> <http://example.com/o> .",
+            "_:b0 <http://example.com/p> \"a literal with spaces\" <http://example.com/g> .",
+            "<http://example.com/s> <http://example.com/p> \"10\"^^<http://www.w3.org/2001/XMLSchema#integer> .",
+            "<http://example.com/s> <http://example.com/p> \"tennis\"@en-GB <http://example.com/g> .",
+            "<http://example.com/s> <http://example.com/p> \"a <http://example.com/x>\" ."
+        ];
+        for statement in statements {
+            let parsed = Statement::from_nquads(statement).unwrap();
+            assert_eq!(statement, parsed.to_string());
+        }
+
+        let statement = Statement::from_nquads(statements[1]).unwrap();
+        assert_eq!(Term::BlankNode("b0".to_string()), statement.subject);
+        assert_eq!(Term::Literal { value: "a literal with spaces".to_string(), datatype: None, language: None }, statement.object);
+        assert_eq!(Some(Term::Iri("http://example.com/g".to_string())), statement.graph);
+
+        assert_eq!(None, Statement::from_nquads("# a comment"));
+    }
+}
@@ -0,0 +1,297 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+use crate::mopper_options::{DeduplicationHasher, DeduplicationStrategy, ErrorMode, InvalidIriPolicy, MopperOptions};
+use crate::source::rate_limit::RateLimit;
+use crate::stats::RunStats;
+
+/// A machine-readable report of a run, to archive or to compare runs with.
+/// Serializes to JSON with [RunReport::write].
+#[derive(Debug, Serialize)]
+pub struct RunReport {
+    pub mopper_version: String,
+    /// The mapping (plan) that was executed, if read from a file
+    pub mapping_file: Option<String>,
+    /// When the run started, in seconds since the Unix epoch
+    pub started_at: u64,
+    pub configuration: RunConfiguration,
+    /// `true` if the run completed without a fatal error
+    pub success: bool,
+    /// Totals of the run. Absent if the run failed.
+    pub totals: Option<RunTotals>,
+    /// Statistics of the run per operator. Absent if the run failed.
+    pub statistics: Option<RunStats>,
+    /// The warnings logged during the run
+    pub warnings: Vec<String>,
+    /// The errors logged during the run, and the fatal error if the run failed
+    pub errors: Vec<String>
+}
+
+/// The options a run was started with.
+#[derive(Debug, Serialize)]
+pub struct RunConfiguration {
+    pub force_to_std_out: bool,
+    pub tag_targets: bool,
+    pub force_to_file: Option<String>,
+    pub write_buffer_capacity: usize,
+    pub flush_interval: Option<Duration>,
+    pub sync_on_close: bool,
+    pub write_in_place: bool,
+    pub source_read_timeout: Option<Duration>,
+    pub sink_write_timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub working_dir_hint: Option<String>,
+    pub message_buffer_capacity: usize,
+    pub deduplicate: bool,
+    pub deduplicate_index_dir: Option<String>,
+    pub deduplicate_globally: bool,
+    pub deduplication_strategy: DeduplicationStrategy,
+    pub deduplication_capacity: usize,
+    pub deduplication_hasher: DeduplicationHasher,
+    pub sink_deduplication: HashMap<usize, bool>,
+    pub benchmark: bool,
+    pub error_log_file: Option<String>,
+    pub shacl_shapes_file: Option<String>,
+    pub void_file: Option<String>,
+    pub dataset_report_file: Option<String>,
+    pub provenance_file: Option<String>,
+    pub sparql_queries: Vec<(String, String)>,
+    pub mapping_documents: Vec<String>,
+    pub invalid_iri_policy: InvalidIriPolicy,
+    pub error_mode: ErrorMode,
+    pub record_limit: Option<usize>,
+    pub sample_fraction: Option<f64>,
+    pub sample_seed: Option<u64>,
+    pub source_rate_limits: HashMap<usize, RateLimit>,
+    pub max_concurrent_sources: Option<usize>
+}
+
+impl From<&MopperOptions> for RunConfiguration {
+    fn from(options: &MopperOptions) -> Self {
+        RunConfiguration {
+            force_to_std_out: options.force_to_std_out(),
+            tag_targets: options.tag_targets(),
+            force_to_file: options.force_to_file().clone(),
+            write_buffer_capacity: options.write_buffer_capacity(),
+            flush_interval: options.flush_interval(),
+            sync_on_close: options.sync_on_close(),
+            write_in_place: options.write_in_place(),
+            source_read_timeout: options.source_read_timeout(),
+            sink_write_timeout: options.sink_write_timeout(),
+            connect_timeout: options.connect_timeout(),
+            working_dir_hint: options.working_dir_hint().clone(),
+            message_buffer_capacity: options.message_buffer_capacity(),
+            deduplicate: options.deduplicate(),
+            deduplicate_index_dir: options.deduplicate_index_dir().clone(),
+            deduplicate_globally: options.deduplicate_globally(),
+            deduplication_strategy: options.deduplication_strategy().clone(),
+            deduplication_capacity: options.deduplication_capacity(),
+            deduplication_hasher: options.deduplication_hasher().clone(),
+            sink_deduplication: options.sink_deduplication().clone(),
+            benchmark: options.benchmark(),
+            error_log_file: options.error_log_file().clone(),
+            shacl_shapes_file: options.shacl_shapes_file().clone(),
+            void_file: options.void_file().clone(),
+            dataset_report_file: options.dataset_report_file().clone(),
+            provenance_file: options.provenance_file().clone(),
+            sparql_queries: options.sparql_queries().to_vec(),
+            mapping_documents: options.mapping_documents().to_vec(),
+            invalid_iri_policy: options.invalid_iri_policy().clone(),
+            error_mode: options.error_mode().clone(),
+            record_limit: options.record_limit(),
+            sample_fraction: options.sample_fraction(),
+            sample_seed: options.sample_seed(),
+            source_rate_limits: options.source_rate_limits().clone(),
+            max_concurrent_sources: options.max_concurrent_sources()
+        }
+    }
+}
+
+/// The totals of a run, as summed up by [RunStats].
+#[derive(Debug, Serialize)]
+pub struct RunTotals {
+    pub records_read: u64,
+    pub statements_written: u64,
+    pub duplicates_removed: u64,
+    pub errors: u64,
+    pub invalid_iris: u64,
+    pub elapsed_seconds: f64
+}
+
+impl From<&RunStats> for RunTotals {
+    fn from(run_stats: &RunStats) -> Self {
+        RunTotals {
+            records_read: run_stats.records_read(),
+            statements_written: run_stats.statements_written(),
+            duplicates_removed: run_stats.duplicates_removed(),
+            errors: run_stats.errors(),
+            invalid_iris: run_stats.invalid_iris(),
+            elapsed_seconds: run_stats.elapsed.as_secs_f64()
+        }
+    }
+}
+
+impl RunReport {
+    /// Starts a report for a run with the given options. Call this right before starting the run.
+    pub fn new(mapping_file: Option<String>, options: &MopperOptions) -> Self {
+        let started_at = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or_default();
+        RunReport {
+            mopper_version: env!("CARGO_PKG_VERSION").to_string(),
+            mapping_file,
+            started_at,
+            configuration: RunConfiguration::from(options),
+            success: false,
+            totals: None,
+            statistics: None,
+            warnings: Vec::new(),
+            errors: Vec::new()
+        }
+    }
+
+    /// Completes the report with the outcome of the run and the warnings and errors logged,
+    /// if collected by `log_collector`.
+    pub fn finish(&mut self, result: &Result<RunStats, String>, log_collector: Option<&LogCollector>) {
+        if let Some(log_collector) = log_collector {
+            self.warnings = log_collector.warnings();
+            self.errors = log_collector.errors();
+        }
+        match result {
+            Ok(run_stats) => {
+                self.success = true;
+                self.totals = Some(RunTotals::from(run_stats));
+                self.statistics = Some(run_stats.clone());
+            },
+            Err(error) => {
+                self.success = false;
+                self.errors.push(error.clone());
+            }
+        }
+    }
+
+    /// Writes the report as JSON to the given file.
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
+
+/// A logger that remembers the warnings and errors logged by mopper, and passes every
+/// log record on to another logger.
+pub struct LogCollector {
+    inner: Box<dyn Log>,
+    warnings: Mutex<Vec<String>>,
+    errors: Mutex<Vec<String>>
+}
+
+impl LogCollector {
+    /// Installs a log collector passing log records on to `inner` as the global logger.
+    /// `inner_max_level` is the most verbose level `inner` logs; warnings and errors are
+    /// always collected.
+    pub fn install(inner: Box<dyn Log>, inner_max_level: LevelFilter) -> Result<&'static LogCollector, log::SetLoggerError> {
+        let log_collector: &'static LogCollector = Box::leak(Box::new(LogCollector {
+            inner,
+            warnings: Mutex::new(Vec::new()),
+            errors: Mutex::new(Vec::new())
+        }));
+        log::set_logger(log_collector)?;
+        log::set_max_level(inner_max_level.max(LevelFilter::Warn));
+        Ok(log_collector)
+    }
+
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.lock().unwrap().clone()
+    }
+
+    pub fn errors(&self) -> Vec<String> {
+        self.errors.lock().unwrap().clone()
+    }
+}
+
+impl Log for LogCollector {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Warn || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target().starts_with(module_path!().split("::").next().unwrap()) {
+            match record.level() {
+                Level::Error => self.errors.lock().unwrap().push(record.args().to_string()),
+                Level::Warn => self.warnings.lock().unwrap().push(record.args().to_string()),
+                _ => {}
+            }
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::mopper_options::MopperOptionsBuilder;
+    use crate::stats::{OperatorCounters, OperatorKind, OperatorStats, RunStats};
+    use super::*;
+
+    #[test]
+    fn report_to_json() {
+        let options = MopperOptionsBuilder::default().deduplicate(true).build().unwrap();
+        let mut report = RunReport::new(Some("mapping.json".to_string()), &options);
+        let counters = OperatorCounters::default();
+        counters.add_out(3);
+        let run_stats = RunStats {
+            elapsed: Duration::from_millis(1500),
+            operators: vec![OperatorStats::new(0, OperatorKind::Source, &counters)],
+            validation: None,
+            dataset: None
+        };
+        report.finish(&Ok(run_stats), None);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["mapping_file"], "mapping.json");
+        assert_eq!(json["success"], true);
+        assert_eq!(json["configuration"]["deduplicate"], true);
+        assert_eq!(json["configuration"]["deduplication_strategy"]["type"], "Exact");
+        assert_eq!(json["totals"]["records_read"], 3);
+        assert_eq!(json["statistics"]["elapsed_seconds"], 1.5);
+        assert_eq!(json["statistics"]["operators"][0]["kind"], "Source");
+    }
+
+    #[test]
+    fn report_of_failed_run() {
+        let options = MopperOptionsBuilder::default().build().unwrap();
+        let mut report = RunReport::new(None, &options);
+        report.finish(&Err("Something went wrong".to_string()), None);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["success"], false);
+        assert!(json["totals"].is_null());
+        assert_eq!(json["errors"][0], "Something went wrong");
+    }
+}
@@ -0,0 +1,171 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Secrets, like passwords of databases or endpoints, do not have to be in the plan. Source and target
+//! configurations refer to them as `${secret:<provider>:<argument>}`, e.g. `${secret:env:DB_PASSWORD}`,
+//! and a [CredentialsProvider] gives their value when the plan is read.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::process::Command;
+use std::sync::Arc;
+use std::{env, fs};
+
+/// Gives the value of a secret, given the argument of the secret reference.
+pub trait CredentialsProvider: Send + Sync {
+    fn secret(&self, argument: &str) -> Result<String, String>;
+}
+
+/// Takes the secret from the environment variable named by the argument.
+pub struct EnvProvider;
+
+impl CredentialsProvider for EnvProvider {
+    fn secret(&self, argument: &str) -> Result<String, String> {
+        env::var(argument).map_err(|err| format!("environment variable {argument}: {err}"))
+    }
+}
+
+/// Reads the secret from the file at the path given by the argument, without a trailing line break.
+pub struct FileProvider;
+
+impl CredentialsProvider for FileProvider {
+    fn secret(&self, argument: &str) -> Result<String, String> {
+        let content = fs::read_to_string(argument).map_err(|err| format!("file {argument}: {err}"))?;
+        Ok(without_trailing_line_break(content))
+    }
+}
+
+/// Runs the command given by the argument, e.g. `pass show db`, and takes its output without
+/// a trailing line break as secret. The command is not run by a shell.
+pub struct CommandProvider;
+
+impl CredentialsProvider for CommandProvider {
+    fn secret(&self, argument: &str) -> Result<String, String> {
+        let mut words = argument.split_whitespace();
+        let program = words.next().ok_or("no command given")?;
+        let output = Command::new(program)
+            .args(words)
+            .output()
+            .map_err(|err| format!("command {program}: {err}"))?;
+        if !output.status.success() {
+            return Err(format!("command {program} failed with {}", output.status));
+        }
+        let stdout = String::from_utf8(output.stdout).map_err(|err| format!("command {program}: {err}"))?;
+        Ok(without_trailing_line_break(stdout))
+    }
+}
+
+fn without_trailing_line_break(mut text: String) -> String {
+    if text.ends_with('\n') {
+        text.pop();
+        if text.ends_with('\r') {
+            text.pop();
+        }
+    }
+    text
+}
+
+/// The credentials providers by name. By default only `env`: as secrets are resolved for any plan, `file`
+/// and `cmd` would let a plan read any file or run any program, so they are only added on purpose.
+#[derive(Clone)]
+pub struct Credentials {
+    providers: HashMap<String, Arc<dyn CredentialsProvider>>
+}
+
+impl Credentials {
+    /// Credentials without any provider.
+    pub fn none() -> Self {
+        Credentials { providers: HashMap::new() }
+    }
+
+    /// Adds a provider, or replaces the one with the same name.
+    pub fn with_provider<P: CredentialsProvider + 'static>(mut self, name: &str, provider: P) -> Self {
+        self.providers.insert(name.to_string(), Arc::new(provider));
+        self
+    }
+
+    /// Resolves a secret reference `<provider>:<argument>`. The error does not contain the secret.
+    pub fn resolve(&self, reference: &str) -> Result<String, String> {
+        let (name, argument) = reference.split_once(':')
+            .ok_or_else(|| format!("Secret reference '{reference}' is not of the form <provider>:<argument>"))?;
+        let provider = self.providers.get(name)
+            .ok_or_else(|| format!("Unknown credentials provider '{name}' in secret reference '{reference}'"))?;
+        provider.secret(argument)
+            .map_err(|err| format!("Cannot get secret '{reference}': {err}"))
+    }
+}
+
+impl Default for Credentials {
+    fn default() -> Self {
+        Credentials::none()
+            .with_provider("env", EnvProvider)
+    }
+}
+
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<&String> = self.providers.keys().collect();
+        names.sort();
+        f.debug_set().entries(names).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn providers() {
+        let credentials = Credentials::default()
+            .with_provider("file", FileProvider)
+            .with_provider("cmd", CommandProvider);
+        env::set_var("MOPPER_TEST_SECRET", "s3cret");
+        assert_eq!(Ok("s3cret".to_string()), credentials.resolve("env:MOPPER_TEST_SECRET"));
+
+        let secret_file = env::temp_dir().join("mopper-test-secret.txt");
+        fs::write(&secret_file, "from file\n").unwrap();
+        assert_eq!(Ok("from file".to_string()), credentials.resolve(&format!("file:{}", secret_file.display())));
+        fs::remove_file(secret_file).unwrap();
+
+        if cfg!(unix) {
+            assert_eq!(Ok("from command".to_string()), credentials.resolve("cmd:echo from command"));
+        }
+
+        assert!(credentials.resolve("env:MOPPER_TEST_SECRET_NOT_SET").is_err());
+        assert!(credentials.resolve("vault:db").unwrap_err().contains("Unknown credentials provider 'vault'"));
+    }
+
+    #[test]
+    fn default_providers() {
+        let credentials = Credentials::default();
+        assert_eq!("{\"env\"}", format!("{credentials:?}"));
+        assert!(credentials.resolve("cmd:echo from command").unwrap_err().contains("Unknown credentials provider 'cmd'"));
+        assert!(credentials.resolve("file:/etc/hostname").unwrap_err().contains("Unknown credentials provider 'file'"));
+    }
+
+    #[test]
+    fn custom_provider() {
+        struct Fixed;
+        impl CredentialsProvider for Fixed {
+            fn secret(&self, argument: &str) -> Result<String, String> {
+                Ok(format!("secret of {argument}"))
+            }
+        }
+        let credentials = Credentials::none().with_provider("fixed", Fixed);
+        assert_eq!(Ok("secret of db".to_string()), credentials.resolve("fixed:db"));
+        assert!(credentials.resolve("env:HOME").is_err());
+    }
+}
@@ -0,0 +1,619 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Validating the generated statements against SHACL shapes when a run is done, and reporting the
+//! violations per mapping rule, i.e. per serializer of the plan that generated the focus node.
+//!
+//! A subset of SHACL Core is supported: node shapes with the targets `sh:targetClass`, `sh:targetNode`,
+//! `sh:targetSubjectsOf` and `sh:targetObjectsOf` (and classes that are shapes), property shapes with a
+//! predicate or inverse path, and the constraints `sh:minCount`, `sh:maxCount`, `sh:hasValue`, `sh:datatype`,
+//! `sh:class`, `sh:nodeKind`, `sh:in`, `sh:minLength` and `sh:maxLength`. Other constraints, like `sh:pattern`
+//! or `sh:node`, are not checked, and make the report inconclusive: it does not conform, even without
+//! violations. The statements of all graphs are validated together, as one graph.
+
+pub mod turtle;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::thread;
+use std::thread::JoinHandle;
+use crossbeam_channel::{bounded, Sender};
+use serde::Serialize;
+use crate::message::Message;
+use crate::rdf::{Statement, Term};
+use crate::shacl::turtle::{parse_turtle, RDF};
+
+const SH: &str = "http://www.w3.org/ns/shacl#";
+const RDFS_CLASS: &str = "http://www.w3.org/2000/01/rdf-schema#Class";
+const RDFS_SUB_CLASS_OF: &str = "http://www.w3.org/2000/01/rdf-schema#subClassOf";
+const XSD_STRING: &str = "http://www.w3.org/2001/XMLSchema#string";
+
+// Properties of shapes that are not constraints
+const NOT_CONSTRAINTS: &[&str] = &["path", "property", "targetClass", "targetNode", "targetSubjectsOf", "targetObjectsOf",
+    "name", "description", "message", "severity", "deactivated", "order", "group"];
+
+/// SHACL shapes to validate statements against.
+#[derive(Debug, Clone, Default)]
+pub struct Shapes {
+    node_shapes: Vec<NodeShape>,
+    // The constraints that are not checked, like `sh:pattern of shape <...>`
+    unsupported_constraints: Vec<String>
+}
+
+#[derive(Debug, Clone)]
+struct NodeShape {
+    id: Term,
+    targets: Vec<Target>,
+    constraints: Vec<Constraint>,
+    properties: Vec<PropertyShape>
+}
+
+#[derive(Debug, Clone)]
+struct PropertyShape {
+    path: PropertyPath,
+    constraints: Vec<Constraint>
+}
+
+#[derive(Debug, Clone)]
+enum PropertyPath {
+    Predicate(Term),
+    Inverse(Term)
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    Class(Term),
+    Node(Term),
+    SubjectsOf(Term),
+    ObjectsOf(Term)
+}
+
+#[derive(Debug, Clone)]
+enum Constraint {
+    MinCount(usize),
+    MaxCount(usize),
+    HasValue(Term),
+    Datatype(String),
+    Class(Term),
+    NodeKind(String),
+    In(Vec<Term>),
+    MinLength(usize),
+    MaxLength(usize)
+}
+
+impl Shapes {
+    /// Reads the shapes in a Turtle document.
+    pub fn from_turtle(turtle: &str) -> Result<Shapes, String> {
+        let statements = parse_turtle(turtle)?;
+        let shapes_graph = ShapesGraph::new(&statements);
+        let sh = |name: &str| Term::Iri(format!("{SH}{name}"));
+        let rdf_type = Term::Iri(format!("{RDF}type"));
+
+        // node shapes are declared as such, or have targets
+        let mut shape_ids: Vec<&Term> = Vec::new();
+        for statement in &statements {
+            let is_node_shape = (statement.predicate == rdf_type && statement.object == sh("NodeShape"))
+                || ["targetClass", "targetNode", "targetSubjectsOf", "targetObjectsOf"].iter().any(|target| statement.predicate == sh(target));
+            if is_node_shape && !shape_ids.contains(&&statement.subject) {
+                shape_ids.push(&statement.subject);
+            }
+        }
+
+        let mut node_shapes = Vec::new();
+        let mut unsupported_constraints = Vec::new();
+        for id in shape_ids {
+            if shapes_graph.values(id, &sh("deactivated")).iter().any(|value| is_true(value)) {
+                continue;
+            }
+            let mut targets = Vec::new();
+            for (target, kind) in [("targetClass", Target::Class as fn(Term) -> Target), ("targetNode", Target::Node),
+                                   ("targetSubjectsOf", Target::SubjectsOf), ("targetObjectsOf", Target::ObjectsOf)] {
+                targets.extend(shapes_graph.values(id, &sh(target)).into_iter().cloned().map(kind));
+            }
+            if shapes_graph.values(id, &rdf_type).contains(&&Term::Iri(RDFS_CLASS.to_string())) {
+                targets.push(Target::Class(id.clone()));
+            }
+            let properties = shapes_graph.values(id, &sh("property")).into_iter()
+                .map(|property| shapes_graph.property_shape(property, &mut unsupported_constraints))
+                .collect::<Result<Vec<PropertyShape>, String>>()?;
+            let constraints = shapes_graph.constraints(id, &mut unsupported_constraints)?;
+            node_shapes.push(NodeShape { id: id.clone(), targets, constraints, properties });
+        }
+        Ok(Shapes { node_shapes, unsupported_constraints })
+    }
+
+    /// Validates the statements, as one graph.
+    pub fn validate(&self, statements: &[Statement]) -> ValidationReport {
+        let mut data_graph = DataGraph::default();
+        for statement in statements {
+            data_graph.add(statement, None);
+        }
+        self.validate_graph(&data_graph)
+    }
+
+    fn validate_graph(&self, data_graph: &DataGraph) -> ValidationReport {
+        let mut violations = Vec::new();
+        for node_shape in &self.node_shapes {
+            for focus_node in data_graph.focus_nodes(&node_shape.targets) {
+                let mut violation = |path: Option<&PropertyPath>, value: Option<&Term>, message: String| {
+                    violations.push(Violation {
+                        focus_node: focus_node.to_string(),
+                        shape: node_shape.id.to_string(),
+                        path: path.map(|path| match path {
+                            PropertyPath::Predicate(predicate) => predicate.to_string(),
+                            PropertyPath::Inverse(predicate) => format!("^{predicate}")
+                        }),
+                        value: value.map(Term::to_string),
+                        message,
+                        mapping_rules: data_graph.origins_of(&focus_node)
+                    });
+                };
+                for (value, message) in check(&node_shape.constraints, &[&focus_node], data_graph) {
+                    violation(None, value, message);
+                }
+                for property in &node_shape.properties {
+                    let values = data_graph.values(&focus_node, &property.path);
+                    for (value, message) in check(&property.constraints, &values, data_graph) {
+                        violation(Some(&property.path), value, message);
+                    }
+                }
+            }
+        }
+        violations.sort_by(|a, b| (&a.mapping_rules, &a.focus_node, &a.path).cmp(&(&b.mapping_rules, &b.focus_node, &b.path)));
+        ValidationReport { violations, unsupported_constraints: self.unsupported_constraints.clone() }
+    }
+}
+
+// Checks the value nodes against the constraints, and returns the violations, with the value they are about, if any
+fn check<'a>(constraints: &[Constraint], values: &[&'a Term], data_graph: &DataGraph) -> Vec<(Option<&'a Term>, String)> {
+    let mut violations = Vec::new();
+    for constraint in constraints {
+        match constraint {
+            Constraint::MinCount(min_count) if values.len() < *min_count =>
+                violations.push((None, format!("{} values, but at least {min_count} expected", values.len()))),
+            Constraint::MaxCount(max_count) if values.len() > *max_count =>
+                violations.push((None, format!("{} values, but at most {max_count} expected", values.len()))),
+            Constraint::HasValue(expected) if !values.contains(&expected) =>
+                violations.push((None, format!("Value {expected} missing"))),
+            Constraint::MinCount(_) | Constraint::MaxCount(_) | Constraint::HasValue(_) => {},
+            _ => for value in values {
+                if let Some(message) = check_value(constraint, value, data_graph) {
+                    violations.push((Some(*value), message));
+                }
+            }
+        }
+    }
+    violations
+}
+
+// Checks one value node against a constraint on every value node
+fn check_value(constraint: &Constraint, value: &Term, data_graph: &DataGraph) -> Option<String> {
+    let lexical_form = match value {
+        Term::Iri(iri) => Some(iri),
+        Term::Literal { value, .. } => Some(value),
+        Term::BlankNode(_) => None
+    };
+    let conforms = match constraint {
+        Constraint::Datatype(datatype) => match value {
+            Term::Literal { datatype: Some(value_datatype), .. } => value_datatype == datatype,
+            Term::Literal { language: Some(_), .. } => datatype == &format!("{RDF}langString"),
+            Term::Literal { .. } => datatype == XSD_STRING,
+            _ => false
+        },
+        Constraint::Class(class) => data_graph.is_instance_of(value, class),
+        Constraint::NodeKind(node_kind) => match value {
+            Term::Iri(_) => node_kind.contains("IRI"),
+            Term::BlankNode(_) => node_kind.contains("BlankNode"),
+            Term::Literal { .. } => node_kind.contains("Literal")
+        },
+        Constraint::In(allowed) => allowed.contains(value),
+        Constraint::MinLength(min_length) => lexical_form.is_some_and(|form| form.chars().count() >= *min_length),
+        Constraint::MaxLength(max_length) => lexical_form.is_some_and(|form| form.chars().count() <= *max_length),
+        Constraint::MinCount(_) | Constraint::MaxCount(_) | Constraint::HasValue(_) => true
+    };
+    match conforms {
+        true => None,
+        false => Some(match constraint {
+            Constraint::Datatype(datatype) => format!("Value does not have datatype <{datatype}>"),
+            Constraint::Class(class) => format!("Value is not an instance of {class}"),
+            Constraint::NodeKind(node_kind) => format!("Value is not of node kind sh:{node_kind}"),
+            Constraint::In(_) => "Value is not one of the allowed values".to_string(),
+            Constraint::MinLength(min_length) => format!("Value is shorter than {min_length} characters"),
+            Constraint::MaxLength(max_length) => format!("Value is longer than {max_length} characters"),
+            _ => "Value does not conform".to_string()
+        })
+    }
+}
+
+fn is_true(term: &Term) -> bool {
+    matches!(term, Term::Literal { value, .. } if value == "true")
+}
+
+// The statements of the shapes, by subject
+struct ShapesGraph<'a> {
+    properties: HashMap<&'a Term, Vec<(&'a Term, &'a Term)>>
+}
+
+impl<'a> ShapesGraph<'a> {
+    fn new(statements: &'a [Statement]) -> Self {
+        let mut properties: HashMap<&Term, Vec<(&Term, &Term)>> = HashMap::new();
+        for statement in statements {
+            properties.entry(&statement.subject).or_default().push((&statement.predicate, &statement.object));
+        }
+        ShapesGraph { properties }
+    }
+
+    fn values(&self, subject: &Term, predicate: &Term) -> Vec<&'a Term> {
+        self.properties.get(subject).into_iter().flatten()
+            .filter(|(property, _)| *property == predicate)
+            .map(|(_, value)| *value)
+            .collect()
+    }
+
+    fn property_shape(&self, id: &Term, unsupported_constraints: &mut Vec<String>) -> Result<PropertyShape, String> {
+        let paths = self.values(id, &Term::Iri(format!("{SH}path")));
+        let inverse_path = |path: &Term| match self.values(path, &Term::Iri(format!("{SH}inversePath")))[..] {
+            [predicate @ Term::Iri(_)] => Some(PropertyPath::Inverse(predicate.clone())),
+            _ => None
+        };
+        let path = match paths[..] {
+            [predicate @ Term::Iri(_)] => PropertyPath::Predicate(predicate.clone()),
+            [path @ Term::BlankNode(_)] => inverse_path(path)
+                .ok_or_else(|| format!("The path of property shape {id} is not supported; only predicates and inverse predicates are"))?,
+            _ => return Err(format!("Property shape {id} must have one path"))
+        };
+        Ok(PropertyShape { path, constraints: self.constraints(id, unsupported_constraints)? })
+    }
+
+    // The constraints of the shape, adding those that are not supported to `unsupported_constraints`
+    fn constraints(&self, shape: &Term, unsupported_constraints: &mut Vec<String>) -> Result<Vec<Constraint>, String> {
+        let mut constraints = Vec::new();
+        for (property, value) in self.properties.get(shape).into_iter().flatten() {
+            let Term::Iri(property) = property else { continue };
+            let Some(name) = property.strip_prefix(SH) else { continue };
+            let number = || match value {
+                Term::Literal { value: number, .. } => number.parse::<usize>().map_err(|_| format!("sh:{name} of shape {shape} must be a number, not {number}")),
+                _ => Err(format!("sh:{name} of shape {shape} must be a number"))
+            };
+            let constraint = match name {
+                "minCount" => Constraint::MinCount(number()?),
+                "maxCount" => Constraint::MaxCount(number()?),
+                "minLength" => Constraint::MinLength(number()?),
+                "maxLength" => Constraint::MaxLength(number()?),
+                "hasValue" => Constraint::HasValue((*value).clone()),
+                "class" => Constraint::Class((*value).clone()),
+                "datatype" => match value {
+                    Term::Iri(datatype) => Constraint::Datatype(datatype.clone()),
+                    _ => return Err(format!("sh:datatype of shape {shape} must be an IRI"))
+                },
+                "nodeKind" => match value {
+                    Term::Iri(node_kind) if node_kind.starts_with(SH) => Constraint::NodeKind(node_kind[SH.len()..].to_string()),
+                    _ => return Err(format!("sh:nodeKind of shape {shape} must be a node kind of SHACL"))
+                },
+                "in" => Constraint::In(self.list(value)?),
+                name if NOT_CONSTRAINTS.contains(&name) => continue,
+                name => {
+                    unsupported_constraints.push(format!("sh:{name} of shape {shape}"));
+                    continue;
+                }
+            };
+            constraints.push(constraint);
+        }
+        Ok(constraints)
+    }
+
+    // The items of an RDF list
+    fn list(&self, mut list: &'a Term) -> Result<Vec<Term>, String> {
+        let nil = Term::Iri(format!("{RDF}nil"));
+        let mut items = Vec::new();
+        while *list != nil {
+            let first = self.values(list, &Term::Iri(format!("{RDF}first")));
+            let rest = self.values(list, &Term::Iri(format!("{RDF}rest")));
+            match (&first[..], &rest[..]) {
+                ([first], [rest]) => {
+                    items.push((*first).clone());
+                    list = rest;
+                },
+                _ => return Err(format!("{list} is not a well-formed list"))
+            }
+        }
+        Ok(items)
+    }
+}
+
+// The statements to validate, by subject and by object, and the serializers that generated the statements
+// about every subject
+#[derive(Default)]
+struct DataGraph {
+    outgoing: HashMap<Term, Vec<(Term, Term)>>,
+    incoming: HashMap<Term, Vec<(Term, Term)>>,
+    origins: HashMap<Term, Vec<usize>>
+}
+
+impl DataGraph {
+    fn add(&mut self, statement: &Statement, origin: Option<usize>) {
+        let Statement { subject, predicate, object, .. } = statement;
+        if let Some(origin) = origin {
+            let origins = self.origins.entry(subject.clone()).or_default();
+            if !origins.contains(&origin) {
+                origins.push(origin);
+            }
+        }
+        let outgoing = self.outgoing.entry(subject.clone()).or_default();
+        if outgoing.iter().any(|(p, o)| p == predicate && o == object) {
+            return;
+        }
+        outgoing.push((predicate.clone(), object.clone()));
+        self.incoming.entry(object.clone()).or_default().push((predicate.clone(), subject.clone()));
+    }
+
+    fn focus_nodes(&self, targets: &[Target]) -> Vec<Term> {
+        let mut focus_nodes: Vec<Term> = Vec::new();
+        let mut add = |node: &Term| if !focus_nodes.contains(node) {
+            focus_nodes.push(node.clone());
+        };
+        for target in targets {
+            match target {
+                Target::Node(node) => add(node),
+                Target::Class(class) => self.outgoing.keys()
+                    .filter(|subject| self.is_instance_of(subject, class))
+                    .for_each(&mut add),
+                Target::SubjectsOf(predicate) => self.outgoing.iter()
+                    .filter(|(_, properties)| properties.iter().any(|(p, _)| p == predicate))
+                    .for_each(|(subject, _)| add(subject)),
+                Target::ObjectsOf(predicate) => self.incoming.iter()
+                    .filter(|(_, properties)| properties.iter().any(|(p, _)| p == predicate))
+                    .for_each(|(object, _)| add(object))
+            }
+        }
+        focus_nodes
+    }
+
+    fn values(&self, focus_node: &Term, path: &PropertyPath) -> Vec<&Term> {
+        let (statements, predicate) = match path {
+            PropertyPath::Predicate(predicate) => (self.outgoing.get(focus_node), predicate),
+            PropertyPath::Inverse(predicate) => (self.incoming.get(focus_node), predicate)
+        };
+        statements.into_iter().flatten()
+            .filter(|(p, _)| p == predicate)
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    // Whether the node has the class or one of its subclasses as type
+    fn is_instance_of(&self, node: &Term, class: &Term) -> bool {
+        let rdf_type = Term::Iri(format!("{RDF}type"));
+        let sub_class_of = Term::Iri(RDFS_SUB_CLASS_OF.to_string());
+        let mut classes: Vec<&Term> = self.values(node, &PropertyPath::Predicate(rdf_type));
+        let mut seen: HashSet<&Term> = HashSet::new();
+        while let Some(node_class) = classes.pop() {
+            if node_class == class {
+                return true;
+            }
+            if seen.insert(node_class) {
+                classes.extend(self.values(node_class, &PropertyPath::Predicate(sub_class_of.clone())));
+            }
+        }
+        false
+    }
+
+    fn origins_of(&self, node: &Term) -> Vec<usize> {
+        let mut origins = self.origins.get(node).cloned().unwrap_or_default();
+        origins.sort_unstable();
+        origins
+    }
+}
+
+/// A focus node that does not conform to a shape.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    pub focus_node: String,
+    pub shape: String,
+    /// The path of the property shape, if the violation is about the values of a property
+    pub path: Option<String>,
+    /// The value the violation is about, if it is about one value
+    pub value: Option<String>,
+    pub message: String,
+    /// The node ids of the serializers that generated statements about the focus node
+    pub mapping_rules: Vec<usize>
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.focus_node)?;
+        if let Some(path) = &self.path {
+            write!(f, " {path}")?;
+        }
+        // The value of a node shape constraint is the focus node itself
+        if let Some(value) = self.value.as_ref().filter(|value| **value != self.focus_node) {
+            write!(f, " {value}")?;
+        }
+        write!(f, ": {} (shape {})", self.message, self.shape)
+    }
+}
+
+/// The violations of the shapes found in the generated statements.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ValidationReport {
+    pub violations: Vec<Violation>,
+    /// The constraints of the shapes that are not supported, and so not checked
+    pub unsupported_constraints: Vec<String>
+}
+
+impl ValidationReport {
+    /// Whether there are no violations and all constraints are checked.
+    pub fn conforms(&self) -> bool {
+        self.violations.is_empty() && self.is_conclusive()
+    }
+
+    /// Whether all constraints of the shapes are checked, i.e. none of them is unsupported.
+    pub fn is_conclusive(&self) -> bool {
+        self.unsupported_constraints.is_empty()
+    }
+
+    /// The violations by the node id of the serializer that generated the focus node, if any.
+    pub fn by_mapping_rule(&self) -> BTreeMap<Option<usize>, Vec<&Violation>> {
+        let mut by_mapping_rule: BTreeMap<Option<usize>, Vec<&Violation>> = BTreeMap::new();
+        for violation in &self.violations {
+            if violation.mapping_rules.is_empty() {
+                by_mapping_rule.entry(None).or_default().push(violation);
+            }
+            for mapping_rule in &violation.mapping_rules {
+                by_mapping_rule.entry(Some(*mapping_rule)).or_default().push(violation);
+            }
+        }
+        by_mapping_rule
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.conforms() {
+            return write!(f, "The generated statements conform to the SHACL shapes.");
+        }
+        write!(f, "{} SHACL violations", self.violations.len())?;
+        for (mapping_rule, violations) in self.by_mapping_rule() {
+            match mapping_rule {
+                Some(node_id) => write!(f, "\nMapping rule of serializer {node_id}: {} violations", violations.len())?,
+                None => write!(f, "\nNot generated by a mapping rule: {} violations", violations.len())?
+            }
+            for violation in violations {
+                write!(f, "\n  {violation}")?;
+            }
+        }
+        if !self.is_conclusive() {
+            write!(f, "\nThe validation is inconclusive: {} constraints are not supported, and not checked", self.unsupported_constraints.len())?;
+            for constraint in &self.unsupported_constraints {
+                write!(f, "\n  {constraint}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collects the statements the serializers generate during a run, to validate them when it is done.
+pub(crate) struct ShaclValidation {
+    shapes: Shapes,
+    collectors: Vec<(usize, JoinHandle<Vec<Statement>>)>
+}
+
+impl ShaclValidation {
+    pub(crate) fn new(shapes: Shapes) -> Self {
+        ShaclValidation { shapes, collectors: Vec::new() }
+    }
+
+    /// A channel to send the messages of the serializer with the given node id to, like to a sink.
+    pub(crate) fn collect_from(&mut self, node_id: usize, capacity: usize) -> Sender<Message> {
+        let (sender, receiver) = bounded::<Message>(capacity);
+        let collector = thread::Builder::new()
+            .name(format!("SHACL collector {node_id}"))
+            .spawn(move || {
+                let mut statements = Vec::new();
+                for message in receiver {
+                    if let Message::Data(values) = message {
+                        statements.extend(values.iter()
+                            .flat_map(|value| value.lines())
+                            .filter_map(Statement::from_nquads));
+                    }
+                }
+                statements
+            }).unwrap();
+        self.collectors.push((node_id, collector));
+        sender
+    }
+
+    /// Waits until all statements are collected, and validates them.
+    pub(crate) fn validate(self) -> ValidationReport {
+        let mut data_graph = DataGraph::default();
+        for (node_id, collector) in self.collectors {
+            for statement in collector.join().unwrap_or_default() {
+                data_graph.add(&statement, Some(node_id));
+            }
+        }
+        self.shapes.validate_graph(&data_graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_validated() {
+        let shapes = Shapes::from_turtle(r#"
+            @prefix sh: <http://www.w3.org/ns/shacl#> .
+            @prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+            @prefix ex: <http://example.com/> .
+
+            ex:PersonShape a sh:NodeShape ;
+                sh:targetClass ex:Person ;
+                sh:nodeKind sh:IRI ;
+                sh:property [ sh:path ex:name ; sh:minCount 1 ; sh:maxCount 1 ; sh:datatype xsd:string ] ;
+                sh:property [ sh:path ex:sport ; sh:in ( ex:Tennis ex:Football ) ] ;
+                sh:property [ sh:path [ sh:inversePath ex:member ] ; sh:class ex:Team ] ;
+                sh:property [ sh:path ex:nickname ; sh:pattern "^[a-z]+$" ] .
+        "#).unwrap();
+        let statements: Vec<Statement> = [
+            "<http://example.com/Venus> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://example.com/Student> .",
+            "<http://example.com/Student> <http://www.w3.org/2000/01/rdf-schema#subClassOf> <http://example.com/Person> .",
+            "<http://example.com/Venus> <http://example.com/name> \"Venus\" .",
+            "<http://example.com/Venus> <http://example.com/name> \"Venus\" .",
+            "<http://example.com/Venus> <http://example.com/sport> <http://example.com/Tennis> .",
+            "<http://example.com/Bob> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://example.com/Person> .",
+            "<http://example.com/Bob> <http://example.com/sport> <http://example.com/Chess> .",
+            "<http://example.com/Club> <http://example.com/member> <http://example.com/Bob> .",
+            "_:anonymous <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://example.com/Person> .",
+            "_:anonymous <http://example.com/name> \"Anonymous\"@en ."
+        ].iter().map(|line| Statement::from_nquads(line).unwrap()).collect();
+
+        let report = shapes.validate(&statements);
+        let mut violations: Vec<String> = report.violations.iter().map(|violation| violation.to_string()).collect();
+        violations.sort();
+        assert_eq!(violations, vec![
+            "<http://example.com/Bob> <http://example.com/name>: 0 values, but at least 1 expected (shape <http://example.com/PersonShape>)",
+            "<http://example.com/Bob> <http://example.com/sport> <http://example.com/Chess>: Value is not one of the allowed values (shape <http://example.com/PersonShape>)",
+            "<http://example.com/Bob> ^<http://example.com/member> <http://example.com/Club>: Value is not an instance of <http://example.com/Team> (shape <http://example.com/PersonShape>)",
+            "_:anonymous <http://example.com/name> \"Anonymous\"@en: Value does not have datatype <http://www.w3.org/2001/XMLSchema#string> (shape <http://example.com/PersonShape>)",
+            "_:anonymous: Value is not of node kind sh:IRI (shape <http://example.com/PersonShape>)"
+        ]);
+        assert!(!report.conforms());
+        assert_eq!(report.by_mapping_rule().keys().collect::<Vec<_>>(), vec![&None]);
+        assert!(!report.is_conclusive());
+        assert_eq!(1, report.unsupported_constraints.len());
+        assert!(report.unsupported_constraints[0].starts_with("sh:pattern of shape _:"));
+    }
+
+    #[test]
+    fn unsupported_constraints_inconclusive() {
+        let shapes = Shapes::from_turtle(r#"
+            @prefix sh: <http://www.w3.org/ns/shacl#> .
+            @prefix ex: <http://example.com/> .
+
+            ex:PersonShape sh:targetClass ex:Person ;
+                sh:or ( [ sh:class ex:Student ] [ sh:class ex:Teacher ] ) .
+        "#).unwrap();
+        let statements = vec![Statement::from_nquads("<http://example.com/Bob> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://example.com/Person> .").unwrap()];
+        let report = shapes.validate(&statements);
+        assert!(report.violations.is_empty());
+        assert!(!report.conforms());
+        assert_eq!(vec!["sh:or of shape <http://example.com/PersonShape>"], report.unsupported_constraints);
+        assert_eq!(concat!(
+            "0 SHACL violations\n",
+            "The validation is inconclusive: 1 constraints are not supported, and not checked\n",
+            "  sh:or of shape <http://example.com/PersonShape>"
+        ), report.to_string());
+    }
+}
@@ -0,0 +1,487 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Reading the statements of Turtle documents, e.g. SHACL shapes or LDES pages, with [oxttl].
+//! [TurtleParser] reads the Turtle syntax of the triple patterns in SPARQL queries, see [crate::sparql].
+
+use std::collections::HashMap;
+use std::str::CharIndices;
+use iri_string::types::{IriAbsoluteStr, IriReferenceStr};
+use crate::rdf::{Statement, Term};
+
+pub(crate) const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// Reads the statements of a Turtle document. Relative IRIs are resolved against its base IRI, if any.
+pub fn parse_turtle(turtle: &str) -> Result<Vec<Statement>, String> {
+    parse_turtle_with_base(turtle, "")
+}
+
+/// Reads the statements of a Turtle or N-Triples document with the given base IRI, e.g. the URL it is read from,
+/// which a base IRI in the document replaces.
+pub fn parse_turtle_with_base(turtle: &str, base: &str) -> Result<Vec<Statement>, String> {
+    let mut parser = oxttl::TurtleParser::new();
+    if !base.is_empty() {
+        parser = parser.with_base_iri(base).map_err(|error| format!("Invalid base IRI <{base}>: {error}"))?;
+    }
+    parser.for_slice(turtle.as_bytes())
+        .map(|triple| {
+            let triple = triple.map_err(|error| error.to_string())?;
+            Ok(Statement { subject: subject(triple.subject), predicate: Term::Iri(triple.predicate.into_string()), object: object(triple.object), graph: None })
+        })
+        .collect()
+}
+
+fn subject(subject: oxrdf::Subject) -> Term {
+    match subject {
+        oxrdf::Subject::NamedNode(iri) => Term::Iri(iri.into_string()),
+        oxrdf::Subject::BlankNode(blank_node) => Term::BlankNode(blank_node.into_string())
+    }
+}
+
+fn object(object: oxrdf::Term) -> Term {
+    match object {
+        oxrdf::Term::NamedNode(iri) => Term::Iri(iri.into_string()),
+        oxrdf::Term::BlankNode(blank_node) => Term::BlankNode(blank_node.into_string()),
+        oxrdf::Term::Literal(literal) => {
+            // plain and language-tagged literals have no datatype, not even xsd:string
+            let (value, datatype, language) = literal.destruct();
+            Term::Literal { value, datatype: datatype.map(oxrdf::NamedNode::into_string), language }
+        }
+    }
+}
+
+pub(crate) struct TurtleParser<'a> {
+    input: &'a str,
+    pub(crate) pos: usize,
+    base: String,
+    prefixes: HashMap<String, String>,
+    blank_nodes: usize,
+    statements: Vec<Statement>,
+    // Whether terms can be SPARQL variables, which become blank nodes labelled `?` and their name
+    variables: bool
+}
+
+// The characters that end a prefixed name, besides white space
+const NAME_DELIMITERS: &[char] = &[';', ',', ')', ']', '(', '[', '{', '}', '"', '\'', '#', '<'];
+
+impl<'a> TurtleParser<'a> {
+    pub(crate) fn new(input: &'a str, variables: bool) -> Self {
+        TurtleParser { input, pos: 0, base: String::new(), prefixes: HashMap::new(), blank_nodes: 0, statements: Vec::new(), variables }
+    }
+
+    /// The statements read since the last call.
+    pub(crate) fn take_statements(&mut self) -> Vec<Statement> {
+        std::mem::take(&mut self.statements)
+    }
+
+    pub(crate) fn prefix(&mut self) -> Result<(), String> {
+        self.skip_white_space();
+        let end = self.rest().find(':').ok_or("Expected ':' after the prefix")?;
+        let prefix = self.rest()[..end].trim().to_string();
+        self.pos += end + 1;
+        self.skip_white_space();
+        let iri = self.iri_ref()?;
+        self.prefixes.insert(prefix, iri);
+        Ok(())
+    }
+
+    pub(crate) fn base(&mut self) -> Result<(), String> {
+        self.skip_white_space();
+        self.base = self.iri_ref()?;
+        Ok(())
+    }
+
+    pub(crate) fn triples(&mut self) -> Result<(), String> {
+        let subject = match self.peek() {
+            Some('[') => {
+                let subject = self.blank_node_property_list()?;
+                // the predicate object list is optional after a blank node property list
+                self.skip_white_space();
+                if self.peek() == Some('.') {
+                    return Ok(());
+                }
+                subject
+            },
+            Some('(') => self.collection()?,
+            _ => self.resource()?
+        };
+        self.predicate_object_list(&subject)
+    }
+
+    fn predicate_object_list(&mut self, subject: &Term) -> Result<(), String> {
+        loop {
+            self.skip_white_space();
+            let predicate = if self.rest().starts_with('a') && self.rest()[1..].starts_with(|c: char| c.is_whitespace() || c == '<' || c == '[' || c == '"') {
+                self.pos += 1;
+                Term::Iri(format!("{RDF}type"))
+            } else {
+                self.resource()?
+            };
+            loop {
+                let object = self.object()?;
+                self.statements.push(Statement { subject: subject.clone(), predicate: predicate.clone(), object, graph: None });
+                self.skip_white_space();
+                if !self.eat(",") {
+                    break;
+                }
+            }
+            let mut more = false;
+            while self.eat(";") {
+                more = true;
+                self.skip_white_space();
+            }
+            if !more || matches!(self.peek(), Some('.') | Some(']') | Some('}') | None) {
+                return Ok(());
+            }
+        }
+    }
+
+    pub(crate) fn object(&mut self) -> Result<Term, String> {
+        self.skip_white_space();
+        match self.peek() {
+            Some('[') => self.blank_node_property_list(),
+            Some('(') => self.collection(),
+            Some('"') | Some('\'') => self.literal(),
+            Some(c) if c.is_ascii_digit() || c == '+' || c == '-' || c == '.' => self.number(),
+            _ if self.eat_keyword("true") => Ok(typed_literal("true", "boolean")),
+            _ if self.eat_keyword("false") => Ok(typed_literal("false", "boolean")),
+            _ => self.resource()
+        }
+    }
+
+    // An IRI, a prefixed name or a labelled blank node
+    pub(crate) fn resource(&mut self) -> Result<Term, String> {
+        self.skip_white_space();
+        if self.peek() == Some('<') {
+            return Ok(Term::Iri(self.iri_ref()?));
+        }
+        if self.variables && self.rest().starts_with(['/', '|', '^', '*']) {
+            return Err("A property path is not supported in the SPARQL subset of mopper".to_string());
+        }
+        let name = self.name();
+        if self.variables && (name.starts_with('?') || name.starts_with('$')) && name.len() > 1 {
+            return Ok(Term::BlankNode(format!("?{}", &name[1..])));
+        }
+        if name.is_empty() {
+            return Err(format!("Unexpected '{}'", self.rest().chars().next().unwrap_or(' ')));
+        }
+        if let Some(label) = name.strip_prefix("_:") {
+            return Ok(Term::BlankNode(format!("turtle-{label}")));
+        }
+        let (prefix, local) = name.split_once(':').ok_or_else(|| format!("'{name}' is not an IRI"))?;
+        let namespace = self.prefixes.get(prefix).ok_or_else(|| format!("Unknown prefix '{prefix}'"))?;
+        Ok(Term::Iri(format!("{namespace}{}", unescape_local_name(local))))
+    }
+
+    // A prefixed name or blank node label; it does not end with a '.'
+    fn name(&mut self) -> &'a str {
+        let rest = self.rest();
+        let mut end = rest.find(|c: char| c.is_whitespace() || NAME_DELIMITERS.contains(&c)).unwrap_or(rest.len());
+        while rest[..end].ends_with('.') {
+            end -= 1;
+        }
+        self.pos += end;
+        &rest[..end]
+    }
+
+    fn iri_ref(&mut self) -> Result<String, String> {
+        self.expect('<')?;
+        let mut iri = String::new();
+        let mut chars = self.rest().char_indices();
+        let end = loop {
+            let (index, c) = chars.next().ok_or("Unterminated IRI")?;
+            match c {
+                '>' => break index + 1,
+                '\\' => match chars.next() {
+                    Some((_, escaped @ ('u' | 'U'))) => iri.push(unicode_escape(&mut chars, escaped)?),
+                    _ => return Err("Only \\u and \\U escapes are allowed in an IRI".to_string())
+                },
+                c if c.is_whitespace() || "<\"{}|^`".contains(c) => return Err(format!("Invalid character '{c}' in IRI")),
+                c => iri.push(c)
+            }
+        };
+        self.pos += end;
+        Ok(self.resolve(&iri))
+    }
+
+    // Resolves a relative IRI against the base IRI, as in RFC 3986 if both are valid
+    fn resolve(&self, iri: &str) -> String {
+        let is_absolute = iri.split_once(':').is_some_and(|(scheme, _)| !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)));
+        let base = self.base.split('#').next().unwrap_or_default();
+        if is_absolute || self.base.is_empty() {
+            iri.to_string()
+        } else if let (Ok(base), Ok(reference)) = (IriAbsoluteStr::new(base), IriReferenceStr::new(iri)) {
+            reference.resolve_against(base).to_string()
+        } else if iri.is_empty() {
+            self.base.clone()
+        } else if iri.starts_with('#') {
+            format!("{}{iri}", self.base.split('#').next().unwrap_or_default())
+        } else {
+            let dir_end = self.base.rfind('/').map_or(self.base.len(), |slash| slash + 1);
+            format!("{}{iri}", &self.base[..dir_end])
+        }
+    }
+
+    fn blank_node_property_list(&mut self) -> Result<Term, String> {
+        self.expect('[')?;
+        let blank_node = self.new_blank_node();
+        self.skip_white_space();
+        if self.peek() != Some(']') {
+            self.predicate_object_list(&blank_node)?;
+        }
+        self.expect(']')?;
+        Ok(blank_node)
+    }
+
+    fn collection(&mut self) -> Result<Term, String> {
+        self.expect('(')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_white_space();
+            if self.eat(")") {
+                break;
+            }
+            if self.rest().is_empty() {
+                return Err("Unterminated collection".to_string());
+            }
+            items.push(self.object()?);
+        }
+        let mut list = Term::Iri(format!("{RDF}nil"));
+        for item in items.into_iter().rev() {
+            let node = self.new_blank_node();
+            self.statements.push(Statement { subject: node.clone(), predicate: Term::Iri(format!("{RDF}first")), object: item, graph: None });
+            self.statements.push(Statement { subject: node.clone(), predicate: Term::Iri(format!("{RDF}rest")), object: list, graph: None });
+            list = node;
+        }
+        Ok(list)
+    }
+
+    pub(crate) fn literal(&mut self) -> Result<Term, String> {
+        let quote = self.peek().ok_or("Expected a literal")?;
+        let long_quote = quote.to_string().repeat(3);
+        let long = self.eat(&long_quote);
+        if !long {
+            self.pos += 1;
+        }
+        let mut value = String::new();
+        let mut chars = self.rest().char_indices();
+        let end = loop {
+            let (index, c) = chars.next().ok_or("Unterminated literal")?;
+            match c {
+                '\\' => {
+                    let (_, escaped) = chars.next().ok_or("Unterminated literal")?;
+                    match escaped {
+                        't' => value.push('\t'),
+                        'n' => value.push('\n'),
+                        'r' => value.push('\r'),
+                        'b' => value.push('\u{8}'),
+                        'f' => value.push('\u{c}'),
+                        'u' | 'U' => value.push(unicode_escape(&mut chars, escaped)?),
+                        other => value.push(other)
+                    }
+                },
+                '\n' | '\r' if !long => return Err("Line break in literal".to_string()),
+                c if c == quote && (!long || self.rest()[index..].starts_with(&long_quote)) => {
+                    break index + if long { 3 } else { 1 };
+                },
+                c => value.push(c)
+            }
+        };
+        self.pos += end;
+
+        if self.eat("@") {
+            let rest = self.rest();
+            let length = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '-').unwrap_or(rest.len());
+            let language = rest[..length].to_string();
+            self.pos += length;
+            return Ok(Term::Literal { value, datatype: None, language: Some(language) });
+        }
+        if self.eat("^^") {
+            let datatype = match self.resource()? {
+                Term::Iri(datatype) => datatype,
+                _ => return Err("The datatype of a literal must be an IRI".to_string())
+            };
+            return Ok(Term::Literal { value, datatype: Some(datatype), language: None });
+        }
+        Ok(Term::Literal { value, datatype: None, language: None })
+    }
+
+    fn number(&mut self) -> Result<Term, String> {
+        let rest = self.rest();
+        let mut end = rest.find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c))).unwrap_or(rest.len());
+        // a final '.' ends the statement
+        while rest[..end].ends_with('.') {
+            end -= 1;
+        }
+        let number = &rest[..end];
+        let datatype = if number.contains(['e', 'E']) {
+            "double"
+        } else if number.contains('.') {
+            "decimal"
+        } else {
+            "integer"
+        };
+        if number.parse::<f64>().is_err() {
+            return Err(format!("Invalid number '{number}'"));
+        }
+        self.pos += end;
+        Ok(typed_literal(number, datatype))
+    }
+
+    fn new_blank_node(&mut self) -> Term {
+        self.blank_nodes += 1;
+        Term::BlankNode(format!("turtle{}", self.blank_nodes))
+    }
+
+    pub(crate) fn skip_white_space(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if !trimmed.starts_with('#') {
+                return;
+            }
+            self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+        }
+    }
+
+    pub(crate) fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    pub(crate) fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    pub(crate) fn eat(&mut self, token: &str) -> bool {
+        let found = self.rest().starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    // Eats a keyword, in any case, if it is not the start of a longer name
+    pub(crate) fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.rest().get(..keyword.len()).is_some_and(|start| start.eq_ignore_ascii_case(keyword))
+            && !self.rest()[keyword.len()..].starts_with(|c: char| c.is_alphanumeric() || c == ':' || c == '_');
+        if found {
+            self.pos += keyword.len();
+        }
+        found
+    }
+
+    pub(crate) fn expect(&mut self, token: char) -> Result<(), String> {
+        self.skip_white_space();
+        match self.peek() {
+            Some(c) if c == token => {
+                self.pos += c.len_utf8();
+                Ok(())
+            },
+            Some(c) => Err(format!("Expected '{token}' but found '{c}'")),
+            None => Err(format!("Expected '{token}' but the document ended"))
+        }
+    }
+
+    pub(crate) fn line(&self) -> usize {
+        self.input[..self.pos].matches('\n').count() + 1
+    }
+}
+
+fn typed_literal(value: &str, xsd_type: &str) -> Term {
+    Term::Literal { value: value.to_string(), datatype: Some(format!("{XSD}{xsd_type}")), language: None }
+}
+
+// The character of a `\u` or `\U` escape, which has 4 or 8 hexadecimal digits after the `u` or `U`
+fn unicode_escape(chars: &mut CharIndices, escaped: char) -> Result<char, String> {
+    let length = if escaped == 'u' { 4 } else { 8 };
+    let hex: String = chars.by_ref().take(length).map(|(_, c)| c).collect();
+    u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+        .ok_or_else(|| format!("Invalid escape \\{escaped}{hex}"))
+}
+
+fn unescape_local_name(local: &str) -> String {
+    local.replace('\\', "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turtle_parsed() {
+        let turtle = r#"
+            @prefix ex: <http://example.com/> .
+            PREFIX sh: <http://www.w3.org/ns/shacl#>
+            @base <http://example.com/shapes/> .
+
+            # a comment
+            <PersonShape> a sh:NodeShape ;
+                sh:targetClass ex:Person ;
+                sh:property [
+                    sh:path ex:age ;
+                    sh:maxCount 1 ;
+                    sh:in ( "young" 'old'@en ) ;
+                ] .
+            ex:s ex:p """a "long"
+literal""", "tab\there"^^ex:type, true, -1.5e3 .
+        "#;
+        let statements = parse_turtle(turtle).unwrap();
+        let shape = Term::Iri("http://example.com/shapes/PersonShape".to_string());
+        let iri = |iri: &str| Term::Iri(iri.to_string());
+        assert!(statements.contains(&Statement { subject: shape.clone(), predicate: iri(&format!("{RDF}type")), object: iri("http://www.w3.org/ns/shacl#NodeShape"), graph: None }));
+        assert!(statements.contains(&Statement { subject: shape, predicate: iri("http://www.w3.org/ns/shacl#targetClass"), object: iri("http://example.com/Person"), graph: None }));
+        let objects_of_s: Vec<&Term> = statements.iter()
+            .filter(|statement| statement.subject == iri("http://example.com/s"))
+            .map(|statement| &statement.object)
+            .collect();
+        assert_eq!(objects_of_s, vec![
+            &Term::Literal { value: "a \"long\"\nliteral".to_string(), datatype: None, language: None },
+            &Term::Literal { value: "tab\there".to_string(), datatype: Some("http://example.com/type".to_string()), language: None },
+            &typed_literal("true", "boolean"),
+            &typed_literal("-1.5e3", "double")
+        ]);
+        // the shape, its property, the two items of the list and the four objects of ex:s
+        assert_eq!(statements.len(), 3 + 3 + 2 * 2 + 4);
+
+        let error = parse_turtle("@prefix ex: <http://example.com/> .\nex:s ex:p unknown:o .").unwrap_err();
+        assert_eq!(error, "Parser error at line 2 between columns 11 and 20: The prefix unknown: has not been declared");
+    }
+
+    #[test]
+    fn relative_iris() {
+        let turtle = "<#m> <../p> </ldes/2>, <?page=2>, <>, <other> .";
+        let statements = parse_turtle_with_base(turtle, "https://example.com/feed/pages/1#page").unwrap();
+        let iris: Vec<String> = [&statements[0].subject, &statements[0].predicate].into_iter()
+            .chain(statements.iter().map(|statement| &statement.object))
+            .map(Term::to_string)
+            .collect();
+        assert_eq!(vec![
+            "<https://example.com/feed/pages/1#m>", "<https://example.com/feed/p>", "<https://example.com/ldes/2>",
+            "<https://example.com/feed/pages/1?page=2>", "<https://example.com/feed/pages/1>", "<https://example.com/feed/pages/other>"
+        ], iris);
+    }
+
+    #[test]
+    fn escaped_iris() {
+        let statements = parse_turtle(r"<http://example.com/caf\u00E9> <http://example.com/p> <http://example.com/\U0001F600> .").unwrap();
+        assert_eq!(Term::Iri("http://example.com/café".to_string()), statements[0].subject);
+        assert_eq!(Term::Iri("http://example.com/😀".to_string()), statements[0].object);
+        assert!(parse_turtle(r"<http://example.com/a\>b> <http://example.com/p> <http://example.com/o> .").is_err());
+        assert!(parse_turtle("<http://example.com/a b> <http://example.com/p> <http://example.com/o> .").is_err());
+    }
+}
@@ -0,0 +1,615 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::hash::{BuildHasher, DefaultHasher, Hash, Hasher, RandomState};
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{env, process, thread};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use log::{debug, warn};
+use crate::mopper_options::DeduplicationHasher;
+
+// The initial capacity of the sets of statements, unless configured otherwise
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// Decides which statements a sink still has to write.
+pub trait Deduplicator {
+    /// Returns `true` if the statement was not seen before, i.e. it has to be written.
+    fn insert(&mut self, statement: &str) -> bool;
+
+    /// Like [Deduplicator::insert], but for a batch of statements. Returns for every statement
+    /// if it has to be written. Statements occurring twice in the batch are only written once.
+    fn insert_all(&mut self, statements: &[&str]) -> Vec<bool> {
+        statements.iter()
+            .map(|statement| self.insert(statement))
+            .collect()
+    }
+
+    /// Called once when the sink has received all its data. Deduplicators that hold back
+    /// statements instead of letting them through write them to `out` now.
+    /// Returns the number of statements written to `out`.
+    fn finish(&mut self, _out: &mut dyn Write) -> io::Result<u64> {
+        Ok(0)
+    }
+}
+
+/// Builds the hashers of the sets of statements that exact deduplicators keep, as chosen with
+/// [DeduplicationHasher].
+#[derive(Clone, Debug)]
+pub struct StatementHashBuilder {
+    hasher: DeduplicationHasher,
+    random_state: RandomState
+}
+
+impl StatementHashBuilder {
+    pub fn new(hasher: &DeduplicationHasher) -> Self {
+        StatementHashBuilder { hasher: hasher.clone(), random_state: RandomState::new() }
+    }
+}
+
+impl BuildHasher for StatementHashBuilder {
+    type Hasher = StatementHasher;
+
+    fn build_hasher(&self) -> StatementHasher {
+        match self.hasher {
+            DeduplicationHasher::SipHash => StatementHasher(HasherKind::Sip(self.random_state.build_hasher())),
+            DeduplicationHasher::Fx => StatementHasher(HasherKind::Fx(0))
+        }
+    }
+}
+
+/// The hasher built by [StatementHashBuilder].
+pub struct StatementHasher(HasherKind);
+
+enum HasherKind {
+    Sip(DefaultHasher),
+
+    // FxHash, as used by the Rust compiler: fast on short keys, but not resistant to keys crafted to collide
+    Fx(u64)
+}
+
+const FX_SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+fn fx_add_to_hash(hash: u64, word: u64) -> u64 {
+    (hash.rotate_left(5) ^ word).wrapping_mul(FX_SEED)
+}
+
+impl Hasher for StatementHasher {
+    fn finish(&self) -> u64 {
+        match &self.0 {
+            HasherKind::Sip(hasher) => hasher.finish(),
+            HasherKind::Fx(hash) => *hash
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        match &mut self.0 {
+            HasherKind::Sip(hasher) => hasher.write(bytes),
+            HasherKind::Fx(hash) => {
+                for chunk in bytes.chunks(8) {
+                    let mut word = [0u8; 8];
+                    word[..chunk.len()].copy_from_slice(chunk);
+                    *hash = fx_add_to_hash(*hash, u64::from_le_bytes(word));
+                }
+            }
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        match &mut self.0 {
+            HasherKind::Sip(hasher) => hasher.write_u8(i),
+            HasherKind::Fx(hash) => *hash = fx_add_to_hash(*hash, i as u64)
+        }
+    }
+}
+
+/// The set of statements exact deduplicators keep.
+type StatementSet = HashSet<String, StatementHashBuilder>;
+
+fn statement_set(capacity: usize, hasher: &DeduplicationHasher) -> StatementSet {
+    HashSet::with_capacity_and_hasher(capacity, StatementHashBuilder::new(hasher))
+}
+
+/// Keeps every statement seen during this run in memory.
+pub struct HashSetDeduplicator {
+    seen: StatementSet
+}
+
+impl HashSetDeduplicator {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY, &DeduplicationHasher::default())
+    }
+
+    /// A deduplicator with room for `capacity` statements before its set has to grow, hashing
+    /// statements with `hasher`.
+    pub fn with_capacity(capacity: usize, hasher: &DeduplicationHasher) -> Self {
+        // At this moment deduplication is simply done with a HashSet.
+        // Could be replaced with a more memory-efficient (or memory mapped) data structure.
+        HashSetDeduplicator { seen: statement_set(capacity, hasher) }
+    }
+}
+
+impl Default for HashSetDeduplicator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deduplicator for HashSetDeduplicator {
+    fn insert(&mut self, statement: &str) -> bool {
+        if self.seen.contains(statement) {
+            false
+        } else {
+            self.seen.insert(statement.to_string())
+        }
+    }
+}
+
+/// Like [HashSetDeduplicator], but the statements are also kept in an index file
+/// (one statement per line), so duplicates are removed across runs too.
+/// Statements found in the index file when starting are considered "already written".
+pub struct PersistentDeduplicator {
+    seen: StatementSet,
+    index_writer: BufWriter<File>
+}
+
+impl PersistentDeduplicator {
+    pub fn new(index_file: &Path) -> io::Result<Self> {
+        Self::with_capacity(index_file, DEFAULT_CAPACITY, &DeduplicationHasher::default())
+    }
+
+    /// Like [PersistentDeduplicator::new], with room for `capacity` statements before its set has
+    /// to grow, hashing statements with `hasher`.
+    pub fn with_capacity(index_file: &Path, capacity: usize, hasher: &DeduplicationHasher) -> io::Result<Self> {
+        let mut seen = statement_set(capacity, hasher);
+        if index_file.exists() {
+            let reader = BufReader::new(File::open(index_file)?);
+            for line in reader.lines() {
+                let line = line?;
+                if !line.is_empty() {
+                    seen.insert(line);
+                }
+            }
+            debug!("Loaded {} statements from deduplication index {}", seen.len(), index_file.display());
+        }
+        let index_out = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(index_file)?;
+        Ok(PersistentDeduplicator {
+            seen,
+            index_writer: BufWriter::new(index_out)
+        })
+    }
+
+    /// Returns the path of the index file with the given name in the given directory.
+    /// The directory is created if it doesn't exist.
+    pub fn index_file(index_dir: &str, name: &str) -> io::Result<PathBuf> {
+        fs::create_dir_all(index_dir)?;
+        Ok(Path::new(index_dir).join(format!("{name}.idx")))
+    }
+}
+
+impl Deduplicator for PersistentDeduplicator {
+    fn insert(&mut self, statement: &str) -> bool {
+        if self.seen.contains(statement) {
+            false
+        } else {
+            // A failing index write only means the statement might be written again in a next run.
+            if let Err(err) = writeln!(self.index_writer, "{statement}") {
+                warn!("Cannot write to deduplication index: {err}");
+            }
+            self.seen.insert(statement.to_string())
+        }
+    }
+
+    fn finish(&mut self, _out: &mut dyn Write) -> io::Result<u64> {
+        self.index_writer.flush()?;
+        Ok(0)
+    }
+}
+
+/// Approximate deduplication with a Bloom filter. It uses a fixed amount of memory, but
+/// a statement that was not seen before may be considered a duplicate (a "false positive")
+/// and not be written! The chance of this happening is (about) the configured false positive rate,
+/// as long as no more than the expected number of statements are inserted.
+pub struct BloomDeduplicator {
+    bits: Vec<u64>,
+    nr_of_bits: u64,
+    nr_of_hashes: u32
+}
+
+impl BloomDeduplicator {
+    pub fn new(false_positive_rate: f64, expected_statements: usize) -> Self {
+        let expected_statements = expected_statements.max(1) as f64;
+        let ln_2 = std::f64::consts::LN_2;
+        let nr_of_bits = (-expected_statements * false_positive_rate.ln() / (ln_2 * ln_2)).ceil().max(64.0) as u64;
+        let nr_of_hashes = ((nr_of_bits as f64 / expected_statements) * ln_2).round().max(1.0) as u32;
+        debug!("Bloom filter with {nr_of_bits} bits and {nr_of_hashes} hash functions");
+        BloomDeduplicator {
+            bits: vec![0u64; nr_of_bits.div_ceil(64) as usize],
+            nr_of_bits,
+            nr_of_hashes
+        }
+    }
+}
+
+impl Deduplicator for BloomDeduplicator {
+    fn insert(&mut self, statement: &str) -> bool {
+        // Double hashing: the i-th hash is h1 + i * h2
+        let mut hasher = DefaultHasher::new();
+        statement.hash(&mut hasher);
+        let h1 = hasher.finish();
+        0x9e37_79b9_7f4a_7c15u64.hash(&mut hasher);
+        let h2 = hasher.finish() | 1;
+
+        let mut is_new = false;
+        for i in 0..self.nr_of_hashes as u64 {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % self.nr_of_bits;
+            let word = &mut self.bits[(bit / 64) as usize];
+            let mask = 1u64 << (bit % 64);
+            if *word & mask == 0 {
+                *word |= mask;
+                is_new = true;
+            }
+        }
+        is_new
+    }
+}
+
+// A shard receives (position in batch, statement) pairs and answers with the positions of the new statements
+type Shard = (Sender<Vec<(usize, String)>>, Receiver<Vec<usize>>);
+
+/// Exact deduplication where the statements are divided over several shards by their hash.
+/// Every shard is a [HashSet] owned by its own thread, so a batch of statements is
+/// deduplicated in parallel.
+pub struct ShardedDeduplicator {
+    shards: Vec<Shard>
+}
+
+impl ShardedDeduplicator {
+    pub fn new(nr_of_shards: usize) -> Self {
+        Self::with_capacity(nr_of_shards, DEFAULT_CAPACITY, &DeduplicationHasher::default())
+    }
+
+    /// Like [ShardedDeduplicator::new], with room for `capacity` statements over all shards before
+    /// their sets have to grow, hashing statements with `hasher`.
+    pub fn with_capacity(nr_of_shards: usize, capacity: usize, hasher: &DeduplicationHasher) -> Self {
+        let nr_of_shards = nr_of_shards.max(1);
+        let shards = (0..nr_of_shards)
+            .map(|shard_nr| {
+                let mut seen = statement_set(capacity.div_ceil(nr_of_shards), hasher);
+                let (batch_tx, batch_rx) = bounded::<Vec<(usize, String)>>(1);
+                let (result_tx, result_rx) = bounded::<Vec<usize>>(1);
+                thread::Builder::new()
+                    .name(format!("Dedup shard {shard_nr}"))
+                    .spawn(move || {
+                        for batch in batch_rx {
+                            let new_positions: Vec<usize> = batch.into_iter()
+                                .filter_map(|(position, statement)| seen.insert(statement).then_some(position))
+                                .collect();
+                            if result_tx.send(new_positions).is_err() {
+                                break
+                            }
+                        }
+                    }).unwrap();
+                (batch_tx, result_rx)
+            })
+            .collect();
+        ShardedDeduplicator { shards }
+    }
+}
+
+impl Deduplicator for ShardedDeduplicator {
+    fn insert(&mut self, statement: &str) -> bool {
+        self.insert_all(&[statement])[0]
+    }
+
+    fn insert_all(&mut self, statements: &[&str]) -> Vec<bool> {
+        let nr_of_shards = self.shards.len();
+        let mut batches: Vec<Vec<(usize, String)>> = vec![Vec::new(); nr_of_shards];
+        for (position, statement) in statements.iter().enumerate() {
+            let mut hasher = DefaultHasher::new();
+            statement.hash(&mut hasher);
+            let shard_nr = (hasher.finish() % nr_of_shards as u64) as usize;
+            batches[shard_nr].push((position, statement.to_string()));
+        }
+
+        // First hand out all batches, then collect the results, so the shards work in parallel
+        let mut busy_shards = Vec::with_capacity(nr_of_shards);
+        for (shard_nr, batch) in batches.into_iter().enumerate() {
+            if !batch.is_empty() {
+                self.shards[shard_nr].0.send(batch).unwrap();
+                busy_shards.push(shard_nr);
+            }
+        }
+        let mut result = vec![false; statements.len()];
+        for shard_nr in busy_shards {
+            for position in self.shards[shard_nr].1.recv().unwrap() {
+                result[position] = true;
+            }
+        }
+        result
+    }
+
+    fn finish(&mut self, _out: &mut dyn Write) -> io::Result<u64> {
+        // stops the shard threads
+        self.shards.clear();
+        Ok(0)
+    }
+}
+
+// Used to give the spill files of every deduplicator in this process a unique name
+static EXTERNAL_SORT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Exact deduplication with bounded memory. Statements are held back in memory until there are
+/// `max_in_memory` of them. Then they are sorted and written to a "run" file on disk.
+/// When finishing, all runs are merged and every distinct statement is written once, in sorted order.
+pub struct ExternalSortDeduplicator {
+    buffer: Vec<String>,
+    max_in_memory: usize,
+    spill_dir: PathBuf,
+    name: String,
+    runs: Vec<PathBuf>
+}
+
+impl ExternalSortDeduplicator {
+    pub fn new(max_in_memory: usize, spill_dir: &Option<String>) -> io::Result<Self> {
+        let spill_dir = match spill_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => env::temp_dir()
+        };
+        fs::create_dir_all(&spill_dir)?;
+        let name = format!("mopper-dedup-{}-{}", process::id(), EXTERNAL_SORT_COUNTER.fetch_add(1, Ordering::Relaxed));
+        Ok(ExternalSortDeduplicator {
+            buffer: Vec::with_capacity(max_in_memory.min(1 << 20)),
+            max_in_memory: max_in_memory.max(1),
+            spill_dir,
+            name,
+            runs: Vec::new()
+        })
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        self.buffer.sort_unstable();
+        self.buffer.dedup();
+        let run_path = self.spill_dir.join(format!("{}-{}.run", self.name, self.runs.len()));
+        debug!("Spilling {} statements to {}", self.buffer.len(), run_path.display());
+        let mut run_writer = BufWriter::new(File::create(&run_path)?);
+        for statement in self.buffer.drain(..) {
+            writeln!(run_writer, "{statement}")?;
+        }
+        run_writer.flush()?;
+        self.runs.push(run_path);
+        Ok(())
+    }
+}
+
+impl Deduplicator for ExternalSortDeduplicator {
+    fn insert(&mut self, statement: &str) -> bool {
+        self.buffer.push(statement.to_string());
+        if self.buffer.len() >= self.max_in_memory {
+            if let Err(err) = self.spill() {
+                // Not much we can do here but keep everything in memory
+                warn!("Cannot spill statements to disk: {err}");
+                self.max_in_memory = self.max_in_memory.saturating_mul(2);
+            }
+        }
+        // the statement is written when finishing
+        false
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<u64> {
+        // The statements still in memory form the last run
+        self.buffer.sort_unstable();
+        self.buffer.dedup();
+        let mut last_run = self.buffer.drain(..);
+
+        let mut run_readers = Vec::with_capacity(self.runs.len());
+        for run in &self.runs {
+            run_readers.push(BufReader::new(File::open(run)?).lines());
+        }
+
+        // k-way merge; the index of the last run (the one in memory) is the number of run files
+        let in_memory_run_index = run_readers.len();
+        let mut heap: BinaryHeap<Reverse<(String, usize)>> = BinaryHeap::with_capacity(in_memory_run_index + 1);
+        for (run_index, run_reader) in run_readers.iter_mut().enumerate() {
+            if let Some(statement) = run_reader.next() {
+                heap.push(Reverse((statement?, run_index)));
+            }
+        }
+        if let Some(statement) = last_run.next() {
+            heap.push(Reverse((statement, in_memory_run_index)));
+        }
+
+        let mut nr_written = 0;
+        let mut last_written: Option<String> = None;
+        while let Some(Reverse((statement, run_index))) = heap.pop() {
+            let next_statement = match run_index == in_memory_run_index {
+                true => last_run.next(),
+                false => run_readers[run_index].next().transpose()?
+            };
+            if let Some(next_statement) = next_statement {
+                heap.push(Reverse((next_statement, run_index)));
+            }
+            if last_written.as_ref() != Some(&statement) {
+                out.write_all(statement.as_bytes())?;
+                out.write_all(b"\n")?;
+                last_written = Some(statement);
+                nr_written += 1;
+            }
+        }
+
+        for run in self.runs.drain(..) {
+            fs::remove_file(run)?;
+        }
+        Ok(nr_written)
+    }
+}
+
+/// Lets several sinks share one deduplicator, so a statement written by one sink
+/// is not written again by another one.
+/// The shared deduplicator is only finished when the last of the `sharers` sinks finishes,
+/// because the other sinks may still be inserting statements.
+pub struct SharedDeduplicator {
+    inner: Arc<Mutex<Box<dyn Deduplicator + Send>>>,
+    unfinished_sharers: Arc<AtomicUsize>,
+    finished: bool
+}
+
+impl SharedDeduplicator {
+    pub fn new(inner: Arc<Mutex<Box<dyn Deduplicator + Send>>>, unfinished_sharers: Arc<AtomicUsize>) -> Self {
+        SharedDeduplicator { inner, unfinished_sharers, finished: false }
+    }
+}
+
+impl Deduplicator for SharedDeduplicator {
+    fn insert(&mut self, statement: &str) -> bool {
+        self.inner.lock().unwrap().insert(statement)
+    }
+
+    fn insert_all(&mut self, statements: &[&str]) -> Vec<bool> {
+        self.inner.lock().unwrap().insert_all(statements)
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<u64> {
+        if self.finished {
+            return Ok(0)
+        }
+        self.finished = true;
+        match self.unfinished_sharers.fetch_sub(1, Ordering::AcqRel) {
+            1 => self.inner.lock().unwrap().finish(out),
+            _ => Ok(0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::{Arc, Mutex};
+    use crate::mopper_options::DeduplicationHasher;
+    use crate::sink::dedup::{BloomDeduplicator, Deduplicator, ExternalSortDeduplicator, HashSetDeduplicator, PersistentDeduplicator, ShardedDeduplicator, SharedDeduplicator};
+
+    #[test]
+    fn hash_set_removes_duplicates() {
+        let mut dedup = HashSetDeduplicator::new();
+        assert!(dedup.insert("<a> <b> <c> ."));
+        assert!(dedup.insert("<a> <b> <d> ."));
+        assert!(!dedup.insert("<a> <b> <c> ."));
+    }
+
+    #[test]
+    fn fx_hash_set_removes_duplicates() {
+        let mut dedup = HashSetDeduplicator::with_capacity(2, &DeduplicationHasher::Fx);
+        assert!(dedup.insert("<a> <b> <c> ."));
+        assert!(dedup.insert("<a> <b> <d> ."));
+        assert!(dedup.insert("<a> <b> <e> ."));
+        assert!(!dedup.insert("<a> <b> <c> ."));
+        assert!(!dedup.insert("<a> <b> <e> ."));
+    }
+
+    #[test]
+    fn bloom_removes_duplicates() {
+        let mut dedup = BloomDeduplicator::new(0.001, 1000);
+        let mut nr_written = 0;
+        for i in 0..1000 {
+            let statement = format!("<a> <b> <c{i}> .");
+            if dedup.insert(&statement) {
+                nr_written += 1;
+            }
+            assert!(!dedup.insert(&statement));
+        }
+        // some false positives are allowed
+        assert!(nr_written > 990);
+    }
+
+    #[test]
+    fn sharded_removes_duplicates() {
+        let mut dedup = ShardedDeduplicator::new(4);
+        let result = dedup.insert_all(&["<a> <b> <c> .", "<a> <b> <d> .", "<a> <b> <c> .", "<a> <b> <e> ."]);
+        assert_eq!(vec![true, true, false, true], result);
+        assert!(!dedup.insert("<a> <b> <e> ."));
+        assert!(dedup.insert("<a> <b> <f> ."));
+        dedup.finish(&mut Vec::new()).unwrap();
+    }
+
+    #[test]
+    fn external_sort_removes_duplicates() {
+        let mut dedup = ExternalSortDeduplicator::new(2, &None).unwrap();
+        for statement in ["<a> <b> <d> .", "<a> <b> <c> .", "<a> <b> <d> .", "<a> <b> <e> .", "<a> <b> <c> ."] {
+            assert!(!dedup.insert(statement));
+        }
+        let mut out: Vec<u8> = Vec::new();
+        dedup.finish(&mut out).unwrap();
+        assert_eq!("<a> <b> <c> .\n<a> <b> <d> .\n<a> <b> <e> .\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn persistent_index_survives_runs() {
+        let index_dir = env::temp_dir().join(format!("mopper-dedup-test-{}", std::process::id()));
+        let index_file = PersistentDeduplicator::index_file(index_dir.to_str().unwrap(), "sink-3").unwrap();
+        let _ = fs::remove_file(&index_file);
+
+        let mut first_run = PersistentDeduplicator::new(&index_file).unwrap();
+        assert!(first_run.insert("<a> <b> <c> ."));
+        assert!(!first_run.insert("<a> <b> <c> ."));
+        first_run.finish(&mut Vec::new()).unwrap();
+
+        let mut second_run = PersistentDeduplicator::new(&index_file).unwrap();
+        assert!(!second_run.insert("<a> <b> <c> ."));
+        assert!(second_run.insert("<a> <b> <d> ."));
+        second_run.finish(&mut Vec::new()).unwrap();
+
+        fs::remove_dir_all(index_dir).unwrap();
+    }
+
+    #[test]
+    fn shared_removes_duplicates_across_sinks() {
+        let inner: Box<dyn Deduplicator + Send> = Box::new(HashSetDeduplicator::new());
+        let shared = Arc::new(Mutex::new(inner));
+        let sharers = Arc::new(AtomicUsize::new(2));
+        let mut sink_1 = SharedDeduplicator::new(shared.clone(), sharers.clone());
+        let mut sink_2 = SharedDeduplicator::new(shared, sharers);
+        assert!(sink_1.insert("<a> <b> <c> ."));
+        assert!(!sink_2.insert("<a> <b> <c> ."));
+        assert!(sink_2.insert("<a> <b> <d> ."));
+    }
+
+    #[test]
+    fn shared_finishes_after_last_sink() {
+        let inner: Box<dyn Deduplicator + Send> = Box::new(ShardedDeduplicator::new(2));
+        let shared = Arc::new(Mutex::new(inner));
+        let sharers = Arc::new(AtomicUsize::new(2));
+        let mut sink_1 = SharedDeduplicator::new(shared.clone(), sharers.clone());
+        let mut sink_2 = SharedDeduplicator::new(shared, sharers);
+        assert_eq!(sink_1.insert_all(&["<a> <b> <c> .", "<a> <b> <d> ."]), vec![true, true]);
+        sink_1.finish(&mut Vec::new()).unwrap();
+        // finishing twice does not count as another sink finishing
+        sink_1.finish(&mut Vec::new()).unwrap();
+        assert_eq!(sink_2.insert_all(&["<a> <b> <c> .", "<a> <b> <e> ."]), vec![false, true]);
+        sink_2.finish(&mut Vec::new()).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::io;
+use operator::formats::DataFormat;
+use operator::Target;
+use crate::sink::Sink;
+
+/// The key of the HTTP sink: targets with `"sink": "http"` in their configuration are written to it.
+pub const HTTP_SINK: &str = "http";
+
+// The number of statements per request if the target does not say
+const DEFAULT_BATCH_SIZE: usize = 1000;
+
+/// A sink that POSTs the statements in batches to an HTTP endpoint, e.g. the ingestion API of
+/// another system. It is configured by the target:
+/// - `url`: where to POST to;
+/// - `batch_size`: the number of statements per request, 1000 by default;
+/// - `content_type`: the content type of the requests, by default `application/n-quads` or
+///   `application/n-triples`, after the data format of the target;
+/// - `header.NAME`: a header to send with every request, e.g. `header.Authorization`.
+pub struct HttpSink {
+    agent: ureq::Agent,
+    url: String,
+    batch_size: usize,
+    content_type: String,
+    headers: Vec<(String, String)>,
+    batch: String,
+    statements_in_batch: usize
+}
+
+impl HttpSink {
+    pub fn new(target: &Target) -> Result<Self, String> {
+        let url = target.configuration.get("url")
+            .ok_or_else(|| "HTTP target without url".to_string())?;
+        let batch_size = match target.configuration.get("batch_size") {
+            Some(batch_size) => batch_size.parse().ok().filter(|batch_size| *batch_size > 0)
+                .ok_or_else(|| format!("batch size {batch_size} of HTTP target is not a positive number"))?,
+            None => DEFAULT_BATCH_SIZE
+        };
+        let content_type = match target.configuration.get("content_type") {
+            Some(content_type) => content_type.clone(),
+            None if target.data_format == DataFormat::NTriples => "application/n-triples".to_string(),
+            None => "application/n-quads".to_string()
+        };
+        let mut headers: Vec<(String, String)> = target.configuration.iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix("header.")?.to_string(), value.clone())))
+            .collect();
+        headers.sort();
+        Ok(HttpSink {
+            agent: ureq::Agent::new(),
+            url: url.clone(),
+            batch_size,
+            content_type,
+            headers,
+            batch: String::new(),
+            statements_in_batch: 0
+        })
+    }
+
+    // Sends the statements collected so far, if any
+    fn post(&mut self) -> io::Result<()> {
+        if self.statements_in_batch == 0 {
+            return Ok(());
+        }
+        let mut request = self.agent.post(&self.url).set("Content-Type", &self.content_type);
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        match request.send_string(&self.batch) {
+            Ok(_response) => {},
+            Err(ureq::Error::Status(status, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                return Err(io::Error::other(format!("{} answered with status {status}: {}", self.url, body.trim())));
+            },
+            Err(err) => return Err(io::Error::other(format!("Cannot POST to {}: {err}", self.url)))
+        }
+        self.batch.clear();
+        self.statements_in_batch = 0;
+        Ok(())
+    }
+}
+
+impl Sink for HttpSink {
+    fn write(&mut self, statements: &str) -> io::Result<()> {
+        for statement in statements.lines() {
+            self.batch.push_str(statement);
+            self.batch.push('\n');
+            self.statements_in_batch += 1;
+            if self.statements_in_batch == self.batch_size {
+                self.post()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.post()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use operator::IOType;
+    use super::*;
+
+    // The headers and body of every request served
+    type ServedRequests = Arc<Mutex<Vec<(Vec<String>, String)>>>;
+
+    // Serves HTTP on a local port, answering every request with the status, and keeps the requests
+    fn serve(status: u16) -> (String, ServedRequests) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/ingest", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let served = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut headers = Vec::new();
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).unwrap_or(0) > 0 && line != "\r\n" {
+                        headers.push(line.trim_end().to_string());
+                        line.clear();
+                    }
+                    if headers.is_empty() {
+                        break;
+                    }
+                    let length: usize = headers.iter()
+                        .find_map(|header| header.to_lowercase().strip_prefix("content-length: ")?.parse().ok())
+                        .unwrap_or(0);
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+                    served.lock().unwrap().push((headers, String::from_utf8(body).unwrap()));
+                    write!(stream, "HTTP/1.1 {status} Status\r\nContent-Length: 4\r\n\r\nbusy").unwrap();
+                }
+            }
+        });
+        (url, requests)
+    }
+
+    fn target(configuration: &[(&str, &str)]) -> Target {
+        Target {
+            configuration: configuration.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect::<HashMap<_, _>>(),
+            target_type: IOType::StdOut,
+            data_format: DataFormat::NTriples
+        }
+    }
+
+    #[test]
+    fn batches_posted() {
+        let (url, requests) = serve(200);
+        let target = target(&[("sink", "http"), ("url", &url), ("batch_size", "2"), ("header.X-Api-Key", "secret")]);
+        let mut sink = HttpSink::new(&target).unwrap();
+        sink.write("<a> <b> <c> .\n<a> <b> <d> .\n<a> <b> <e> .\n").unwrap();
+        assert_eq!(1, requests.lock().unwrap().len());
+        sink.finish().unwrap();
+
+        let requests = requests.lock().unwrap();
+        let bodies: Vec<&str> = requests.iter().map(|(_headers, body)| body.as_str()).collect();
+        assert_eq!(vec!["<a> <b> <c> .\n<a> <b> <d> .\n", "<a> <b> <e> .\n"], bodies);
+        let headers = &requests[0].0;
+        assert!(headers[0].starts_with("POST /ingest "));
+        assert!(headers.contains(&"Content-Type: application/n-triples".to_string()));
+        assert!(headers.contains(&"X-Api-Key: secret".to_string()));
+    }
+
+    #[test]
+    fn failed_requests() {
+        let (url, _requests) = serve(503);
+        let mut sink = HttpSink::new(&target(&[("url", &url)])).unwrap();
+        sink.write("<a> <b> <c> .\n").unwrap();
+        let err = sink.finish().unwrap_err();
+        assert_eq!(format!("{url} answered with status 503: busy"), err.to_string());
+
+        assert_eq!(Some("HTTP target without url".to_string()), HttpSink::new(&target(&[])).err());
+        assert!(HttpSink::new(&target(&[("url", &url), ("batch_size", "0")])).is_err());
+    }
+}
@@ -14,4 +14,435 @@
  *    limitations under the License.
  */
 
-pub mod writer_sink;
\ No newline at end of file
+//! Sinks take the statements of a target. Besides the built-in ones, library callers can
+//! register their own [Sink]s for a target type with [crate::mopper_options::MopperOptionsBuilder::sink],
+//! so statements can go to systems mopper does not know about.
+
+pub mod writer_sink;
+pub mod dedup;
+pub mod http;
+pub mod partition;
+
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use flate2::write::GzEncoder;
+use operator::Target;
+
+/// Where the statements of a target go.
+pub trait Sink: Send {
+    /// Takes statements in N-Triples or N-Quads, one per line. Every line is a complete statement.
+    fn write(&mut self, statements: &str) -> io::Result<()>;
+
+    /// Called once when all statements are written.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A sink that writes the statements to a writer, e.g. standard out or a file.
+pub struct WriteSink<W: Write + Send> {
+    out: W
+}
+
+impl<W: Write + Send> WriteSink<W> {
+    pub fn new(out: W) -> Self {
+        WriteSink { out }
+    }
+}
+
+impl<W: Write + Send> Sink for WriteSink<W> {
+    fn write(&mut self, statements: &str) -> io::Result<()> {
+        self.out.write_all(statements.as_bytes())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// A sink that writes the statements to a writer shared by several sinks, e.g. standard out, with a comment
+/// line naming its target in front of every batch, so the statements of the targets can be told apart.
+pub struct TaggedSink<W: Write + Send> {
+    out: W,
+    tag: String
+}
+
+impl<W: Write + Send> TaggedSink<W> {
+    pub fn new(out: W, target: &str) -> Self {
+        TaggedSink { out, tag: format!("# {target}\n") }
+    }
+}
+
+impl<W: Write + Send> Sink for TaggedSink<W> {
+    fn write(&mut self, statements: &str) -> io::Result<()> {
+        // one write per batch, so the batches of other sinks do not end up in between
+        let mut batch = String::with_capacity(self.tag.len() + statements.len());
+        batch.push_str(&self.tag);
+        batch.push_str(statements);
+        self.out.write_all(batch.as_bytes())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// How a file target is compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip
+}
+
+impl Compression {
+    /// The compression of a target, from `compression` in its configuration: `none` or `gzip`, or an IRI
+    /// ending in one of them, like the RML compression `http://w3id.org/rml/gzip`.
+    pub fn of(target: &Target) -> Result<Compression, String> {
+        let Some(compression) = target.configuration.get("compression") else {
+            return Ok(Compression::None);
+        };
+        let name = compression.rsplit(['/', '#']).next().unwrap_or_default();
+        match name.to_lowercase().as_str() {
+            "" | "none" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            _ => Err(format!("compression {compression} is not supported"))
+        }
+    }
+}
+
+// A file, compressed or not
+enum FileWriter {
+    Plain(File),
+    Gzip(GzEncoder<File>)
+}
+
+impl FileWriter {
+    // Writes what is left to the file, and returns it
+    fn finish(&mut self) -> io::Result<&File> {
+        match self {
+            FileWriter::Plain(file) => Ok(file),
+            FileWriter::Gzip(encoder) => {
+                encoder.try_finish()?;
+                Ok(encoder.get_ref())
+            }
+        }
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            FileWriter::Plain(file) => file.write(buf),
+            FileWriter::Gzip(encoder) => encoder.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FileWriter::Plain(file) => file.flush(),
+            FileWriter::Gzip(encoder) => encoder.flush()
+        }
+    }
+}
+
+/// A sink that writes the statements to a file through a buffer, flushing it at most every
+/// `flush_interval` if set, and syncing the file to disk on finishing if `sync_on_close` is set.
+pub struct FileSink {
+    out: BufWriter<FileWriter>,
+    flush_interval: Option<Duration>,
+    last_flush: Instant,
+    sync_on_close: bool
+}
+
+impl FileSink {
+    pub fn new(file: File, buffer_capacity: usize, flush_interval: Option<Duration>, sync_on_close: bool) -> Self {
+        FileSink::compressed(file, Compression::None, buffer_capacity, flush_interval, sync_on_close)
+    }
+
+    /// A sink that compresses the statements it writes to the file.
+    pub fn compressed(file: File, compression: Compression, buffer_capacity: usize, flush_interval: Option<Duration>, sync_on_close: bool) -> Self {
+        let file_writer = match compression {
+            Compression::None => FileWriter::Plain(file),
+            Compression::Gzip => FileWriter::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+        };
+        FileSink {
+            out: BufWriter::with_capacity(buffer_capacity, file_writer),
+            flush_interval,
+            last_flush: Instant::now(),
+            sync_on_close
+        }
+    }
+}
+
+impl Sink for FileSink {
+    fn write(&mut self, statements: &str) -> io::Result<()> {
+        self.out.write_all(statements.as_bytes())?;
+        if let Some(flush_interval) = self.flush_interval {
+            if self.last_flush.elapsed() >= flush_interval {
+                self.out.flush()?;
+                self.last_flush = Instant::now();
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()?;
+        let file = self.out.get_mut().finish()?;
+        if self.sync_on_close {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+/// An output file that is written under a temporary name next to it, and only takes its place when
+/// committed, so nobody watching the file sees it half-written. If it is not committed, e.g. because
+/// the run failed, the temporary file is removed and a previous version of the file stays as it was.
+pub(crate) struct AtomicFile {
+    temporary_path: PathBuf,
+    path: PathBuf,
+    committed: bool
+}
+
+impl AtomicFile {
+    /// Creates the temporary file to write to.
+    pub(crate) fn create(path: &Path) -> io::Result<(File, AtomicFile)> {
+        let temporary_path = hidden_sibling(path, &format!("mopper-{}.tmp", std::process::id()))?;
+        let file = File::create(&temporary_path)?;
+        Ok((file, AtomicFile { temporary_path, path: path.to_path_buf(), committed: false }))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Puts the temporary file in the place of the file.
+    pub(crate) fn commit(mut self) -> io::Result<()> {
+        fs::rename(&self.temporary_path, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temporary_path);
+        }
+    }
+}
+
+/// An advisory lock on an output file while a run writes it, so that another run that writes the same file
+/// fails instead of interleaving its output with this one. It locks a lock file next to the output file,
+/// because the output file itself is replaced when written as an [`AtomicFile`]. The lock file is removed
+/// when the lock is released.
+pub(crate) struct OutputLock {
+    _lock_file: File,
+    path: PathBuf
+}
+
+impl OutputLock {
+    /// Locks the output file, or fails with an error of kind `WouldBlock` if it is locked already.
+    pub(crate) fn acquire(path: &Path) -> io::Result<OutputLock> {
+        let lock_path = hidden_sibling(path, "mopper.lock")?;
+        loop {
+            let lock_file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&lock_path)?;
+            match lock_file.try_lock() {
+                Ok(()) => {},
+                Err(TryLockError::WouldBlock) => return Err(io::Error::new(io::ErrorKind::WouldBlock, format!("{} is locked", path.display()))),
+                Err(TryLockError::Error(err)) => return Err(err)
+            }
+            // the run holding the lock before may have removed the lock file after it was opened here,
+            // so another run can lock a new one; then try again with that one
+            if is_same_file(&lock_file, &lock_path) {
+                return Ok(OutputLock { _lock_file: lock_file, path: lock_path });
+            }
+        }
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        // removed while still locked, and unlocked when the file is closed
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Whether the open file is the file at the path
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(file_metadata), Ok(path_metadata)) => file_metadata.dev() == path_metadata.dev() && file_metadata.ino() == path_metadata.ino(),
+        _ => false
+    }
+}
+
+// Whether the open file is the file at the path; without inode numbers, only a removed file is noticed
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+// The path of a hidden file next to the given one, e.g. `.out.nq.<suffix>` for `out.nq`
+fn hidden_sibling(path: &Path, suffix: &str) -> io::Result<PathBuf> {
+    let file_name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a file name", path.display())))?;
+    Ok(path.with_file_name(format!(".{}.{suffix}", file_name.to_string_lossy())))
+}
+
+type CreateSink = dyn Fn(&Target) -> io::Result<Box<dyn Sink>> + Send + Sync;
+
+/// Creates the sink of a target, from the target in the plan. A factory is called once per target.
+#[derive(Clone)]
+pub struct SinkFactory(Arc<CreateSink>);
+
+impl SinkFactory {
+    pub fn new(create: impl Fn(&Target) -> io::Result<Box<dyn Sink>> + Send + Sync + 'static) -> Self {
+        SinkFactory(Arc::new(create))
+    }
+
+    pub fn create(&self, target: &Target) -> io::Result<Box<dyn Sink>> {
+        (self.0)(target)
+    }
+}
+
+impl Debug for SinkFactory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SinkFactory")
+    }
+}
+
+/// The key a sink factory is registered with for the target: the value of `sink` in its configuration,
+/// e.g. a name or IRI of a custom target type, or else its target type, e.g. `Kafka`.
+pub fn sink_key(target: &Target) -> String {
+    match target.configuration.get("sink") {
+        Some(sink) => sink.clone(),
+        None => format!("{:?}", target.target_type)
+    }
+}
+
+// Passes what is written to a sink, in complete lines
+pub(crate) struct LineWriter<'a> {
+    sink: &'a mut dyn Sink,
+    buffer: Vec<u8>
+}
+
+impl<'a> LineWriter<'a> {
+    pub(crate) fn new(sink: &'a mut dyn Sink) -> Self {
+        LineWriter { sink, buffer: Vec::new() }
+    }
+}
+
+impl Write for LineWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if let Some(position) = self.buffer.iter().rposition(|byte| *byte == b'\n') {
+            let lines: Vec<u8> = self.buffer.drain(..=position).collect();
+            self.sink.write(&String::from_utf8_lossy(&lines))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            let mut rest: Vec<u8> = self.buffer.drain(..).collect();
+            rest.push(b'\n');
+            self.sink.write(&String::from_utf8_lossy(&rest))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Read;
+    use super::*;
+
+    #[test]
+    fn file_sink_flush_interval() {
+        let path = std::env::temp_dir().join("mopper-file-sink.nt");
+        let mut sink = FileSink::new(File::create(&path).unwrap(), 1024, Some(Duration::ZERO), true);
+        sink.write("<a> <b> <c> .\n").unwrap();
+        assert_eq!("<a> <b> <c> .\n", fs::read_to_string(&path).unwrap());
+
+        let mut sink = FileSink::new(File::create(&path).unwrap(), 1024, None, false);
+        sink.write("<a> <b> <d> .\n").unwrap();
+        assert_eq!("", fs::read_to_string(&path).unwrap());
+        sink.finish().unwrap();
+        assert_eq!("<a> <b> <d> .\n", fs::read_to_string(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn atomic_file() {
+        let path = std::env::temp_dir().join("mopper-atomic-file.nt");
+        fs::write(&path, "previous\n").unwrap();
+
+        let (file, atomic_file) = AtomicFile::create(&path).unwrap();
+        let mut sink = FileSink::new(file, 16, None, false);
+        sink.write("<a> <b> <c> .\n").unwrap();
+        sink.finish().unwrap();
+        assert_eq!("previous\n", fs::read_to_string(&path).unwrap());
+        drop(atomic_file);
+        assert_eq!("previous\n", fs::read_to_string(&path).unwrap());
+
+        let (file, atomic_file) = AtomicFile::create(&path).unwrap();
+        let mut sink = FileSink::new(file, 16, None, false);
+        sink.write("<a> <b> <c> .\n").unwrap();
+        sink.finish().unwrap();
+        atomic_file.commit().unwrap();
+        assert_eq!("<a> <b> <c> .\n", fs::read_to_string(&path).unwrap());
+        assert!(!path.with_file_name(format!(".mopper-atomic-file.nt.mopper-{}.tmp", std::process::id())).exists());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn output_lock() {
+        let path = std::env::temp_dir().join("mopper-output-lock.nt");
+        let lock = OutputLock::acquire(&path).unwrap();
+        let error = OutputLock::acquire(&path).err().unwrap();
+        assert_eq!(io::ErrorKind::WouldBlock, error.kind());
+        drop(lock);
+        let lock_path = path.with_file_name(".mopper-output-lock.nt.mopper.lock");
+        assert!(!lock_path.exists());
+        let lock = OutputLock::acquire(&path).unwrap();
+        assert!(lock_path.exists());
+        drop(lock);
+        assert!(!lock_path.exists());
+    }
+
+    #[test]
+    fn tagged_sink() {
+        let mut out = Vec::new();
+        let mut sink = TaggedSink::new(&mut out, "target 9: sports.nt");
+        sink.write("<a> <b> <c> .\n<a> <b> <d> .\n").unwrap();
+        sink.write("<e> <f> <g> .\n").unwrap();
+        sink.finish().unwrap();
+        assert_eq!("# target 9: sports.nt\n<a> <b> <c> .\n<a> <b> <d> .\n# target 9: sports.nt\n<e> <f> <g> .\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn file_sink_compressed() {
+        let path = std::env::temp_dir().join("mopper-file-sink.nt.gz");
+        let mut sink = FileSink::compressed(File::create(&path).unwrap(), Compression::Gzip, 1024, None, false);
+        sink.write("<a> <b> <c> .\n").unwrap();
+        sink.finish().unwrap();
+        let mut statements = String::new();
+        flate2::read::GzDecoder::new(File::open(&path).unwrap()).read_to_string(&mut statements).unwrap();
+        assert_eq!("<a> <b> <c> .\n", statements);
+        fs::remove_file(&path).unwrap();
+    }
+}
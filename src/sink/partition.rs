@@ -0,0 +1,133 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io;
+use crate::rdf::{Statement, Term};
+use crate::sink::Sink;
+
+/// The placeholder in the path of a file target that partitions its output by graph, e.g. `out/{graph}.nq`.
+pub const GRAPH_PLACEHOLDER: &str = "{graph}";
+
+// What the placeholder is replaced with for the statements in the default graph
+const DEFAULT_GRAPH_NAME: &str = "default";
+
+type CreateGraphSink = dyn FnMut(Option<&Term>) -> io::Result<Box<dyn Sink>> + Send;
+
+/// A sink that writes the statements of every graph to a sink of its own, e.g. a file per graph.
+/// The sink of a graph is created when its first statement arrives; triples are in the default graph.
+pub struct GraphPartitionedSink {
+    create_sink: Box<CreateGraphSink>,
+    sinks: HashMap<Option<Term>, Box<dyn Sink>>
+}
+
+impl GraphPartitionedSink {
+    pub fn new(create_sink: impl FnMut(Option<&Term>) -> io::Result<Box<dyn Sink>> + Send + 'static) -> Self {
+        GraphPartitionedSink { create_sink: Box::new(create_sink), sinks: HashMap::new() }
+    }
+}
+
+impl Sink for GraphPartitionedSink {
+    fn write(&mut self, statements: &str) -> io::Result<()> {
+        let mut partitions: HashMap<Option<Term>, String> = HashMap::new();
+        for line in statements.split_inclusive('\n') {
+            // lines that are no statements, e.g. comments, go with the default graph
+            let graph = Statement::from_nquads(line).and_then(|statement| statement.graph);
+            partitions.entry(graph).or_default().push_str(line);
+        }
+        for (graph, statements) in partitions {
+            let sink = match self.sinks.get_mut(&graph) {
+                Some(sink) => sink,
+                None => {
+                    let sink = (self.create_sink)(graph.as_ref())?;
+                    self.sinks.entry(graph).or_insert(sink)
+                }
+            };
+            sink.write(&statements)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.sinks.values_mut().try_for_each(|sink| sink.finish())
+    }
+}
+
+/// The path of the output of a graph: the path with the placeholder replaced by `default` for the default
+/// graph, or else by the graph IRI or blank node with all characters but letters, digits, `-`, `_` and `.`
+/// percent-encoded, so every graph gets a file name of its own.
+pub fn graph_path(path: &str, graph: Option<&Term>) -> String {
+    let name = match graph {
+        Some(Term::Iri(iri)) => file_name_encode(iri),
+        Some(graph) => file_name_encode(&graph.to_string()),
+        None => DEFAULT_GRAPH_NAME.to_string()
+    };
+    path.replace(GRAPH_PLACEHOLDER, &name)
+}
+
+fn file_name_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.') {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use super::*;
+
+    struct CollectingSink(Arc<Mutex<String>>);
+
+    impl Sink for CollectingSink {
+        fn write(&mut self, statements: &str) -> io::Result<()> {
+            self.0.lock().unwrap().push_str(statements);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn statements_partitioned_by_graph() {
+        let outputs: Arc<Mutex<HashMap<String, Arc<Mutex<String>>>>> = Arc::default();
+        let created = outputs.clone();
+        let mut sink = GraphPartitionedSink::new(move |graph| {
+            let output: Arc<Mutex<String>> = Arc::default();
+            created.lock().unwrap().insert(graph_path("{graph}.nq", graph), output.clone());
+            Ok(Box::new(CollectingSink(output)))
+        });
+        sink.write("<http://example.com/s> <http://example.com/p> \"a b\" <http://example.com/g1> .\n\
+            <http://example.com/s> <http://example.com/p> <http://example.com/o> .\n").unwrap();
+        sink.write("<http://example.com/s> <http://example.com/q> \"c\" <http://example.com/g1> .\n\
+            _:b0 <http://example.com/p> <http://example.com/o> _:g2 .\n").unwrap();
+        sink.finish().unwrap();
+
+        let outputs = outputs.lock().unwrap();
+        let mut names: Vec<&String> = outputs.keys().collect();
+        names.sort();
+        assert_eq!(vec!["_%3Ag2.nq", "default.nq", "http%3A%2F%2Fexample.com%2Fg1.nq"], names);
+        assert_eq!("<http://example.com/s> <http://example.com/p> \"a b\" <http://example.com/g1> .\n\
+            <http://example.com/s> <http://example.com/q> \"c\" <http://example.com/g1> .\n",
+            outputs["http%3A%2F%2Fexample.com%2Fg1.nq"].lock().unwrap().as_str());
+        assert_eq!("<http://example.com/s> <http://example.com/p> <http://example.com/o> .\n",
+            outputs["default.nq"].lock().unwrap().as_str());
+    }
+}
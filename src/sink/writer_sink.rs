@@ -14,61 +14,152 @@
  *    limitations under the License.
  */
 
-use std::collections::HashSet;
+use std::io;
 use std::io::Write;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::Instant;
 use crossbeam_channel::Receiver;
 use log::debug;
+use crate::error::MopperError;
+use crate::message::Message;
+use crate::sink::dedup::Deduplicator;
+use crate::sink::{LineWriter, Sink};
+use crate::stats::{OperatorCounters, OperatorKind};
+
+// The maximum number of messages that are processed together
+const MAX_BATCH_SIZE: usize = 256;
+
+// The number of bytes of statements that are collected before they are written to the sink
+const WRITE_BATCH_SIZE: usize = 64 * 1024;
 
 pub struct WriterSink {
-    writer_mutex: Arc<Mutex<dyn Write + Send>>,
-    node_id: String,
-    deduplicate: bool
+    // Taken by the thread writing to it, so it is dropped, e.g. closing a statement stream, when that is done
+    sink_mutex: Arc<Mutex<Option<Box<dyn Sink>>>>,
+    node_id: usize,
+    dedup_mutex: Option<Arc<Mutex<Box<dyn Deduplicator + Send>>>>,
+    counters: Arc<OperatorCounters>
 }
 
 impl WriterSink {
-    pub fn new(out: Box<dyn Write + Send>, node_id: &usize, deduplicate: bool) -> &'static Self {
+    pub fn new(sink: Box<dyn Sink>, node_id: &usize, deduplicator: Option<Box<dyn Deduplicator + Send>>, counters: Arc<OperatorCounters>) -> &'static Self {
         debug!("Creating WriterSink {node_id}...");
         let boxed = Box::new(WriterSink {
-            writer_mutex: Arc::new(Mutex::new(out)),
-            node_id: node_id.to_string(),
-            deduplicate
+            sink_mutex: Arc::new(Mutex::new(Some(sink))),
+            node_id: *node_id,
+            dedup_mutex: deduplicator.map(|dedup| Arc::new(Mutex::new(dedup))),
+            counters
         });
         Box::leak(boxed)
     }
     
-    pub fn start (&'static self, rx_chan: Receiver<Vec<String>>) -> JoinHandle<(u8, String)> {
+    pub fn start (&'static self, rx_chan: Receiver<Message>) -> JoinHandle<Result<(), MopperError>> {
         debug!("Starting WriterSink {}", self.node_id);
         
-        let writer_clone = self.writer_mutex.clone();
+        let sink_clone = self.sink_mutex.clone();
+        let dedup_clone = self.dedup_mutex.clone();
 
         thread::Builder::new()
             .name(format!("WriterSink {}", self.node_id))
             .spawn(move || {
-            let mut dedup_filter: Option<HashSet<String>> = match self.deduplicate {
-                // At this moment deduplication is simply done with a HashMap.
-                // Could be replaced with a more memory-efficient (or memory mapped) data structure.
-                true => Some(HashSet::with_capacity(1024)),
-                false => None
-            };
+            let started = Instant::now();
+            let mut dedup_option = dedup_clone.as_ref().map(|dedup| dedup.lock().unwrap());
+            let mut sink = sink_clone.lock().unwrap().take().expect("A WriterSink is started only once");
+            let mut nr_of_statements_received: u64 = 0;
+            let mut data_to_write = String::with_capacity(WRITE_BATCH_SIZE);
+
+            while let Ok(first_message) = self.counters.blocked(|| rx_chan.recv()) {
+                // Take the messages that are already waiting as well, so they can be deduplicated in one batch
+                let mut batch = vec![first_message];
+                while batch.len() < MAX_BATCH_SIZE {
+                    match rx_chan.try_recv() {
+                        Ok(message) => batch.push(message),
+                        Err(_) => break
+                    }
+                }
 
-            for data in rx_chan {
-                let mut data_to_write = data[1..].join("\n");
-                data_to_write.push('\n');
-                if let Some(dedup_filter) = &mut dedup_filter {
-                    if !dedup_filter.insert(data_to_write.clone()) {
-                        continue
+                // Only data messages have statements; a message can contain several statements, one per line.
+                // Deduplicate them one by one.
+                let batch_data: Vec<&Vec<String>> = batch.iter()
+                    .filter_map(|message| match message {
+                        Message::Data(data) => Some(data),
+                        _ => None
+                    })
+                    .collect();
+                self.counters.add_in(batch_data.len() as u64);
+                let statements = batch_data.iter()
+                    .flat_map(|data| data.iter())
+                    .flat_map(|statements| statements.lines())
+                    .filter(|statement| !statement.is_empty());
+                let mut nr_of_statements = 0;
+                match &mut dedup_option {
+                    Some(dedup) => {
+                        let statements: Vec<&str> = statements.collect();
+                        let to_write = dedup.insert_all(&statements);
+                        nr_of_statements_received += statements.len() as u64;
+                        for (statement, write) in statements.iter().zip(to_write) {
+                            if write {
+                                data_to_write.push_str(statement);
+                                data_to_write.push('\n');
+                                nr_of_statements += 1;
+                            }
+                        }
+                    },
+                    None => {
+                        for statement in statements {
+                            data_to_write.push_str(statement);
+                            data_to_write.push('\n');
+                            nr_of_statements += 1;
+                        }
+                        nr_of_statements_received += nr_of_statements;
                     }
                 }
-                let mut out = writer_clone.lock().unwrap();
-                out.write_all(data_to_write.as_bytes()).unwrap()
+                self.counters.add_out(nr_of_statements);
+
+                // Write in large chunks, but not later than when no more messages are waiting,
+                // so the output does not lag behind when the input comes in slowly
+                if data_to_write.len() >= WRITE_BATCH_SIZE || (!data_to_write.is_empty() && rx_chan.is_empty()) {
+                    if let Err(err) = sink.write(&data_to_write) {
+                        self.counters.finish(started);
+                        return Err(self.error("Cannot write output", err));
+                    }
+                    data_to_write.clear();
+                }
             }
-            let mut out = writer_clone.lock().unwrap();
-            out.flush().unwrap();
+            if !data_to_write.is_empty() {
+                if let Err(err) = sink.write(&data_to_write) {
+                    self.counters.finish(started);
+                    return Err(self.error("Cannot write output", err));
+                }
+            }
+            if let Some(dedup) = &mut dedup_option {
+                let mut line_writer = LineWriter::new(&mut *sink);
+                match dedup.finish(&mut line_writer).and_then(|nr_of_statements| line_writer.flush().map(|_| nr_of_statements)) {
+                    Ok(nr_of_statements) => self.counters.add_out(nr_of_statements),
+                    Err(err) => {
+                        self.counters.finish(started);
+                        return Err(self.error("Cannot finish deduplication", err));
+                    }
+                }
+            }
+            if let Err(err) = sink.finish() {
+                self.counters.finish(started);
+                return Err(self.error("Cannot write output", err));
+            }
+            self.counters.add_duplicates_removed(nr_of_statements_received - self.counters.records_out());
+            self.counters.finish(started);
 
-            (0, String::new())
+            Ok(())
         }).unwrap()
     }
-}
\ No newline at end of file
+
+    // The error of the sink, which timed out if writing did not return in time
+    fn error(&self, msg: &str, err: io::Error) -> MopperError {
+        if err.kind() == io::ErrorKind::TimedOut {
+            MopperError::Timeout { node_id: self.node_id, kind: OperatorKind::Sink, msg: format!("{msg}: {err}") }
+        } else {
+            MopperError::Sink { node_id: self.node_id, msg: msg.to_string(), cause: Some(Box::new(err)) }
+        }
+    }
+}
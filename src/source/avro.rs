@@ -0,0 +1,681 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use flate2::read::DeflateDecoder;
+use serde_json::{Map, Number, Value};
+use crate::source::compression::{content_extension, SourceFile};
+use crate::source::location::FileLocation;
+use crate::source::{flattened_value, Source};
+
+/// The extension of Avro object container files, which file sources read as Avro instead of CSV.
+pub const AVRO_EXTENSION: &str = "avro";
+
+const MAGIC: &[u8; 4] = b"Obj\x01";
+
+/// Reads the records of an Avro object container file, e.g. a dump of a Kafka topic. The fields of nested
+/// records are flattened into dotted names, like `address.city`, and arrays and maps are values in JSON.
+/// `null` is read as an empty string. Blocks without compression or compressed with `deflate` or `snappy` are
+/// supported. Values of a recursive record type, inside a record of that type, are read as JSON.
+pub struct AvroSource {
+    file: SourceFile,
+    container: Option<Container>
+}
+
+// The file being read
+struct Container {
+    reader: BufReader<Box<dyn Read + Send>>,
+    schema: Schema,
+    named_types: HashMap<String, Schema>,
+    codec: Codec,
+    sync_marker: [u8; 16],
+    fields: Vec<String>,
+    block: Cursor<Vec<u8>>,
+    records_left_in_block: i64
+}
+
+// The compression of the blocks of a file
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Codec {
+    Null,
+    Deflate,
+    Snappy
+}
+
+/// Whether the file is an Avro object container file, judging by its extension.
+pub fn is_avro(location: &FileLocation) -> bool {
+    content_extension(location).is_some_and(|extension| extension.eq_ignore_ascii_case(AVRO_EXTENSION))
+}
+
+impl AvroSource {
+    /// The source of the file, which is opened when its fields are asked.
+    pub fn new(file: SourceFile) -> Self {
+        AvroSource { file, container: None }
+    }
+}
+
+impl Source for AvroSource {
+    fn fields(&mut self) -> io::Result<Vec<String>> {
+        let (reader, _size) = self.file.open()?;
+        let container = Container::open(BufReader::new(reader))?;
+        let fields = container.fields.clone();
+        self.container = Some(container);
+        Ok(fields)
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        match self.container.as_mut() {
+            Some(container) => container.next_record(),
+            None => Ok(None)
+        }
+    }
+}
+
+impl Container {
+    // Reads the header of the file
+    fn open(mut reader: BufReader<Box<dyn Read + Send>>) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not an Avro object container file".to_string()));
+        }
+        let mut metadata: HashMap<String, Vec<u8>> = HashMap::new();
+        read_blocks(&mut reader, |reader| {
+            let key = read_string(reader)?;
+            metadata.insert(key, read_bytes(reader)?);
+            Ok(())
+        })?;
+        let schema_json: Value = metadata.get("avro.schema")
+            .and_then(|schema| serde_json::from_slice(schema).ok())
+            .ok_or_else(|| invalid_data("Avro file without valid schema".to_string()))?;
+        let mut named_types = HashMap::new();
+        let schema = Schema::parse(&schema_json, None, &mut named_types)?;
+        let codec = match metadata.get("avro.codec").map(|codec| codec.as_slice()) {
+            None | Some(b"null") => Codec::Null,
+            Some(b"deflate") => Codec::Deflate,
+            Some(b"snappy") => Codec::Snappy,
+            Some(codec) => return Err(invalid_data(format!("Avro codec {} is not supported", String::from_utf8_lossy(codec))))
+        };
+        let mut sync_marker = [0u8; 16];
+        reader.read_exact(&mut sync_marker)?;
+        let mut fields = Vec::new();
+        schema.add_fields("", &mut fields);
+        Ok(Container { reader, schema, named_types, codec, sync_marker, fields, block: Cursor::new(Vec::new()), records_left_in_block: 0 })
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        while self.records_left_in_block == 0 {
+            if !self.read_block()? {
+                return Ok(None);
+            }
+        }
+        self.records_left_in_block -= 1;
+        let record = self.schema.decode(&mut self.block, &self.named_types)?;
+        Ok(Some(self.fields.iter().map(|field| flattened_value(&record, field)).collect()))
+    }
+
+    // Reads the next block of records, if there is one
+    fn read_block(&mut self) -> io::Result<bool> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        let record_count = read_long(&mut self.reader)?;
+        if record_count < 0 {
+            return Err(invalid_data(format!("Invalid Avro block record count {record_count}")));
+        }
+        self.records_left_in_block = record_count;
+        let size = read_long(&mut self.reader)?;
+        let size = usize::try_from(size).map_err(|_| invalid_data(format!("Invalid Avro block size {size}")))?;
+        let mut data = read_exact_len(&mut self.reader, size)?;
+        let mut sync_marker = [0u8; 16];
+        self.reader.read_exact(&mut sync_marker)?;
+        if sync_marker != self.sync_marker {
+            return Err(invalid_data("Avro block does not end with the sync marker of the file".to_string()));
+        }
+        match self.codec {
+            Codec::Null => {},
+            Codec::Deflate => {
+                let mut inflated = Vec::new();
+                DeflateDecoder::new(data.as_slice()).read_to_end(&mut inflated)?;
+                data = inflated;
+            },
+            Codec::Snappy => data = unsnappy(&data)?
+        }
+        self.block = Cursor::new(data);
+        Ok(true)
+    }
+}
+
+/// An Avro schema, with named types resolved.
+#[derive(Debug, Clone, PartialEq)]
+enum Schema {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Record(Vec<(String, Schema)>),
+    Enum(Vec<String>),
+    Array(Box<Schema>),
+    Map(Box<Schema>),
+    Union(Vec<Schema>),
+    Fixed(usize),
+    Decimal { fixed_size: Option<usize>, scale: u32 },
+    // A reference to a record by its full name, used inside the record itself
+    Named(String)
+}
+
+impl Schema {
+    // Parses the schema in JSON, registering the named types it defines under their full and simple names
+    fn parse(json: &Value, namespace: Option<&str>, named_types: &mut HashMap<String, Schema>) -> io::Result<Schema> {
+        match json {
+            Value::String(name) => Schema::primitive(name)
+                .or_else(|| named_types.get(name).cloned())
+                .or_else(|| namespace.and_then(|namespace| named_types.get(&format!("{namespace}.{name}")).cloned()))
+                .ok_or_else(|| invalid_data(format!("Unknown Avro type {name}"))),
+            Value::Array(branches) => branches.iter()
+                .map(|branch| Schema::parse(branch, namespace, named_types))
+                .collect::<io::Result<Vec<Schema>>>()
+                .map(Schema::Union),
+            Value::Object(object) => {
+                let type_name = object.get("type").ok_or_else(|| invalid_data(format!("Avro schema without type: {json}")))?;
+                let Value::String(type_name) = type_name else {
+                    return Schema::parse(type_name, namespace, named_types);
+                };
+                let name = object.get("name").and_then(Value::as_str).unwrap_or_default();
+                let namespace = match name.rsplit_once('.') {
+                    Some((name_namespace, _name)) => Some(name_namespace.to_string()),
+                    None => object.get("namespace").and_then(Value::as_str).map(String::from).or(namespace.map(String::from))
+                };
+                let simple_name = name.rsplit('.').next().unwrap_or(name);
+                let full_name = match &namespace {
+                    Some(namespace) => format!("{namespace}.{simple_name}"),
+                    None => simple_name.to_string()
+                };
+                let logical_type = object.get("logicalType").and_then(Value::as_str);
+                let schema = match (type_name.as_str(), logical_type) {
+                    ("bytes", Some("decimal")) => Schema::Decimal { fixed_size: None, scale: scale(object) },
+                    ("fixed", Some("decimal")) => Schema::Decimal { fixed_size: Some(size(object)?), scale: scale(object) },
+                    ("record" | "error", _) => {
+                        let fields = object.get("fields").and_then(Value::as_array)
+                            .ok_or_else(|| invalid_data(format!("Avro record {name} without fields")))?;
+                        // registered before the fields, which may refer to the record itself
+                        register(named_types, &full_name, simple_name, Schema::Named(full_name.clone()));
+                        let fields = fields.iter()
+                            .map(|field| {
+                                let field_name = field.get("name").and_then(Value::as_str)
+                                    .ok_or_else(|| invalid_data(format!("Field of Avro record {name} without name")))?;
+                                let field_type = field.get("type")
+                                    .ok_or_else(|| invalid_data(format!("Field {field_name} of Avro record {name} without type")))?;
+                                Ok((field_name.to_string(), Schema::parse(field_type, namespace.as_deref(), named_types)?))
+                            })
+                            .collect::<io::Result<Vec<(String, Schema)>>>()?;
+                        Schema::Record(fields)
+                    },
+                    ("enum", _) => Schema::Enum(object.get("symbols").and_then(Value::as_array).into_iter().flatten()
+                        .filter_map(Value::as_str)
+                        .map(String::from)
+                        .collect()),
+                    ("array", _) => Schema::Array(Box::new(Schema::parse(object.get("items").unwrap_or(&Value::Null), namespace.as_deref(), named_types)?)),
+                    ("map", _) => Schema::Map(Box::new(Schema::parse(object.get("values").unwrap_or(&Value::Null), namespace.as_deref(), named_types)?)),
+                    ("fixed", _) => Schema::Fixed(size(object)?),
+                    (primitive, _) => return Schema::parse(&Value::String(primitive.to_string()), namespace.as_deref(), named_types)
+                };
+                if !name.is_empty() {
+                    register(named_types, &full_name, simple_name, schema.clone());
+                }
+                Ok(schema)
+            },
+            _ => Err(invalid_data(format!("Invalid Avro schema {json}")))
+        }
+    }
+
+    fn primitive(name: &str) -> Option<Schema> {
+        match name {
+            "null" => Some(Schema::Null),
+            "boolean" => Some(Schema::Boolean),
+            "int" => Some(Schema::Int),
+            "long" => Some(Schema::Long),
+            "float" => Some(Schema::Float),
+            "double" => Some(Schema::Double),
+            "bytes" => Some(Schema::Bytes),
+            "string" => Some(Schema::String),
+            _ => None
+        }
+    }
+
+    // Adds the names of the flattened fields of values of this schema, with the given name
+    fn add_fields(&self, name: &str, fields: &mut Vec<String>) {
+        match self {
+            Schema::Null => {},
+            Schema::Record(record_fields) => {
+                for (field_name, schema) in record_fields {
+                    let field_name = match name.is_empty() {
+                        true => field_name.clone(),
+                        false => format!("{name}.{field_name}")
+                    };
+                    schema.add_fields(&field_name, fields);
+                }
+            },
+            Schema::Union(branches) => {
+                for branch in branches {
+                    branch.add_fields(name, fields);
+                }
+            },
+            _ => if !fields.iter().any(|field| field == name) {
+                fields.push(name.to_string());
+            }
+        }
+    }
+
+    // Decodes a value of this schema, with records as JSON objects
+    fn decode(&self, reader: &mut impl Read, named_types: &HashMap<String, Schema>) -> io::Result<Value> {
+        Ok(match self {
+            Schema::Null => Value::Null,
+            Schema::Boolean => {
+                let mut byte = [0u8; 1];
+                reader.read_exact(&mut byte)?;
+                Value::Bool(byte[0] != 0)
+            },
+            Schema::Int | Schema::Long => Value::from(read_long(reader)?),
+            Schema::Float => {
+                let mut bytes = [0u8; 4];
+                reader.read_exact(&mut bytes)?;
+                number(f32::from_le_bytes(bytes).to_string())
+            },
+            Schema::Double => {
+                let mut bytes = [0u8; 8];
+                reader.read_exact(&mut bytes)?;
+                number(f64::from_le_bytes(bytes).to_string())
+            },
+            Schema::Bytes => Value::String(String::from_utf8_lossy(&read_bytes(reader)?).to_string()),
+            Schema::String => Value::String(read_string(reader)?),
+            Schema::Record(fields) => {
+                let mut object = Map::new();
+                for (name, schema) in fields {
+                    object.insert(name.clone(), schema.decode(reader, named_types)?);
+                }
+                Value::Object(object)
+            },
+            Schema::Enum(symbols) => {
+                let index = read_long(reader)?;
+                let symbol = usize::try_from(index).ok().and_then(|index| symbols.get(index))
+                    .ok_or_else(|| invalid_data(format!("Invalid Avro enum index {index}")))?;
+                Value::String(symbol.clone())
+            },
+            Schema::Array(items) => {
+                let mut array = Vec::new();
+                read_blocks(reader, |reader| {
+                    array.push(items.decode(reader, named_types)?);
+                    Ok(())
+                })?;
+                Value::Array(array)
+            },
+            Schema::Map(values) => {
+                let mut map = Map::new();
+                read_blocks(reader, |reader| {
+                    let key = read_string(reader)?;
+                    map.insert(key, values.decode(reader, named_types)?);
+                    Ok(())
+                })?;
+                Value::Object(map)
+            },
+            Schema::Union(branches) => {
+                let index = read_long(reader)?;
+                let branch = usize::try_from(index).ok().and_then(|index| branches.get(index))
+                    .ok_or_else(|| invalid_data(format!("Invalid Avro union index {index}")))?;
+                branch.decode(reader, named_types)?
+            },
+            Schema::Fixed(size) => Value::String(String::from_utf8_lossy(&read_exact_len(reader, *size)?).to_string()),
+            Schema::Decimal { fixed_size, scale } => {
+                let bytes = match fixed_size {
+                    Some(size) => read_exact_len(reader, *size)?,
+                    None => read_bytes(reader)?
+                };
+                Value::String(decimal(&bytes, *scale)?)
+            },
+            Schema::Named(name) => named_types.get(name)
+                .ok_or_else(|| invalid_data(format!("Unknown Avro type {name}")))?
+                .decode(reader, named_types)?
+        })
+    }
+}
+
+// Registers a named type under its full and its simple name
+fn register(named_types: &mut HashMap<String, Schema>, full_name: &str, simple_name: &str, schema: Schema) {
+    named_types.insert(simple_name.to_string(), schema.clone());
+    named_types.insert(full_name.to_string(), schema);
+}
+
+fn scale(object: &Map<String, Value>) -> u32 {
+    object.get("scale").and_then(Value::as_u64).unwrap_or(0) as u32
+}
+
+fn size(object: &Map<String, Value>) -> io::Result<usize> {
+    object.get("size").and_then(Value::as_u64).map(|size| size as usize)
+        .ok_or_else(|| invalid_data("Avro fixed type without size".to_string()))
+}
+
+// A JSON number, or a string for values JSON has no numbers for, like NaN
+fn number(text: String) -> Value {
+    text.parse::<f64>().ok()
+        .and_then(Number::from_f64)
+        .map_or(Value::String(text), Value::Number)
+}
+
+// The big-endian two's-complement unscaled value, with the decimal point `scale` digits from the right
+fn decimal(bytes: &[u8], scale: u32) -> io::Result<String> {
+    if bytes.len() > 16 {
+        return Err(invalid_data(format!("Avro decimal of {} bytes is too large", bytes.len())));
+    }
+    let sign_extension = match bytes.first() {
+        Some(byte) if byte & 0x80 != 0 => 0xff,
+        _ => 0
+    };
+    let mut value_bytes = [sign_extension; 16];
+    value_bytes[16 - bytes.len()..].copy_from_slice(bytes);
+    let value = i128::from_be_bytes(value_bytes);
+    let digits = value.unsigned_abs().to_string();
+    let scale = scale as usize;
+    let sign = if value < 0 { "-" } else { "" };
+    Ok(match scale {
+        0 => format!("{sign}{digits}"),
+        _ => {
+            let digits = format!("{digits:0>width$}", width = scale + 1);
+            let (integer, fraction) = digits.split_at(digits.len() - scale);
+            format!("{sign}{integer}.{fraction}")
+        }
+    })
+}
+
+// Reads the items of an array or map, or the metadata of a file, which come in blocks with their count
+fn read_blocks<R: Read>(reader: &mut R, mut read_item: impl FnMut(&mut R) -> io::Result<()>) -> io::Result<()> {
+    loop {
+        let mut count = read_long(reader)?;
+        if count == 0 {
+            return Ok(());
+        }
+        if count < 0 {
+            // the size of the block in bytes follows, to skip it
+            count = count.checked_neg().ok_or_else(|| invalid_data(format!("Invalid Avro block count {count}")))?;
+            read_long(reader)?;
+        }
+        for _item in 0..count {
+            read_item(reader)?;
+        }
+    }
+}
+
+// Reads a variable-length zig-zag encoded int or long
+fn read_long(reader: &mut impl Read) -> io::Result<i64> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    let mut byte = [0u8; 1];
+    loop {
+        reader.read_exact(&mut byte)?;
+        if shift > 63 {
+            return Err(invalid_data("Invalid Avro long".to_string()));
+        }
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+        }
+        shift += 7;
+    }
+}
+
+fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let length = read_long(reader)?;
+    let length = usize::try_from(length).map_err(|_| invalid_data(format!("Invalid Avro length {length}")))?;
+    read_exact_len(reader, length)
+}
+
+// Reads `length` bytes, allocating as they come in rather than trusting the length up front
+fn read_exact_len(reader: &mut impl Read, length: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < length {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    Ok(bytes)
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    String::from_utf8(read_bytes(reader)?).map_err(|err| invalid_data(format!("Invalid Avro string: {err}")))
+}
+
+// Decompresses a block compressed with snappy, which ends with the big-endian CRC-32 of the uncompressed data
+fn unsnappy(data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() < 4 {
+        return Err(invalid_data("Avro snappy block without checksum".to_string()));
+    }
+    let (compressed, checksum) = data.split_at(data.len() - 4);
+    let length = snap::raw::decompress_len(compressed).map_err(|err| invalid_data(format!("Invalid Avro snappy block: {err}")))?;
+    // a snappy copy of at most 64 bytes takes at least 2 bytes, so a valid block can't expand beyond that
+    if length > compressed.len().saturating_mul(32) {
+        return Err(invalid_data(format!("Invalid Avro snappy block length {length}")));
+    }
+    let uncompressed = snap::raw::Decoder::new().decompress_vec(compressed)
+        .map_err(|err| invalid_data(format!("Invalid Avro snappy block: {err}")))?;
+    if crc32fast::hash(&uncompressed).to_be_bytes() != checksum {
+        return Err(invalid_data("Avro snappy block with wrong checksum".to_string()));
+    }
+    Ok(uncompressed)
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor, Read};
+    use std::path::PathBuf;
+    use serde_json::json;
+    use crate::source::avro::{decimal, is_avro, read_long, AvroSource, Container};
+    use crate::source::compression::{SourceCompression, SourceFile};
+    use crate::source::location::FileLocation;
+    use crate::source::Source;
+
+    fn write_long(bytes: &mut Vec<u8>, value: i64) {
+        let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+        while zigzag > 0x7f {
+            bytes.push((zigzag & 0x7f) as u8 | 0x80);
+            zigzag >>= 7;
+        }
+        bytes.push(zigzag as u8);
+    }
+
+    fn write_string(bytes: &mut Vec<u8>, value: &str) {
+        write_long(bytes, value.len() as i64);
+        bytes.extend_from_slice(value.as_bytes());
+    }
+
+    const SYNC_MARKER: [u8; 16] = [7u8; 16];
+
+    // The header of a container file with the given schema and codec
+    fn header(schema: serde_json::Value, codec: &str) -> Vec<u8> {
+        let mut bytes = b"Obj\x01".to_vec();
+        write_long(&mut bytes, 2);
+        write_string(&mut bytes, "avro.schema");
+        write_string(&mut bytes, &schema.to_string());
+        write_string(&mut bytes, "avro.codec");
+        write_string(&mut bytes, codec);
+        write_long(&mut bytes, 0);
+        bytes.extend_from_slice(&SYNC_MARKER);
+        bytes
+    }
+
+    fn write_block(bytes: &mut Vec<u8>, record_count: i64, data: &[u8]) {
+        write_long(bytes, record_count);
+        write_long(bytes, data.len() as i64);
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(&SYNC_MARKER);
+    }
+
+    fn open(bytes: Vec<u8>) -> Container {
+        let reader: Box<dyn Read + Send> = Box::new(Cursor::new(bytes));
+        Container::open(BufReader::new(reader)).unwrap()
+    }
+
+    // An uncompressed container file with one block of the given records
+    fn container(schema: serde_json::Value, record_count: i64, records: &[u8]) -> Container {
+        let mut bytes = header(schema, "null");
+        write_block(&mut bytes, record_count, records);
+        open(bytes)
+    }
+
+    #[test]
+    fn nested_records() {
+        let mut source = AvroSource::new(SourceFile { location: FileLocation::Local(PathBuf::from("test-resources/tests/avro/people.avro")), compression: SourceCompression::None });
+        assert_eq!(vec!["id", "name", "address.city", "address.zip", "tags", "balance"], source.fields().unwrap());
+        assert_eq!(vec!["1", "Alice", "Gent", "9000", r#"["a","b"]"#, "123.45"], source.next_record().unwrap().unwrap());
+        assert_eq!(vec!["2", "Bob", "Brussel", "1000", "[]", "-0.50"], source.next_record().unwrap().unwrap());
+        assert_eq!(vec!["3", "Carol", "Sint-Martens-Latem", "9830", r#"["c"]"#, "0.07"], source.next_record().unwrap().unwrap());
+        assert_eq!(None, source.next_record().unwrap());
+    }
+
+    #[test]
+    fn unions_enums_and_maps() {
+        let schema = json!({"type": "record", "name": "Event", "namespace": "com.example", "fields": [
+            {"name": "kind", "type": {"type": "enum", "name": "Kind", "symbols": ["CREATED", "DELETED"]}},
+            {"name": "previous", "type": ["null", "com.example.Kind"]},
+            {"name": "labels", "type": {"type": "map", "values": "string"}},
+            {"name": "score", "type": ["null", "double", "string"]}
+        ]});
+        let mut records = Vec::new();
+        // DELETED, previous CREATED, labels {"by": "admin"}, score 0.5
+        write_long(&mut records, 1);
+        write_long(&mut records, 1);
+        write_long(&mut records, 0);
+        write_long(&mut records, 1);
+        write_string(&mut records, "by");
+        write_string(&mut records, "admin");
+        write_long(&mut records, 0);
+        write_long(&mut records, 1);
+        records.extend_from_slice(&0.5f64.to_le_bytes());
+        // CREATED, without previous, no labels, score "high"
+        write_long(&mut records, 0);
+        write_long(&mut records, 0);
+        write_long(&mut records, 0);
+        write_long(&mut records, 2);
+        write_string(&mut records, "high");
+
+        let mut container = container(schema, 2, &records);
+        assert_eq!(vec!["kind", "previous", "labels", "score"], container.fields);
+        assert_eq!(Some(vec!["DELETED".to_string(), "CREATED".to_string(), r#"{"by":"admin"}"#.to_string(), "0.5".to_string()]), container.next_record().unwrap());
+        assert_eq!(Some(vec!["CREATED".to_string(), String::new(), "{}".to_string(), "high".to_string()]), container.next_record().unwrap());
+        assert_eq!(None, container.next_record().unwrap());
+    }
+
+    #[test]
+    fn recursive_records() {
+        let schema = json!({"type": "record", "name": "Node", "fields": [
+            {"name": "value", "type": "int"},
+            {"name": "next", "type": ["null", "Node"]}
+        ]});
+        let mut records = Vec::new();
+        // 1 -> 2
+        write_long(&mut records, 1);
+        write_long(&mut records, 1);
+        write_long(&mut records, 2);
+        write_long(&mut records, 0);
+
+        let mut container = container(schema, 1, &records);
+        assert_eq!(vec!["value", "next"], container.fields);
+        assert_eq!(Some(vec!["1".to_string(), r#"{"next":null,"value":2}"#.to_string()]), container.next_record().unwrap());
+        assert_eq!(None, container.next_record().unwrap());
+    }
+
+    #[test]
+    fn snappy_blocks() {
+        let schema = json!({"type": "record", "name": "Person", "fields": [{"name": "name", "type": "string"}]});
+        let mut records = Vec::new();
+        write_string(&mut records, "Alice");
+        write_string(&mut records, "Bob");
+        let mut data = snap::raw::Encoder::new().compress_vec(&records).unwrap();
+        data.extend_from_slice(&crc32fast::hash(&records).to_be_bytes());
+        let mut bytes = header(schema.clone(), "snappy");
+        write_block(&mut bytes, 2, &data);
+
+        let mut container = open(bytes);
+        assert_eq!(Some(vec!["Alice".to_string()]), container.next_record().unwrap());
+        assert_eq!(Some(vec!["Bob".to_string()]), container.next_record().unwrap());
+        assert_eq!(None, container.next_record().unwrap());
+
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        let mut bytes = header(schema, "snappy");
+        write_block(&mut bytes, 2, &data);
+        assert!(open(bytes).next_record().is_err());
+    }
+
+    #[test]
+    fn invalid_blocks() {
+        let schema = json!({"type": "record", "name": "Tagged", "fields": [{"name": "tags", "type": {"type": "array", "items": "string"}}]});
+        // a negative record count
+        assert!(container(schema.clone(), -1, &[0]).next_record().is_err());
+        // a block size far beyond the end of the file
+        let mut bytes = header(schema.clone(), "null");
+        write_long(&mut bytes, 1);
+        write_long(&mut bytes, i64::MAX);
+        assert!(open(bytes).next_record().is_err());
+        // an array block count that can't be negated
+        let mut records = Vec::new();
+        write_long(&mut records, i64::MIN);
+        write_long(&mut records, 0);
+        assert!(container(schema, 1, &records).next_record().is_err());
+    }
+
+    #[test]
+    fn invalid_files() {
+        let reader: Box<dyn Read + Send> = Box::new(Cursor::new(b"id,name\n".to_vec()));
+        assert!(Container::open(BufReader::new(reader)).is_err());
+        let mut source = AvroSource::new(SourceFile { location: FileLocation::Local(PathBuf::from("test-resources/rml-testcases/RMLTC1036-CSV/sport.csv")), compression: SourceCompression::None });
+        assert!(source.fields().is_err());
+    }
+
+    #[test]
+    fn decimals() {
+        assert_eq!("123.45", decimal(&[0x30, 0x39], 2).unwrap());
+        assert_eq!("-0.50", decimal(&[0xce], 2).unwrap());
+        assert_eq!("0.007", decimal(&[0x07], 3).unwrap());
+        assert_eq!("-1", decimal(&[0xff, 0xff], 0).unwrap());
+        assert_eq!("0", decimal(&[], 0).unwrap());
+        assert!(decimal(&[1; 17], 0).is_err());
+    }
+
+    #[test]
+    fn longs() {
+        for value in [0, 1, -1, 63, -64, 64, 1 << 40, i64::MAX, i64::MIN] {
+            let mut bytes = Vec::new();
+            write_long(&mut bytes, value);
+            assert_eq!(value, read_long(&mut bytes.as_slice()).unwrap());
+        }
+    }
+
+    #[test]
+    fn avro_extension() {
+        assert!(is_avro(&FileLocation::Local(PathBuf::from("dumps/people.avro"))));
+        assert!(is_avro(&FileLocation::Remote("http://example.com/people.AVRO?version=2".to_string())));
+        assert!(!is_avro(&FileLocation::Local(PathBuf::from("people.csv"))));
+        assert!(!is_avro(&FileLocation::Local(PathBuf::from("avro"))));
+    }
+}
@@ -0,0 +1,166 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufReader, Read};
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use crate::source::location::FileLocation;
+
+/// How a source file is compressed. It is decompressed while it is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceCompression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2
+}
+
+impl SourceCompression {
+    /// The compression of the file of a source, from `compression` in its configuration: `none`, `gzip`, `zstd`
+    /// or `bzip2`, or an IRI ending in one of them, like the RML compression `http://w3id.org/rml/gzip`.
+    /// Without it, the compression follows from the extension of the file: `gz`, `zst` or `bz2`.
+    pub fn of(config: &HashMap<String, String>, location: &FileLocation) -> Result<SourceCompression, String> {
+        let Some(compression) = config.get("compression") else {
+            return Ok(SourceCompression::of_file(location));
+        };
+        let name = compression.rsplit(['/', '#']).next().unwrap_or_default();
+        match name.to_lowercase().as_str() {
+            "" | "none" => Ok(SourceCompression::None),
+            name => SourceCompression::of_extension(name)
+                .ok_or_else(|| format!("compression {compression} is not supported"))
+        }
+    }
+
+    /// The compression of the file, from its extension.
+    pub fn of_file(location: &FileLocation) -> SourceCompression {
+        let file_name = location.file_name().unwrap_or_default();
+        SourceCompression::of_extension(extension(&file_name)).unwrap_or(SourceCompression::None)
+    }
+
+    fn of_extension(extension: &str) -> Option<SourceCompression> {
+        match extension.to_lowercase().as_str() {
+            "gzip" | "gz" => Some(SourceCompression::Gzip),
+            "zstd" | "zst" => Some(SourceCompression::Zstd),
+            "bzip2" | "bz2" => Some(SourceCompression::Bzip2),
+            _ => None
+        }
+    }
+
+}
+
+/// The file of a source, and how it is compressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub location: FileLocation,
+    pub compression: SourceCompression
+}
+
+impl SourceFile {
+    /// Opens the file for reading its decompressed content, with its size if it is known, which is only
+    /// when it is not compressed.
+    pub fn open(&self) -> io::Result<(Box<dyn Read + Send>, Option<u64>)> {
+        let (file, size) = self.location.open()?;
+        let reader: Box<dyn Read + Send> = match self.compression {
+            SourceCompression::None => return Ok((file, size)),
+            SourceCompression::Gzip => Box::new(MultiGzDecoder::new(BufReader::new(file))),
+            SourceCompression::Zstd => Box::new(zstd::Decoder::new(file)?),
+            SourceCompression::Bzip2 => Box::new(MultiBzDecoder::new(BufReader::new(file)))
+        };
+        Ok((reader, None))
+    }
+}
+
+/// The extension of the content of the file, i.e. without the extension of its compression, e.g. `csv` for
+/// `people.csv.gz`.
+pub fn content_extension(location: &FileLocation) -> Option<String> {
+    let file_name = location.file_name()?;
+    let content_name = match SourceCompression::of_extension(extension(&file_name)) {
+        Some(_) => file_name.rsplit_once('.').map(|(name, _extension)| name).unwrap_or_default(),
+        None => file_name.as_str()
+    };
+    content_name.rsplit_once('.').map(|(_name, extension)| extension.to_string())
+}
+
+fn extension(file_name: &str) -> &str {
+    file_name.rsplit_once('.').map(|(_name, extension)| extension).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+    use crate::source::compression::{content_extension, SourceCompression, SourceFile};
+    use crate::source::location::FileLocation;
+
+    fn location(file: &str) -> FileLocation {
+        FileLocation::Local(PathBuf::from(file))
+    }
+
+    #[test]
+    fn compression_of_sources() {
+        let no_config = HashMap::new();
+        assert_eq!(Ok(SourceCompression::None), SourceCompression::of(&no_config, &location("people.csv")));
+        assert_eq!(Ok(SourceCompression::Gzip), SourceCompression::of(&no_config, &location("people.csv.gz")));
+        assert_eq!(Ok(SourceCompression::Zstd), SourceCompression::of(&no_config, &location("people.csv.ZST")));
+        assert_eq!(Ok(SourceCompression::Bzip2), SourceCompression::of(&no_config, &location("people.csv.bz2")));
+
+        let config = |compression: &str| HashMap::from([("compression".to_string(), compression.to_string())]);
+        assert_eq!(Ok(SourceCompression::Zstd), SourceCompression::of(&config("zstd"), &location("people.data")));
+        assert_eq!(Ok(SourceCompression::Gzip), SourceCompression::of(&config("http://w3id.org/rml/gzip"), &location("people.csv")));
+        assert_eq!(Ok(SourceCompression::None), SourceCompression::of(&config("none"), &location("people.csv.gz")));
+        assert_eq!(Err("compression http://w3id.org/rml/targz is not supported".to_string()),
+            SourceCompression::of(&config("http://w3id.org/rml/targz"), &location("people.tar.gz")));
+    }
+
+    #[test]
+    fn content_extensions() {
+        assert_eq!(Some("csv".to_string()), content_extension(&location("data/people.csv")));
+        assert_eq!(Some("tsv".to_string()), content_extension(&location("data/people.tsv.bz2")));
+        assert_eq!(Some("avro".to_string()), content_extension(&location("people.avro.zst")));
+        assert_eq!(None, content_extension(&location("people.gz")));
+        assert_eq!(None, content_extension(&location("people")));
+    }
+
+    #[test]
+    fn decompression() {
+        let content = "ID,Name\n1,Alice\n2,Bob\n";
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(content.as_bytes()).unwrap();
+        let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bzip2.write_all(content.as_bytes()).unwrap();
+        let files = [
+            ("mopper-decompression.csv", content.as_bytes().to_vec(), Some(content.len() as u64)),
+            ("mopper-decompression.csv.gz", gzip.finish().unwrap(), None),
+            ("mopper-decompression.csv.zst", zstd::encode_all(content.as_bytes(), 0).unwrap(), None),
+            ("mopper-decompression.csv.bz2", bzip2.finish().unwrap(), None)
+        ];
+        for (file_name, bytes, expected_size) in files {
+            let path = std::env::temp_dir().join(file_name);
+            fs::write(&path, bytes).unwrap();
+            let location = FileLocation::Local(path.clone());
+            let source_file = SourceFile { compression: SourceCompression::of_file(&location), location };
+            let (mut reader, size) = source_file.open().unwrap();
+            let mut decompressed = String::new();
+            reader.read_to_string(&mut decompressed).unwrap();
+            assert_eq!(content, decompressed, "{file_name}");
+            assert_eq!(expected_size, size, "{file_name}");
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -14,26 +14,128 @@
  *    limitations under the License.
  */
 
-use std::collections::HashSet;
-use std::fs::File;
+use std::collections::{HashMap, HashSet};
 use std::io::BufReader;
-use std::iter::once;
 use std::ops::Index;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
-use crossbeam_channel::Sender;
-use log::{debug, error, warn};
+use std::time::Instant;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use csv::StringRecord;
+use log::{debug, error};
+use serde::Serialize;
+use crate::error::MopperError;
+use crate::error_log::RecordErrorHandler;
+use crate::message::Message;
+use crate::source::{RecordProjection, RecordSelection, SourcePermits};
+use crate::source::compression::{content_extension, SourceFile};
+use crate::source::location::FileLocation;
+use crate::stats::{OperatorCounters, OperatorKind};
+use crate::util::send_to_all;
 
+/// How the values of a CSV file are separated, quoted and escaped, and which lines are comments. What is not set
+/// is as in RFC 4180: values separated by commas, quoted with `"`, quotes escaped by doubling them and no comments,
+/// except that files with the extension `tsv` are separated by tabs.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct CsvDialect {
+    pub delimiter: Option<u8>,
+    pub quote: Option<u8>,
+    /// Whether values can be quoted at all. If not, quotes are read as part of the values.
+    pub quoting: Option<bool>,
+    /// The character that escapes a quote in a quoted value, e.g. `\`, besides doubling it.
+    pub escape: Option<u8>,
+    /// Lines starting with this character are skipped.
+    pub comment: Option<u8>
+}
+
+impl CsvDialect {
+    /// The dialect in the configuration of a source, with the names of CSVW: `delimiter`, `quoteChar` (empty to not
+    /// quote values), `escapeChar` and `commentPrefix`. Every one of them is a single ASCII character, or `\t` for a tab.
+    pub fn from_config(config: &HashMap<String, String>) -> Result<Self, String> {
+        let character = |key: &str| config.get(key)
+            .filter(|value| !value.is_empty())
+            .map(|value| parse_csv_char(value).map_err(|msg| format!("Invalid {key}: {msg}")))
+            .transpose();
+        let quoting = config.get("quoteChar").filter(|quote| quote.is_empty()).map(|_| false);
+        let mut escape = character("escapeChar")?;
+        let quote = character("quoteChar")?;
+        // an escape character that is the quote character means quotes are doubled, which is the default
+        if escape.is_some() && escape == quote.or(Some(b'"')) {
+            escape = None;
+        }
+        Ok(CsvDialect { delimiter: character("delimiter")?, quote, quoting, escape, comment: character("commentPrefix")? })
+    }
+
+    /// This dialect with what is set in `overrides` replaced.
+    pub fn with_overrides(self, overrides: &CsvDialect) -> Self {
+        CsvDialect {
+            delimiter: overrides.delimiter.or(self.delimiter),
+            quote: overrides.quote.or(self.quote),
+            quoting: overrides.quoting.or(self.quoting),
+            escape: overrides.escape.or(self.escape),
+            comment: overrides.comment.or(self.comment)
+        }
+    }
+
+    // A reader of the file in this dialect
+    pub(crate) fn reader_builder(&self, location: &FileLocation) -> csv::ReaderBuilder {
+        let tab_separated = content_extension(location).is_some_and(|extension| extension.eq_ignore_ascii_case("tsv"));
+        self.delimited_reader_builder(if tab_separated { b'\t' } else { b',' })
+    }
+
+    // A reader in this dialect, separating values with the given delimiter if the dialect has none
+    pub(crate) fn delimited_reader_builder(&self, default_delimiter: u8) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder.has_headers(false)
+            .delimiter(self.delimiter.unwrap_or(default_delimiter))
+            .quote(self.quote.unwrap_or(b'"'))
+            .quoting(self.quoting.unwrap_or(true))
+            .escape(self.escape)
+            .comment(self.comment);
+        builder
+    }
+}
+
+/// Parses a character of a [CsvDialect]: a single ASCII character, or `\t` or `tab` for a tab.
+pub fn parse_csv_char(value: &str) -> Result<u8, String> {
+    match value {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if value.len() == 1 && value.is_ascii() => Ok(value.as_bytes()[0]),
+        _ => Err(format!("'{value}' is not a single ASCII character"))
+    }
+}
+
+/// Reads the records of one or more CSV files, e.g. the files matching a glob pattern. The headers are sent once,
+/// from the first file that is read; the files after it must have the columns the plan uses too. By default the
+/// files are read one after the other, in order; they can be read several at once, see [CSVFileSource::start].
 pub struct CSVFileSource {
-    file_path: String,
-    // TODO: delimiter etc
+    files: Vec<SourceFile>,
+    dialect: CsvDialect,
     attributes: Vec<String>,     // TODO: remove Option part?
-    node_id: String
+    node_id: String,
+    record_selection: RecordSelection,
+    counters: Arc<OperatorCounters>,
+    record_errors: RecordErrorHandler
+}
+
+// The number of records the thread reading a file hands over at once
+const RECORD_BATCH_SIZE: usize = 1024;
+
+// What the threads reading the files report about a file, by its number in the files of the source
+enum FileEvent {
+    // The file cannot be opened or its header cannot be read: the message, and the error that stops the source
+    Failed(String, MopperError),
+    // The fields in the header of the file, and the size of the file if it is known
+    Header(usize, Vec<String>, Option<u64>),
+    Records(usize, Vec<csv::Result<StringRecord>>),
+    End(usize)
 }
 
 impl CSVFileSource {
 
-    pub fn new(file_path: String, attributes: &Option<HashSet<String>>, node_id: &usize) -> &'static Self {
+    pub fn new(files: Vec<SourceFile>, dialect: CsvDialect, attributes: &Option<HashSet<String>>, node_id: &usize, record_selection: RecordSelection, counters: Arc<OperatorCounters>, record_errors: RecordErrorHandler) -> &'static Self {
         debug!("Creating CSVFileSource...");
         let attributes_vec: Vec<String> = match  attributes { 
             Some(attr) => attr.iter().map(|value| value.to_string()).collect(),
@@ -41,76 +143,263 @@ impl CSVFileSource {
         };
         let boxed = Box::new(
             CSVFileSource{
-                file_path,
+                files,
+                dialect,
                 attributes: attributes_vec,
-                node_id: node_id.to_string()
+                node_id: node_id.to_string(),
+                record_selection,
+                counters,
+                record_errors
             },
         );
         Box::leak(boxed)
     }
 
-    pub fn start(&'static self, tx_channels: Vec<Sender<Vec<String>>>) -> JoinHandle<(u8, String)> {
+    /// Starts reading the files, at most `files_at_once` of them at the same time. With more than one,
+    /// the records of the files are sent as they are read, so not in the order of the files.
+    pub fn start(&'static self, tx_channels: Vec<Sender<Message>>, permits: SourcePermits, files_at_once: usize) -> JoinHandle<Result<(), MopperError>> {
         thread::Builder::new()
             .name(format!("CSVFileSource {}", self.node_id))
             .spawn(move || {
             debug!("Starting CSVFileSource!");
-                        
-            let file_res = File::open(self.file_path.clone());
-            if let Err(file_err) = file_res {
-                let msg = format!("Cannot open {}: {}", self.file_path, file_err.to_string());
-                error!("{msg}");
-                return (1u8, msg)
+            let started = Instant::now();
+            let _permit = self.counters.blocked(|| permits.acquire());
+
+            let nr_of_readers = files_at_once.clamp(1, self.files.len().max(1));
+            let next_file = AtomicUsize::new(0);
+            let result = thread::scope(|scope| {
+                let (events_tx, events_rx) = bounded(nr_of_readers * 2);
+                for reader_nr in 0..nr_of_readers {
+                    let events_tx = events_tx.clone();
+                    let next_file = &next_file;
+                    thread::Builder::new()
+                        .name(format!("CSVFileSource {} reader {reader_nr}", self.node_id))
+                        .spawn_scoped(scope, move || {
+                            // every reader takes the next file that is not read yet, until the source stops listening
+                            loop {
+                                let file_nr = next_file.fetch_add(1, Ordering::Relaxed);
+                                if file_nr >= self.files.len() || !self.read_file(file_nr, &events_tx) {
+                                    break;
+                                }
+                            }
+                        }).unwrap();
+                }
+                drop(events_tx);
+                // the readers stop when the events are dropped, also if the source stops early
+                self.process(events_rx, &tx_channels)
+            });
+            self.counters.finish(started);
+            result
+        }).unwrap()
+    }
+
+    // Reads the file with the given number, and reports what is read. Returns whether the source still listens.
+    fn read_file(&self, file_nr: usize, events: &Sender<FileEvent>) -> bool {
+        let file = &self.files[file_nr];
+        let (reader, size) = match file.open() {
+            Ok(opened) => opened,
+            Err(err) => {
+                let msg = format!("Cannot open {}: {err}", file.location);
+                let error = MopperError::Source { node_id: self.record_errors.node_id(), msg: msg.clone(), cause: Some(Box::new(err)) };
+                return events.send(FileEvent::Failed(msg, error)).is_ok();
             }
-                //.expect(format!("File not found: {}", self.file_path).as_str());
-            let br = BufReader::new(file_res.unwrap());
-            let mut rdr = 
-                csv::ReaderBuilder::new()
-                    .has_headers(false)
-                    .from_reader(br);
-            
-            let mut attribute_indices: Vec<usize> = Vec::with_capacity(self.attributes.len());
-            
-            // First map the headers / field names to an index
-            let mut iter = rdr.records();
-            let headers_result = iter.next();
-            if headers_result.is_some() {
-                let headers = headers_result.unwrap().unwrap();
-                for attribute in &self.attributes {
-                    let index = headers.iter().position(|r| r == attribute);
-                    match index {
-                        Some(i) => {
-                            attribute_indices.push(i);
-                        },
+        };
+        let rdr = self.dialect.reader_builder(&file.location)
+            .from_reader(BufReader::new(reader));
+
+        // First the headers / field names
+        let mut records = rdr.into_records();
+        match records.next() {
+            Some(Ok(header)) => if events.send(FileEvent::Header(file_nr, header.iter().map(String::from).collect(), size)).is_err() {
+                return false;
+            },
+            Some(Err(err)) => {
+                let msg = format!("Cannot read the header of {}: {err}", file.location);
+                let error = MopperError::Parse { node_id: self.record_errors.node_id(), kind: OperatorKind::Source, msg: msg.clone(), cause: Some(Box::new(err)) };
+                return events.send(FileEvent::Failed(msg, error)).is_ok();
+            },
+            None => return events.send(FileEvent::End(file_nr)).is_ok()
+        }
+        loop {
+            let batch: Vec<csv::Result<StringRecord>> = records.by_ref().take(RECORD_BATCH_SIZE).collect();
+            if batch.is_empty() {
+                return events.send(FileEvent::End(file_nr)).is_ok();
+            }
+            if events.send(FileEvent::Records(file_nr, batch)).is_err() {
+                return false;
+            }
+        }
+    }
+
+    // Sends the records the readers report, with the headers of the first file
+    fn process(&self, events: Receiver<FileEvent>, tx_channels: &[Sender<Message>]) -> Result<(), MopperError> {
+        // the headers sent, and the file they are from
+        let mut sent_headers: Option<(Vec<String>, &FileLocation)> = None;
+        // the projections of the files being read that have the headers sent
+        let mut projections: HashMap<usize, RecordProjection> = HashMap::new();
+        let mut record_selection = self.record_selection;
+        let mut record_number: u64 = 0;
+        // the bytes read of every file, and of all files
+        let mut file_bytes_read: HashMap<usize, u64> = HashMap::new();
+        let mut bytes_read: u64 = 0;
+        for event in events {
+            match event {
+                FileEvent::Failed(msg, error) => if self.file_error_stops(&msg) {
+                    return Err(error);
+                },
+                FileEvent::Header(file_nr, fields, size) => {
+                    let file = &self.files[file_nr];
+                    if let Some(size) = size {
+                        self.counters.set_bytes_total(self.counters.bytes_total() + size);
+                    }
+                    let file_name = file.location.file_name();
+                    let (projection, headers) = RecordProjection::new(&self.attributes, &fields, file_name.as_deref());
+                    match &sent_headers {
                         None => {
-                            warn!("WARNING: no field found with name {}", attribute);
+                            if !send_to_all(tx_channels, Message::schema(headers.clone())) {
+                                return Ok(());
+                            }
+                            sent_headers = Some((headers, &file.location));
+                        },
+                        Some((sent, first_file)) if *sent != headers => {
+                            let msg = format!("{} does not have the columns {} of {first_file}", file.location, sent.join(", "));
+                            if self.file_error_stops(&msg) {
+                                return Err(MopperError::Source { node_id: self.record_errors.node_id(), msg, cause: None });
+                            }
+                            continue;
+                        },
+                        Some(_) => {}
+                    }
+                    projections.insert(file_nr, projection);
+                },
+                FileEvent::Records(file_nr, records) => {
+                    // the records of a skipped file are ignored
+                    let Some(projection) = projections.get(&file_nr) else {
+                        continue;
+                    };
+                    for result in records {
+                        if record_selection.is_done() {
+                            break;
+                        }
+                        record_number += 1;
+                        // skip records that cannot be parsed, or stop
+                        let record = match result {
+                            Ok(record) => record,
+                            Err(err) => {
+                                let msg = format!("Cannot read {}: {err}", self.files[file_nr].location);
+                                if self.record_errors.handle(&msg, &[], &[]) {
+                                    return Err(MopperError::Parse {
+                                        node_id: self.record_errors.node_id(),
+                                        kind: OperatorKind::Source,
+                                        msg,
+                                        cause: Some(Box::new(err))
+                                    });
+                                }
+                                continue;
+                            }
+                        };
+                        if !record_selection.select() {
+                            continue;
+                        }
+                        let data = projection.project(|index| record.index(index).to_string(), record_number);
+                        if !self.counters.blocked(|| send_to_all(tx_channels, Message::Data(data))) {
+                            return Ok(());
                         }
+                        self.counters.add_out(1);
+                        if let Some(position) = record.position() {
+                            let file_bytes = file_bytes_read.entry(file_nr).or_default();
+                            bytes_read += position.byte().saturating_sub(*file_bytes);
+                            *file_bytes = position.byte();
+                            self.counters.set_bytes_read(bytes_read);
+                        }
+                    }
+                    if record_selection.is_done() {
+                        debug!("CSVFileSource {} reached the record limit", self.node_id);
+                        break;
                     }
+                },
+                FileEvent::End(file_nr) => {
+                    projections.remove(&file_nr);
+                    file_bytes_read.remove(&file_nr);
                 }
-                
-                // prepend node_id to attributes
-                let node_id_plus_headers: Vec<String> = once(&self.node_id)
-                    .chain(self.attributes.iter())
-                    .map(|data| data.to_string())
-                    .collect();
-                
-                tx_channels.iter()
-                    .for_each(|tx_chan| tx_chan.send(node_id_plus_headers.clone()).unwrap());
-            }
-            
-            for result in iter {
-                let record = result.unwrap();
-                let node_id_plus_data: Vec<String> = once(&self.node_id)
-                    .map(|data| data.to_string())
-                    .chain(
-                        attribute_indices.iter()
-                            .map(|index| String::from(&record.index(*index).to_string()))
-                    )
-                    .collect();
-                tx_channels.iter()
-                    .for_each(|tx_chan| tx_chan.send(node_id_plus_data.clone()).unwrap());
             }
+        }
 
-            (0, String::new())
-        }).unwrap()
+        send_to_all(tx_channels, Message::End);
+        self.counters.set_bytes_read(self.counters.bytes_total());
+        Ok(())
     }
-}
\ No newline at end of file
+
+    // Handles a file that cannot be read, and returns whether the source has to stop. A source of one file stops;
+    // one of more files skips the file, unless errors stop the run.
+    fn file_error_stops(&self, msg: &str) -> bool {
+        if self.files.len() == 1 {
+            error!("{msg}");
+            return true;
+        }
+        self.record_errors.handle(msg, &[], &[])
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use crate::source::csv_file::{parse_csv_char, CsvDialect};
+    use crate::source::location::FileLocation;
+
+    fn config(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn dialect_from_config() {
+        assert_eq!(CsvDialect::default(), CsvDialect::from_config(&config(&[("path", "people.csv")])).unwrap());
+        assert_eq!(
+            CsvDialect { delimiter: Some(b';'), quote: Some(b'\''), quoting: None, escape: Some(b'\\'), comment: Some(b'#') },
+            CsvDialect::from_config(&config(&[("delimiter", ";"), ("quoteChar", "'"), ("escapeChar", "\\"), ("commentPrefix", "#")])).unwrap()
+        );
+        // no quoting, and quotes escaped by doubling them
+        assert_eq!(
+            CsvDialect { delimiter: Some(b'\t'), quoting: Some(false), ..CsvDialect::default() },
+            CsvDialect::from_config(&config(&[("delimiter", "\\t"), ("quoteChar", ""), ("escapeChar", "\"")])).unwrap()
+        );
+        assert_eq!(Err("Invalid commentPrefix: '//' is not a single ASCII character".to_string()),
+            CsvDialect::from_config(&config(&[("commentPrefix", "//")])));
+    }
+
+    #[test]
+    fn dialect_overrides() {
+        let dialect = CsvDialect { delimiter: Some(b';'), comment: Some(b'#'), ..CsvDialect::default() };
+        let overrides = CsvDialect { delimiter: Some(b'|'), quoting: Some(false), ..CsvDialect::default() };
+        assert_eq!(CsvDialect { delimiter: Some(b'|'), quoting: Some(false), comment: Some(b'#'), ..CsvDialect::default() },
+            dialect.with_overrides(&overrides));
+        assert_eq!(dialect, dialect.with_overrides(&CsvDialect::default()));
+    }
+
+    #[test]
+    fn dialect_readers() {
+        let read = |dialect: CsvDialect, file: &str, content: &str| -> Vec<Vec<String>> {
+            dialect.reader_builder(&FileLocation::Local(PathBuf::from(file)))
+                .from_reader(content.as_bytes())
+                .records()
+                .map(|record| record.unwrap().iter().map(String::from).collect())
+                .collect()
+        };
+        assert_eq!(vec![vec!["a", "b,c"]], read(CsvDialect::default(), "values.tsv", "a\tb,c\n"));
+        assert_eq!(vec![vec!["a\tb", "c"]], read(CsvDialect::default(), "values.csv", "a\tb,c\n"));
+        let dialect = CsvDialect { quoting: Some(false), comment: Some(b'#'), ..CsvDialect::default() };
+        assert_eq!(vec![vec!["\"a\"", "b"]], read(dialect, "values.csv", "# values\n\"a\",b\n"));
+        let dialect = CsvDialect { escape: Some(b'\\'), ..CsvDialect::default() };
+        assert_eq!(vec![vec!["say \"hi\"", "b"]], read(dialect, "values.csv", "\"say \\\"hi\\\"\",b\n"));
+    }
+
+    #[test]
+    fn csv_chars() {
+        assert_eq!(Ok(b';'), parse_csv_char(";"));
+        assert_eq!(Ok(b'\t'), parse_csv_char("\t"));
+        assert_eq!(Ok(b'\t'), parse_csv_char("\\t"));
+        assert_eq!(Ok(b'\t'), parse_csv_char("tab"));
+        assert!(parse_csv_char("").is_err());
+        assert!(parse_csv_char("é").is_err());
+    }
+}
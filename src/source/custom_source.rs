@@ -0,0 +1,138 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::collections::HashSet;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Instant;
+use crossbeam_channel::Sender;
+use log::debug;
+use crate::error::MopperError;
+use crate::error_log::RecordErrorHandler;
+use crate::message::Message;
+use crate::source::{RecordProjection, RecordSelection, Source, SourcePermits};
+use crate::stats::{OperatorCounters, OperatorKind};
+use crate::util::send_to_all;
+
+/// Sends the records of a custom [Source], projected on the attributes the plan uses.
+/// The source is dropped when it has no more records, when the record limit is reached, or when the run stops.
+pub struct CustomSource {
+    source_mutex: Mutex<Option<Box<dyn Source>>>,
+    attributes: Vec<String>,
+    node_id: String,
+    record_selection: RecordSelection,
+    counters: Arc<OperatorCounters>,
+    record_errors: RecordErrorHandler,
+    file_name: Option<String>
+}
+
+impl CustomSource {
+    pub fn new(source: Box<dyn Source>, attributes: &Option<HashSet<String>>, node_id: &usize, record_selection: RecordSelection, counters: Arc<OperatorCounters>, record_errors: RecordErrorHandler, file_name: Option<String>) -> &'static Self {
+        debug!("Creating CustomSource {node_id}...");
+        let attributes_vec: Vec<String> = match attributes {
+            Some(attr) => attr.iter().map(|value| value.to_string()).collect(),
+            None => Vec::new()
+        };
+        let boxed = Box::new(CustomSource {
+            source_mutex: Mutex::new(Some(source)),
+            attributes: attributes_vec,
+            node_id: node_id.to_string(),
+            record_selection,
+            counters,
+            record_errors,
+            file_name
+        });
+        Box::leak(boxed)
+    }
+
+    pub fn start(&'static self, tx_channels: Vec<Sender<Message>>, permits: SourcePermits) -> JoinHandle<Result<(), MopperError>> {
+        thread::Builder::new()
+            .name(format!("CustomSource {}", self.node_id))
+            .spawn(move || {
+            debug!("Starting CustomSource {}!", self.node_id);
+            let started = Instant::now();
+            let _permit = self.counters.blocked(|| permits.acquire());
+            let Some(mut source) = self.source_mutex.lock().unwrap().take() else {
+                self.counters.finish(started);
+                return Ok(());
+            };
+
+            // First map the fields to an index
+            let fields = match source.fields() {
+                Ok(fields) => fields,
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                    self.counters.finish(started);
+                    return Err(MopperError::Timeout { node_id: self.record_errors.node_id(), kind: OperatorKind::Source, msg: format!("Cannot get the fields: {err}") });
+                },
+                Err(err) => {
+                    self.counters.finish(started);
+                    return Err(MopperError::Source { node_id: self.record_errors.node_id(), msg: format!("Cannot get the fields: {err}"), cause: Some(Box::new(err)) });
+                }
+            };
+            let (projection, headers) = RecordProjection::new(&self.attributes, &fields, self.file_name.as_deref());
+            if !send_to_all(&tx_channels, Message::schema(headers)) {
+                self.counters.finish(started);
+                return Ok(());
+            }
+
+            let mut record_selection = self.record_selection;
+            let mut record_number: u64 = 0;
+            loop {
+                if record_selection.is_done() {
+                    debug!("CustomSource {} reached the record limit", self.node_id);
+                    break;
+                }
+                // skip records that cannot be read, or stop
+                let next_record = source.next_record();
+                if let Ok(Some(_)) = next_record {
+                    record_number += 1;
+                }
+                let msg = match next_record {
+                    Ok(None) => break,
+                    Ok(Some(record)) if record.len() == fields.len() => {
+                        if !record_selection.select() {
+                            continue;
+                        }
+                        let data = projection.project(|index| record[index].clone(), record_number);
+                        if !self.counters.blocked(|| send_to_all(&tx_channels, Message::Data(data))) {
+                            self.counters.finish(started);
+                            return Ok(());
+                        }
+                        self.counters.add_out(1);
+                        continue;
+                    },
+                    Ok(Some(record)) => format!("Record has {} values instead of {}", record.len(), fields.len()),
+                    // a source that hangs will not recover by skipping a record
+                    Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                        self.counters.finish(started);
+                        return Err(MopperError::Timeout { node_id: self.record_errors.node_id(), kind: OperatorKind::Source, msg: format!("Cannot read record: {err}") });
+                    },
+                    Err(err) => format!("Cannot read record: {err}")
+                };
+                if self.record_errors.handle(&msg, &[], &[]) {
+                    self.counters.finish(started);
+                    return Err(MopperError::Parse { node_id: self.record_errors.node_id(), kind: OperatorKind::Source, msg, cause: None });
+                }
+            }
+
+            send_to_all(&tx_channels, Message::End);
+            self.counters.finish(started);
+            Ok(())
+        }).unwrap()
+    }
+}
@@ -0,0 +1,159 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! A source that runs SQL with DuckDB, directly over local CSV, Parquet and JSON files, so filtering, joining
+//! and aggregating them is done by DuckDB before the rows are mapped.
+
+use std::io;
+use std::thread;
+use crossbeam_channel::{Receiver, Sender};
+use duckdb::{AccessMode, Config, Connection};
+use operator::Source as SourceConfig;
+use crate::source::{sql_query, Source};
+
+/// The key of the built-in source that queries with DuckDB, i.e. of sources with `"source": "duckdb"`.
+pub const DUCKDB_SOURCE: &str = "duckdb";
+
+// The number of rows read ahead of the records that are taken
+const READ_AHEAD: usize = 1000;
+
+/// Reads the rows of a DuckDB query, with the columns as fields. The rows are those of `query`, e.g.
+/// `SELECT * FROM 'people/*.parquet' WHERE age > 18`, or else of `table`, which can also be a file,
+/// like `'people.csv'`. Relative paths of files are relative to the working directory. The query runs
+/// in memory, or on the DuckDB database file `database`, which is opened read-only. Values are read as
+/// their text in DuckDB, and `NULL` as an empty string.
+pub struct DuckDbSource {
+    fields: Vec<String>,
+    rows: Receiver<io::Result<Vec<String>>>
+}
+
+impl DuckDbSource {
+    /// Starts the query, in a thread of its own that reads the rows ahead, and waits for its columns.
+    pub fn connect(source: &SourceConfig) -> io::Result<Self> {
+        let query = sql_query(&source.config, "DuckDB")?;
+        let database = source.config.get("database").cloned();
+        // the columns come first, then the rows
+        let (sender, rows) = crossbeam_channel::bounded(READ_AHEAD);
+        thread::Builder::new()
+            .name("DuckDB query".to_string())
+            .spawn(move || if let Err(err) = run_query(database.as_deref(), &query, &sender) {
+                let _ = sender.send(Err(io::Error::other(err.to_string())));
+            })?;
+        match rows.recv() {
+            Ok(fields) => Ok(DuckDbSource { fields: fields?, rows }),
+            Err(_) => Err(io::Error::other("The DuckDB query stopped without result"))
+        }
+    }
+}
+
+impl Source for DuckDbSource {
+    fn fields(&mut self) -> io::Result<Vec<String>> {
+        Ok(self.fields.clone())
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        // the query is done when the thread stops sending
+        self.rows.recv().ok().transpose()
+    }
+}
+
+// Sends the columns of the query, and then its rows, until they are sent or the source is dropped
+fn run_query(database: Option<&str>, query: &str, rows: &Sender<io::Result<Vec<String>>>) -> duckdb::Result<()> {
+    let connection = match database {
+        Some(database) => Connection::open_with_flags(database, Config::default().access_mode(AccessMode::ReadOnly)?)?,
+        None => Connection::open_in_memory()?
+    };
+    let columns: Vec<String> = connection.prepare(&format!("DESCRIBE {query}"))?
+        .query_map([], |row| row.get(0))?
+        .collect::<duckdb::Result<Vec<String>>>()?;
+    // every value is cast to text by DuckDB, so it reads like DuckDB prints it; the columns are renamed
+    // by their position, as a query can have columns with the same name
+    let positions: Vec<String> = (0..columns.len()).map(|index| format!("column{index}")).collect();
+    let casts: Vec<String> = positions.iter().map(|position| format!("CAST({position} AS VARCHAR)")).collect();
+    let mut statement = connection.prepare(&format!("SELECT {} FROM ({query}) AS query_rows({})", casts.join(", "), positions.join(", ")))?;
+    if rows.send(Ok(columns.clone())).is_err() {
+        return Ok(());
+    }
+    let mut result = statement.query([])?;
+    while let Some(row) = result.next()? {
+        let values = (0..columns.len())
+            .map(|index| row.get::<_, Option<String>>(index).map(Option::unwrap_or_default))
+            .collect::<duckdb::Result<Vec<String>>>()?;
+        if rows.send(Ok(values)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use operator::IOType;
+    use crate::source::builtin_source_factory;
+    use super::*;
+
+    fn source_config(entries: &[(&str, &str)]) -> SourceConfig {
+        SourceConfig {
+            config: entries.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect::<HashMap<String, String>>(),
+            source_type: IOType::File,
+            root_iterator: Default::default()
+        }
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn query_files() {
+        let dir = std::env::temp_dir().join("mopper-duckdb-source");
+        fs::create_dir_all(&dir).unwrap();
+        let people = dir.join("people.csv");
+        fs::write(&people, "id,name,born,planet\n1,Venus,2001-02-03,2\n2,Mars,,4\n3,Earth,1999-12-31,3\n").unwrap();
+        let planets = dir.join("planets.csv");
+        fs::write(&planets, "id,\"planet \"\"name\"\"\"\n2,Venus\n3,Earth\n").unwrap();
+
+        let query = format!("SELECT p.id, name, born, p2.* FROM '{}' p LEFT JOIN '{}' p2 ON p.planet = p2.id WHERE p.id > 1 ORDER BY p.id;",
+                            people.display(), planets.display());
+        let source = source_config(&[("source", DUCKDB_SOURCE), ("query", &query)]);
+        let mut duckdb_source = builtin_source_factory(&source).unwrap().create(&source).unwrap();
+        assert_eq!(strings(&["id", "name", "born", "id", "planet \"name\""]), duckdb_source.fields().unwrap());
+        assert_eq!(Some(strings(&["2", "Mars", "", "", ""])), duckdb_source.next_record().unwrap());
+        assert_eq!(Some(strings(&["3", "Earth", "1999-12-31", "3", "Earth"])), duckdb_source.next_record().unwrap());
+        assert_eq!(None, duckdb_source.next_record().unwrap());
+
+        // a Parquet file as table
+        let parquet = dir.join("people.parquet");
+        Connection::open_in_memory().unwrap()
+            .execute_batch(&format!("COPY (SELECT * FROM '{}') TO '{}' (FORMAT parquet)", people.display(), parquet.display()))
+            .unwrap();
+        let table = format!("'{}'", parquet.display());
+        let mut duckdb_source = DuckDbSource::connect(&source_config(&[("table", &table)])).unwrap();
+        assert_eq!(strings(&["id", "name", "born", "planet"]), duckdb_source.fields().unwrap());
+        assert_eq!(3, std::iter::from_fn(|| duckdb_source.next_record().unwrap()).count());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn query_errors() {
+        let connect = |entries: &[(&str, &str)]| DuckDbSource::connect(&source_config(entries)).err().unwrap().to_string();
+        assert_eq!("DuckDB source without query or table", connect(&[]));
+        assert!(connect(&[("query", "SELECT * FROM 'mopper-missing.csv'")]).contains("mopper-missing.csv"));
+        assert!(connect(&[("query", "SELECT 1"), ("database", "mopper-missing.duckdb")]).contains("mopper-missing.duckdb"));
+    }
+}
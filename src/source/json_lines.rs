@@ -0,0 +1,154 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+use std::io;
+use std::io::{BufReader, Read};
+use std::vec::IntoIter;
+use serde_json::de::IoRead;
+use serde_json::{StreamDeserializer, Value};
+use crate::source::compression::SourceFile;
+use crate::source::{flattened_value, Source};
+
+// The JSON values read one after the other from the file
+type JsonValues = StreamDeserializer<'static, IoRead<BufReader<Box<dyn Read + Send>>>, Value>;
+
+/// Reads the records of a sequence of JSON values, like JSON Lines, e.g. from standard input. With the iterator
+/// `$`, every value is a record; with `$[*]`, every item of a value that is an array, like the objects of a JSON
+/// file with an array of objects. The fields are the references the plan uses, with the fields of nested objects
+/// named with dots, like `address.city`. Arrays and objects are values in JSON, and `null` is an empty string.
+pub struct JsonLinesSource {
+    file: SourceFile,
+    fields: Vec<String>,
+    array_items: bool,
+    values: Option<JsonValues>,
+    items: IntoIter<Value>
+}
+
+impl JsonLinesSource {
+    /// The source of the file, which is opened when its fields are asked.
+    pub fn new(file: SourceFile, fields: Vec<String>, array_items: bool) -> Self {
+        JsonLinesSource { file, fields, array_items, values: None, items: Vec::new().into_iter() }
+    }
+
+    /// Whether the records are the items of arrays, for the JSONPath iterator `$[*]`, or the values themselves,
+    /// for `$` or no iterator. Other iterators are not supported.
+    pub fn iterates_array_items(iterator: &Option<String>) -> Result<bool, String> {
+        match iterator.as_deref().map(str::trim) {
+            None | Some("" | "$") => Ok(false),
+            Some("$[*]" | "$.*") => Ok(true),
+            Some(other) => Err(format!("JSONPath iterator {other} is not supported; records are read from JSON values with $, or from their array items with $[*]"))
+        }
+    }
+
+    fn record(&self, value: &Value) -> Vec<String> {
+        self.fields.iter().map(|field| flattened_value(value, field)).collect()
+    }
+}
+
+impl Source for JsonLinesSource {
+    fn fields(&mut self) -> io::Result<Vec<String>> {
+        let (reader, _size) = self.file.open()?;
+        self.values = Some(serde_json::Deserializer::from_reader(BufReader::new(reader)).into_iter());
+        Ok(self.fields.clone())
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        loop {
+            if let Some(item) = self.items.next() {
+                return Ok(Some(self.record(&item)));
+            }
+            let Some(values) = self.values.as_mut() else {
+                return Ok(None);
+            };
+            match values.next() {
+                None => return Ok(None),
+                Some(Err(err)) => {
+                    // the rest of the input cannot be read after a syntax error
+                    self.values = None;
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid JSON in {}: {err}", self.file.location)));
+                },
+                Some(Ok(Value::Array(items))) if self.array_items => self.items = items.into_iter(),
+                Some(Ok(value)) => return Ok(Some(self.record(&value)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::source::compression::{SourceCompression, SourceFile};
+    use crate::source::json_lines::JsonLinesSource;
+    use crate::source::location::FileLocation;
+    use crate::source::Source;
+
+    fn read(file_name: &str, content: &str, array_items: bool) -> (Vec<Vec<String>>, Option<String>) {
+        let path = std::env::temp_dir().join(file_name);
+        fs::write(&path, content).unwrap();
+        let file = SourceFile { location: FileLocation::Local(path.clone()), compression: SourceCompression::None };
+        let fields = vec!["id".to_string(), "address.city".to_string(), "tags".to_string()];
+        let mut source = JsonLinesSource::new(file, fields.clone(), array_items);
+        assert_eq!(fields, source.fields().unwrap());
+        let mut records = Vec::new();
+        let mut error = None;
+        loop {
+            match source.next_record() {
+                Ok(Some(record)) => records.push(record),
+                Ok(None) => break,
+                Err(err) => error = Some(err.to_string())
+            }
+        }
+        fs::remove_file(path).unwrap();
+        (records, error)
+    }
+
+    #[test]
+    fn json_lines() {
+        let content = "{\"id\": 1, \"address\": {\"city\": \"Gent\"}, \"tags\": [\"a\"]}\n{\"id\": \"2\", \"address\": null}\n\n{\"id\": 3.5, \"address.city\": \"Brussel\"}";
+        let (records, error) = read("mopper-json-lines.jsonl", content, false);
+        assert_eq!(vec![
+            vec!["1", "Gent", r#"["a"]"#],
+            vec!["2", "", ""],
+            vec!["3.5", "Brussel", ""]
+        ], records);
+        assert_eq!(None, error);
+    }
+
+    #[test]
+    fn json_array_items() {
+        let content = "[\n  {\"id\": 1},\n  {\"id\": 2}\n]\n[{\"id\": 3}]";
+        let (records, error) = read("mopper-json-array.json", content, true);
+        assert_eq!(vec![vec!["1", "", ""], vec!["2", "", ""], vec!["3", "", ""]], records);
+        assert_eq!(None, error);
+        // the arrays themselves are the records with the iterator $
+        let (records, _error) = read("mopper-json-array-values.json", content, false);
+        assert_eq!(2, records.len());
+    }
+
+    #[test]
+    fn invalid_json() {
+        let (records, error) = read("mopper-invalid-json.jsonl", "{\"id\": 1}\n{\"id\": \n", false);
+        assert_eq!(vec![vec!["1", "", ""]], records);
+        assert!(error.unwrap().starts_with("Invalid JSON in "));
+    }
+
+    #[test]
+    fn json_iterators() {
+        assert_eq!(Ok(false), JsonLinesSource::iterates_array_items(&None));
+        assert_eq!(Ok(false), JsonLinesSource::iterates_array_items(&Some("$".to_string())));
+        assert_eq!(Ok(true), JsonLinesSource::iterates_array_items(&Some("$[*]".to_string())));
+        assert!(JsonLinesSource::iterates_array_items(&Some("$.people[*]".to_string())).is_err());
+    }
+}
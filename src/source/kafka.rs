@@ -0,0 +1,149 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::time::Duration;
+use log::warn;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::Message;
+use crate::source::messages::MessageFormat;
+use crate::source::Source;
+
+// The prefix of the keys in the configuration of a source that are passed on to the consumer, e.g. `kafka.security.protocol`
+const CLIENT_PROPERTY_PREFIX: &str = "kafka.";
+
+// How long a poll waits for a message before polling again
+const POLL_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Consumes the messages of a Kafka topic, as a member of a consumer group, and reads records from them. The
+/// topic is `topic`, the brokers to connect to first are `brokers`, e.g. `localhost:9092`, and the consumer group
+/// is `groupId`. A group that did not consume the topic before starts at its first message. Other properties of
+/// the consumer are set with the prefix `kafka.`, e.g. `kafka.auto.offset.reset` to `latest` to only read new
+/// messages. The fields are the references the plan uses. The source does not end, but waits for new messages.
+pub struct KafkaSource {
+    consumer: BaseConsumer,
+    topic: String,
+    format: MessageFormat,
+    fields: Vec<String>,
+    records: VecDeque<Vec<String>>
+}
+
+impl KafkaSource {
+    /// Creates the consumer of the source and subscribes to its topic. It connects to the brokers in the background.
+    pub fn connect(config: &HashMap<String, String>, format: MessageFormat, fields: Vec<String>) -> io::Result<Self> {
+        let (client_config, topic) = client_config(config)
+            .map_err(|msg| io::Error::new(io::ErrorKind::InvalidInput, msg))?;
+        let consumer: BaseConsumer = client_config.create().map_err(io::Error::other)?;
+        consumer.subscribe(&[&topic]).map_err(io::Error::other)?;
+        Ok(KafkaSource { consumer, topic, format, fields, records: VecDeque::new() })
+    }
+}
+
+impl Source for KafkaSource {
+    fn fields(&mut self) -> io::Result<Vec<String>> {
+        Ok(self.fields.clone())
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        loop {
+            if let Some(record) = self.records.pop_front() {
+                return Ok(Some(record));
+            }
+            match self.consumer.poll(POLL_TIMEOUT) {
+                None => {},
+                Some(Ok(message)) => if let Some(payload) = message.payload() {
+                    self.records.extend(self.format.records(payload, &self.fields)?);
+                },
+                // the consumer recovers from other errors itself, e.g. by connecting to another broker
+                Some(Err(err)) => match self.consumer.client().fatal_error() {
+                    Some((_, reason)) => return Err(io::Error::other(format!("Cannot consume topic {}: {reason}", self.topic))),
+                    None => warn!("Error consuming topic {}: {err}", self.topic)
+                }
+            }
+        }
+    }
+}
+
+/// The configuration of the consumer of the source, and the topic it subscribes to.
+pub fn client_config(config: &HashMap<String, String>) -> Result<(ClientConfig, String), String> {
+    let setting = |keys: &[&str]| keys.iter()
+        .find_map(|key| config.get(*key))
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| format!("Kafka source without {}", keys[0]));
+    let brokers = setting(&["brokers", "broker"])?;
+    let topic = setting(&["topic"])?;
+    let group_id = setting(&["groupId", "group_id"])?;
+    let mut client_config = ClientConfig::new();
+    client_config.set("bootstrap.servers", brokers)
+        .set("group.id", group_id)
+        .set("auto.offset.reset", "earliest");
+    for (key, value) in config {
+        if let Some(property) = key.strip_prefix(CLIENT_PROPERTY_PREFIX) {
+            client_config.set(property, value);
+        }
+    }
+    Ok((client_config, topic.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::producer::{BaseProducer, BaseRecord, Producer};
+    use super::*;
+
+    fn config(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn fields(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn client_configs() {
+        let (consumer_config, topic) = client_config(&config(&[("brokers", "localhost:9092"), ("topic", "people"), ("groupId", "mapper"), ("kafka.auto.offset.reset", "latest")])).unwrap();
+        assert_eq!("people", topic);
+        assert_eq!(Some("localhost:9092"), consumer_config.get("bootstrap.servers"));
+        assert_eq!(Some("mapper"), consumer_config.get("group.id"));
+        assert_eq!(Some("latest"), consumer_config.get("auto.offset.reset"));
+
+        assert_eq!(Some("earliest"), client_config(&config(&[("broker", "localhost:9092"), ("topic", "people"), ("group_id", "mapper")])).unwrap().0.get("auto.offset.reset"));
+        assert_eq!("Kafka source without brokers", client_config(&config(&[("topic", "people"), ("groupId", "mapper")])).unwrap_err());
+        assert_eq!("Kafka source without topic", client_config(&config(&[("brokers", "localhost:9092"), ("topic", ""), ("groupId", "mapper")])).unwrap_err());
+        assert_eq!("Kafka source without groupId", client_config(&config(&[("brokers", "localhost:9092"), ("topic", "people")])).unwrap_err());
+    }
+
+    #[test]
+    fn consume_topic() {
+        let cluster = MockCluster::new(1).unwrap();
+        cluster.create_topic("people", 1, 1).unwrap();
+        let producer: BaseProducer = ClientConfig::new()
+            .set("bootstrap.servers", cluster.bootstrap_servers())
+            .create().unwrap();
+        for payload in [r#"{"id": 1, "name": "Venus"}"#, r#"{"id": 2, "name": "Mars"}"#] {
+            producer.send(BaseRecord::<(), str>::to("people").payload(payload)).unwrap();
+        }
+        producer.flush(Duration::from_secs(10)).unwrap();
+
+        let source_config = config(&[("brokers", &cluster.bootstrap_servers()), ("topic", "people"), ("groupId", "mapper")]);
+        let mut source = KafkaSource::connect(&source_config, MessageFormat::Json { array_items: false }, fields(&["name"])).unwrap();
+        assert_eq!(fields(&["name"]), source.fields().unwrap());
+        assert_eq!(Some(fields(&["Venus"])), source.next_record().unwrap());
+        assert_eq!(Some(fields(&["Mars"])), source.next_record().unwrap());
+    }
+}
@@ -0,0 +1,329 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::thread;
+use std::time::Duration;
+use log::debug;
+use crate::rdf::{Statement, Term};
+use crate::shacl::turtle::parse_turtle_with_base;
+use crate::source::Source;
+
+/// The key of the built-in source that follows a Linked Data Event Stream, i.e. of sources with
+/// `"source": "LDES"` in their configuration.
+pub const LDES_SOURCE: &str = "LDES";
+
+/// The special field with the IRI of a member.
+pub const MEMBER_ID_FIELD: &str = "@id";
+
+const TREE: &str = "https://w3id.org/tree#";
+
+/// Reads the members of a Linked Data Event Stream (LDES) as records, following the pages of its TREE
+/// collection from `url`, the event stream or one of its pages. The pages are Turtle or N-Triples, and are
+/// fetched breadth-first; every member is read once, in the order it is found. The field `@id` is the IRI
+/// of a member, and the other fields are the values of the member for a property, given by its IRI or by
+/// its local name, e.g. `name` for `http://schema.org/name`: one value as it is, several as a JSON array,
+/// and none as an empty string. Requests get a header for every `header.NAME` in the configuration.
+///
+/// Without `pollInterval`, the source ends when all pages are read. With it, the pages that may still change,
+/// i.e. that are not cached as `immutable`, are fetched again every `pollInterval` seconds for new members and
+/// pages, and the source does not end.
+pub struct LdesSource {
+    agent: ureq::Agent,
+    headers: Vec<(String, String)>,
+    fields: Vec<String>,
+    poll_interval: Option<Duration>,
+    pages: VecDeque<String>,
+    fetched: HashSet<String>,
+    mutable_pages: Vec<String>,
+    members: HashSet<Term>,
+    records: VecDeque<Vec<String>>
+}
+
+impl LdesSource {
+    /// Fetches the first page of the event stream, so a stream that cannot be read fails right away.
+    pub fn connect(config: &HashMap<String, String>, fields: Vec<String>) -> io::Result<Self> {
+        let url = ldes_url(config)
+            .map_err(|msg| io::Error::new(io::ErrorKind::InvalidInput, msg))?;
+        let poll_interval = poll_interval(config)
+            .map_err(|msg| io::Error::new(io::ErrorKind::InvalidInput, msg))?;
+        let mut headers: Vec<(String, String)> = config.iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix("header.")?.to_string(), value.clone())))
+            .collect();
+        headers.sort();
+        let mut source = LdesSource {
+            agent: ureq::Agent::new(),
+            headers,
+            fields,
+            poll_interval,
+            pages: VecDeque::new(),
+            fetched: HashSet::new(),
+            mutable_pages: Vec::new(),
+            members: HashSet::new(),
+            records: VecDeque::new()
+        };
+        source.read_page(page_iri(url))?;
+        Ok(source)
+    }
+
+    // Fetches a page, and takes the members and links to other pages from it
+    fn read_page(&mut self, url: String) -> io::Result<()> {
+        debug!("Fetching LDES page {url}");
+        self.fetched.insert(url.clone());
+        let mut request = self.agent.get(&url).set("Accept", "text/turtle, application/n-triples;q=0.9");
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status, _response)) => return Err(io::Error::other(format!("{url} answered with status {status}"))),
+            Err(err) => return Err(io::Error::other(format!("Cannot fetch {url}: {err}")))
+        };
+        if response.content_type().contains("json") {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, format!("{url} is JSON-LD, but only Turtle and N-Triples pages can be read")));
+        }
+        let immutable = response.header("Cache-Control")
+            .is_some_and(|cache_control| cache_control.split(',').any(|directive| directive.trim() == "immutable"));
+        // relative IRIs are resolved against the page after redirects
+        let base = response.get_url().to_string();
+        let turtle = response.into_string()?;
+        let statements = parse_turtle_with_base(&turtle, &base)
+            .map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, format!("Cannot parse {url}: {msg}")))?;
+        match immutable {
+            true => self.mutable_pages.retain(|page| *page != url),
+            false if !self.mutable_pages.contains(&url) => self.mutable_pages.push(url),
+            false => {}
+        }
+
+        for statement in &statements {
+            let Term::Iri(predicate) = &statement.predicate else { continue };
+            match predicate.strip_prefix(TREE) {
+                Some("member") if self.members.insert(statement.object.clone()) => {
+                    let record = self.record(&statement.object, &statements);
+                    self.records.push_back(record);
+                },
+                Some("view") => self.follow(&statement.object),
+                Some("relation") => {
+                    let nodes: Vec<Term> = statements.iter()
+                        .filter(|node| node.subject == statement.object && node.predicate == Term::Iri(format!("{TREE}node")))
+                        .map(|node| node.object.clone())
+                        .collect();
+                    nodes.iter().for_each(|node| self.follow(node));
+                },
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Queues a page that is not fetched yet
+    fn follow(&mut self, page: &Term) {
+        if let Term::Iri(iri) = page {
+            let url = page_iri(iri);
+            if !self.fetched.contains(&url) && !self.pages.contains(&url) {
+                self.pages.push_back(url);
+            }
+        }
+    }
+
+    // The values of the fields for a member, from the statements about it on the page
+    fn record(&self, member: &Term, statements: &[Statement]) -> Vec<String> {
+        self.fields.iter()
+            .map(|field| match (field.as_str(), member) {
+                (MEMBER_ID_FIELD, Term::Iri(id) | Term::BlankNode(id)) => id.clone(),
+                _ => {
+                    let values: Vec<&str> = statements.iter()
+                        .filter(|statement| statement.subject == *member && matches!(&statement.predicate, Term::Iri(predicate) if is_property(predicate, field)))
+                        .map(|statement| lexical_form(&statement.object))
+                        .collect();
+                    match values.as_slice() {
+                        [] => String::new(),
+                        [value] => value.to_string(),
+                        _ => serde_json::to_string(&values).unwrap()
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+impl Source for LdesSource {
+    fn fields(&mut self) -> io::Result<Vec<String>> {
+        Ok(self.fields.clone())
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        loop {
+            if let Some(record) = self.records.pop_front() {
+                return Ok(Some(record));
+            }
+            if let Some(page) = self.pages.pop_front() {
+                // a page that cannot be read is skipped, unless the run is strict
+                self.read_page(page)?;
+                continue;
+            }
+            let Some(poll_interval) = self.poll_interval else {
+                return Ok(None);
+            };
+            thread::sleep(poll_interval);
+            // immutable pages are not fetched again
+            self.pages.extend(self.mutable_pages.iter().cloned());
+        }
+    }
+}
+
+/// The URL of the event stream of the source.
+pub fn ldes_url(config: &HashMap<String, String>) -> Result<&String, String> {
+    config.get("url")
+        .filter(|url| !url.is_empty())
+        .ok_or_else(|| "LDES source without url".to_string())
+}
+
+/// The time between fetching the pages of the source again, if they are polled.
+pub fn poll_interval(config: &HashMap<String, String>) -> Result<Option<Duration>, String> {
+    match config.get("pollInterval") {
+        None => Ok(None),
+        Some(seconds) => match seconds.parse::<f64>() {
+            Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(Some(Duration::from_secs_f64(seconds))),
+            _ => Err(format!("The pollInterval of an LDES source must be a positive number of seconds, not {seconds}"))
+        }
+    }
+}
+
+// The page an IRI is on, i.e. the IRI without its fragment
+fn page_iri(iri: &str) -> String {
+    iri.split('#').next().unwrap_or_default().to_string()
+}
+
+// Whether the field names the property, by its IRI or by its local name
+fn is_property(predicate: &str, field: &str) -> bool {
+    predicate == field || predicate.rsplit(['#', '/']).next() == Some(field)
+}
+
+fn lexical_form(term: &Term) -> &str {
+    match term {
+        Term::Iri(value) | Term::BlankNode(value) => value,
+        Term::Literal { value, .. } => value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use super::*;
+
+    type Page = (&'static str, &'static str, String);
+
+    // Serves the page for the path and the number of times it was requested before, with its content type
+    // and cache control, and returns the URL of the server and the paths requested from it
+    fn serve(pages: impl Fn(&str, usize) -> Option<Page> + Send + 'static) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requested = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream: TcpStream = stream.unwrap();
+                let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+                let request_line = lines.next().unwrap().unwrap();
+                while !lines.next().unwrap().unwrap().is_empty() {}
+                let path = request_line.split(' ').nth(1).unwrap().to_string();
+                let times = requested.lock().unwrap().iter().filter(|request| **request == path).count();
+                requested.lock().unwrap().push(path.clone());
+                let response = match pages(&path, times) {
+                    Some((content_type, cache_control, body)) => format!("HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nCache-Control: {cache_control}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len()),
+                    None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    fn turtle(body: &str) -> Option<Page> {
+        Some(("text/turtle", "max-age=60", format!("@prefix tree: <https://w3id.org/tree#> .\n@prefix schema: <http://schema.org/> .\n{body}")))
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn follow_pages() {
+        let (url, requests) = serve(|path, _times| match path {
+            "/people" => turtle("<> tree:view <people/1> ."),
+            "/people/1" => turtle(r#"
+                </people> tree:member <#venus>, <#mars> .
+                <#venus> schema:name "Venus" ; schema:knows <#mars>, <#jupiter> .
+                <#mars> schema:name "Mars"@en .
+                <> tree:relation [ a tree:GreaterThanRelation ; tree:node <2> ] ."#),
+            "/people/2" => turtle(r#"
+                </people> tree:member </people/1#mars>, <#saturn> .
+                <#saturn> schema:name "Saturn" .
+                <> tree:relation [ tree:node <1> ], [ tree:node <3> ] ."#),
+            _ => None
+        });
+        let config = HashMap::from([("url".to_string(), format!("{url}/people"))]);
+        let mut source = LdesSource::connect(&config, strings(&["@id", "http://schema.org/knows", "name"])).unwrap();
+        assert_eq!(strings(&["@id", "http://schema.org/knows", "name"]), source.fields().unwrap());
+        assert_eq!(vec![
+            strings(&[&format!("{url}/people/1#venus"), &format!(r#"["{url}/people/1#mars","{url}/people/1#jupiter"]"#), "Venus"]),
+            strings(&[&format!("{url}/people/1#mars"), "", "Mars"]),
+            strings(&[&format!("{url}/people/2#saturn"), "", "Saturn"])
+        ], (0..3).map(|_| source.next_record().unwrap().unwrap()).collect::<Vec<_>>());
+        // the page that is not found is skipped
+        assert_eq!(format!("{url}/people/3 answered with status 404"), source.next_record().unwrap_err().to_string());
+        assert_eq!(None, source.next_record().unwrap());
+        assert_eq!(vec!["/people", "/people/1", "/people/2", "/people/3"], *requests.lock().unwrap());
+    }
+
+    #[test]
+    fn poll_pages() {
+        let (url, requests) = serve(|path, times| match (path, times) {
+            ("/feed", _) => Some(("text/turtle", "public, immutable", r#"
+                <> <https://w3id.org/tree#member> <#1> ; <https://w3id.org/tree#relation> [ <https://w3id.org/tree#node> <latest> ] .
+                <#1> <http://schema.org/name> "first" ."#.to_string())),
+            ("/latest", 0) => turtle(r#"<feed> tree:member <#2> . <#2> schema:name "second" ."#),
+            ("/latest", _) => turtle(r#"<feed> tree:member <#2>, <#3> . <#2> schema:name "second" . <#3> schema:name "third" ."#),
+            _ => None
+        });
+        let config = HashMap::from([("url".to_string(), format!("{url}/feed")), ("pollInterval".to_string(), "0.05".to_string())]);
+        let mut source = LdesSource::connect(&config, strings(&["name"])).unwrap();
+        assert_eq!(Some(strings(&["first"])), source.next_record().unwrap());
+        assert_eq!(Some(strings(&["second"])), source.next_record().unwrap());
+        assert_eq!(Some(strings(&["third"])), source.next_record().unwrap());
+        assert_eq!(vec!["/feed", "/latest", "/latest"], *requests.lock().unwrap());
+    }
+
+    #[test]
+    fn stream_errors() {
+        let (url, _requests) = serve(|path, _times| match path {
+            "/json" => Some(("application/ld+json", "no-cache", "{}".to_string())),
+            "/invalid" => turtle("<a> <b> ."),
+            _ => None
+        });
+        let connect = |config: &[(&str, String)]| LdesSource::connect(&config.iter().map(|(key, value)| (key.to_string(), value.clone())).collect(), Vec::new())
+            .err().unwrap().to_string();
+        assert_eq!("LDES source without url", connect(&[]));
+        assert_eq!("The pollInterval of an LDES source must be a positive number of seconds, not 0", connect(&[("url", url.clone()), ("pollInterval", "0".to_string())]));
+        assert_eq!(format!("{url}/missing answered with status 404"), connect(&[("url", format!("{url}/missing"))]));
+        assert_eq!(format!("{url}/json is JSON-LD, but only Turtle and N-Triples pages can be read"), connect(&[("url", format!("{url}/json"))]));
+        assert!(connect(&[("url", format!("{url}/invalid#stream"))]).starts_with(&format!("Cannot parse {url}/invalid: ")));
+    }
+}
@@ -0,0 +1,390 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use flate2::read::DeflateDecoder;
+use iri_string::types::{UriAbsoluteStr, UriReferenceStr};
+use zip::{CompressionMethod, ZipArchive};
+use crate::source::file_server;
+use crate::source::file_server::{server_file, Login};
+
+/// Where the file a source reads is: on the local file system, on a web server, when the working
+/// directory hint is the URL of a remote mapping document, on an FTP or SFTP server, for a URL like
+/// `sftp://files.example.com/people.csv`, in a local zip archive, for a path like
+/// `archive.zip!data/people.csv`, or standard input, for the path `-`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileLocation {
+    Local(PathBuf),
+    Remote(String),
+    /// A file on an FTP or SFTP server, with the URL without the login.
+    Server { url: String, login: Login },
+    ZipMember { archive: PathBuf, member: String },
+    StdIn
+}
+
+/// The path of a file source that reads standard input.
+pub const STD_IN_PATH: &str = "-";
+
+impl FileLocation {
+    /// Opens the file for reading, with its size if it is known.
+    pub fn open(&self) -> io::Result<(Box<dyn Read + Send>, Option<u64>)> {
+        match self {
+            FileLocation::Local(path) => {
+                let file = File::open(path)?;
+                let size = file.metadata().ok().map(|metadata| metadata.len());
+                Ok((Box::new(file), size))
+            },
+            FileLocation::Remote(url) => {
+                let response = ureq::get(url).call().map_err(io::Error::other)?;
+                let size = response.header("Content-Length").and_then(|length| length.parse().ok());
+                Ok((Box::new(response.into_reader()), size))
+            },
+            FileLocation::Server { url, login } => file_server::open(url, login),
+            // the member is read straight from the archive, without unpacking it
+            FileLocation::ZipMember { archive, member } => {
+                let mut zip_archive = ZipArchive::new(File::open(archive)?).map_err(io::Error::other)?;
+                let (compression, data_start, compressed_size, size) = {
+                    let entry = zip_archive.by_name(member).map_err(io::Error::other)?;
+                    (entry.compression(), entry.data_start(), entry.compressed_size(), entry.size())
+                };
+                let mut file = zip_archive.into_inner();
+                file.seek(SeekFrom::Start(data_start))?;
+                let data = file.take(compressed_size);
+                let reader: Box<dyn Read + Send> = match compression {
+                    CompressionMethod::Stored => Box::new(data),
+                    CompressionMethod::Deflated => Box::new(DeflateDecoder::new(data)),
+                    other => return Err(io::Error::new(io::ErrorKind::Unsupported, format!("{self} is compressed with {other}, which is not supported")))
+                };
+                Ok((reader, Some(size)))
+            },
+            FileLocation::StdIn => Ok((Box::new(io::stdin()), None))
+        }
+    }
+
+    /// The location with the login of a file on a server replaced by what is set in the configuration of its
+    /// source, see [Login::with_config].
+    pub fn with_login(self, config: &HashMap<String, String>) -> Self {
+        match self {
+            FileLocation::Server { url, login } => FileLocation::Server { url, login: login.with_config(config) },
+            other => other
+        }
+    }
+
+    /// The name of the file, i.e. the last segment of its path.
+    pub fn file_name(&self) -> Option<String> {
+        match self {
+            FileLocation::Local(path) => path.file_name().map(|file_name| file_name.to_string_lossy().to_string()),
+            FileLocation::Remote(url) | FileLocation::Server { url, .. } => url.split(['?', '#']).next()
+                .and_then(|url| url.rsplit('/').next())
+                .filter(|file_name| !file_name.is_empty())
+                .map(percent_decode),
+            FileLocation::ZipMember { member, .. } => member.rsplit('/').next()
+                .filter(|file_name| !file_name.is_empty())
+                .map(String::from),
+            FileLocation::StdIn => None
+        }
+    }
+}
+
+impl Display for FileLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileLocation::Local(path) => write!(f, "{}", path.display()),
+            FileLocation::Remote(url) | FileLocation::Server { url, .. } => f.write_str(url),
+            FileLocation::ZipMember { archive, member } => write!(f, "{}!{member}", archive.display()),
+            FileLocation::StdIn => f.write_str(STD_IN_PATH)
+        }
+    }
+}
+
+/// Finds the file with the given path or `file://` URI, as it is or relative to the working directory hint,
+/// which can be a directory, a `file://` URI or the `http(s)://` URL of a remote directory. Windows and UNC
+/// paths are absolute. Remote files, also those with an `ftp://` or `sftp://` URL, are assumed to exist. A path
+/// like `archive.zip!data/people.csv` is the member `data/people.csv` of a local zip archive, which is found like
+/// any other file. If the file is not found, returns all locations where it was looked for. The path `-` is
+/// standard input.
+pub fn find_file(file: &str, working_dir_hint: &Option<String>) -> Result<FileLocation, Vec<String>> {
+    if file == STD_IN_PATH {
+        return Ok(FileLocation::StdIn);
+    }
+    if let Some((archive, member)) = zip_member(file) {
+        return match find_file(archive, working_dir_hint)? {
+            FileLocation::Local(archive) if has_member(&archive, &member) => Ok(FileLocation::ZipMember { archive, member }),
+            FileLocation::Local(archive) => Err(vec![format!("{}!{member}", archive.display())]),
+            other => Err(vec![format!("{other}!{member}, but only local zip archives can be read")])
+        };
+    }
+    if is_remote(file) {
+        return Ok(FileLocation::Remote(file.to_string()));
+    }
+    if let Some(server_location) = server_file(file) {
+        return Ok(server_location);
+    }
+    let file_path = local_path(file);
+    let mut attempted = vec![file_path.display().to_string()];
+    if file_path.exists() {
+        return Ok(FileLocation::Local(file_path));
+    }
+    if is_absolute(&file_path) {
+        return Err(attempted);
+    }
+    if let Some(working_dir) = working_dir_hint {
+        if is_remote(working_dir) {
+            // a relative path of a file is a relative URL of a remote file
+            let reference = file.strip_prefix("file:").unwrap_or(file).replace('\\', "/").replace(' ', "%20");
+            match resolve_url(working_dir, &reference) {
+                Some(url) => return Ok(FileLocation::Remote(url)),
+                None => attempted.push(format!("{working_dir} + {file}"))
+            }
+        } else {
+            let new_path = local_path(working_dir).join(&file_path);
+            attempted.push(new_path.display().to_string());
+            if new_path.exists() {
+                return Ok(FileLocation::Local(new_path));
+            }
+        }
+    }
+    Err(attempted)
+}
+
+/// Finds the files with the given path: the file found by [find_file], or else the local files that match the
+/// path as a glob pattern, like `data/*.csv` or `logs/**/day-?.csv`, in the order of their paths. If no file is
+/// found, returns all locations where it was looked for.
+pub fn find_files(path: &str, working_dir_hint: &Option<String>) -> Result<Vec<FileLocation>, Vec<String>> {
+    let attempted = match find_file(path, working_dir_hint) {
+        Ok(file_location) => return Ok(vec![file_location]),
+        Err(attempted) => attempted
+    };
+    match matching_pattern(path, working_dir_hint).map(|pattern| glob_files(&pattern)) {
+        Some(files) if !files.is_empty() => Ok(files.into_iter().map(FileLocation::Local).collect()),
+        _ => Err(attempted)
+    }
+}
+
+/// The glob pattern of the path, as it is or relative to the working directory hint, if it matches local files.
+pub fn matching_pattern(path: &str, working_dir_hint: &Option<String>) -> Option<String> {
+    if !path.contains(['*', '?', '[']) || is_remote(path) {
+        return None;
+    }
+    let pattern = local_path(path);
+    let relative_pattern = local_working_dir(working_dir_hint)
+        .filter(|_| !is_absolute(&pattern))
+        .map(|working_dir| Path::new(&glob::Pattern::escape(&working_dir.display().to_string())).join(&pattern));
+    [Some(pattern), relative_pattern].into_iter().flatten()
+        .map(|pattern| pattern.display().to_string())
+        .find(|pattern| !glob_files(pattern).is_empty())
+}
+
+// The files that match the glob pattern, sorted
+fn glob_files(pattern: &str) -> Vec<PathBuf> {
+    let Ok(paths) = glob::glob(pattern) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = paths.flatten()
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    files
+}
+
+/// The local directory that relative paths are relative to, if the working directory hint is one.
+pub fn local_working_dir(working_dir_hint: &Option<String>) -> Option<PathBuf> {
+    working_dir_hint.as_ref()
+        .filter(|working_dir| !is_remote(working_dir))
+        .map(|working_dir| local_path(working_dir))
+}
+
+// The path of the zip archive and the name of the member in it, for a path like `archive.zip!data/people.csv`
+fn zip_member(file: &str) -> Option<(&str, String)> {
+    let separator = file.match_indices('!')
+        .map(|(index, _)| index)
+        .find(|index| file[..*index].to_ascii_lowercase().ends_with(".zip"))?;
+    // members are separated by slashes, also when written like `archive.zip!/data\people.csv`
+    let member = file[separator + 1..].replace('\\', "/").trim_start_matches('/').to_string();
+    Some((&file[..separator], member))
+}
+
+fn has_member(archive: &Path, member: &str) -> bool {
+    File::open(archive).ok()
+        .and_then(|file| ZipArchive::new(file).ok())
+        .is_some_and(|zip_archive| zip_archive.index_for_name(member).is_some())
+}
+
+fn is_remote(location: &str) -> bool {
+    let scheme = location.split_once("://").map(|(scheme, _rest)| scheme.to_ascii_lowercase());
+    matches!(scheme.as_deref(), Some("http" | "https"))
+}
+
+// Windows paths like C:\data or \\server\share\data are absolute, also when not on Windows
+fn is_absolute(path: &Path) -> bool {
+    let path_str = path.to_string_lossy();
+    let bytes = path_str.as_bytes();
+    path.is_absolute()
+        || path_str.starts_with("\\\\")
+        || path_str.starts_with("//")
+        || (bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && (bytes[2] == b'\\' || bytes[2] == b'/'))
+}
+
+// The path of a `file://` URI (decoded, and as a UNC path if it has a host), or the path as it is
+fn local_path(location: &str) -> PathBuf {
+    let Some(rest) = location.strip_prefix("file://") else {
+        return PathBuf::from(location.strip_prefix("file:").unwrap_or(location));
+    };
+    let path = percent_decode(rest);
+    let path = match path.split_once('/') {
+        // file:///data or file://localhost/data
+        Some(("" | "localhost", path)) => format!("/{path}"),
+        // file://server/share/data
+        Some(_) => format!("//{path}"),
+        None => path
+    };
+    // file:///C:/data
+    match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => PathBuf::from(&path[1..]),
+        _ => PathBuf::from(path)
+    }
+}
+
+pub(crate) fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes.get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+// The relative URL resolved against the URL of a directory
+fn resolve_url(base: &str, reference: &str) -> Option<String> {
+    let base = match base.ends_with('/') {
+        true => base.to_string(),
+        false => format!("{base}/")
+    };
+    let base = UriAbsoluteStr::new(&base).ok()?;
+    let reference = UriReferenceStr::new(reference).ok()?;
+    Some(reference.resolve_against(base).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    #[test]
+    fn file_uris() {
+        assert_eq!(PathBuf::from("/data/my people.csv"), local_path("file:///data/my%20people.csv"));
+        assert_eq!(PathBuf::from("/data/people.csv"), local_path("file://localhost/data/people.csv"));
+        assert_eq!(PathBuf::from("C:/data/people.csv"), local_path("file:///C:/data/people.csv"));
+        assert_eq!(PathBuf::from("//server/share/people.csv"), local_path("file://server/share/people.csv"));
+        assert_eq!(PathBuf::from("data/people.csv"), local_path("file:data/people.csv"));
+        assert_eq!(PathBuf::from("data/people.csv"), local_path("data/people.csv"));
+    }
+
+    #[test]
+    fn windows_paths() {
+        assert!(is_absolute(Path::new("C:\\data\\people.csv")));
+        assert!(is_absolute(Path::new("c:/data/people.csv")));
+        assert!(is_absolute(Path::new("\\\\server\\share\\people.csv")));
+        assert!(!is_absolute(Path::new("data\\people.csv")));
+        // not looked for relative to the working directory
+        assert_eq!(Err(vec!["C:\\data\\people.csv".to_string()]), find_file("C:\\data\\people.csv", &Some("test-resources".to_string())));
+    }
+
+    #[test]
+    fn files_found() {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let expected = FileLocation::Local(Path::new(test_dir).join("sport.csv"));
+        assert_eq!(Ok(expected.clone()), find_file("sport.csv", &Some(test_dir.to_string())));
+        let working_dir = format!("file://{}", std::path::absolute(test_dir).unwrap().display());
+        let found = find_file("file:sport.csv", &Some(working_dir.clone()));
+        assert!(matches!(found, Ok(FileLocation::Local(path)) if path.ends_with("RMLTC1036-CSV/sport.csv")));
+        assert_eq!(Ok(expected), find_file(&format!("{test_dir}/sport.csv"), &None));
+
+        let attempted = find_file("sports.csv", &Some(test_dir.to_string())).unwrap_err();
+        assert_eq!(vec!["sports.csv".to_string(), format!("{test_dir}/sports.csv")], attempted);
+    }
+
+    #[test]
+    fn glob_patterns() {
+        let test_dir = "test-resources/tests/glob";
+        let files = find_files("sport-*.csv", &Some(test_dir.to_string())).unwrap();
+        let expected: Vec<FileLocation> = ["sport-1.csv", "sport-2.csv", "sport-3.csv"].iter()
+            .map(|file| FileLocation::Local(Path::new(test_dir).join(file)))
+            .collect();
+        assert_eq!(expected, files);
+        assert_eq!(Ok(expected[1..].to_vec()), find_files(&format!("{test_dir}/sport-[23].csv"), &None));
+        assert_eq!(Some(format!("{test_dir}/sport-?.csv")), matching_pattern("sport-?.csv", &Some(test_dir.to_string())));
+
+        // a file is found as it is, and a pattern that matches nothing is not found
+        assert_eq!(Ok(vec![expected[0].clone()]), find_files("sport-1.csv", &Some(test_dir.to_string())));
+        assert_eq!(Err(vec!["sport-*.tsv".to_string(), format!("{test_dir}/sport-*.tsv")]), find_files("sport-*.tsv", &Some(test_dir.to_string())));
+        assert_eq!(None, matching_pattern("https://example.com/sport-*.csv", &None));
+    }
+
+    #[test]
+    fn remote_files() {
+        let working_dir = Some("https://example.com/mappings".to_string());
+        assert_eq!(Ok(FileLocation::Remote("https://example.com/mappings/data/my%20people.csv".to_string())), find_file("data/my people.csv", &working_dir));
+        assert_eq!(Ok(FileLocation::Remote("https://example.com/data/people.csv".to_string())), find_file("../data/people.csv", &working_dir));
+        assert_eq!(Ok(FileLocation::Remote("http://example.org/people.csv".to_string())), find_file("http://example.org/people.csv", &None));
+        assert_eq!(None, local_working_dir(&working_dir));
+        assert_eq!(Some(PathBuf::from("/data")), local_working_dir(&Some("file:///data".to_string())));
+    }
+
+    #[test]
+    fn zip_members() {
+        assert_eq!(Some(("data/archive.zip", "people.csv".to_string())), zip_member("data/archive.zip!people.csv"));
+        assert_eq!(Some(("archive.ZIP", "data/people.csv".to_string())), zip_member("archive.ZIP!/data\\people.csv"));
+        assert_eq!(None, zip_member("news!.csv"));
+
+        let working_dir_hint = Some("test-resources/tests/zip".to_string());
+        let location = find_file("people.zip!data/people.csv", &working_dir_hint).unwrap();
+        let archive = PathBuf::from("test-resources/tests/zip/people.zip");
+        assert_eq!(FileLocation::ZipMember { archive: archive.clone(), member: "data/people.csv".to_string() }, location);
+        assert_eq!(Some("people.csv".to_string()), location.file_name());
+        assert_eq!("test-resources/tests/zip/people.zip!data/people.csv", location.to_string());
+        assert_eq!(Err(vec!["test-resources/tests/zip/people.zip!data/missing.csv".to_string()]),
+            find_file("people.zip!data/missing.csv", &working_dir_hint));
+        assert!(find_file("missing.zip!data/people.csv", &working_dir_hint).is_err());
+
+        // deflated and stored members
+        let expected = fs::read_to_string("test-resources/tests/blank-nodes/people.csv").unwrap();
+        for member in ["data/people.csv", "stored/people.csv"] {
+            let (mut reader, size) = FileLocation::ZipMember { archive: archive.clone(), member: member.to_string() }.open().unwrap();
+            let mut content = String::new();
+            reader.read_to_string(&mut content).unwrap();
+            assert_eq!(expected, content);
+            assert_eq!(Some(expected.len() as u64), size);
+        }
+    }
+}
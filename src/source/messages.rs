@@ -0,0 +1,165 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::io;
+use operator::formats::ReferenceFormulation;
+use operator::{IOType, Source as SourceConfig};
+use serde_json::Value;
+use crate::source::csv_file::CsvDialect;
+#[cfg(feature = "kafka")]
+use crate::source::kafka::KafkaSource;
+use crate::source::json_lines::JsonLinesSource;
+use crate::source::websocket::WebSocketSource;
+use crate::source::{flattened_value, Source};
+
+/// How the messages of a stream, e.g. a Kafka topic or a WebSocket, are read as records.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MessageFormat {
+    /// Every message is CSV in the dialect. With `columns`, every row is a record with these columns, e.g. a
+    /// message of one line; without, the first row of every message is its header, and every row after it a record.
+    Csv { dialect: CsvDialect, columns: Option<Vec<String>> },
+    /// Every message holds one or more JSON values, and every value is a record, or every item of a value that
+    /// is an array if `array_items`.
+    Json { array_items: bool }
+}
+
+impl MessageFormat {
+    /// The format of the messages of the source, by its reference formulation: CSV for `CSVRows`, in the dialect
+    /// of its configuration with what is set in `csv_overrides` replaced, and with the columns of `columns`, a CSV
+    /// row like `id,name`, if it is given; JSON for `JSONPath`.
+    pub fn of(source: &SourceConfig, csv_overrides: &CsvDialect) -> Result<Self, String> {
+        match &source.root_iterator.reference_formulation {
+            ReferenceFormulation::CSVRows => {
+                let dialect = CsvDialect::from_config(&source.config)?.with_overrides(csv_overrides);
+                let columns = match source.config.get("columns") {
+                    Some(columns) => dialect.delimited_reader_builder(b',').from_reader(columns.as_bytes()).into_records().next()
+                        .transpose()
+                        .map_err(|err| format!("Invalid columns: {err}"))?
+                        .map(|columns| columns.iter().map(String::from).collect()),
+                    None => None
+                };
+                Ok(MessageFormat::Csv { dialect, columns })
+            },
+            ReferenceFormulation::JSONPath => Ok(MessageFormat::Json { array_items: JsonLinesSource::iterates_array_items(&source.root_iterator.reference)? }),
+            other => Err(format!("reference formulation {other:?} is not supported for messages"))
+        }
+    }
+
+    /// The records in a message, with the values of the fields. Fields that are not in the message have no value.
+    pub fn records(&self, message: &[u8], fields: &[String]) -> io::Result<Vec<Vec<String>>> {
+        let mut records = Vec::new();
+        match self {
+            MessageFormat::Csv { dialect, columns } => {
+                let invalid = |err: csv::Error| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid CSV message: {err}"));
+                let mut rows = dialect.delimited_reader_builder(b',').from_reader(message).into_records();
+                let header_row: Vec<String>;
+                let header = match columns {
+                    Some(columns) => columns,
+                    None => match rows.next().transpose().map_err(invalid)? {
+                        Some(header) => {
+                            header_row = header.iter().map(String::from).collect();
+                            &header_row
+                        },
+                        None => return Ok(records)
+                    }
+                };
+                let indexes: Vec<Option<usize>> = fields.iter()
+                    .map(|field| header.iter().position(|name| name == field))
+                    .collect();
+                for row in rows {
+                    let row = row.map_err(invalid)?;
+                    records.push(indexes.iter()
+                        .map(|index| index.and_then(|index| row.get(index)).unwrap_or_default().to_string())
+                        .collect());
+                }
+            },
+            MessageFormat::Json { array_items } => {
+                let json_record = |value: &Value| fields.iter().map(|field| flattened_value(value, field)).collect();
+                for value in serde_json::Deserializer::from_slice(message).into_iter::<Value>() {
+                    match value.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid JSON message: {err}")))? {
+                        Value::Array(items) if *array_items => records.extend(items.iter().map(json_record)),
+                        value => records.push(json_record(&value))
+                    }
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Connects to the stream of the source, a Kafka topic when built with the `kafka` feature or a WebSocket, to read
+/// records with the fields from its messages.
+pub(crate) fn connect_stream(source: &SourceConfig, format: MessageFormat, fields: Vec<String>) -> io::Result<Box<dyn Source>> {
+    match source.source_type {
+        #[cfg(feature = "kafka")]
+        IOType::Kafka => Ok(Box::new(KafkaSource::connect(&source.config, format, fields)?)),
+        IOType::Websocket => Ok(Box::new(WebSocketSource::connect(&source.config, format, fields)?)),
+        ref other => Err(io::Error::new(io::ErrorKind::Unsupported, format!("source type {other:?} is not supported")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn fields(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn csv_messages() {
+        let format = MessageFormat::Csv { dialect: CsvDialect::default(), columns: None };
+        let records = format.records(b"id,name\n1,Venus\n2,\"Mars, the red\"\n", &fields(&["name", "age", "id"])).unwrap();
+        assert_eq!(vec![vec!["Venus", "", "1"], vec!["Mars, the red", "", "2"]], records);
+        assert!(format.records(b"", &fields(&["name"])).unwrap().is_empty());
+        assert!(format.records(b"id,name\n1,Venus,Mars\n", &fields(&["name"])).is_err());
+
+        let format = MessageFormat::Csv { dialect: CsvDialect { delimiter: Some(b';'), ..Default::default() }, columns: Some(fields(&["id", "name"])) };
+        assert_eq!(vec![vec!["Venus"]], format.records(b"1;Venus", &fields(&["name"])).unwrap());
+    }
+
+    #[test]
+    fn json_messages() {
+        let message = br#"{"id": 1, "name": "Venus", "moons": []} {"id": 2, "name": {"first": "Mars"}}"#;
+        let records = MessageFormat::Json { array_items: false }.records(message, &fields(&["id", "name.first"])).unwrap();
+        assert_eq!(vec![vec!["1", ""], vec!["2", "Mars"]], records);
+
+        let message = br#"[{"id": 1}, {"id": 2, "name": null}]"#;
+        let records = MessageFormat::Json { array_items: true }.records(message, &fields(&["id", "name"])).unwrap();
+        assert_eq!(vec![vec!["1", ""], vec!["2", ""]], records);
+        assert!(MessageFormat::Json { array_items: false }.records(b"{\"id\": ", &fields(&["id"])).is_err());
+    }
+
+    #[test]
+    fn message_formats() {
+        let config = HashMap::from([("delimiter".to_string(), ";".to_string()), ("columns".to_string(), "id;name".to_string())]);
+        let mut source = SourceConfig { config, source_type: IOType::Websocket, root_iterator: Default::default() };
+        source.root_iterator.reference_formulation = ReferenceFormulation::CSVRows;
+        let overrides = CsvDialect { quote: Some(b'\''), ..Default::default() };
+        let dialect = CsvDialect { delimiter: Some(b';'), quote: Some(b'\''), ..Default::default() };
+        assert_eq!(MessageFormat::Csv { dialect, columns: Some(fields(&["id", "name"])) }, MessageFormat::of(&source, &overrides).unwrap());
+
+        source.root_iterator.reference_formulation = ReferenceFormulation::JSONPath;
+        source.root_iterator.reference = Some("$[*]".to_string());
+        assert_eq!(MessageFormat::Json { array_items: true }, MessageFormat::of(&source, &overrides).unwrap());
+        source.root_iterator.reference = Some("$.people[*]".to_string());
+        assert!(MessageFormat::of(&source, &overrides).is_err());
+
+        source.root_iterator.reference_formulation = ReferenceFormulation::XMLPath;
+        assert!(MessageFormat::of(&source, &overrides).is_err());
+    }
+}
@@ -14,4 +14,375 @@
  *    limitations under the License.
  */
 
-pub mod csv_file;
\ No newline at end of file
+//! Sources read the records of the plan. Besides the built-in ones, library callers can register
+//! their own [Source]s with [crate::mopper_options::MopperOptionsBuilder::source], to feed records
+//! from systems mopper does not know about, e.g. in-memory tables or streams.
+
+pub mod avro;
+pub mod compression;
+pub mod csv_file;
+pub mod custom_source;
+#[cfg(feature = "duckdb")]
+pub mod duckdb;
+pub mod file_server;
+pub mod json_lines;
+pub mod ldes;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod location;
+pub mod messages;
+#[cfg(feature = "odbc")]
+pub mod odbc;
+pub mod postgres;
+pub mod rate_limit;
+pub mod websocket;
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::sync::{Arc, Condvar, LazyLock, Mutex};
+use std::vec::IntoIter;
+use log::warn;
+use operator::Source as SourceConfig;
+use serde_json::Value;
+use crate::source::postgres::{PostgresSource, POSTGRES_SOURCE};
+
+/// The special reference to the number of a record in its source, from 1 for the first record (after the
+/// header of a CSV file), e.g. for identifiers that can be traced back to the source.
+pub const RECORD_NUMBER_REFERENCE: &str = "_rownum";
+
+/// The special reference to the name of the file a record is read from.
+pub const FILE_NAME_REFERENCE: &str = "_filename";
+
+/// Where the records of a source come from. Every record has a value for every field.
+pub trait Source: Send {
+    /// The names of the fields of the records, in the order of their values. Called once, before
+    /// the first record is taken.
+    fn fields(&mut self) -> io::Result<Vec<String>>;
+
+    /// The next record, or `None` if there are no more records. A record that cannot be read is skipped
+    /// or stops the run, depending on the [crate::mopper_options::ErrorMode].
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>>;
+}
+
+/// A source of records that are already in memory.
+pub struct TableSource {
+    fields: Vec<String>,
+    records: IntoIter<Vec<String>>
+}
+
+impl TableSource {
+    pub fn new(fields: Vec<String>, records: Vec<Vec<String>>) -> Self {
+        TableSource { fields, records: records.into_iter() }
+    }
+}
+
+impl Source for TableSource {
+    fn fields(&mut self) -> io::Result<Vec<String>> {
+        Ok(self.fields.clone())
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        Ok(self.records.next())
+    }
+}
+
+type CreateSource = dyn Fn(&SourceConfig) -> io::Result<Box<dyn Source>> + Send + Sync;
+
+/// Creates a source, from the source in the plan. A factory is called once per source node.
+#[derive(Clone)]
+pub struct SourceFactory(Arc<CreateSource>);
+
+impl SourceFactory {
+    pub fn new(create: impl Fn(&SourceConfig) -> io::Result<Box<dyn Source>> + Send + Sync + 'static) -> Self {
+        SourceFactory(Arc::new(create))
+    }
+
+    pub fn create(&self, source: &SourceConfig) -> io::Result<Box<dyn Source>> {
+        (self.0)(source)
+    }
+}
+
+impl Debug for SourceFactory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SourceFactory")
+    }
+}
+
+/// The key a source factory is registered with for the source: the value of `source` in its configuration,
+/// e.g. the name of a custom source type, or else its source type, e.g. `Kafka`.
+pub fn source_key(source: &SourceConfig) -> String {
+    match source.config.get("source") {
+        Some(key) => key.clone(),
+        None => format!("{:?}", source.source_type)
+    }
+}
+
+/// The factory of the built-in source for the source, if there is one, i.e. for PostgreSQL databases, for
+/// databases with an ODBC driver when built with the `odbc` feature, and for DuckDB queries when built with
+/// the `duckdb` feature.
+pub(crate) fn builtin_source_factory(source: &SourceConfig) -> Option<&'static SourceFactory> {
+    static POSTGRES: LazyLock<SourceFactory> = LazyLock::new(|| SourceFactory::new(|source| Ok(Box::new(PostgresSource::connect(source)?))));
+    #[cfg(feature = "odbc")]
+    static ODBC: LazyLock<SourceFactory> = LazyLock::new(|| SourceFactory::new(|source| Ok(Box::new(odbc::OdbcSource::connect(source)?))));
+    #[cfg(feature = "duckdb")]
+    static DUCKDB: LazyLock<SourceFactory> = LazyLock::new(|| SourceFactory::new(|source| Ok(Box::new(duckdb::DuckDbSource::connect(source)?))));
+    match source_key(source).as_str() {
+        POSTGRES_SOURCE => Some(&POSTGRES),
+        #[cfg(feature = "odbc")]
+        odbc::ODBC_SOURCE | odbc::SQL_SERVER_SOURCE => Some(&ODBC),
+        #[cfg(feature = "duckdb")]
+        duckdb::DUCKDB_SOURCE => Some(&DUCKDB),
+        _ => None
+    }
+}
+
+/// The SQL query with the rows that a database source reads: its `query`, or else all rows of its `table`.
+/// The parts of the table name are quoted as identifiers in the syntax of the database, e.g. `"People"`, or
+/// `[People]` for SQL Server, so their case is kept, unless they are quoted already.
+pub(crate) fn sql_query(config: &HashMap<String, String>, database: &str) -> io::Result<String> {
+    match (config.get("query"), config.get("table")) {
+        (Some(query), _) => Ok(query.trim().trim_end_matches(';').to_string()),
+        (None, Some(table)) => Ok(format!("SELECT * FROM {}", quoted_table_name(table, database))),
+        (None, None) => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{database} source without query or table")))
+    }
+}
+
+// The table name, e.g. `school.People`, with its parts quoted; a part that is quoted already, or a string
+// like the files a DuckDB table reads, is left as it is
+fn quoted_table_name(table: &str, database: &str) -> String {
+    if table.starts_with('\'') {
+        return table.to_string();
+    }
+    table.split('.')
+        .map(|part| match (part.starts_with(['"', '[', '`']), database) {
+            (true, _) => part.to_string(),
+            (false, "SQL Server") => format!("[{}]", part.replace(']', "]]")),
+            (false, _) => format!("\"{}\"", part.replace('"', "\"\""))
+        })
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+/// The value of a field of a record in JSON, with the fields of nested objects named with dots, like
+/// `address.city`: text as it is, other scalars as in JSON, arrays and objects in JSON, and `null` or
+/// a missing field as an empty string. A field with a dot in its name takes precedence.
+pub(crate) fn flattened_value(record: &Value, field: &str) -> String {
+    let value = match (field.is_empty(), record.get(field)) {
+        (true, _) => Some(record),
+        (false, Some(value)) => Some(value),
+        (false, None) => field.split('.').try_fold(record, |value, name| value.get(name))
+    };
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(other) => other.to_string()
+    }
+}
+
+// Where the value of an attribute of a record comes from
+enum ValueSource {
+    Field(usize),
+    RecordNumber,
+    FileName
+}
+
+/// Picks the values of the attributes the plan uses from the records of a source: the values of its fields,
+/// or of the special references [RECORD_NUMBER_REFERENCE] and [FILE_NAME_REFERENCE]. A field with the name of
+/// a special reference takes precedence.
+pub(crate) struct RecordProjection {
+    value_sources: Vec<ValueSource>,
+    file_name: String
+}
+
+impl RecordProjection {
+    /// The projection of records with the given fields on the attributes, and the attributes found, in the
+    /// order of the projected values. Attributes that are not found are left out, so references to them have no value.
+    pub(crate) fn new(attributes: &[String], fields: &[String], file_name: Option<&str>) -> (Self, Vec<String>) {
+        let mut value_sources = Vec::with_capacity(attributes.len());
+        let mut found_attributes = Vec::with_capacity(attributes.len());
+        for attribute in attributes {
+            let value_source = match fields.iter().position(|field| field == attribute) {
+                Some(index) => ValueSource::Field(index),
+                None if attribute == RECORD_NUMBER_REFERENCE => ValueSource::RecordNumber,
+                None if attribute == FILE_NAME_REFERENCE && file_name.is_some() => ValueSource::FileName,
+                None => {
+                    warn!("WARNING: no field found with name {}", attribute);
+                    continue;
+                }
+            };
+            value_sources.push(value_source);
+            found_attributes.push(attribute.clone());
+        }
+        let projection = RecordProjection { value_sources, file_name: file_name.unwrap_or_default().to_string() };
+        (projection, found_attributes)
+    }
+
+    /// The values of the attributes for the record with the given number, given the values of its fields.
+    pub(crate) fn project(&self, field_value: impl Fn(usize) -> String, record_number: u64) -> Vec<String> {
+        self.value_sources.iter()
+            .map(|value_source| match value_source {
+                ValueSource::Field(index) => field_value(*index),
+                ValueSource::RecordNumber => record_number.to_string(),
+                ValueSource::FileName => self.file_name.clone()
+            })
+            .collect()
+    }
+}
+
+/// The number of sources of a run that may read at the same time, e.g. when a run executes many mappings
+/// at once. Every source waits for a permit before it reads its first record, and returns it when done.
+#[derive(Clone, Debug)]
+pub struct SourcePermits(Option<Arc<(Mutex<usize>, Condvar)>>);
+
+impl SourcePermits {
+    /// Permits for at most `max_sources` sources at the same time; for any number if not given.
+    pub fn new(max_sources: Option<usize>) -> Self {
+        SourcePermits(max_sources.map(|max_sources| Arc::new((Mutex::new(max_sources), Condvar::new()))))
+    }
+
+    /// Waits until a source may read, which it may until the permit is dropped.
+    pub fn acquire(&self) -> SourcePermit {
+        if let Some(permits) = &self.0 {
+            let (available, released) = permits.as_ref();
+            let mut available = released.wait_while(available.lock().unwrap(), |available| *available == 0).unwrap();
+            *available -= 1;
+        }
+        SourcePermit(self.0.clone())
+    }
+}
+
+/// Lets a source read, until it is dropped.
+pub struct SourcePermit(Option<Arc<(Mutex<usize>, Condvar)>>);
+
+impl Drop for SourcePermit {
+    fn drop(&mut self) {
+        if let Some(permits) = &self.0 {
+            let (available, released) = permits.as_ref();
+            *available.lock().unwrap() += 1;
+            released.notify_one();
+        }
+    }
+}
+
+/// Which records a source sends: all of them, a random sample, and at most a number of them.
+/// Every source thread works on its own copy.
+#[derive(Clone, Copy, Debug)]
+pub struct RecordSelection {
+    limit: Option<usize>,
+    sample_fraction: Option<f64>,
+    random_state: u64,
+    records_selected: usize
+}
+
+impl RecordSelection {
+    pub fn new(limit: Option<usize>, sample_fraction: Option<f64>, seed: u64) -> Self {
+        RecordSelection { limit, sample_fraction, random_state: seed, records_selected: 0 }
+    }
+
+    /// Whether the limit is reached, so no more records have to be read.
+    pub fn is_done(&self) -> bool {
+        self.limit == Some(self.records_selected)
+    }
+
+    /// Whether the next record is sent. Every record is sent with the probability of the sample fraction.
+    pub fn select(&mut self) -> bool {
+        if let Some(sample_fraction) = self.sample_fraction {
+            if self.next_random() >= sample_fraction {
+                return false;
+            }
+        }
+        self.records_selected += 1;
+        true
+    }
+
+    // A random number from 0 up to 1 (SplitMix64), the same ones for the same seed
+    fn next_random(&mut self) -> f64 {
+        self.random_state = self.random_state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.random_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample() {
+        let selected = |seed: u64| {
+            let mut selection = RecordSelection::new(None, Some(0.1), seed);
+            (0..10_000).map(|_| selection.select()).collect::<Vec<bool>>()
+        };
+        let sample = selected(42);
+        let sample_size = sample.iter().filter(|selected| **selected).count();
+        assert!((800..1200).contains(&sample_size), "{sample_size}");
+        assert_eq!(sample, selected(42));
+        assert_ne!(sample, selected(43));
+    }
+
+    #[test]
+    fn record_projection() {
+        let attributes = ["name".to_string(), RECORD_NUMBER_REFERENCE.to_string(), FILE_NAME_REFERENCE.to_string(), "age".to_string()];
+        let fields = ["id".to_string(), "name".to_string()];
+        let (projection, found_attributes) = RecordProjection::new(&attributes, &fields, Some("people.csv"));
+        assert_eq!(vec!["name", "_rownum", "_filename"], found_attributes);
+        let record = ["7", "Alice"];
+        assert_eq!(vec!["Alice", "3", "people.csv"], projection.project(|index| record[index].to_string(), 3));
+
+        // without a file, and with a field of the same name as a special reference
+        let fields = ["_rownum".to_string(), "name".to_string()];
+        let (projection, found_attributes) = RecordProjection::new(&attributes, &fields, None);
+        assert_eq!(vec!["name", "_rownum"], found_attributes);
+        let record = ["12", "Bob"];
+        assert_eq!(vec!["Bob", "12"], projection.project(|index| record[index].to_string(), 1));
+    }
+
+    #[test]
+    fn sql_queries() {
+        let config = |entries: &[(&str, &str)]| -> HashMap<String, String> {
+            entries.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+        };
+        assert_eq!("SELECT id, name FROM people", sql_query(&config(&[("query", " SELECT id, name FROM people; "), ("table", "students")]), "PostgreSQL").unwrap());
+        assert_eq!("SELECT * FROM \"Students\"", sql_query(&config(&[("table", "\"Students\"")]), "PostgreSQL").unwrap());
+        assert_eq!("SELECT * FROM \"People\"", sql_query(&config(&[("table", "People")]), "PostgreSQL").unwrap());
+        assert_eq!("SELECT * FROM \"school\".\"Odd \"\"name\"\"\"", sql_query(&config(&[("table", "school.Odd \"name\"")]), "ODBC").unwrap());
+        assert_eq!("SELECT * FROM [dbo].[People]", sql_query(&config(&[("table", "dbo.People")]), "SQL Server").unwrap());
+        assert_eq!("SELECT * FROM 'people/*.csv'", sql_query(&config(&[("table", "'people/*.csv'")]), "DuckDB").unwrap());
+        assert_eq!("ODBC source without query or table", sql_query(&config(&[]), "ODBC").unwrap_err().to_string());
+    }
+
+    #[test]
+    fn source_permits() {
+        let permits = SourcePermits::new(Some(1));
+        let permit = permits.acquire();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let waiting_permits = permits.clone();
+        let waiting = std::thread::spawn(move || {
+            let _permit = waiting_permits.acquire();
+            tx.send(()).unwrap();
+        });
+        assert!(rx.recv_timeout(std::time::Duration::from_millis(100)).is_err());
+        drop(permit);
+        assert!(rx.recv_timeout(std::time::Duration::from_secs(5)).is_ok());
+        waiting.join().unwrap();
+
+        // without a maximum, sources never wait
+        let permits = SourcePermits::new(None);
+        let _permits = [permits.acquire(), permits.acquire()];
+    }
+
+    #[test]
+    fn sample_with_limit() {
+        let mut selection = RecordSelection::new(Some(5), Some(0.5), 7);
+        let mut records_read = 0;
+        while !selection.is_done() {
+            selection.select();
+            records_read += 1;
+        }
+        assert!(records_read > 5);
+    }
+}
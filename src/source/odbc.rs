@@ -0,0 +1,124 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+//! A source that reads from any database with an ODBC driver, e.g. SQL Server or Oracle, through the
+//! ODBC driver manager of the system: unixODBC (`libodbc`) on Linux and macOS, `odbc32` on Windows.
+
+use std::collections::HashMap;
+use std::io;
+use odbc_api::{environment, ConnectionOptions, Cursor, CursorImpl, ResultSetMetadata, StatementConnection};
+use operator::Source as SourceConfig;
+use crate::source::{source_key, sql_query, Source};
+
+/// The key of the built-in source that reads through ODBC, i.e. of sources with `"source": "odbc"`.
+pub const ODBC_SOURCE: &str = "odbc";
+
+/// Sources of type `SQLServer` are read through ODBC too.
+pub const SQL_SERVER_SOURCE: &str = "SQLServer";
+
+/// Reads the rows of a table or query from a database through ODBC, with the columns as fields. The database
+/// is given by `connection`, an ODBC connection string like `Driver={ODBC Driver 18 for SQL Server};Server=db;Database=school`,
+/// or by the data source name `dsn`, with `username` and `password`. The rows are those of `query`, or else of
+/// `table`. Their values are read as UTF-8 text, and `NULL` as an empty string.
+pub struct OdbcSource {
+    // The cursor owns the connection it reads from
+    cursor: CursorImpl<StatementConnection<'static>>,
+    fields: Vec<String>,
+    buffer: Vec<u8>
+}
+
+impl OdbcSource {
+    /// Connects to the database of the source, and executes the query.
+    pub fn connect(source: &SourceConfig) -> io::Result<Self> {
+        let connection_string = connection_string(&source.config)?;
+        let database = match source_key(source) == SQL_SERVER_SOURCE {
+            true => "SQL Server",
+            false => "ODBC"
+        };
+        let query = sql_query(&source.config, database)?;
+        let connection = environment()
+            .and_then(|environment| environment.connect_with_connection_string(&connection_string, ConnectionOptions::default()))
+            .map_err(io::Error::other)?;
+        let Some(mut cursor) = connection.into_cursor(&query, ()).map_err(|err| io::Error::other(err.error))? else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("ODBC query {query} does not return a result set")));
+        };
+        let fields = cursor.column_names()
+            .and_then(|column_names| column_names.collect::<Result<Vec<String>, _>>())
+            .map_err(io::Error::other)?;
+        Ok(OdbcSource { cursor, fields, buffer: Vec::new() })
+    }
+}
+
+impl Source for OdbcSource {
+    fn fields(&mut self) -> io::Result<Vec<String>> {
+        Ok(self.fields.clone())
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        let Some(mut row) = self.cursor.next_row().map_err(io::Error::other)? else {
+            return Ok(None);
+        };
+        let mut record = Vec::with_capacity(self.fields.len());
+        for column in 1..=self.fields.len() as u16 {
+            // the buffer is cleared for NULL
+            row.get_text(column, &mut self.buffer).map_err(io::Error::other)?;
+            record.push(String::from_utf8_lossy(&self.buffer).to_string());
+        }
+        Ok(Some(record))
+    }
+}
+
+// The ODBC connection string, as it is or from a data source name with credentials
+fn connection_string(config: &HashMap<String, String>) -> io::Result<String> {
+    if let Some(connection) = config.get("connection") {
+        return Ok(connection.clone());
+    }
+    let Some(dsn) = config.get("dsn") else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "ODBC source without connection or dsn"));
+    };
+    let mut connection = format!("DSN={}", attribute_value(dsn));
+    if let Some(username) = config.get("username") {
+        connection.push_str(&format!(";UID={}", attribute_value(username)));
+    }
+    if let Some(password) = config.get("password") {
+        connection.push_str(&format!(";PWD={}", attribute_value(password)));
+    }
+    Ok(connection)
+}
+
+// The value of an attribute of a connection string, in braces if it has characters with a special meaning there
+fn attribute_value(value: &str) -> String {
+    match value.contains([';', '{', '}', '=']) || value.starts_with(' ') || value.ends_with(' ') {
+        true => format!("{{{}}}", value.replace('}', "}}")),
+        false => value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn connection_strings() {
+        let connection = "Driver={ODBC Driver 18 for SQL Server};Server=db;Database=school";
+        assert_eq!(connection, connection_string(&config(&[("connection", connection), ("dsn", "school")])).unwrap());
+        assert_eq!("DSN=school;UID=mapper;PWD={se;cr}}et}", connection_string(&config(&[("dsn", "school"), ("username", "mapper"), ("password", "se;cr}et")])).unwrap());
+        assert!(connection_string(&config(&[("table", "people")])).is_err());
+    }
+}
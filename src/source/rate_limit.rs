@@ -0,0 +1,124 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::source::Source;
+
+/// The most records, and the most bytes of record values, a source may read per second, so reading
+/// from a third-party API does not trip its throttling.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct RateLimit {
+    pub records_per_second: Option<f64>,
+    pub bytes_per_second: Option<u64>
+}
+
+impl RateLimit {
+    /// Sets the limits of `other` that are set, and keeps the others.
+    pub fn merge(&mut self, other: &RateLimit) {
+        self.records_per_second = other.records_per_second.or(self.records_per_second);
+        self.bytes_per_second = other.bytes_per_second.or(self.bytes_per_second);
+    }
+}
+
+/// Spreads records or requests evenly over time, so they stay within a [RateLimit] without bursts.
+/// Sources that make several requests per record can use it themselves.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    // When the next record, and the next byte, may be taken at the earliest
+    next_record: Option<Instant>,
+    next_byte: Option<Instant>
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter { limit, next_record: None, next_byte: None }
+    }
+
+    /// Waits until the next record or request may be taken.
+    pub fn wait(&self) {
+        let now = Instant::now();
+        if let Some(next) = self.next_record.max(self.next_byte).filter(|next| *next > now) {
+            thread::sleep(next - now);
+        }
+    }
+
+    /// Counts a record or request of the given size against the limit.
+    pub fn taken(&mut self, bytes: usize) {
+        let now = Instant::now();
+        let later = |next: Option<Instant>, seconds: f64| Some(next.map_or(now, |next| next.max(now)) + Duration::from_secs_f64(seconds));
+        if let Some(records_per_second) = self.limit.records_per_second {
+            self.next_record = later(self.next_record, 1.0 / records_per_second);
+        }
+        if let Some(bytes_per_second) = self.limit.bytes_per_second {
+            self.next_byte = later(self.next_byte, bytes as f64 / bytes_per_second as f64);
+        }
+    }
+}
+
+/// A source that reads the records of another one within a [RateLimit]. The size of a record is
+/// the number of bytes of its values.
+pub struct RateLimitedSource {
+    source: Box<dyn Source>,
+    limiter: RateLimiter
+}
+
+impl RateLimitedSource {
+    pub fn new(source: Box<dyn Source>, limit: RateLimit) -> Self {
+        RateLimitedSource { source, limiter: RateLimiter::new(limit) }
+    }
+}
+
+impl Source for RateLimitedSource {
+    fn fields(&mut self) -> io::Result<Vec<String>> {
+        self.source.fields()
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        self.limiter.wait();
+        let record = self.source.next_record()?;
+        if let Some(values) = &record {
+            self.limiter.taken(values.iter().map(String::len).sum());
+        }
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::source::TableSource;
+    use super::*;
+
+    fn read_all(limit: RateLimit) -> Duration {
+        let records = (0..5).map(|index| vec![format!("record {index}")]).collect();
+        let mut source = RateLimitedSource::new(Box::new(TableSource::new(vec!["value".to_string()], records)), limit);
+        let started = Instant::now();
+        while source.next_record().unwrap().is_some() {}
+        started.elapsed()
+    }
+
+    #[test]
+    fn limited_rates() {
+        // the first record is read at once, the others wait their turn
+        assert!(read_all(RateLimit { records_per_second: Some(100.0), bytes_per_second: None }) >= Duration::from_millis(40));
+        // 8 bytes per record
+        assert!(read_all(RateLimit { records_per_second: None, bytes_per_second: Some(400) }) >= Duration::from_millis(80));
+        assert!(read_all(RateLimit::default()) < Duration::from_millis(40));
+    }
+}
@@ -0,0 +1,126 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::TcpStream;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+use crate::source::messages::MessageFormat;
+use crate::source::Source;
+
+/// Reads records from the messages a WebSocket endpoint sends, e.g. a live feed. The endpoint is `url`, like
+/// `wss://feed.example.com/trains`, and `subscribe` is a text message sent to it after connecting, e.g. to
+/// subscribe to a channel. The fields are the references the plan uses. The source ends when the endpoint
+/// closes the connection.
+pub struct WebSocketSource {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    url: String,
+    format: MessageFormat,
+    fields: Vec<String>,
+    records: VecDeque<Vec<String>>
+}
+
+impl WebSocketSource {
+    /// Connects to the endpoint of the source, and sends the subscribe message if there is one.
+    pub fn connect(config: &HashMap<String, String>, format: MessageFormat, fields: Vec<String>) -> io::Result<Self> {
+        let url = websocket_url(config)
+            .map_err(|msg| io::Error::new(io::ErrorKind::InvalidInput, msg))?;
+        let (mut socket, _response) = tungstenite::connect(url)
+            .map_err(|err| io::Error::other(format!("Cannot connect to {url}: {err}")))?;
+        if let Some(subscribe) = config.get("subscribe") {
+            socket.send(Message::Text(subscribe.clone()))
+                .map_err(|err| io::Error::other(format!("Cannot subscribe at {url}: {err}")))?;
+        }
+        Ok(WebSocketSource { socket, url: url.clone(), format, fields, records: VecDeque::new() })
+    }
+}
+
+impl Source for WebSocketSource {
+    fn fields(&mut self) -> io::Result<Vec<String>> {
+        Ok(self.fields.clone())
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        loop {
+            if let Some(record) = self.records.pop_front() {
+                return Ok(Some(record));
+            }
+            // pings and closing the connection are answered while reading, which ends when the connection is closed
+            match self.socket.read() {
+                Ok(Message::Text(text)) => self.records.extend(self.format.records(text.as_bytes(), &self.fields)?),
+                Ok(Message::Binary(data)) => self.records.extend(self.format.records(&data, &self.fields)?),
+                Ok(_) => {},
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => return Ok(None),
+                Err(err) => return Err(io::Error::other(format!("Cannot read from {}: {err}", self.url)))
+            }
+        }
+    }
+}
+
+/// The URL of the endpoint of the source.
+pub fn websocket_url(config: &HashMap<String, String>) -> Result<&String, String> {
+    config.get("url")
+        .filter(|url| !url.is_empty())
+        .ok_or_else(|| "WebSocket source without url".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+    use super::*;
+
+    fn fields(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn read_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/people", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut socket = tungstenite::accept(listener.accept().unwrap().0).unwrap();
+            let subscribe = socket.read().unwrap();
+            socket.send(Message::Text(r#"{"id": 1, "name": "Venus"}"#.to_string())).unwrap();
+            socket.send(Message::Ping(Vec::new())).unwrap();
+            socket.send(Message::Binary(br#"{"id": 2, "name": "Mars"} {"id": 3}"#.to_vec())).unwrap();
+            socket.close(None).unwrap();
+            // wait for the close to be acknowledged
+            while socket.read().is_ok() {}
+            subscribe
+        });
+
+        let config = HashMap::from([("url".to_string(), url), ("subscribe".to_string(), "people".to_string())]);
+        let mut source = WebSocketSource::connect(&config, MessageFormat::Json { array_items: false }, fields(&["name"])).unwrap();
+        assert_eq!(fields(&["name"]), source.fields().unwrap());
+        assert_eq!(Some(fields(&["Venus"])), source.next_record().unwrap());
+        assert_eq!(Some(fields(&["Mars"])), source.next_record().unwrap());
+        assert_eq!(Some(fields(&[""])), source.next_record().unwrap());
+        assert_eq!(None, source.next_record().unwrap());
+        assert_eq!(Message::Text("people".to_string()), server.join().unwrap());
+    }
+
+    #[test]
+    fn connection_errors() {
+        // nothing listens on the port any more
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let format = MessageFormat::Json { array_items: false };
+        let config = HashMap::from([("url".to_string(), format!("ws://127.0.0.1:{port}/people"))]);
+        assert!(WebSocketSource::connect(&config, format.clone(), Vec::new()).err().unwrap().to_string().starts_with("Cannot connect to ws://127.0.0.1"));
+        assert_eq!("WebSocket source without url", WebSocketSource::connect(&HashMap::new(), format, Vec::new()).err().unwrap().to_string());
+    }
+}
@@ -0,0 +1,817 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! SPARQL queries over the generated dataset, run when the mapping is done, to write derived views
+//! of it without a second tool. The results of a `SELECT` query are written as SPARQL CSV results,
+//! those of a `CONSTRUCT` query as N-Triples.
+//!
+//! This is not a SPARQL engine but a restricted query language with the syntax of SPARQL: the queries can
+//! use prefixes, basic graph patterns, `OPTIONAL`, `GRAPH`, `FILTER` with comparisons, logical operators
+//! and the functions `bound`, `isIRI`, `isBlank`, `isLiteral`, `isNumeric`, `str`, `lang`, `datatype`,
+//! `strlen`, `lcase`, `ucase`, `contains`, `strstarts` and `strends`, and the modifiers `DISTINCT`,
+//! `ORDER BY`, `LIMIT` and `OFFSET`. Patterns outside `GRAPH` match the statements of all graphs.
+//! Queries using anything else, like `UNION`, `BIND`, `VALUES`, aggregates, other functions or property
+//! paths, are rejected when they are parsed; to run those, load the output into a triple store.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::thread;
+use std::thread::JoinHandle;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use crate::message::Message;
+use crate::rdf::{Statement, Term};
+use crate::shacl::turtle::TurtleParser;
+
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// A parsed SPARQL query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    form: QueryForm,
+    pattern: Vec<Pattern>,
+    order_by: Vec<(Expression, bool)>,
+    limit: Option<usize>,
+    offset: usize
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum QueryForm {
+    // The projected variables, all of them if `None`
+    Select { variables: Option<Vec<String>>, distinct: bool },
+    Construct(Vec<Statement>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Pattern {
+    Triples(Vec<Statement>),
+    Group(Vec<Pattern>),
+    Optional(Vec<Pattern>),
+    Graph(Term, Vec<Pattern>),
+    Filter(Expression)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    Constant(Term),
+    Variable(String),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Compare(&'static str, Box<Expression>, Box<Expression>),
+    Call(String, Vec<Expression>)
+}
+
+// Graph patterns of SPARQL that are rejected by name, rather than failing on what follows them
+const UNSUPPORTED_PATTERNS: &[&str] = &["UNION", "MINUS", "BIND", "VALUES", "SERVICE"];
+
+const FUNCTIONS: &[&str] = &["bound", "isiri", "isuri", "isblank", "isliteral", "isnumeric", "str", "lang", "datatype",
+    "strlen", "lcase", "ucase", "contains", "strstarts", "strends"];
+
+/// The results of a query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryResults {
+    /// The values of the projected variables, per solution
+    Solutions { variables: Vec<String>, rows: Vec<Vec<Option<Term>>> },
+    Graph(Vec<Statement>)
+}
+
+// The values of the variables, and of the blank nodes of patterns, of a solution
+type Solution = HashMap<String, Term>;
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, String> {
+        let mut parser = TurtleParser::new(query, true);
+        parse_query(&mut parser).map_err(|msg| format!("{msg} at line {}", parser.line()))
+    }
+
+    /// Evaluates the query over the given statements.
+    pub fn evaluate(&self, statements: &[Statement]) -> QueryResults {
+        let dataset = Dataset::new(statements);
+        let mut solutions = dataset.evaluate_group(&self.pattern, vec![Solution::new()], None);
+        if !self.order_by.is_empty() {
+            solutions.sort_by(|left, right| self.order_by.iter()
+                .map(|(expression, descending)| {
+                    let ordering = compare_terms(expression.evaluate(left).as_ref(), expression.evaluate(right).as_ref());
+                    if *descending { ordering.reverse() } else { ordering }
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal));
+        }
+        match &self.form {
+            QueryForm::Select { variables, distinct } => {
+                let variables = variables.clone().unwrap_or_else(|| pattern_variables(&self.pattern));
+                let mut rows: Vec<Vec<Option<Term>>> = solutions.iter()
+                    .map(|solution| variables.iter().map(|variable| solution.get(variable).cloned()).collect())
+                    .collect();
+                if *distinct {
+                    let mut seen = HashSet::new();
+                    rows.retain(|row| seen.insert(row.clone()));
+                }
+                let rows = rows.into_iter().skip(self.offset).take(self.limit.unwrap_or(usize::MAX)).collect();
+                QueryResults::Solutions { variables, rows }
+            },
+            QueryForm::Construct(template) => {
+                let mut statements = Vec::new();
+                let mut seen = HashSet::new();
+                let solutions = solutions.iter().skip(self.offset).take(self.limit.unwrap_or(usize::MAX));
+                for (index, solution) in solutions.enumerate() {
+                    for pattern in template {
+                        if let Some(statement) = instantiate(pattern, solution, index) {
+                            if seen.insert(statement.clone()) {
+                                statements.push(statement);
+                            }
+                        }
+                    }
+                }
+                QueryResults::Graph(statements)
+            }
+        }
+    }
+}
+
+impl QueryResults {
+    /// Writes solutions as SPARQL CSV results, and statements as N-Triples.
+    pub fn write(&self, out: impl Write) -> io::Result<()> {
+        match self {
+            QueryResults::Solutions { variables, rows } => {
+                let mut writer = csv::Writer::from_writer(out);
+                writer.write_record(variables)?;
+                for row in rows {
+                    writer.write_record(row.iter().map(|value| match value {
+                        Some(Term::Iri(iri)) => iri.clone(),
+                        Some(Term::BlankNode(label)) => format!("_:{label}"),
+                        Some(Term::Literal { value, .. }) => value.clone(),
+                        None => String::new()
+                    }))?;
+                }
+                writer.flush()
+            },
+            QueryResults::Graph(statements) => {
+                let mut out = out;
+                for statement in statements {
+                    writeln!(out, "{statement}")?;
+                }
+                out.flush()
+            }
+        }
+    }
+}
+
+fn parse_query(parser: &mut TurtleParser) -> Result<Query, String> {
+    loop {
+        parser.skip_white_space();
+        if parser.eat_keyword("PREFIX") {
+            parser.prefix()?;
+        } else if parser.eat_keyword("BASE") {
+            parser.base()?;
+        } else {
+            break;
+        }
+    }
+    let form = if parser.eat_keyword("SELECT") {
+        parser.skip_white_space();
+        let distinct = parser.eat_keyword("DISTINCT") || parser.eat_keyword("REDUCED");
+        parser.skip_white_space();
+        let variables = if parser.eat("*") {
+            None
+        } else {
+            let mut variables = Vec::new();
+            while let Some(variable) = variable(parser) {
+                variables.push(variable);
+                parser.skip_white_space();
+            }
+            if variables.is_empty() && parser.peek() == Some('(') {
+                return Err(unsupported("An expression in SELECT"));
+            }
+            if variables.is_empty() {
+                return Err("Expected '*' or variables after SELECT".to_string());
+            }
+            Some(variables)
+        };
+        QueryForm::Select { variables, distinct }
+    } else if parser.eat_keyword("CONSTRUCT") {
+        parser.expect('{')?;
+        loop {
+            parser.skip_white_space();
+            if parser.eat("}") {
+                break;
+            }
+            parser.triples()?;
+            parser.skip_white_space();
+            parser.eat(".");
+        }
+        QueryForm::Construct(parser.take_statements())
+    } else {
+        return Err("Only SELECT and CONSTRUCT queries are supported".to_string());
+    };
+
+    parser.skip_white_space();
+    if parser.eat_keyword("FROM") {
+        return Err("FROM is not supported; the queries run over the generated dataset".to_string());
+    }
+    parser.eat_keyword("WHERE");
+    parser.expect('{')?;
+    let pattern = group(parser)?;
+
+    let mut query = Query { form, pattern, order_by: Vec::new(), limit: None, offset: 0 };
+    loop {
+        parser.skip_white_space();
+        if parser.eat_keyword("ORDER") {
+            parser.skip_white_space();
+            if !parser.eat_keyword("BY") {
+                return Err("Expected BY after ORDER".to_string());
+            }
+            loop {
+                parser.skip_white_space();
+                let descending = if parser.eat_keyword("DESC") {
+                    true
+                } else {
+                    parser.eat_keyword("ASC");
+                    false
+                };
+                parser.skip_white_space();
+                match parser.peek() {
+                    Some('(') => query.order_by.push((primary(parser)?, descending)),
+                    Some('?') | Some('$') => query.order_by.push((primary(parser)?, descending)),
+                    _ => break
+                }
+            }
+            if query.order_by.is_empty() {
+                return Err("Expected a variable or expression after ORDER BY".to_string());
+            }
+        } else if parser.eat_keyword("LIMIT") {
+            query.limit = Some(integer(parser)?);
+        } else if parser.eat_keyword("OFFSET") {
+            query.offset = integer(parser)?;
+        } else if parser.eat_keyword("GROUP") {
+            return Err(unsupported("GROUP BY"));
+        } else if parser.eat_keyword("HAVING") {
+            return Err(unsupported("HAVING"));
+        } else if parser.rest().is_empty() {
+            return Ok(query);
+        } else {
+            return Err(format!("Unexpected '{}'", parser.rest().split_whitespace().next().unwrap_or_default()));
+        }
+    }
+}
+
+// The patterns of a group, after its '{'
+fn group(parser: &mut TurtleParser) -> Result<Vec<Pattern>, String> {
+    let mut patterns = Vec::new();
+    loop {
+        parser.skip_white_space();
+        if parser.eat("}") {
+            return Ok(patterns);
+        }
+        if parser.rest().is_empty() {
+            return Err("Unterminated group pattern".to_string());
+        }
+        if parser.eat_keyword("OPTIONAL") {
+            parser.expect('{')?;
+            patterns.push(Pattern::Optional(group(parser)?));
+        } else if parser.eat_keyword("GRAPH") {
+            let graph = parser.object()?;
+            parser.expect('{')?;
+            patterns.push(Pattern::Graph(graph, group(parser)?));
+        } else if parser.eat_keyword("FILTER") {
+            parser.skip_white_space();
+            patterns.push(Pattern::Filter(primary(parser)?));
+        } else if parser.eat("{") {
+            patterns.push(Pattern::Group(group(parser)?));
+        } else if let Some(keyword) = UNSUPPORTED_PATTERNS.iter().find(|keyword| parser.eat_keyword(keyword)) {
+            return Err(unsupported(keyword));
+        } else {
+            parser.triples()?;
+            patterns.push(Pattern::Triples(parser.take_statements()));
+        }
+        parser.skip_white_space();
+        parser.eat(".");
+    }
+}
+
+fn expression(parser: &mut TurtleParser) -> Result<Expression, String> {
+    let mut left = conjunction(parser)?;
+    loop {
+        parser.skip_white_space();
+        if !parser.eat("||") {
+            return Ok(left);
+        }
+        left = Expression::Or(Box::new(left), Box::new(conjunction(parser)?));
+    }
+}
+
+fn conjunction(parser: &mut TurtleParser) -> Result<Expression, String> {
+    let mut left = comparison(parser)?;
+    loop {
+        parser.skip_white_space();
+        if !parser.eat("&&") {
+            return Ok(left);
+        }
+        left = Expression::And(Box::new(left), Box::new(comparison(parser)?));
+    }
+}
+
+fn comparison(parser: &mut TurtleParser) -> Result<Expression, String> {
+    let left = primary(parser)?;
+    parser.skip_white_space();
+    for operator in ["!=", "<=", ">=", "=", "<", ">"] {
+        if parser.eat(operator) {
+            return Ok(Expression::Compare(operator, Box::new(left), Box::new(primary(parser)?)));
+        }
+    }
+    Ok(left)
+}
+
+fn primary(parser: &mut TurtleParser) -> Result<Expression, String> {
+    parser.skip_white_space();
+    if parser.eat("(") {
+        let expression = expression(parser)?;
+        parser.expect(')')?;
+        return Ok(expression);
+    }
+    if parser.eat("!") {
+        return Ok(Expression::Not(Box::new(primary(parser)?)));
+    }
+    if parser.eat_keyword("EXISTS") || parser.eat_keyword("NOT") {
+        return Err(unsupported("EXISTS"));
+    }
+    if let Some(variable) = variable(parser) {
+        return Ok(Expression::Variable(variable));
+    }
+    let rest = parser.rest();
+    let name_length = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+    let name = rest[..name_length].to_lowercase();
+    if name_length > 0 && rest[name_length..].trim_start().starts_with('(') {
+        if !FUNCTIONS.contains(&name.as_str()) {
+            return Err(unsupported(&format!("The function {name}")));
+        }
+        parser.eat_keyword(&rest[..name_length]);
+        parser.expect('(')?;
+        let mut arguments = Vec::new();
+        parser.skip_white_space();
+        if !parser.eat(")") {
+            loop {
+                arguments.push(expression(parser)?);
+                parser.skip_white_space();
+                if parser.eat(")") {
+                    break;
+                }
+                parser.expect(',')?;
+            }
+        }
+        return Ok(Expression::Call(name, arguments));
+    }
+    Ok(Expression::Constant(parser.object()?))
+}
+
+fn unsupported(feature: &str) -> String {
+    format!("{feature} is not supported in the SPARQL subset of mopper")
+}
+
+// A variable, like `?name` or `$name`
+fn variable(parser: &mut TurtleParser) -> Option<String> {
+    let rest = parser.rest();
+    if !rest.starts_with(['?', '$']) {
+        return None;
+    }
+    let length = rest[1..].find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len() - 1);
+    if length == 0 {
+        return None;
+    }
+    parser.pos += length + 1;
+    Some(rest[1..=length].to_string())
+}
+
+fn integer(parser: &mut TurtleParser) -> Result<usize, String> {
+    parser.skip_white_space();
+    let rest = parser.rest();
+    let length = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let number = rest[..length].parse().map_err(|_| "Expected a number".to_string())?;
+    parser.pos += length;
+    Ok(number)
+}
+
+// The name of the variable or blank node of a pattern term: variables are blank nodes labelled `?` and
+// their name, and other blank nodes are variables that cannot be selected
+fn pattern_variable(term: &Term) -> Option<String> {
+    match term {
+        Term::BlankNode(label) => Some(label.strip_prefix('?').map_or_else(|| format!("_:{label}"), str::to_string)),
+        _ => None
+    }
+}
+
+// The variables of the patterns, in the order they appear in
+fn pattern_variables(patterns: &[Pattern]) -> Vec<String> {
+    let mut variables = Vec::new();
+    for pattern in patterns {
+        let terms: Vec<&Term> = match pattern {
+            Pattern::Triples(triples) => triples.iter().flat_map(|triple| [&triple.subject, &triple.predicate, &triple.object]).collect(),
+            Pattern::Graph(graph, _) => vec![graph],
+            _ => Vec::new()
+        };
+        for term in terms {
+            if let Some(variable) = pattern_variable(term).filter(|variable| !variable.starts_with("_:")) {
+                if !variables.contains(&variable) {
+                    variables.push(variable);
+                }
+            }
+        }
+        if let Pattern::Group(group) | Pattern::Optional(group) | Pattern::Graph(_, group) = pattern {
+            for variable in pattern_variables(group) {
+                if !variables.contains(&variable) {
+                    variables.push(variable);
+                }
+            }
+        }
+    }
+    variables
+}
+
+// A statement of the template with the values of the solution; blank nodes are new for every solution
+fn instantiate(pattern: &Statement, solution: &Solution, index: usize) -> Option<Statement> {
+    let term = |term: &Term| match term {
+        Term::BlankNode(label) if label.starts_with('?') => solution.get(&label[1..]).cloned(),
+        Term::BlankNode(label) => Some(Term::BlankNode(format!("{label}-{index}"))),
+        other => Some(other.clone())
+    };
+    let statement = Statement { subject: term(&pattern.subject)?, predicate: term(&pattern.predicate)?, object: term(&pattern.object)?, graph: None };
+    let valid = !matches!(statement.subject, Term::Literal { .. }) && matches!(statement.predicate, Term::Iri(_));
+    valid.then_some(statement)
+}
+
+// The statements, indexed by predicate: distinct triples for patterns outside GRAPH, and quads for the others
+struct Dataset<'a> {
+    triples: Vec<&'a Statement>,
+    quads: Vec<&'a Statement>,
+    triples_by_predicate: HashMap<&'a Term, Vec<usize>>,
+    quads_by_predicate: HashMap<&'a Term, Vec<usize>>
+}
+
+impl<'a> Dataset<'a> {
+    fn new(statements: &'a [Statement]) -> Self {
+        let mut seen = HashSet::new();
+        let triples: Vec<&Statement> = statements.iter()
+            .filter(|statement| seen.insert((&statement.subject, &statement.predicate, &statement.object)))
+            .collect();
+        let quads: Vec<&Statement> = statements.iter().filter(|statement| statement.graph.is_some()).collect();
+        let index = |statements: &[&'a Statement]| {
+            let mut by_predicate: HashMap<&'a Term, Vec<usize>> = HashMap::new();
+            for (position, statement) in statements.iter().enumerate() {
+                by_predicate.entry(&statement.predicate).or_default().push(position);
+            }
+            by_predicate
+        };
+        Dataset { triples_by_predicate: index(&triples), quads_by_predicate: index(&quads), triples, quads }
+    }
+
+    // The solutions of a group that extend the given ones; the filters apply to the whole group
+    fn evaluate_group(&self, patterns: &[Pattern], mut solutions: Vec<Solution>, graph: Option<&Term>) -> Vec<Solution> {
+        for pattern in patterns {
+            solutions = match pattern {
+                Pattern::Triples(triples) => triples.iter()
+                    .fold(solutions, |solutions, triple| self.match_triple(triple, solutions, graph)),
+                Pattern::Group(group) => self.evaluate_group(group, solutions, graph),
+                Pattern::Optional(group) => solutions.into_iter()
+                    .flat_map(|solution| {
+                        let extended = self.evaluate_group(group, vec![solution.clone()], graph);
+                        if extended.is_empty() { vec![solution] } else { extended }
+                    })
+                    .collect(),
+                Pattern::Graph(graph, group) => self.evaluate_group(group, solutions, Some(graph)),
+                Pattern::Filter(_) => solutions
+            };
+        }
+        for pattern in patterns {
+            if let Pattern::Filter(filter) = pattern {
+                solutions.retain(|solution| filter.evaluate(solution).as_ref().and_then(effective_boolean_value).unwrap_or(false));
+            }
+        }
+        solutions
+    }
+
+    fn match_triple(&self, triple: &Statement, solutions: Vec<Solution>, graph: Option<&Term>) -> Vec<Solution> {
+        let (statements, by_predicate) = match graph {
+            Some(_) => (&self.quads, &self.quads_by_predicate),
+            None => (&self.triples, &self.triples_by_predicate)
+        };
+        let mut matches = Vec::new();
+        for solution in solutions {
+            let predicate = bound_term(&triple.predicate, &solution);
+            let candidates: Box<dyn Iterator<Item = &&Statement>> = match &predicate {
+                Some(predicate) => Box::new(by_predicate.get(predicate).into_iter().flatten().map(|position| &statements[*position])),
+                None => Box::new(statements.iter())
+            };
+            for statement in candidates {
+                let mut extended = solution.clone();
+                let matched = bind(&triple.subject, &statement.subject, &mut extended)
+                    && bind(&triple.predicate, &statement.predicate, &mut extended)
+                    && bind(&triple.object, &statement.object, &mut extended)
+                    && match (graph, &statement.graph) {
+                        (Some(graph_pattern), Some(statement_graph)) => bind(graph_pattern, statement_graph, &mut extended),
+                        (Some(_), None) => false,
+                        (None, _) => true
+                    };
+                if matched {
+                    matches.push(extended);
+                }
+            }
+        }
+        matches
+    }
+}
+
+// The value of a pattern term in a solution, if it is known
+fn bound_term(pattern: &Term, solution: &Solution) -> Option<Term> {
+    match pattern_variable(pattern) {
+        Some(variable) => solution.get(&variable).cloned(),
+        None => Some(pattern.clone())
+    }
+}
+
+// Matches a pattern term with a term, binding its variable if it has no value yet
+fn bind(pattern: &Term, term: &Term, solution: &mut Solution) -> bool {
+    match pattern_variable(pattern) {
+        Some(variable) => match solution.get(&variable) {
+            Some(value) => value == term,
+            None => {
+                solution.insert(variable, term.clone());
+                true
+            }
+        },
+        None => pattern == term
+    }
+}
+
+impl Expression {
+    // The value of the expression, or `None` if it is an error, e.g. because a variable is not bound
+    fn evaluate(&self, solution: &Solution) -> Option<Term> {
+        match self {
+            Expression::Constant(term) => Some(term.clone()),
+            Expression::Variable(variable) => solution.get(variable).cloned(),
+            Expression::Not(expression) => effective_boolean_value(&expression.evaluate(solution)?).map(|value| boolean(!value)),
+            Expression::And(left, right) => {
+                let left = left.evaluate(solution).as_ref().and_then(effective_boolean_value);
+                let right = right.evaluate(solution).as_ref().and_then(effective_boolean_value);
+                match (left, right) {
+                    (Some(false), _) | (_, Some(false)) => Some(boolean(false)),
+                    (Some(true), Some(true)) => Some(boolean(true)),
+                    _ => None
+                }
+            },
+            Expression::Or(left, right) => {
+                let left = left.evaluate(solution).as_ref().and_then(effective_boolean_value);
+                let right = right.evaluate(solution).as_ref().and_then(effective_boolean_value);
+                match (left, right) {
+                    (Some(true), _) | (_, Some(true)) => Some(boolean(true)),
+                    (Some(false), Some(false)) => Some(boolean(false)),
+                    _ => None
+                }
+            },
+            Expression::Compare(operator, left, right) => {
+                let (left, right) = (left.evaluate(solution)?, right.evaluate(solution)?);
+                let ordering = match (numeric_value(&left), numeric_value(&right)) {
+                    (Some(left), Some(right)) => left.partial_cmp(&right)?,
+                    _ if matches!(*operator, "=" | "!=") => if left == right { Ordering::Equal } else { Ordering::Less },
+                    _ => match (&left, &right) {
+                        (Term::Literal { value: left, .. }, Term::Literal { value: right, .. }) => left.cmp(right),
+                        _ => return None
+                    }
+                };
+                Some(boolean(match *operator {
+                    "=" => ordering.is_eq(),
+                    "!=" => ordering.is_ne(),
+                    "<" => ordering.is_lt(),
+                    "<=" => ordering.is_le(),
+                    ">" => ordering.is_gt(),
+                    _ => ordering.is_ge()
+                }))
+            },
+            Expression::Call(function, arguments) => {
+                if function == "bound" {
+                    return match arguments.as_slice() {
+                        [Expression::Variable(variable)] => Some(boolean(solution.contains_key(variable))),
+                        _ => None
+                    };
+                }
+                let values: Vec<Term> = arguments.iter()
+                    .map(|argument| argument.evaluate(solution))
+                    .collect::<Option<_>>()?;
+                call(function, &values)
+            }
+        }
+    }
+}
+
+fn call(function: &str, arguments: &[Term]) -> Option<Term> {
+    match (function, arguments) {
+        ("isiri" | "isuri", [term]) => Some(boolean(matches!(term, Term::Iri(_)))),
+        ("isblank", [term]) => Some(boolean(matches!(term, Term::BlankNode(_)))),
+        ("isliteral", [term]) => Some(boolean(matches!(term, Term::Literal { .. }))),
+        ("isnumeric", [term]) => Some(boolean(numeric_value(term).is_some())),
+        ("str", [Term::Iri(value)] | [Term::Literal { value, .. }]) => Some(string(value)),
+        ("lang", [Term::Literal { language, .. }]) => Some(string(language.as_deref().unwrap_or_default())),
+        ("datatype", [Term::Literal { datatype, language, .. }]) => Some(Term::Iri(match (datatype, language) {
+            (Some(datatype), _) => datatype.clone(),
+            (None, Some(_)) => "http://www.w3.org/1999/02/22-rdf-syntax-ns#langString".to_string(),
+            (None, None) => format!("{XSD}string")
+        })),
+        ("strlen", [Term::Literal { value, .. }]) => Some(Term::Literal { value: value.chars().count().to_string(), datatype: Some(format!("{XSD}integer")), language: None }),
+        ("lcase", [Term::Literal { value, datatype, language }]) => Some(Term::Literal { value: value.to_lowercase(), datatype: datatype.clone(), language: language.clone() }),
+        ("ucase", [Term::Literal { value, datatype, language }]) => Some(Term::Literal { value: value.to_uppercase(), datatype: datatype.clone(), language: language.clone() }),
+        ("contains", [Term::Literal { value, .. }, Term::Literal { value: part, .. }]) => Some(boolean(value.contains(part.as_str()))),
+        ("strstarts", [Term::Literal { value, .. }, Term::Literal { value: part, .. }]) => Some(boolean(value.starts_with(part.as_str()))),
+        ("strends", [Term::Literal { value, .. }, Term::Literal { value: part, .. }]) => Some(boolean(value.ends_with(part.as_str()))),
+        _ => None
+    }
+}
+
+fn boolean(value: bool) -> Term {
+    Term::Literal { value: value.to_string(), datatype: Some(format!("{XSD}boolean")), language: None }
+}
+
+fn string(value: &str) -> Term {
+    Term::Literal { value: value.to_string(), datatype: None, language: None }
+}
+
+fn numeric_value(term: &Term) -> Option<f64> {
+    match term {
+        Term::Literal { value, datatype: Some(datatype), .. } => {
+            let xsd_type = datatype.strip_prefix(XSD)?;
+            let numeric = matches!(xsd_type, "integer" | "decimal" | "double" | "float" | "int" | "long" | "short" | "byte"
+                | "nonNegativeInteger" | "positiveInteger" | "negativeInteger" | "nonPositiveInteger"
+                | "unsignedInt" | "unsignedLong" | "unsignedShort" | "unsignedByte");
+            if numeric { value.trim().parse().ok() } else { None }
+        },
+        _ => None
+    }
+}
+
+fn effective_boolean_value(term: &Term) -> Option<bool> {
+    match term {
+        Term::Literal { value, datatype: Some(datatype), .. } if *datatype == format!("{XSD}boolean") => Some(value == "true" || value == "1"),
+        Term::Literal { datatype: Some(_), .. } if numeric_value(term).is_some() => numeric_value(term).map(|value| value != 0.0 && !value.is_nan()),
+        Term::Literal { value, .. } => Some(!value.is_empty()),
+        _ => None
+    }
+}
+
+// The order of ORDER BY: unbound, blank nodes, IRIs, then literals, numerically if both are numbers
+fn compare_terms(left: Option<&Term>, right: Option<&Term>) -> Ordering {
+    let rank = |term: Option<&Term>| match term {
+        None => 0,
+        Some(Term::BlankNode(_)) => 1,
+        Some(Term::Iri(_)) => 2,
+        Some(Term::Literal { .. }) => 3
+    };
+    match (left, right) {
+        (Some(left_term), Some(right_term)) if rank(left) == rank(right) => {
+            if let (Some(left_number), Some(right_number)) = (numeric_value(left_term), numeric_value(right_term)) {
+                return left_number.partial_cmp(&right_number).unwrap_or(Ordering::Equal);
+            }
+            let value = |term: &Term| match term {
+                Term::Iri(value) | Term::BlankNode(value) | Term::Literal { value, .. } => value.clone()
+            };
+            value(left_term).cmp(&value(right_term))
+        },
+        _ => rank(left).cmp(&rank(right))
+    }
+}
+
+/// Collects the statements of the serializers while the plan executes, and runs the queries over them
+/// when it is done.
+pub(crate) struct SparqlQueries {
+    // The queries, with the files to write their results to
+    queries: Vec<(Query, String)>,
+    sender: Sender<Message>,
+    collector: JoinHandle<Vec<Statement>>
+}
+
+impl SparqlQueries {
+    pub(crate) fn new(queries: Vec<(Query, String)>, capacity: usize) -> Self {
+        let (sender, receiver) = bounded::<Message>(capacity);
+        let collector = thread::Builder::new()
+            .name("SPARQL collector".to_string())
+            .spawn(move || collect(receiver)).unwrap();
+        SparqlQueries { queries, sender, collector }
+    }
+
+    /// A channel to send the messages of a serializer to, like to a sink.
+    pub(crate) fn collect_from(&self) -> Sender<Message> {
+        self.sender.clone()
+    }
+
+    /// Waits until all statements are collected, and runs the queries.
+    pub(crate) fn run(self) -> io::Result<()> {
+        let SparqlQueries { queries, sender, collector } = self;
+        drop(sender);
+        let statements = collector.join().map_err(|_| io::Error::other("The SPARQL collector panicked"))?;
+        for (query, output_file) in queries {
+            query.evaluate(&statements).write(BufWriter::new(File::create(output_file)?))?;
+        }
+        Ok(())
+    }
+}
+
+fn collect(receiver: Receiver<Message>) -> Vec<Statement> {
+    let mut statements = Vec::new();
+    for message in receiver {
+        if let Message::Data(values) = message {
+            statements.extend(values.iter()
+                .flat_map(|value| value.lines())
+                .filter_map(Statement::from_nquads));
+        }
+    }
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statements() -> Vec<Statement> {
+        [
+            "<http://example.com/Venus> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://example.com/Person> <http://example.com/people> .",
+            "<http://example.com/Venus> <http://example.com/name> \"Venus\" <http://example.com/people> .",
+            "<http://example.com/Venus> <http://example.com/age> \"44\"^^<http://www.w3.org/2001/XMLSchema#integer> .",
+            "<http://example.com/Venus> <http://example.com/sport> <http://example.com/Tennis> .",
+            "<http://example.com/Bob> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://example.com/Person> .",
+            "<http://example.com/Bob> <http://example.com/name> \"Bob\" .",
+            "<http://example.com/Bob> <http://example.com/age> \"9\"^^<http://www.w3.org/2001/XMLSchema#integer> .",
+            "<http://example.com/Tennis> <http://example.com/name> \"tennis\"@en ."
+        ].iter().map(|line| Statement::from_nquads(line).unwrap()).collect()
+    }
+
+    fn csv(results: &QueryResults) -> String {
+        let mut out = Vec::new();
+        results.write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn select_evaluated() {
+        let query = Query::parse(r#"
+            PREFIX ex: <http://example.com/>
+            SELECT ?name ?age ?sport WHERE {
+                ?person a ex:Person ; ex:name ?name ; ex:age ?age .
+                OPTIONAL { ?person ex:sport [ ex:name ?sport ] }
+                FILTER (?age >= 9 && !contains(str(?name), "x"))
+            }
+            ORDER BY DESC(?age)
+        "#).unwrap();
+        assert_eq!("name,age,sport\nVenus,44,tennis\nBob,9,\n", csv(&query.evaluate(&statements())));
+
+        let query = Query::parse("SELECT DISTINCT * WHERE { GRAPH ?g { ?s ?p ?o } } ORDER BY ?p LIMIT 1 OFFSET 1").unwrap();
+        assert_eq!("g,s,p,o\nhttp://example.com/people,http://example.com/Venus,http://www.w3.org/1999/02/22-rdf-syntax-ns#type,http://example.com/Person\n",
+                   csv(&query.evaluate(&statements())));
+    }
+
+    #[test]
+    fn construct_evaluated() {
+        let query = Query::parse(r#"
+            PREFIX ex: <http://example.com/>
+            CONSTRUCT { ?person ex:label ?name ; ex:profile [ ex:adult true ] }
+            WHERE { ?person ex:name ?name ; ex:age ?age . FILTER (?age > 18) }
+        "#).unwrap();
+        assert_eq!(concat!(
+            "<http://example.com/Venus> <http://example.com/label> \"Venus\" .\n",
+            "_:turtle1-0 <http://example.com/adult> \"true\"^^<http://www.w3.org/2001/XMLSchema#boolean> .\n",
+            "<http://example.com/Venus> <http://example.com/profile> _:turtle1-0 .\n"
+        ), csv(&query.evaluate(&statements())));
+    }
+
+    #[test]
+    fn invalid_queries() {
+        assert_eq!(Err("Only SELECT and CONSTRUCT queries are supported at line 1".to_string()), Query::parse("ASK { ?s ?p ?o }"));
+        assert_eq!(Err("Unknown prefix 'ex' at line 2".to_string()), Query::parse("SELECT * WHERE {\n ?s ex:p ?o }"));
+        assert!(Query::parse("SELECT ?s WHERE { ?s ?p ?o } LIMIT").is_err());
+    }
+
+    #[test]
+    fn unsupported_queries() {
+        let error = |query| Query::parse(query).unwrap_err();
+        assert_eq!("UNION is not supported in the SPARQL subset of mopper at line 1", error("SELECT * WHERE { { ?s ?p ?o } UNION { ?o ?p ?s } }"));
+        assert_eq!("BIND is not supported in the SPARQL subset of mopper at line 1", error("SELECT * WHERE { ?s ?p ?o BIND (str(?o) AS ?text) }"));
+        assert_eq!("VALUES is not supported in the SPARQL subset of mopper at line 1", error("SELECT * WHERE { VALUES ?s { <http://example.com/Bob> } ?s ?p ?o }"));
+        assert_eq!("A property path is not supported in the SPARQL subset of mopper at line 1", error("SELECT * WHERE { ?s <http://example.com/knows>/<http://example.com/name> ?o }"));
+        assert_eq!("An expression in SELECT is not supported in the SPARQL subset of mopper at line 1", error("SELECT (COUNT(?s) AS ?count) WHERE { ?s ?p ?o }"));
+        assert_eq!("GROUP BY is not supported in the SPARQL subset of mopper at line 1", error("SELECT ?s WHERE { ?s ?p ?o } GROUP BY ?s"));
+        assert_eq!("The function regex is not supported in the SPARQL subset of mopper at line 1", error("SELECT * WHERE { ?s ?p ?o FILTER regex(?o, \"^a\") }"));
+        assert_eq!("EXISTS is not supported in the SPARQL subset of mopper at line 1", error("SELECT * WHERE { ?s ?p ?o FILTER NOT EXISTS { ?o ?p ?s } }"));
+    }
+}
@@ -0,0 +1,490 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crossbeam_channel::{bounded, RecvTimeoutError, Sender};
+use log::info;
+use serde::{Serialize, Serializer};
+use crate::shacl::ValidationReport;
+use crate::void::DatasetStatistics;
+
+/// The kind of operator, as it runs in the execution engine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum OperatorKind {
+    Source,
+    Extend,
+    Join,
+    Serializer,
+    Sink
+}
+
+impl Display for OperatorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            OperatorKind::Source => "Source",
+            OperatorKind::Extend => "Extend",
+            OperatorKind::Join => "Join",
+            OperatorKind::Serializer => "Serializer",
+            OperatorKind::Sink => "Sink"
+        };
+        f.write_str(name)
+    }
+}
+
+/// Counters of one operator, updated by the operator's thread while running.
+#[derive(Default, Debug)]
+pub struct OperatorCounters {
+    // measure the time blocked on channels?
+    timing: bool,
+    blocked_nanos: AtomicU64,
+    records_in: AtomicU64,
+    records_out: AtomicU64,
+    elapsed_nanos: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_total: AtomicU64,
+    duplicates_removed: AtomicU64,
+    errors: AtomicU64,
+    invalid_iris: AtomicU64
+}
+
+impl OperatorCounters {
+    /// Creates counters that also measure the time the operator is blocked, waiting for data to
+    /// receive or for room to send data, if `timing` is set.
+    pub fn new(timing: bool) -> Self {
+        OperatorCounters {
+            timing,
+            ..Default::default()
+        }
+    }
+
+    /// Runs `blocking_operation` (typically sending or receiving over a channel), registering
+    /// the time it takes as time blocked if timing is on.
+    pub fn blocked<T, F: FnOnce() -> T>(&self, blocking_operation: F) -> T {
+        if self.timing {
+            let start = Instant::now();
+            let result = blocking_operation();
+            self.blocked_nanos.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            result
+        } else {
+            blocking_operation()
+        }
+    }
+
+    pub fn add_in(&self, nr_of_records: u64) {
+        self.records_in.fetch_add(nr_of_records, Ordering::Relaxed);
+    }
+
+    pub fn add_out(&self, nr_of_records: u64) {
+        self.records_out.fetch_add(nr_of_records, Ordering::Relaxed);
+    }
+
+    /// Registers the time the operator was running.
+    pub fn finish(&self, started: Instant) {
+        self.elapsed_nanos.store(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// For sinks: registers the number of duplicate statements that were not written.
+    pub fn add_duplicates_removed(&self, nr_of_statements: u64) {
+        self.duplicates_removed.fetch_add(nr_of_statements, Ordering::Relaxed);
+    }
+
+    /// Registers that the operator encountered an error processing a record.
+    pub fn add_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registers that the operator generated an invalid IRI.
+    pub fn add_invalid_iri(&self) {
+        self.invalid_iris.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// For sources: registers the number of bytes read so far.
+    pub fn set_bytes_read(&self, bytes_read: u64) {
+        self.bytes_read.store(bytes_read, Ordering::Relaxed);
+    }
+
+    /// For sources: registers the total number of bytes to read, if known.
+    pub fn set_bytes_total(&self, bytes_total: u64) {
+        self.bytes_total.store(bytes_total, Ordering::Relaxed);
+    }
+
+    pub fn records_in(&self) -> u64 {
+        self.records_in.load(Ordering::Relaxed)
+    }
+
+    pub fn records_out(&self) -> u64 {
+        self.records_out.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_nanos.load(Ordering::Relaxed))
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_total(&self) -> u64 {
+        self.bytes_total.load(Ordering::Relaxed)
+    }
+
+    pub fn duplicates_removed(&self) -> u64 {
+        self.duplicates_removed.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn invalid_iris(&self) -> u64 {
+        self.invalid_iris.load(Ordering::Relaxed)
+    }
+
+    pub fn blocked_time(&self) -> Duration {
+        Duration::from_nanos(self.blocked_nanos.load(Ordering::Relaxed))
+    }
+}
+
+fn serialize_seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Statistics of one operator after a run.
+#[derive(Clone, Debug, Serialize)]
+pub struct OperatorStats {
+    /// The id of the operator's node in the (rewritten) plan
+    pub node_id: usize,
+    pub kind: OperatorKind,
+    /// For sources: always 0. For sinks: the number of messages received.
+    pub records_in: u64,
+    /// For sources: the number of records read. For sinks: the number of statements written.
+    pub records_out: u64,
+    /// The time the operator was running
+    #[serde(rename = "elapsed_seconds", serialize_with = "serialize_seconds")]
+    pub elapsed: Duration,
+    /// For sinks: the number of duplicate statements that were not written
+    pub duplicates_removed: u64,
+    /// The number of errors encountered processing records
+    pub errors: u64,
+    /// The number of invalid IRIs generated, whatever the [crate::mopper_options::InvalidIriPolicy]
+    pub invalid_iris: u64,
+    /// The time the operator was blocked, waiting on other operators to receive or send data.
+    /// Only measured in benchmark mode, 0 otherwise.
+    #[serde(rename = "blocked_seconds", serialize_with = "serialize_seconds")]
+    pub blocked: Duration
+}
+
+impl OperatorStats {
+    pub fn new(node_id: usize, kind: OperatorKind, counters: &OperatorCounters) -> Self {
+        OperatorStats {
+            node_id,
+            kind,
+            records_in: counters.records_in(),
+            records_out: counters.records_out(),
+            elapsed: counters.elapsed(),
+            duplicates_removed: counters.duplicates_removed(),
+            errors: counters.errors(),
+            invalid_iris: counters.invalid_iris(),
+            blocked: counters.blocked_time()
+        }
+    }
+
+    /// The time the operator was actually working, i.e. not blocked.
+    pub fn busy(&self) -> Duration {
+        self.elapsed.saturating_sub(self.blocked)
+    }
+}
+
+/// The number of statements a target of the plan gets in a run, as returned by [crate::count].
+#[derive(Clone, Debug, Serialize)]
+pub struct TargetCount {
+    /// The id of the sink's node in the (rewritten) plan
+    pub node_id: usize,
+    /// The kind of target, e.g. `StdOut` or the key of a custom sink, and its path if it has one
+    pub target: String,
+    pub statements: u64,
+    /// The number of duplicate statements the target does not get, if deduplicating
+    pub duplicates_removed: u64
+}
+
+impl TargetCount {
+    pub fn new(target: String, sink_stats: &OperatorStats) -> Self {
+        TargetCount {
+            node_id: sink_stats.node_id,
+            target,
+            statements: sink_stats.records_out,
+            duplicates_removed: sink_stats.duplicates_removed
+        }
+    }
+}
+
+impl Display for TargetCount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Target {:>4} ({}): {} statements", self.node_id, self.target, self.statements)?;
+        if self.duplicates_removed > 0 {
+            write!(f, ", {} duplicates removed", self.duplicates_removed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Statistics of a complete run, as returned by [crate::start].
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RunStats {
+    /// Wall time of the whole run, including setting up the execution engine
+    #[serde(rename = "elapsed_seconds", serialize_with = "serialize_seconds")]
+    pub elapsed: Duration,
+    /// The statistics of every operator, ordered by node id
+    pub operators: Vec<OperatorStats>,
+    /// The violations of the SHACL shapes in the generated statements, if they are validated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validation: Option<ValidationReport>,
+    /// The statistics of the generated dataset, if its VoID description or report is written
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dataset: Option<DatasetStatistics>
+}
+
+impl RunStats {
+    /// The statistics of the run for the operators with the given node ids only, e.g. those of one of several
+    /// plans executed in one run. The validation and dataset statistics are of the whole run, so they are left out.
+    pub fn of_nodes(&self, node_ids: &HashSet<usize>) -> RunStats {
+        RunStats {
+            elapsed: self.elapsed,
+            operators: self.operators.iter()
+                .filter(|operator_stats| node_ids.contains(&operator_stats.node_id))
+                .cloned()
+                .collect(),
+            validation: None,
+            dataset: None
+        }
+    }
+
+    /// The statistics of the operators of the given kind.
+    pub fn operators_of_kind(&self, kind: OperatorKind) -> impl Iterator<Item = &OperatorStats> {
+        self.operators.iter()
+            .filter(move |operator_stats| operator_stats.kind == kind)
+    }
+
+    /// The total number of records read by all sources.
+    pub fn records_read(&self) -> u64 {
+        self.operators_of_kind(OperatorKind::Source)
+            .map(|operator_stats| operator_stats.records_out)
+            .sum()
+    }
+
+    /// The total number of statements written by all sinks.
+    pub fn statements_written(&self) -> u64 {
+        self.operators_of_kind(OperatorKind::Sink)
+            .map(|operator_stats| operator_stats.records_out)
+            .sum()
+    }
+
+    /// The total number of duplicate statements not written by all sinks.
+    pub fn duplicates_removed(&self) -> u64 {
+        self.operators_of_kind(OperatorKind::Sink)
+            .map(|operator_stats| operator_stats.duplicates_removed)
+            .sum()
+    }
+
+    /// The total number of errors encountered by all operators.
+    pub fn errors(&self) -> u64 {
+        self.operators.iter()
+            .map(|operator_stats| operator_stats.errors)
+            .sum()
+    }
+
+    /// The total number of invalid IRIs generated by all operators.
+    pub fn invalid_iris(&self) -> u64 {
+        self.operators.iter()
+            .map(|operator_stats| operator_stats.invalid_iris)
+            .sum()
+    }
+
+    /// A table with the busy and blocked time of every operator. The operator that was busy the
+    /// longest is most likely the bottleneck. Blocked times are only measured in benchmark mode.
+    pub fn timing_report(&self) -> String {
+        let mut report = String::from("  Node  Operator      Elapsed (s)   Busy (s)  Blocked (s)  Busy %   Records in  Records out\n");
+        for operator_stats in &self.operators {
+            let elapsed = operator_stats.elapsed.as_secs_f64();
+            let busy = operator_stats.busy().as_secs_f64();
+            let busy_percentage = if elapsed > 0.0 { busy / elapsed * 100.0 } else { 0.0 };
+            report.push_str(&format!("  {:>4}  {:<12} {:>12.3} {:>10.3} {:>12.3} {:>6.1}% {:>12} {:>12}\n",
+                                     operator_stats.node_id, operator_stats.kind.to_string(), elapsed, busy,
+                                     operator_stats.blocked.as_secs_f64(), busy_percentage,
+                                     operator_stats.records_in, operator_stats.records_out));
+        }
+        if let Some(bottleneck) = self.operators.iter().max_by_key(|operator_stats| operator_stats.busy()) {
+            report.push_str(&format!("Most likely bottleneck: {} {}\n", bottleneck.kind, bottleneck.node_id));
+        }
+        report.push_str(&format!("Total elapsed: {:.3}s", self.elapsed.as_secs_f64()));
+        report
+    }
+}
+
+impl Display for RunStats {
+    /// A human-readable summary of the run.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Run summary")?;
+        for source_stats in self.operators_of_kind(OperatorKind::Source) {
+            writeln!(f, "  Source {:>4}: {} records read", source_stats.node_id, source_stats.records_out)?;
+        }
+        for sink_stats in self.operators_of_kind(OperatorKind::Sink) {
+            writeln!(f, "  Sink   {:>4}: {} statements written, {} duplicates removed",
+                     sink_stats.node_id, sink_stats.records_out, sink_stats.duplicates_removed)?;
+        }
+        writeln!(f, "  Total:       {} records read, {} statements written, {} duplicates removed",
+                 self.records_read(), self.statements_written(), self.duplicates_removed())?;
+        writeln!(f, "  Errors:      {}", self.errors())?;
+        writeln!(f, "  Invalid IRIs: {}", self.invalid_iris())?;
+        write!(f, "  Elapsed:     {:.3}s", self.elapsed.as_secs_f64())
+    }
+}
+
+/// Runs a task periodically in its own thread, until stopped.
+pub(crate) struct PeriodicTask {
+    stop_tx: Sender<()>,
+    join_handle: JoinHandle<()>
+}
+
+impl PeriodicTask {
+    pub fn start<F>(name: &str, interval: Duration, mut task: F) -> Self
+        where F: FnMut() + Send + 'static
+    {
+        let (stop_tx, stop_rx) = bounded::<()>(1);
+        let join_handle = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(interval) {
+                    task();
+                }
+            }).unwrap();
+        PeriodicTask { stop_tx, join_handle }
+    }
+
+    pub fn stop(self) {
+        // If sending fails, the thread is already gone
+        let _ = self.stop_tx.send(());
+        let _ = self.join_handle.join();
+    }
+}
+
+/// Periodically logs the number of records processed by every operator and the throughput
+/// since the previous time.
+pub(crate) fn log_throughput(operators: Vec<(usize, OperatorKind, Arc<OperatorCounters>)>, interval: Duration) -> PeriodicTask {
+    let mut previous: Vec<(u64, u64)> = vec![(0, 0); operators.len()];
+    let mut previous_time = Instant::now();
+    PeriodicTask::start("Throughput logger", interval, move || {
+        let seconds = previous_time.elapsed().as_secs_f64();
+        previous_time = Instant::now();
+        for ((node_id, kind, counters), (previous_in, previous_out)) in operators.iter().zip(previous.iter_mut()) {
+            let records_in = counters.records_in();
+            let records_out = counters.records_out();
+            let rate_in = (records_in - *previous_in) as f64 / seconds;
+            let rate_out = (records_out - *previous_out) as f64 / seconds;
+            match kind {
+                OperatorKind::Source => info!("{kind} {node_id}: {records_out} records out ({rate_out:.0}/s)"),
+                _ => info!("{kind} {node_id}: {records_in} records in ({rate_in:.0}/s), {records_out} records out ({rate_out:.0}/s)")
+            }
+            *previous_in = records_in;
+            *previous_out = records_out;
+        }
+    })
+}
+
+/// The progress of a run, as reported to a [ProgressListener].
+#[derive(Clone, Debug)]
+pub struct Progress {
+    /// The number of records read by all sources so far
+    pub records_read: u64,
+    /// The number of bytes read by all sources so far
+    pub bytes_read: u64,
+    /// The total number of bytes all sources will read, or 0 if unknown
+    pub bytes_total: u64,
+    /// The time since the start of the run
+    pub elapsed: Duration,
+    /// `true` when this is the last report of the run
+    pub finished: bool
+}
+
+impl Progress {
+    /// The fraction of the input processed, between 0 and 1, if it can be estimated.
+    pub fn fraction(&self) -> Option<f64> {
+        match (self.finished, self.bytes_total) {
+            (true, _) => Some(1.0),
+            (false, 0) => None,
+            (false, total) => Some((self.bytes_read as f64 / total as f64).min(1.0))
+        }
+    }
+
+    /// An estimate of the total number of records the sources will read, based on the
+    /// average record size so far.
+    pub fn estimated_records_total(&self) -> Option<u64> {
+        if self.finished {
+            return Some(self.records_read)
+        }
+        if self.bytes_total == 0 || self.bytes_read == 0 {
+            return None
+        }
+        let bytes_per_record = self.bytes_read as f64 / self.records_read.max(1) as f64;
+        Some((self.bytes_total as f64 / bytes_per_record) as u64)
+    }
+}
+
+/// Gets called periodically with the progress of a run. See [crate::mopper_options::MopperOptionsBuilder::progress_listener].
+#[derive(Clone)]
+pub struct ProgressListener(Arc<dyn Fn(&Progress) + Send + Sync>);
+
+impl ProgressListener {
+    pub fn new<F>(listener: F) -> Self
+        where F: Fn(&Progress) + Send + Sync + 'static
+    {
+        ProgressListener(Arc::new(listener))
+    }
+
+    pub fn report(&self, progress: &Progress) {
+        (self.0)(progress)
+    }
+}
+
+impl Debug for ProgressListener {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressListener")
+    }
+}
+
+/// Calculates the progress from the counters of the sources.
+pub(crate) fn progress(sources: &[Arc<OperatorCounters>], started: Instant, finished: bool) -> Progress {
+    Progress {
+        records_read: sources.iter().map(|counters| counters.records_out()).sum(),
+        bytes_read: sources.iter().map(|counters| counters.bytes_read()).sum(),
+        bytes_total: sources.iter().map(|counters| counters.bytes_total()).sum(),
+        elapsed: started.elapsed(),
+        finished
+    }
+}
+
+/// Periodically reports the progress of the sources to the listener.
+pub(crate) fn report_progress(sources: Vec<Arc<OperatorCounters>>, listener: ProgressListener, interval: Duration, started: Instant) -> PeriodicTask {
+    PeriodicTask::start("Progress reporter", interval, move || {
+        listener.report(&progress(&sources, started, false));
+    })
+}
@@ -0,0 +1,82 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Streaming the generated statements to the caller, instead of writing them to a target.
+
+use std::io;
+use crossbeam_channel::{Receiver, Sender};
+use log::warn;
+use crate::error::MopperError;
+use crate::Execution;
+use crate::rdf::Statement;
+use crate::sink::Sink;
+use crate::stats::RunStats;
+
+/// The statements a run generates, in the order the sinks receive them.
+/// Iterating ends when the run is done; [StatementStream::finish] then tells if it was successful.
+///
+/// If the stream is dropped or finished before all statements are taken, the sinks cannot
+/// deliver the remaining statements and the run stops with an error.
+pub struct StatementStream {
+    receiver: Receiver<Statement>,
+    execution: Execution
+}
+
+impl StatementStream {
+    pub(crate) fn new(receiver: Receiver<Statement>, execution: Execution) -> Self {
+        StatementStream { receiver, execution }
+    }
+
+    /// Waits until the run is done, and returns its statistics when successful.
+    pub fn finish(self) -> Result<RunStats, MopperError> {
+        drop(self.receiver);
+        self.execution.wait()
+    }
+}
+
+impl Iterator for StatementStream {
+    type Item = Statement;
+
+    fn next(&mut self) -> Option<Statement> {
+        // the channel is closed when the sinks are done, and have dropped their senders
+        self.receiver.recv().ok()
+    }
+}
+
+/// Takes the statements of a target and sends them as [Statement]s.
+pub(crate) struct StatementSink {
+    sender: Sender<Statement>
+}
+
+impl StatementSink {
+    pub(crate) fn new(sender: Sender<Statement>) -> Self {
+        StatementSink { sender }
+    }
+}
+
+impl Sink for StatementSink {
+    fn write(&mut self, statements: &str) -> io::Result<()> {
+        for line in statements.lines().filter(|line| !line.trim().is_empty()) {
+            let Some(statement) = Statement::from_nquads(line) else {
+                warn!("Cannot parse statement {line}; leaving it out of the stream.");
+                continue;
+            };
+            self.sender.send(statement)
+                .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "The statement stream is closed"))?;
+        }
+        Ok(())
+    }
+}
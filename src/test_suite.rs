@@ -0,0 +1,566 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Running a suite of RML test cases, such as the ones of the RML test cases repository, and comparing
+//! the statements every test case generates with the expected ones.
+//!
+//! A test case is a directory with its mapping, as AlgeMapLoom plan `mapping.json` or in RML as
+//! `mapping.ttl`, its sources, and the statements it should generate in `output.nq`. A test case without
+//! `output.nq` should fail. Test cases are named after their directory, and the part after the last `-`
+//! is taken as the input format, e.g. `RMLTC0000-CSV`.
+//!
+//! The outcomes can be written as an [EARL](https://www.w3.org/TR/EARL10-Schema/) report, the RDF vocabulary
+//! the RML community publishes the conformance of implementations in.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use log::info;
+use crate::error::MopperError;
+use crate::logging::utc_timestamp;
+use crate::mopper_options::MopperOptions;
+use crate::rdf::{Statement, Term};
+use crate::{mapping_to_plan, start_streaming, MappingLang};
+
+const PLAN_FILE: &str = "mapping.json";
+const RML_FILE: &str = "mapping.ttl";
+const EXPECTED_OUTPUT_FILE: &str = "output.nq";
+
+// The number of differing statements shown for a failed test case
+const DIFFERENCES_SHOWN: usize = 5;
+
+/// The IRIs of the test cases of the RML core test suite start with this.
+pub const RML_CORE_TEST_BASE_IRI: &str = "http://w3id.org/rml/core/test/";
+
+// mopper, as the subject and the assertor of EARL reports
+pub(crate) const MOPPER_IRI: &str = "https://github.com/ghsnd/mopper";
+
+/// The outcome of a test case.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestOutcome {
+    Passed,
+
+    /// The generated statements differ from the expected ones, or the run succeeded although it should fail.
+    Failed(String),
+
+    /// The run failed although it should succeed, or the test case cannot be read.
+    Error(String)
+}
+
+/// The outcome of a test case, by its name.
+#[derive(Debug, Clone)]
+pub struct TestCaseResult {
+    pub name: String,
+    pub outcome: TestOutcome
+}
+
+/// The outcomes of all test cases of a suite. Displays as a matrix of the test cases by input format,
+/// followed by the reasons of the failures and a summary.
+#[derive(Debug, Clone, Default)]
+pub struct TestSuiteReport {
+    pub results: Vec<TestCaseResult>
+}
+
+impl TestSuiteReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|result| result.outcome == TestOutcome::Passed).count()
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.passed() == self.results.len()
+    }
+
+    /// The outcome of every test case, with a row per test and a column per input format.
+    pub fn matrix(&self) -> String {
+        let mut tests: Vec<&str> = Vec::new();
+        let mut formats: Vec<&str> = Vec::new();
+        let mut cells: HashMap<(&str, &str), &str> = HashMap::new();
+        for result in &self.results {
+            let (test, format) = result.name.rsplit_once('-').unwrap_or((&result.name, ""));
+            if !tests.contains(&test) {
+                tests.push(test);
+            }
+            if !formats.contains(&format) {
+                formats.push(format);
+            }
+            let cell = match result.outcome {
+                TestOutcome::Passed => "pass",
+                TestOutcome::Failed(_) => "FAIL",
+                TestOutcome::Error(_) => "ERROR"
+            };
+            cells.insert((test, format), cell);
+        }
+
+        let test_width = tests.iter().map(|test| test.len()).chain(Some("test case".len())).max().unwrap_or(0);
+        let format_widths: Vec<usize> = formats.iter().map(|format| format.len().max("ERROR".len())).collect();
+        let mut matrix = format!("{:test_width$}", "test case");
+        for (format, width) in formats.iter().zip(&format_widths) {
+            matrix.push_str(&format!("  {format:width$}"));
+        }
+        matrix = matrix.trim_end().to_string();
+        for test in tests {
+            let mut row = format!("\n{test:test_width$}");
+            for (format, width) in formats.iter().zip(&format_widths) {
+                let cell = cells.get(&(test, *format)).unwrap_or(&"");
+                row.push_str(&format!("  {cell:width$}"));
+            }
+            matrix.push_str(row.trim_end());
+        }
+        matrix
+    }
+
+    /// The outcomes as an EARL report in Turtle, with mopper as the software tested and as the assertor.
+    /// The IRI of a test case is `test_base_iri` followed by its name, without the directories it is in.
+    pub fn to_earl(&self, test_base_iri: &str) -> String {
+        let date = utc_timestamp(SystemTime::now());
+        let mut earl = format!("\
+@prefix earl: <http://www.w3.org/ns/earl#> .
+@prefix doap: <http://usefulinc.com/ns/doap#> .
+@prefix dcterms: <http://purl.org/dc/terms/> .
+@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+
+<{MOPPER_IRI}> a doap:Project, earl:TestSubject, earl:Software, earl:Assertor ;
+    doap:name \"mopper\" ;
+    doap:programming-language \"Rust\" ;
+    doap:release [ doap:revision \"{}\" ] .
+", env!("CARGO_PKG_VERSION"));
+        for result in &self.results {
+            let (outcome, info) = match &result.outcome {
+                TestOutcome::Passed => ("passed", None),
+                TestOutcome::Failed(reason) | TestOutcome::Error(reason) => ("failed", Some(reason))
+            };
+            earl.push_str(&format!("
+[] a earl:Assertion ;
+    earl:assertedBy <{MOPPER_IRI}> ;
+    earl:subject <{MOPPER_IRI}> ;
+    earl:test <{test_base_iri}{}> ;
+    earl:mode earl:automatic ;
+    earl:result [
+        a earl:TestResult ;
+        earl:outcome earl:{outcome} ;
+", test_id(&result.name)));
+            if let Some(info) = info {
+                earl.push_str(&format!("        earl:info {} ;\n", turtle_string(info)));
+            }
+            earl.push_str(&format!("        dcterms:date \"{date}\"^^xsd:dateTime\n    ] .\n"));
+        }
+        earl
+    }
+}
+
+// The name of the test case without the directories it is in, as in the IRIs of test cases
+fn test_id(name: &str) -> &str {
+    name.rsplit(['/', '\\']).next().unwrap_or(name)
+}
+
+// The value as a Turtle string literal
+pub(crate) fn turtle_string(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            _ => literal.push(c)
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+impl Display for TestSuiteReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.matrix())?;
+        let mut failed = 0;
+        let mut errors = 0;
+        for result in &self.results {
+            let (kind, reason) = match &result.outcome {
+                TestOutcome::Passed => continue,
+                TestOutcome::Failed(reason) => { failed += 1; ("FAIL", reason) },
+                TestOutcome::Error(reason) => { errors += 1; ("ERROR", reason) }
+            };
+            write!(f, "\n{} {kind}: {reason}\n", result.name)?;
+        }
+        write!(f, "\n{} passed, {failed} failed, {errors} errors", self.passed())
+    }
+}
+
+/// Runs all test cases in `dir` and its subdirectories, in the order of their names, with the given options.
+/// The sources of a test case are looked up in its directory, and the targets of its plan are ignored.
+pub fn run_test_suite(dir: impl AsRef<Path>, options: &MopperOptions) -> Result<TestSuiteReport, MopperError> {
+    let dir = dir.as_ref();
+    let mut test_case_dirs = Vec::new();
+    find_test_cases(dir, &mut test_case_dirs)
+        .map_err(|err| MopperError::Plan { msg: format!("Cannot read test cases in {}: {err}", dir.display()), cause: Some(Box::new(err)) })?;
+
+    let results = test_case_dirs.iter()
+        .map(|test_case_dir| {
+            let name = test_case_dir.strip_prefix(dir).ok()
+                .filter(|relative_dir| !relative_dir.as_os_str().is_empty())
+                .map(|relative_dir| relative_dir.to_string_lossy().to_string())
+                .unwrap_or_else(|| dir_name(test_case_dir));
+            info!("Running test case {name}");
+            TestCaseResult { name, outcome: run_test_case(test_case_dir, options) }
+        })
+        .collect();
+    Ok(TestSuiteReport { results })
+}
+
+/// Runs the test case in `dir` with the given options, and compares the statements it generates
+/// with the expected ones.
+pub fn run_test_case(dir: &Path, options: &MopperOptions) -> TestOutcome {
+    let plan = match read_plan(dir) {
+        Ok(plan) => plan,
+        Err(err) => return TestOutcome::Error(err)
+    };
+    let expected_output_file = dir.join(EXPECTED_OUTPUT_FILE);
+    let expected = if expected_output_file.exists() {
+        match read_statements(&expected_output_file) {
+            Ok(statements) => Some(statements),
+            Err(err) => return TestOutcome::Error(err)
+        }
+    } else {
+        None
+    };
+
+    let options = options.clone().with_working_dir_hint(&dir.to_string_lossy());
+    let run_result = start_streaming(&plan, &options).and_then(|mut stream| {
+        let statements: Vec<Statement> = stream.by_ref().collect();
+        stream.finish().map(|_| statements)
+    });
+    match (run_result, expected) {
+        (Ok(generated), Some(expected)) => match compare_graphs(&expected, &generated) {
+            Ok(()) => TestOutcome::Passed,
+            Err(differences) => TestOutcome::Failed(differences)
+        },
+        (Ok(_), None) => TestOutcome::Failed("the run succeeded, but it should fail".to_string()),
+        (Err(_), None) => TestOutcome::Passed,
+        (Err(err), Some(_)) => TestOutcome::Error(err.to_string())
+    }
+}
+
+// The name of the directory, also if it is given as e.g. `.`
+fn dir_name(dir: &Path) -> String {
+    dir.canonicalize().ok()
+        .and_then(|dir| dir.file_name().map(|name| name.to_string_lossy().to_string()))
+        .unwrap_or_else(|| dir.to_string_lossy().to_string())
+}
+
+// Adds the directories with a mapping to the test cases, and looks for more in the other directories
+fn find_test_cases(dir: &Path, test_case_dirs: &mut Vec<PathBuf>) -> io::Result<()> {
+    if dir.join(PLAN_FILE).is_file() || dir.join(RML_FILE).is_file() {
+        test_case_dirs.push(dir.to_path_buf());
+        return Ok(());
+    }
+    let mut sub_dirs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            sub_dirs.push(path);
+        }
+    }
+    sub_dirs.sort();
+    for sub_dir in sub_dirs {
+        find_test_cases(&sub_dir, test_case_dirs)?;
+    }
+    Ok(())
+}
+
+// Reads the plan of a test case, translating the RML mapping if there is no plan
+pub(crate) fn read_plan(dir: &Path) -> Result<String, String> {
+    let plan_file = dir.join(PLAN_FILE);
+    if plan_file.is_file() {
+        return fs::read_to_string(&plan_file)
+            .map_err(|err| format!("Cannot read plan {}: {err}", plan_file.display()));
+    }
+    let rml_file = dir.join(RML_FILE);
+    let mapping = fs::read_to_string(&rml_file)
+        .map_err(|err| format!("Cannot read mapping {}: {err}", rml_file.display()))?;
+    mapping_to_plan(&mapping, MappingLang::RML)
+        .map_err(|err| err.to_string())
+}
+
+// Reads the statements of an N-Triples or N-Quads file, skipping empty lines and comments
+fn read_statements(file: &Path) -> Result<Vec<Statement>, String> {
+    let content = fs::read_to_string(file)
+        .map_err(|err| format!("Cannot read expected output {}: {err}", file.display()))?;
+    content.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(line_nr, line)| Statement::from_nquads(line)
+            .ok_or_else(|| format!("Cannot parse line {} of {}: {line}", line_nr + 1, file.display())))
+        .collect()
+}
+
+/// Compares two RDF datasets, ignoring duplicate statements and the labels of blank nodes: the datasets
+/// are the same if the blank nodes of the one can be renamed one to one to the ones of the other.
+/// Returns the differences if the datasets are not the same.
+pub fn compare_graphs(expected: &[Statement], generated: &[Statement]) -> Result<(), String> {
+    let expected: HashSet<&Statement> = expected.iter().collect();
+    let generated: HashSet<&Statement> = generated.iter().collect();
+    let (expected_colors, generated_colors) = blank_node_colors(&expected, &generated);
+    if expected.len() == generated.len() && BlankNodeMatcher::new(&expected, &expected_colors, &generated, &generated_colors).matches() {
+        return Ok(());
+    }
+
+    // show the blank nodes by their colors, so the differences are not those of the labels
+    let expected = colored_statements(&expected, &expected_colors);
+    let generated = colored_statements(&generated, &generated_colors);
+    let missing: Vec<&String> = expected.difference(&generated).collect();
+    let unexpected: Vec<&String> = generated.difference(&expected).collect();
+    if missing.is_empty() && unexpected.is_empty() {
+        return Err("the blank nodes of the generated statements cannot be renamed one to one to the expected ones".to_string());
+    }
+    let mut differences = format!("{} statements missing, {} unexpected", missing.len(), unexpected.len());
+    for statement in missing.iter().take(DIFFERENCES_SHOWN) {
+        differences.push_str(&format!("\n  - {statement}"));
+    }
+    for statement in unexpected.iter().take(DIFFERENCES_SHOWN) {
+        differences.push_str(&format!("\n  + {statement}"));
+    }
+    Err(differences)
+}
+
+// Colors the blank nodes of both datasets after the statements they are in, refining the colors with
+// the ones of the blank nodes around them until no more blank nodes can be told apart. Blank nodes that
+// can be renamed to each other get the same color; ones with another color cannot.
+fn blank_node_colors<'a>(expected: &HashSet<&'a Statement>, generated: &HashSet<&'a Statement>) -> (Colors<'a>, Colors<'a>) {
+    let uncolored = |statements: &HashSet<&'a Statement>| -> Colors<'a> {
+        statements.iter().flat_map(|statement| blank_node_labels(statement)).map(|label| (label, 0)).collect()
+    };
+    let distinct = |colors: &Colors| colors.values().collect::<HashSet<_>>().len();
+    let mut expected_colors = uncolored(expected);
+    let mut generated_colors = uncolored(generated);
+    // both are refined equally often, so their colors can be compared
+    for _ in 0..expected_colors.len().max(generated_colors.len()) {
+        let new_expected_colors = refine_colors(expected, &expected_colors);
+        let new_generated_colors = refine_colors(generated, &generated_colors);
+        let refined = distinct(&new_expected_colors) != distinct(&expected_colors)
+            || distinct(&new_generated_colors) != distinct(&generated_colors);
+        expected_colors = new_expected_colors;
+        generated_colors = new_generated_colors;
+        if !refined {
+            break;
+        }
+    }
+    (expected_colors, generated_colors)
+}
+
+type Colors<'a> = HashMap<&'a str, u64>;
+
+// Gives every blank node a color after its own color and the statements it is in
+fn refine_colors<'a>(statements: &HashSet<&'a Statement>, colors: &Colors<'a>) -> Colors<'a> {
+    let mut signatures: HashMap<&str, Vec<String>> = HashMap::new();
+    for statement in statements {
+        for label in blank_node_labels(statement) {
+            let signature = relabel(statement, |other| match other == label {
+                true => "self".to_string(),
+                false => colors[other].to_string()
+            });
+            signatures.entry(label).or_default().push(signature.to_string());
+        }
+    }
+    signatures.into_iter()
+        .map(|(label, mut signature)| {
+            signature.sort_unstable();
+            let mut hasher = DefaultHasher::new();
+            colors[label].hash(&mut hasher);
+            signature.hash(&mut hasher);
+            (label, hasher.finish())
+        })
+        .collect()
+}
+
+fn colored_statements(statements: &HashSet<&Statement>, colors: &Colors) -> BTreeSet<String> {
+    statements.iter()
+        .map(|statement| relabel(statement, |label| format!("c{:016x}", colors[label])).to_string())
+        .collect()
+}
+
+// Looks for a one-to-one renaming of the expected blank nodes to generated ones of the same color that
+// turns every expected statement into a generated one, trying the blank nodes with the fewest candidates first
+struct BlankNodeMatcher<'a> {
+    labels: Vec<&'a str>,
+    candidates: HashMap<u64, Vec<&'a str>>,
+    expected_colors: &'a Colors<'a>,
+    statements_of: HashMap<&'a str, Vec<&'a Statement>>,
+    ground_statements: Vec<&'a Statement>,
+    generated: &'a HashSet<&'a Statement>
+}
+
+impl<'a> BlankNodeMatcher<'a> {
+    fn new(expected: &'a HashSet<&'a Statement>, expected_colors: &'a Colors<'a>,
+           generated: &'a HashSet<&'a Statement>, generated_colors: &'a Colors<'a>) -> Self {
+        let mut candidates: HashMap<u64, Vec<&str>> = HashMap::new();
+        for (label, color) in generated_colors {
+            candidates.entry(*color).or_default().push(label);
+        }
+        let mut statements_of: HashMap<&str, Vec<&Statement>> = HashMap::new();
+        let mut ground_statements = Vec::new();
+        for statement in expected {
+            let mut labels = blank_node_labels(statement).peekable();
+            if labels.peek().is_none() {
+                ground_statements.push(*statement);
+            }
+            for label in labels {
+                statements_of.entry(label).or_default().push(statement);
+            }
+        }
+        let mut labels: Vec<&str> = expected_colors.keys().copied().collect();
+        let nr_of_candidates = |label: &&str| candidates.get(&expected_colors[label]).map_or(0, Vec::len);
+        labels.sort_by(|a, b| nr_of_candidates(a).cmp(&nr_of_candidates(b)).then(a.cmp(b)));
+        BlankNodeMatcher { labels, candidates, expected_colors, statements_of, ground_statements, generated }
+    }
+
+    fn matches(&self) -> bool {
+        self.ground_statements.iter().all(|statement| self.generated.contains(statement))
+            && self.labels.len() == self.candidates.values().map(Vec::len).sum::<usize>()
+            && self.extend(&mut HashMap::new(), &mut HashSet::new())
+    }
+
+    // Renames the next expected blank node, and backtracks if the renaming so far cannot be completed
+    fn extend(&self, renaming: &mut HashMap<&'a str, &'a str>, used: &mut HashSet<&'a str>) -> bool {
+        let Some(label) = self.labels.get(renaming.len()) else {
+            return true;
+        };
+        let Some(candidates) = self.candidates.get(&self.expected_colors[label]) else {
+            return false;
+        };
+        for candidate in candidates {
+            if !used.insert(candidate) {
+                continue;
+            }
+            renaming.insert(label, candidate);
+            if self.renamed_statements_generated(label, renaming) && self.extend(renaming, used) {
+                return true;
+            }
+            renaming.remove(label);
+            used.remove(candidate);
+        }
+        false
+    }
+
+    // Whether the statements with the blank node whose blank nodes are all renamed are generated ones
+    fn renamed_statements_generated(&self, label: &str, renaming: &HashMap<&str, &str>) -> bool {
+        self.statements_of[label].iter()
+            .filter(|statement| blank_node_labels(statement).all(|label| renaming.contains_key(label)))
+            .all(|statement| self.generated.contains(&relabel(statement, |label| renaming[label].to_string())))
+    }
+}
+
+fn blank_node_labels(statement: &Statement) -> impl Iterator<Item = &str> {
+    [Some(&statement.subject), Some(&statement.object), statement.graph.as_ref()].into_iter()
+        .flatten()
+        .filter_map(|term| match term {
+            Term::BlankNode(label) => Some(label.as_str()),
+            _ => None
+        })
+}
+
+fn relabel(statement: &Statement, new_label: impl Fn(&str) -> String) -> Statement {
+    let relabel_term = |term: &Term| match term {
+        Term::BlankNode(label) => Term::BlankNode(new_label(label)),
+        _ => term.clone()
+    };
+    Statement {
+        subject: relabel_term(&statement.subject),
+        predicate: statement.predicate.clone(),
+        object: relabel_term(&statement.object),
+        graph: statement.graph.as_ref().map(relabel_term)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statements(lines: &[&str]) -> Vec<Statement> {
+        lines.iter().map(|line| Statement::from_nquads(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn graphs_compared() {
+        let expected = statements(&[
+            "_:a <http://example.com/knows> _:b .",
+            "_:a <http://example.com/name> \"Alice\" .",
+            "_:b <http://example.com/name> \"Bob\" ."
+        ]);
+        let relabelled = statements(&[
+            "_:x1 <http://example.com/name> \"Bob\" .",
+            "_:x0 <http://example.com/knows> _:x1 .",
+            "_:x0 <http://example.com/name> \"Alice\" .",
+            "_:x0 <http://example.com/name> \"Alice\" ."
+        ]);
+        assert_eq!(Ok(()), compare_graphs(&expected, &relabelled));
+
+        let reversed = statements(&[
+            "_:x1 <http://example.com/knows> _:x0 .",
+            "_:x0 <http://example.com/name> \"Alice\" .",
+            "_:x1 <http://example.com/name> \"Bob\" ."
+        ]);
+        let differences = compare_graphs(&expected, &reversed).unwrap_err();
+        assert!(differences.starts_with("3 statements missing, 3 unexpected"), "{differences}");
+    }
+
+    #[test]
+    fn blank_nodes_that_look_alike_compared() {
+        let cycle = |labels: &[&str]| -> Vec<Statement> {
+            labels.iter().zip(labels.iter().cycle().skip(1))
+                .map(|(from, to)| Statement::from_nquads(&format!("_:{from} <http://example.com/next> _:{to} .")).unwrap())
+                .collect()
+        };
+        let hexagon = cycle(&["a", "b", "c", "d", "e", "f"]);
+        let renamed_hexagon = cycle(&["u", "w", "y", "v", "x", "z"]);
+        assert_eq!(Ok(()), compare_graphs(&hexagon, &renamed_hexagon));
+
+        // every blank node is in the same statements in both, but the ones of two triangles are not of a hexagon
+        let mut triangles = cycle(&["u", "v", "w"]);
+        triangles.extend(cycle(&["x", "y", "z"]));
+        assert!(compare_graphs(&hexagon, &triangles).is_err());
+    }
+
+    #[test]
+    fn outcome_matrix() {
+        let result = |name: &str, outcome: TestOutcome| TestCaseResult { name: name.to_string(), outcome };
+        let report = TestSuiteReport { results: vec![
+            result("RMLTC0000-CSV", TestOutcome::Passed),
+            result("RMLTC0000-JSON", TestOutcome::Failed("1 statements missing, 0 unexpected".to_string())),
+            result("RMLTC0001-JSON", TestOutcome::Error("Cannot read plan".to_string()))
+        ]};
+        assert_eq!(report.matrix(), "\
+test case  CSV    JSON
+RMLTC0000  pass   FAIL
+RMLTC0001         ERROR");
+        assert_eq!(report.passed(), 1);
+        assert!(!report.all_passed());
+        assert!(report.to_string().ends_with("1 passed, 1 failed, 1 errors"));
+
+        let earl = report.to_earl(RML_CORE_TEST_BASE_IRI);
+        assert_eq!(3, earl.matches("a earl:Assertion").count());
+        assert!(earl.contains("earl:test <http://w3id.org/rml/core/test/RMLTC0000-CSV> ;"));
+        assert_eq!("RMLTC0000-CSV", test_id("core/RMLTC0000-CSV"));
+        assert_eq!(2, earl.matches("earl:outcome earl:failed").count());
+        assert!(earl.contains("earl:info \"1 statements missing, 0 unexpected\" ;"));
+        assert_eq!("\"a \\\"quoted\\\"\\nline\"", turtle_string("a \"quoted\"\nline"));
+    }
+}
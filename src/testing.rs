@@ -0,0 +1,110 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Golden-file tests of mappings: execute a mapping on its input with the installed mopper version, and
+//! compare the generated statements with the expected ones in a file. Meant to be used in the tests of
+//! projects that depend on mopper to generate their data, e.g.
+//!
+//! ```no_run
+//! #[test]
+//! fn people() -> std::io::Result<()> {
+//!     let run_stats = mopper::testing::exec("tests/people")?;
+//!     assert_eq!(10, run_stats.records_read());
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use crate::mopper_options::MopperOptionsBuilder;
+use crate::rdf::Statement;
+use crate::stats::RunStats;
+use crate::test_suite::{compare_graphs, read_plan};
+use crate::start;
+
+/// The file a test case's generated statements are written to, in its directory.
+pub const GENERATED_OUTPUT_FILE: &str = "output-mopper.nq";
+
+/// Executes the test case in `test_dir` with the default options, like [exec_with_options].
+pub fn exec(test_dir: impl AsRef<Path>) -> io::Result<RunStats> {
+    exec_with_options(test_dir, &MopperOptionsBuilder::default())
+}
+
+/// Executes the test case in `test_dir` — a directory with the mapping, as AlgeMapLoom plan `mapping.json`
+/// or in RML as `mapping.ttl`, its sources and the expected statements in `output.nq` — with the given options.
+/// The generated statements are written to `output-mopper.nq` in the same directory, ignoring the targets of the
+/// mapping, and compared with the expected ones regardless of their order, duplicates and blank node labels.
+/// Returns the statistics of the run.
+///
+/// # Panics
+/// If the run fails, or if the generated statements differ from the expected ones, like an assertion.
+pub fn exec_with_options(test_dir: impl AsRef<Path>, options_builder: &MopperOptionsBuilder) -> io::Result<RunStats> {
+    let test_dir = test_dir.as_ref();
+    let generated_output_file = test_dir.join(GENERATED_OUTPUT_FILE);
+    let options = options_builder.clone()
+        .force_to_file(generated_output_file.to_string_lossy())
+        .working_dir_hint(test_dir.to_string_lossy())
+        .build()
+        .unwrap_or_else(|err| panic!("Invalid options for test case {}: {err}", test_dir.display()));
+
+    let plan = read_plan(test_dir).map_err(io::Error::other)?;
+    let run_stats = start(&plan, &options)
+        .unwrap_or_else(|err| panic!("Test case {} failed: {err}", test_dir.display()));
+    assert_same_statements(test_dir.join("output.nq"), generated_output_file)?;
+    Ok(run_stats)
+}
+
+/// Compares the statements in two N-Triples or N-Quads files regardless of their order, duplicates and
+/// blank node labels.
+///
+/// # Panics
+/// If the files contain different statements, or a line that is not a statement, like an assertion.
+pub fn assert_same_statements(expected_file: impl AsRef<Path>, generated_file: impl AsRef<Path>) -> io::Result<()> {
+    let parse = |file: &Path| -> io::Result<Vec<Statement>> {
+        Ok(read_and_sort(file)?.iter()
+            .map(|line| Statement::from_nquads(line)
+                .unwrap_or_else(|| panic!("Not a statement in {}: {line}", file.display())))
+            .collect())
+    };
+    let expected = parse(expected_file.as_ref())?;
+    let generated = parse(generated_file.as_ref())?;
+    if let Err(differences) = compare_graphs(&expected, &generated) {
+        panic!("The statements in {} differ from the expected ones in {}: {differences}",
+               generated_file.as_ref().display(), expected_file.as_ref().display());
+    }
+    Ok(())
+}
+
+/// Reads the lines of a file with statements, without empty lines and comments and with repeated white space
+/// replaced by one space, so two outputs with the same statements in another order give the same set.
+pub fn read_and_sort<P: AsRef<Path>>(file: P) -> io::Result<HashSet<String>> {
+    let file_handle = File::open(file)?;
+    let rdr = BufReader::new(file_handle);
+    
+    let result: HashSet<String> = rdr.lines().map_while(Result::ok)
+        .filter(|line| !line.trim().is_empty())
+        .filter(|line| !line.trim().starts_with('#'))
+        .map(|line| {
+                    // remove repeated white space
+                    let parts: Vec<_> = line.split_whitespace().collect();
+                    parts.join(" ")
+                })
+                .collect();
+    Ok(result)
+}
@@ -19,67 +19,1321 @@ mod test_cases {
     use std::collections::HashSet;
     use std::fs;
     use std::fs::File;
-    use std::io::{BufRead, BufReader, Error};
-    use std::path::Path;
-    use crate::start;
-    use crate::mopper_options::MopperOptionsBuilder;
+    use std::io;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::ops::ControlFlow;
+    use std::thread;
+    use std::time::Duration;
+    use std::io::Error;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use flate2::read::GzDecoder;
+    use serde_json::{json, Value};
+    use crate::{count, direct_mapping_to_plan, start, start_external, start_from_path, start_from_reader, start_plan, start_plans, start_streaming, turtle_base_iri};
+    use crate::daemon::{run_daemon, run_reloading_daemon, Schedule};
+    use crate::error::MopperError;
+    use crate::function::counter::{COUNTER_FUNCTION, COUNTER_START_PARAMETER};
+    use crate::mopper_options::{DeduplicationHasher, DeduplicationStrategy, ErrorMode, InvalidIriPolicy, MissingReferencePolicy, MopperOptionsBuilder};
+    use crate::plan::{merge_plans, PlanGraph};
+    use crate::plan_rewriter::rewrite;
+    use crate::rdf::Statement;
+    use crate::sink::{OutputLock, Sink, SinkFactory};
+    use crate::source::{Source, SourceFactory, TableSource};
+    use crate::source::csv_file::CsvDialect;
+    use crate::stats::OperatorKind;
+    use crate::test_suite::run_test_suite;
+    use crate::testing::{assert_same_statements, exec, exec_with_options, read_and_sort};
 
-    fn exec(test_dir: &str) -> Result<(), Error> {
-        let test_dir_path = Path::new(test_dir);
-        let mapping_file = test_dir_path.join("mapping.json");
-        let expected_output_file = test_dir_path.join("output.nq");
-        let mopper_output_file = test_dir_path.join("output-mopper.nq");
+    // The number of temporary files named so far
+    static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
 
-        // set some options
+    // A temporary file of its own, named after the process and a counter, so tests running at the same time,
+    // also in other processes, do not use the same file
+    fn temp_path(name: &str, extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mopper-{name}-{}-{}.{extension}", std::process::id(), TEMP_FILES.fetch_add(1, Ordering::Relaxed)))
+    }
+
+    #[test]
+    fn rml_tc_0000_csv() -> Result<(), Error> {
+        exec("test-resources/rml-testcases/RMLTC0000-CSV")?;
+        Ok(())
+    }
+
+    #[test]
+    fn rml_tc_0008b_csv() -> Result<(), Error> {
+        exec("test-resources/rml-testcases/RMLTC0008b-CSV")?;
+        Ok(())
+    }
+
+    #[test]
+    fn rml_tc_1036_csv() -> Result<(), Error> {
+        let run_stats = exec("test-resources/rml-testcases/RMLTC1036-CSV")?;
+        // sport.csv and student.csv
+        assert_eq!(10, run_stats.records_read());
+        // 32 distinct statements, but without deduplication some are written more than once
+        assert_eq!(38, run_stats.statements_written());
+        Ok(())
+    }
+
+    #[test]
+    fn blank_nodes() -> Result<(), Error> {
+        // mopper labels the blank nodes after the IDs, the expected output after the names
+        let run_stats = exec("test-resources/tests/blank-nodes")?;
+        assert_eq!(6, run_stats.statements_written());
+        Ok(())
+    }
+
+    #[test]
+    fn avro_source() -> Result<(), Error> {
+        let run_stats = exec("test-resources/tests/avro")?;
+        assert_eq!(3, run_stats.records_read());
+        Ok(())
+    }
+
+    #[test]
+    fn zip_archive_source() -> Result<(), Error> {
+        // the people are read from people.zip!data/people.csv
+        let run_stats = exec("test-resources/tests/zip")?;
+        assert_eq!(6, run_stats.statements_written());
+        Ok(())
+    }
+
+    #[test]
+    fn rml_test_suite() {
+        let options = MopperOptionsBuilder::default().build().unwrap();
+        let report = run_test_suite("test-resources/rml-testcases", &options).unwrap();
+        assert_eq!(3, report.results.len());
+        assert!(report.all_passed(), "{report}");
+    }
+
+    #[test]
+    fn shacl_validation() -> Result<(), Error> {
+        let mut options = MopperOptionsBuilder::default();
+        options.shacl_shapes_file("test-resources/tests/shacl/shapes.ttl");
+        let run_stats = exec_with_options("test-resources/rml-testcases/RMLTC1036-CSV", &options)?;
+
+        // Only the label of sport 400 is too long, and it is generated by serializer 14
+        let validation = run_stats.validation.unwrap();
+        assert!(!validation.conforms());
+        assert_eq!(1, validation.violations.len());
+        let violation = &validation.violations[0];
+        assert_eq!("<http://example.com/resource/sport_400>", violation.focus_node);
+        assert_eq!(vec![14], violation.mapping_rules);
+        assert_eq!(vec![Some(14)], validation.by_mapping_rule().into_keys().collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn void_description() -> Result<(), Error> {
+        let void_file = std::env::temp_dir().join("mopper-void-description.ttl");
+        let mut options = MopperOptionsBuilder::default();
+        options.void_file(void_file.to_str().unwrap());
+        let run_stats = exec_with_options("test-resources/rml-testcases/RMLTC1036-CSV", &options)?;
+
+        // 38 statements are generated, some of them twice
+        let dataset = run_stats.dataset.unwrap();
+        assert_eq!(32, dataset.triples);
+        assert_eq!(Some(&6), dataset.classes.get("http://example.com/ontology/Student"));
+        let void = fs::read_to_string(&void_file)?;
+        assert!(void.contains("void:triples 32 ;"));
+        assert!(void.contains("RMLTC1036-CSV/student.csv> ;"));
+        fs::remove_file(void_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn dataset_report() -> Result<(), Error> {
+        let report_file = std::env::temp_dir().join("mopper-dataset-report.csv");
+        let mut options = MopperOptionsBuilder::default();
+        options.dataset_report_file(report_file.to_str().unwrap());
+        exec_with_options("test-resources/rml-testcases/RMLTC1036-CSV", &options)?;
+
+        let report = fs::read_to_string(&report_file)?;
+        let rows: Vec<&str> = report.lines().collect();
+        assert_eq!("statistic,iri,count", rows[0]);
+        assert!(rows.contains(&"distinct_subjects,,10"));
+        assert!(rows.contains(&"class_triples,http://example.com/ontology/Student,24"));
+        assert!(rows.contains(&"datatype_literals,http://www.w3.org/2001/XMLSchema#string,10"));
+        fs::remove_file(report_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn sparql_query() -> Result<(), Error> {
+        let results_file = std::env::temp_dir().join("mopper-sparql-query.csv");
+        let mut options = MopperOptionsBuilder::default();
+        options.sparql_query(("test-resources/tests/sparql/practises.rq".to_string(), results_file.to_str().unwrap().to_string()));
+        exec_with_options("test-resources/rml-testcases/RMLTC1036-CSV", &options)?;
+
+        // The practises statements are in two graphs, but every solution is only found once
+        let results = fs::read_to_string(&results_file)?;
+        let rows: Vec<&str> = results.lines().collect();
+        assert_eq!(vec!["name,sport", "De René,Vissen", "Demi Moore,Fietsen"], rows[..3]);
+        assert_eq!(7, rows.len());
+        fs::remove_file(results_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn rewritten_plan() -> Result<(), Error> {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?;
+        let plan_graph = PlanGraph::from_json(&plan).unwrap();
+        let reduced_plan = rewrite(&plan_graph, true).unwrap();
+        // 2 sources, 1 join, 3 extends, 3 serializers and 1 target
+        assert_eq!(10, reduced_plan.len());
+
+        let mopper_output_file = std::env::temp_dir().join("mopper-rewritten-plan.nq");
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(mopper_output_file.to_str().unwrap())
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+        start_plan(&reduced_plan, &options).unwrap();
+        assert_same_statements(Path::new(test_dir).join("output.nq"), &mopper_output_file)?;
+        fs::remove_file(mopper_output_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn plans_with_concurrent_sources_limited() -> Result<(), Error> {
+        // two mappings in one run, with three sources and a join between two of them, one source at a time
+        let test_dirs = ["test-resources/rml-testcases/RMLTC1036-CSV", "test-resources/rml-testcases/RMLTC0008b-CSV"];
+        let mut plans = Vec::new();
+        let mut expected = String::new();
+        for test_dir in test_dirs {
+            plans.push((fs::read_to_string(Path::new(test_dir).join("mapping.json"))?, Some(test_dir.to_string())));
+            expected.push_str(&fs::read_to_string(Path::new(test_dir).join("output.nq"))?);
+        }
+        let plan = merge_plans(&plans).unwrap();
+        let expected_file = temp_path("concurrent-sources-expected", "nq");
+        fs::write(&expected_file, expected)?;
+
+        let mopper_output_file = temp_path("concurrent-sources", "nq");
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(mopper_output_file.to_str().unwrap())
+            .max_concurrent_sources(1)
+            .build().unwrap();
+        start(&plan, &options).unwrap();
+        assert_same_statements(&expected_file, &mopper_output_file)?;
+        fs::remove_file(expected_file)?;
+        fs::remove_file(mopper_output_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn several_plans() -> Result<(), Error> {
+        // the first and last plan read the same sources, into their own files
+        let test_dirs = ["test-resources/rml-testcases/RMLTC1036-CSV", "test-resources/rml-testcases/RMLTC0008b-CSV", "test-resources/rml-testcases/RMLTC1036-CSV"];
+        let mut plans = Vec::new();
+        let mut output_files = Vec::new();
+        for (plan_nr, test_dir) in test_dirs.iter().enumerate() {
+            let output_file = std::env::temp_dir().join(format!("mopper-several-plans-{}-{plan_nr}.nq", std::process::id()));
+            let mut plan: Value = serde_json::from_str(&fs::read_to_string(Path::new(test_dir).join("mapping.json"))?)?;
+            for node in plan["nodes"].as_array_mut().unwrap() {
+                if node["operator"]["type"] == "TargetOp" {
+                    node["operator"]["config"] = json!({"target_type": "File", "data_format": "NQuads", "path": output_file.to_str().unwrap()});
+                }
+            }
+            plans.push((plan.to_string(), Some(test_dir.to_string())));
+            output_files.push(output_file);
+        }
+        let options = MopperOptionsBuilder::default()
+            .max_concurrent_sources(2)
+            .build().unwrap();
+        let (run_stats, plan_stats) = start_plans(&plans, &options).unwrap();
+        assert_eq!(3, plan_stats.len());
+        for (test_dir, output_file) in test_dirs.iter().zip(&output_files) {
+            assert_same_statements(Path::new(test_dir).join("output.nq"), output_file)?;
+        }
+        // every plan gets the statistics of a run of its own
+        for ((plan, test_dir), stats) in plans.iter().zip(test_dirs).zip(&plan_stats) {
+            let options = MopperOptionsBuilder::default().working_dir_hint(test_dir).build().unwrap();
+            let own_run_stats = start(&plan.0, &options).unwrap();
+            assert_eq!(own_run_stats.records_read(), stats.records_read());
+            assert_eq!(own_run_stats.statements_written(), stats.statements_written());
+        }
+        // the sources the first and last plan have in common are read once
+        assert_eq!(plan_stats[0].records_read() + plan_stats[1].records_read(), run_stats.records_read());
+        assert_eq!(plan_stats.iter().map(|stats| stats.statements_written()).sum::<u64>(), run_stats.statements_written());
+        for output_file in output_files {
+            fs::remove_file(output_file)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn streaming() -> Result<(), Error> {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?;
+        let options = MopperOptionsBuilder::default()
+            .working_dir_hint(test_dir)
+            .deduplicate(true)
+            .build().unwrap();
+        let mut stream = start_streaming(&plan, &options).unwrap();
+        let statements: Vec<Statement> = stream.by_ref().collect();
+        let run_stats = stream.finish().unwrap();
+        assert_eq!(32, run_stats.statements_written());
+
+        let streamed_output: HashSet<String> = statements.iter().map(|statement| statement.to_string()).collect();
+        assert_eq!(read_and_sort(Path::new(test_dir).join("output.nq"))?, streamed_output);
+        assert!(statements.iter().all(|statement| statement.graph.is_some()));
+        Ok(())
+    }
+
+    #[test]
+    fn record_limit() -> Result<(), Error> {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?;
+        let options = MopperOptionsBuilder::default()
+            .working_dir_hint(test_dir)
+            .record_limit(2)
+            .build().unwrap();
+        let mut stream = start_streaming(&plan, &options).unwrap();
+        stream.by_ref().for_each(drop);
+        let run_stats = stream.finish().unwrap();
+        assert!(run_stats.operators_of_kind(OperatorKind::Source).all(|source| source.records_out == 2));
+        assert_eq!(4, run_stats.records_read());
+        Ok(())
+    }
+
+    #[test]
+    fn sample() -> Result<(), Error> {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?;
+        let sample = |seed: u64| {
+            let options = MopperOptionsBuilder::default()
+                .working_dir_hint(test_dir)
+                .sample_fraction(0.5)
+                .sample_seed(seed)
+                .build().unwrap();
+            let mut stream = start_streaming(&plan, &options).unwrap();
+            let statements: HashSet<String> = stream.by_ref().map(|statement| statement.to_string()).collect();
+            let run_stats = stream.finish().unwrap();
+            (statements, run_stats.records_read())
+        };
+        let (statements, records_read) = sample(1);
+        assert!(records_read < 10, "{records_read}");
+        assert_eq!(statements, sample(1).0);
+
+        assert!(MopperOptionsBuilder::default().sample_fraction(0.0).build().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_options() {
+        let output_file = std::env::temp_dir().join("mopper-invalid-options.nq");
+        let output_file = output_file.to_str().unwrap();
+        assert!(MopperOptionsBuilder::default().force_to_file(output_file).build().is_ok());
+
+        let error = MopperOptionsBuilder::default().force_to_std_out(true).force_to_file(output_file).build().unwrap_err();
+        assert_eq!(error.to_string(), "Output cannot be forced both to standard out and to a file");
+        let error = MopperOptionsBuilder::default().force_to_file("does/not/exist/output.nq").build().unwrap_err();
+        assert!(error.to_string().starts_with("Cannot write output to does/not/exist/output.nq"), "{error}");
+        let temp_dir = std::env::temp_dir();
+        assert!(MopperOptionsBuilder::default().force_to_file(temp_dir.to_str().unwrap()).build().is_err());
+        assert!(MopperOptionsBuilder::default().write_buffer_capacity(0).build().is_err());
+        assert!(MopperOptionsBuilder::default()
+            .deduplication_strategy(DeduplicationStrategy::Approximate { false_positive_rate: 1.0, expected_statements: 10 })
+            .build().is_err());
+        assert!(MopperOptionsBuilder::default().deduplication_strategy(DeduplicationStrategy::Sharded { shards: 0 }).build().is_err());
+        let error = MopperOptionsBuilder::default()
+            .deduplicate_globally(true)
+            .deduplication_strategy(DeduplicationStrategy::ExternalSort { max_in_memory: 10, spill_dir: None })
+            .build().unwrap_err();
+        assert_eq!(error.to_string(), "Global deduplication is not supported with external sort deduplication");
+        let error = MopperOptionsBuilder::default()
+            .deduplicate_index_dir(temp_dir.to_str().unwrap())
+            .deduplication_strategy(DeduplicationStrategy::Sharded { shards: 2 })
+            .build().unwrap_err();
+        assert_eq!(error.to_string(), "A deduplication index is only supported with the exact deduplication strategy");
+        assert!(MopperOptionsBuilder::default().max_concurrent_files(0).build().is_err());
+        assert!(MopperOptionsBuilder::default().max_concurrent_sources(0).build().is_err());
+        let error = MopperOptionsBuilder::default().throughput_log_interval(Duration::ZERO).build().unwrap_err();
+        assert_eq!(error.to_string(), "The throughput log interval must be longer than 0");
+        let error = MopperOptionsBuilder::default().tag_targets(true).build().unwrap_err();
+        assert_eq!(error.to_string(), "Targets can only be tagged when output is forced to standard out");
+    }
+
+    #[test]
+    fn tagged_targets() -> Result<(), Error> {
+        // the sports go to a file target, the rest to standard out
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let mut plan: Value = serde_json::from_str(&fs::read_to_string(Path::new(test_dir).join("mapping.json"))?)?;
+        plan["nodes"][15]["operator"]["config"] = json!({"target_type": "File", "data_format": "NQuads", "path": "sports.nq"});
+        let sinks = |tag_targets: bool| -> Vec<usize> {
+            let options = MopperOptionsBuilder::default()
+                .force_to_std_out(true)
+                .tag_targets(tag_targets)
+                .working_dir_hint(test_dir)
+                .build().unwrap();
+            start(&plan.to_string(), &options).unwrap().operators.iter()
+                .filter(|operator_stats| operator_stats.kind == OperatorKind::Sink)
+                .map(|operator_stats| operator_stats.node_id)
+                .collect()
+        };
+        assert_eq!(1, sinks(false).len());
+        // the targets are kept apart to tell their statements apart
+        assert_eq!(2, sinks(true).len());
+        assert!(!Path::new(test_dir).join("sports.nq").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn count_per_target() -> Result<(), Error> {
+        // one of the targets is a file, which is not written when counting
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?
+            .replacen(r#"{"target_type":"StdOut","data_format":"NQuads"}"#, r#"{"target_type":"File","data_format":"NQuads","path":"sports.nq"}"#, 1);
+        let options = MopperOptionsBuilder::default()
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+        let (run_stats, target_counts) = count(&plan, &options).unwrap();
+        let targets: Vec<(&str, u64)> = target_counts.iter()
+            .map(|target_count| (target_count.target.as_str(), target_count.statements))
+            .collect();
+        assert_eq!(vec![("File sports.nq", 18), ("StdOut", 20)], targets);
+        assert_eq!(run_stats.statements_written(), target_counts.iter().map(|target_count| target_count.statements).sum::<u64>());
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_file_target() -> Result<(), Error> {
+        // the sports go to a gzipped N-Triples file, the rest to standard out
+        let target_file = std::env::temp_dir().join(format!("mopper-compressed-file-target-{}.nt.gz", std::process::id()));
+        let mut plan = sports::plan();
+        plan["nodes"][15]["operator"]["config"] = json!({
+            "target_type": "File", "data_format": "NTriples", "path": target_file.to_str().unwrap(), "compression": "http://w3id.org/rml/gzip"
+        });
+        let options = MopperOptionsBuilder::default()
+            .working_dir_hint(sports::TEST_DIR)
+            .build().unwrap();
+        start(&plan.to_string(), &options).unwrap();
+
+        let mut statements = String::new();
+        GzDecoder::new(File::open(&target_file)?).read_to_string(&mut statements)?;
+        let mut statements: Vec<&str> = statements.lines().collect();
+        statements.sort();
+        assert_eq!(8, statements.len());
+        assert_eq!("<http://example.com/resource/sport_100> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://example.com/ontology/Sport> .", statements[0]);
+        fs::remove_file(target_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn record_provenance() -> Result<(), Error> {
+        // label the sports with the file and number of the record they come from
+        let mut plan = sports::plan();
+        plan["nodes"][1]["operator"]["config"]["projection_attributes"] = json!(["Name", "ID", "_rownum", "_filename"]);
+        plan["nodes"][13]["operator"]["config"]["?tm0_o0_0"]["inner_function"] = json!({
+            "type": "TemplateFunctionValue", "template": "{_filename}#{_rownum}",
+            "variable_function_pairs": [["_filename", {"type": "Reference", "value": "_filename"}], ["_rownum", {"type": "Reference", "value": "_rownum"}]]
+        });
+        let (_run_stats, statements) = sports::exec_plan(plan, &MopperOptionsBuilder::default()).unwrap();
+        assert_eq!(vec![
+            r#"<http://example.com/resource/sport_100> <http://www.w3.org/2000/01/rdf-schema#label> "sport.csv#1" ."#,
+            r#"<http://example.com/resource/sport_200> <http://www.w3.org/2000/01/rdf-schema#label> "sport.csv#2" ."#,
+            r#"<http://example.com/resource/sport_300> <http://www.w3.org/2000/01/rdf-schema#label> "sport.csv#3" ."#,
+            r#"<http://example.com/resource/sport_400> <http://www.w3.org/2000/01/rdf-schema#label> "sport.csv#4" ."#
+        ], sports::labels(&statements));
+        Ok(())
+    }
+
+    #[test]
+    fn csv_dialect() -> Result<(), Error> {
+        // sports separated by semicolons and quoted with single quotes, and separated by tabs
+        let sport_file = std::env::temp_dir().join(format!("mopper-csv-dialect-sport-{}.csv", std::process::id()));
+        fs::write(&sport_file, "# sports of the school\nID;Name\n100;'Tennis; indoor'\n200;Football\n")?;
+        let tsv_sport_file = std::env::temp_dir().join(format!("mopper-csv-dialect-sport-{}.tsv", std::process::id()));
+        fs::write(&tsv_sport_file, "ID\tName\n100\tTennis, indoor\n")?;
+        let source_config = sports::plan()["nodes"][0]["operator"]["config"].clone();
+        let options = MopperOptionsBuilder::default();
+
+        let mut csv_config = source_config.clone();
+        csv_config["path"] = json!(sport_file.to_str().unwrap());
+        csv_config["delimiter"] = json!(";");
+        csv_config["quoteChar"] = json!("'");
+        csv_config["commentPrefix"] = json!("#");
+        let (_run_stats, statements) = sports::exec(csv_config, &options).unwrap();
+        assert_eq!(vec![
+            r#"<http://example.com/resource/sport_100> <http://www.w3.org/2000/01/rdf-schema#label> "Tennis; indoor" ."#,
+            r#"<http://example.com/resource/sport_200> <http://www.w3.org/2000/01/rdf-schema#label> "Football" ."#
+        ], sports::labels(&statements));
+
+        let mut tsv_config = source_config.clone();
+        tsv_config["path"] = json!(tsv_sport_file.to_str().unwrap());
+        let (_run_stats, statements) = sports::exec(tsv_config, &options).unwrap();
+        assert_eq!(vec![
+            r#"<http://example.com/resource/sport_100> <http://www.w3.org/2000/01/rdf-schema#label> "Tennis, indoor" ."#
+        ], sports::labels(&statements));
+
+        let mut invalid_config = source_config;
+        invalid_config["delimiter"] = json!("||");
+        let err = sports::exec(invalid_config, &options).unwrap_err();
+        assert!(err.to_string().contains("Invalid delimiter: '||' is not a single ASCII character"), "{err}");
+
+        for file in [sport_file, tsv_sport_file] {
+            fs::remove_file(file)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_sources() -> Result<(), Error> {
+        // the sports compressed with zstd, recognised by the extension, and with bzip2, as configured
+        let sports = fs::read(Path::new(sports::TEST_DIR).join("sport.csv"))?;
+        let zstd_sport_file = std::env::temp_dir().join(format!("mopper-compressed-sport-{}.csv.zst", std::process::id()));
+        fs::write(&zstd_sport_file, zstd::encode_all(sports.as_slice(), 0)?)?;
+        let bzip2_sport_file = std::env::temp_dir().join(format!("mopper-compressed-sport-{}.data", std::process::id()));
+        let mut bzip2 = bzip2::write::BzEncoder::new(File::create(&bzip2_sport_file)?, bzip2::Compression::default());
+        bzip2.write_all(&sports)?;
+        bzip2.finish()?;
+
+        for (sport_file, compression) in [(&zstd_sport_file, None), (&bzip2_sport_file, Some("bzip2"))] {
+            let mut source_config = sports::plan()["nodes"][0]["operator"]["config"].clone();
+            source_config["path"] = json!(sport_file.to_str().unwrap());
+            if let Some(compression) = compression {
+                source_config["compression"] = json!(compression);
+            }
+            let (_run_stats, statements) = sports::exec(source_config, &MopperOptionsBuilder::default()).unwrap();
+            assert_eq!(4, sports::labels(&statements).len(), "{}", sport_file.display());
+        }
+
+        for file in [zstd_sport_file, bzip2_sport_file] {
+            fs::remove_file(file)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn glob_source() -> Result<(), Error> {
+        // the sports are in sport-1.csv and sport-2.csv, with the columns in another order; sport-3.csv has no Name
+        let mut source_config = sports::plan()["nodes"][0]["operator"]["config"].clone();
+        source_config["path"] = json!("test-resources/tests/glob/sport-*.csv");
+        let (run_stats, statements) = sports::exec(source_config.clone(), &MopperOptionsBuilder::default()).unwrap();
+        let labels = sports::labels(&statements);
+        assert_eq!(4, labels.len());
+        assert!(labels.iter().any(|label| label.contains("sport_300") && label.contains("\"Vissen\"")));
+        assert_eq!(1, run_stats.errors());
+
+        // the same when the files are read at the same time
+        let mut options = MopperOptionsBuilder::default();
+        options.max_concurrent_files(3);
+        let (run_stats, concurrent_statements) = sports::exec(source_config.clone(), &options).unwrap();
+        assert_eq!(labels, sports::labels(&concurrent_statements));
+        assert_eq!(1, run_stats.errors());
+
+        // the file without Name stops a strict run
+        let mut options = MopperOptionsBuilder::default();
+        options.error_mode(ErrorMode::Strict);
+        let err = sports::exec(source_config, &options).unwrap_err();
+        assert!(err.to_string().contains("sport-3.csv does not have the columns"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn websocket_source() -> Result<(), Error> {
+        // every sport is a message of one CSV line, until the endpoint closes the connection
+        let sports = fs::read_to_string(Path::new(sports::TEST_DIR).join("sport.csv"))?;
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("ws://{}/sports", listener.local_addr()?);
+        let server = thread::spawn(move || {
+            let mut socket = tungstenite::accept(listener.accept().unwrap().0).unwrap();
+            for line in sports.lines().skip(1) {
+                socket.send(tungstenite::Message::Text(line.to_string())).unwrap();
+            }
+            socket.close(None).unwrap();
+            while socket.read().is_ok() {}
+        });
+
+        let source_config = json!({"source_type": "Websocket", "url": url, "columns": "ID,Name",
+            "root_iterator": {"reference": null, "reference_formulation": "CSVRows", "fields": [], "alias": null}});
+        let (_run_stats, statements) = sports::exec(source_config, &MopperOptionsBuilder::default()).unwrap();
+        server.join().unwrap();
+        assert_eq!(4, sports::labels(&statements).len());
+        Ok(())
+    }
+
+    #[test]
+    fn websocket_source_timeout() -> Result<(), Error> {
+        // the server accepts the connection, but never completes the handshake
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("ws://{}/sports", listener.local_addr()?);
+        thread::spawn(move || {
+            let _stream = listener.accept();
+            thread::sleep(Duration::from_secs(10));
+        });
+
+        let source_config = json!({"source_type": "Websocket", "url": url, "columns": "ID,Name",
+            "root_iterator": {"reference": null, "reference_formulation": "CSVRows", "fields": [], "alias": null}});
+        let mut options = MopperOptionsBuilder::default();
+        options.connect_timeout(Duration::from_millis(100));
+        let error = sports::exec(source_config, &options).unwrap_err();
+        assert!(matches!(error, MopperError::Timeout { kind: OperatorKind::Source, .. }), "{error}");
+        Ok(())
+    }
+
+    #[test]
+    fn ldes_source() -> Result<(), Error> {
+        // every sport is a member of the event stream, with its columns as properties, on a single page
+        let members: String = fs::read_to_string(Path::new(sports::TEST_DIR).join("sport.csv"))?.lines().skip(1)
+            .filter_map(|line| line.split_once(','))
+            .map(|(id, name)| format!("<sports> tree:member <#{id}> . <#{id}> ex:ID \"{id}\" ; ex:Name \"{name}\" .\n"))
+            .collect();
+        let page = format!("@prefix tree: <https://w3id.org/tree#> .\n@prefix ex: <http://example.com/> .\n{members}");
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/sports", listener.local_addr()?);
+        let server = thread::spawn(move || {
+            let mut stream = listener.accept().unwrap().0;
+            let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+            while !lines.next().unwrap().unwrap().is_empty() {}
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/turtle\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{page}", page.len()).unwrap();
+        });
+
+        let source_config = json!({"source_type": "File", "source": "LDES", "url": url,
+            "root_iterator": {"reference": null, "reference_formulation": "CSVRows", "fields": [], "alias": null}});
+        let (_run_stats, statements) = sports::exec(source_config, &MopperOptionsBuilder::default()).unwrap();
+        server.join().unwrap();
+        assert_eq!(4, sports::labels(&statements).len());
+        Ok(())
+    }
+
+    #[test]
+    fn ldes_source_timeout() -> Result<(), Error> {
+        // the server accepts the connection, but never answers
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/sports", listener.local_addr()?);
+        thread::spawn(move || {
+            let _stream = listener.accept();
+            thread::sleep(Duration::from_secs(10));
+        });
+
+        let source_config = json!({"source_type": "File", "source": "LDES", "url": url,
+            "root_iterator": {"reference": null, "reference_formulation": "CSVRows", "fields": [], "alias": null}});
+        let mut options = MopperOptionsBuilder::default();
+        options.connect_timeout(Duration::from_millis(100)).source_read_timeout(Duration::from_millis(100));
+        let error = sports::exec(source_config, &options).unwrap_err();
+        assert!(matches!(error, MopperError::Timeout { kind: OperatorKind::Source, .. }), "{error}");
+        Ok(())
+    }
+
+    #[cfg(feature = "duckdb")]
+    #[test]
+    fn duckdb_source() -> Result<(), Error> {
+        // the sports are filtered by DuckDB, straight from the CSV file, which is relative to the working directory
+        let source_config = json!({"source_type": "File", "source": "duckdb",
+            "query": format!("SELECT ID, Name FROM read_csv('{}/sport.csv', all_varchar = true) WHERE ID <> '400'", sports::TEST_DIR),
+            "root_iterator": {"reference": null, "reference_formulation": "CSVRows", "fields": [], "alias": null}});
+        let (_run_stats, statements) = sports::exec(source_config, &MopperOptionsBuilder::default()).unwrap();
+        assert_eq!(3, sports::labels(&statements).len());
+        Ok(())
+    }
+
+    #[cfg(feature = "kafka")]
+    #[test]
+    fn kafka_source() -> Result<(), Error> {
+        use rdkafka::config::ClientConfig;
+        use rdkafka::mocking::MockCluster;
+        use rdkafka::producer::{BaseProducer, BaseRecord, Producer};
+
+        // the sports are a CSV message on a topic, which is read until the record limit as it does not end
+        let cluster = MockCluster::new(1).map_err(Error::other)?;
+        cluster.create_topic("sports", 1, 1).map_err(Error::other)?;
+        let producer: BaseProducer = ClientConfig::new()
+            .set("bootstrap.servers", cluster.bootstrap_servers())
+            .create().map_err(Error::other)?;
+        let sports = fs::read(Path::new(sports::TEST_DIR).join("sport.csv"))?;
+        producer.send(BaseRecord::<(), [u8]>::to("sports").payload(&sports)).map_err(|(err, _)| Error::other(err))?;
+        producer.flush(Duration::from_secs(10)).map_err(Error::other)?;
+
+        let source_config = json!({"source_type": "Kafka", "brokers": cluster.bootstrap_servers(), "topic": "sports", "groupId": "mopper",
+            "root_iterator": {"reference": null, "reference_formulation": "CSVRows", "fields": [], "alias": null}});
+        let mut options = MopperOptionsBuilder::default();
+        options.record_limit(4);
+        let (_run_stats, statements) = sports::exec(source_config, &options).unwrap();
+        assert_eq!(4, sports::labels(&statements).len());
+        Ok(())
+    }
+
+    #[test]
+    fn counter() -> Result<(), Error> {
+        // number the sports from 1000 instead of identifying them by ID
+        let mut plan = sports::plan();
+        plan["nodes"][13]["operator"]["config"]["?tm0_sm"]["inner_function"] = json!({
+            "type": "TemplateFunctionValue", "template": "http://example.com/sport/{n}",
+            "variable_function_pairs": [["n", {"type": "FnO", "fno_identifier": COUNTER_FUNCTION,
+                "parameters": [[COUNTER_START_PARAMETER, {"type": "Constant", "value": "1000"}]]}]]
+        });
+        let (_run_stats, statements) = sports::exec_plan(plan, &MopperOptionsBuilder::default()).unwrap();
+        assert_eq!(vec![
+            r#"<http://example.com/sport/1000> <http://www.w3.org/2000/01/rdf-schema#label> "Tennis" ."#,
+            r#"<http://example.com/sport/1001> <http://www.w3.org/2000/01/rdf-schema#label> "Fietsen" ."#,
+            r#"<http://example.com/sport/1002> <http://www.w3.org/2000/01/rdf-schema#label> "Vissen" ."#,
+            r#"<http://example.com/sport/1003> <http://www.w3.org/2000/01/rdf-schema#label> "De sport die niemand doet" ."#
+        ], sports::labels(&statements));
+        Ok(())
+    }
+
+    #[test]
+    fn deduplicate_single_sink() -> Result<(), Error> {
+        // all statements go to sink 9
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?;
+        let mopper_output_file = std::env::temp_dir().join("mopper-deduplicate-single-sink.nq");
         let options = MopperOptionsBuilder::default()
             .force_to_file(mopper_output_file.to_str().unwrap())
             .working_dir_hint(test_dir)
+            .deduplicate(true)
+            .deduplicate_sink((9, false))
             .build().unwrap();
+        assert_eq!(38, start(&plan, &options).unwrap().statements_written());
 
-        // execute the plan
-        let plan = fs::read_to_string(mapping_file).unwrap();
-        let result = start(&plan, &options);
-        assert!(result.is_ok());
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(mopper_output_file.to_str().unwrap())
+            .working_dir_hint(test_dir)
+            .deduplicate_sink((9, true))
+            .deduplication_hasher(DeduplicationHasher::Fx)
+            .deduplication_capacity(16)
+            .build().unwrap();
+        assert_eq!(32, start(&plan, &options).unwrap().statements_written());
+        assert_same_statements(Path::new(test_dir).join("output.nq"), &mopper_output_file)?;
+        fs::remove_file(mopper_output_file)?;
+        Ok(())
+    }
 
-        // compare results
-        let expected_output = read_and_sort(expected_output_file)?;
-        let mopper_output = read_and_sort(mopper_output_file)?;
-        assert_eq!(expected_output, mopper_output);
+    #[test]
+    fn deduplicate_globally_with_shards() -> Result<(), Error> {
+        struct CollectingSink(Arc<Mutex<Vec<String>>>);
+        impl Sink for CollectingSink {
+            fn write(&mut self, statements: &str) -> io::Result<()> {
+                self.0.lock().unwrap().extend(statements.lines().map(|statement| statement.to_string()));
+                Ok(())
+            }
+        }
 
+        // both targets share the sharded deduplicator; the one finishing first must not stop it for the other
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let mut plan: Value = serde_json::from_str(&fs::read_to_string(Path::new(test_dir).join("mapping.json"))?
+            .replace("\"target_type\":\"StdOut\"", "\"target_type\":\"Kafka\""))?;
+        plan["nodes"][15]["operator"]["config"] = json!({"target_type": "Kafka", "data_format": "NQuads", "topic": "sports"});
+        let plan = plan.to_string();
+        let statements = Arc::new(Mutex::new(Vec::new()));
+        let sink_statements = statements.clone();
+        let options = MopperOptionsBuilder::default()
+            .working_dir_hint(test_dir)
+            .deduplicate(true)
+            .deduplicate_globally(true)
+            .deduplication_strategy(DeduplicationStrategy::Sharded { shards: 2 })
+            .sink(("Kafka".to_string(), SinkFactory::new(move |_target| Ok(Box::new(CollectingSink(sink_statements.clone()))))))
+            .build().unwrap();
+        let run_stats = start(&plan, &options).unwrap();
+        let collected_output = statements.lock().unwrap().clone();
+        let distinct_output: HashSet<String> = collected_output.iter().cloned().collect();
+        assert_eq!(2, run_stats.operators.iter().filter(|operator_stats| operator_stats.kind == OperatorKind::Sink).count());
+        assert_eq!(distinct_output.len(), collected_output.len());
+        assert_eq!(read_and_sort(Path::new(test_dir).join("output.nq"))?, distinct_output);
+        Ok(())
+    }
+
+    #[test]
+    fn custom_sink() -> Result<(), Error> {
+        struct CollectingSink(Arc<Mutex<Vec<String>>>);
+        impl Sink for CollectingSink {
+            fn write(&mut self, statements: &str) -> io::Result<()> {
+                self.0.lock().unwrap().extend(statements.lines().map(|statement| statement.to_string()));
+                Ok(())
+            }
+        }
+
+        // without a custom sink, Kafka targets are not supported
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?
+            .replace("\"target_type\":\"StdOut\"", "\"target_type\":\"Kafka\"");
+        let options = MopperOptionsBuilder::default()
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+        assert!(matches!(start(&plan, &options), Err(MopperError::Validation(_))));
+
+        let statements = Arc::new(Mutex::new(Vec::new()));
+        let sink_statements = statements.clone();
+        let options = MopperOptionsBuilder::default()
+            .working_dir_hint(test_dir)
+            .deduplicate(true)
+            .sink(("Kafka".to_string(), SinkFactory::new(move |_target| Ok(Box::new(CollectingSink(sink_statements.clone()))))))
+            .build().unwrap();
+        start(&plan, &options).unwrap();
+        let collected_output: HashSet<String> = statements.lock().unwrap().iter().cloned().collect();
+        assert_eq!(read_and_sort(Path::new(test_dir).join("output.nq"))?, collected_output);
         Ok(())
     }
 
-    fn read_and_sort<P: AsRef<Path>>(file: P) -> Result<HashSet<String>, Error>{
-        let file_handle = File::open(file)?;
-        let rdr = BufReader::new(file_handle);
-        
-        let result: HashSet<String> = rdr.lines().flatten()
-            .filter(|line| !line.trim().is_empty())
-            .filter(|line| !line.trim().starts_with('#'))
-            .map(|line| {
-                        // remove repeated white space
-                        let parts: Vec<_> = line.split_whitespace().collect();
-                        parts.join(" ")
-                    })
+    #[test]
+    fn custom_source() -> Result<(), Error> {
+        // the sports come from a table in memory instead of sport.csv
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?
+            .replace("\"path\":\"sport.csv\"", "\"source\":\"sports\"");
+        let options = MopperOptionsBuilder::default()
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+        assert!(matches!(start(&plan, &options), Err(MopperError::Validation(_))));
+
+        let mopper_output_file = std::env::temp_dir().join("mopper-custom-source.nq");
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(mopper_output_file.to_str().unwrap())
+            .working_dir_hint(test_dir)
+            .source(("sports".to_string(), SourceFactory::new(|_source| {
+                let fields = vec!["Name".to_string(), "ID".to_string()];
+                let records = [("Tennis", "100"), ("Fietsen", "200"), ("Vissen", "300"), ("De sport die niemand doet", "400")].iter()
+                    .map(|(name, id)| vec![name.to_string(), id.to_string()])
                     .collect();
-        Ok(result)
+                Ok(Box::new(TableSource::new(fields, records)))
+            })))
+            .build().unwrap();
+        start(&plan, &options).unwrap();
+        assert_same_statements(Path::new(test_dir).join("output.nq"), &mopper_output_file)?;
+        fs::remove_file(mopper_output_file)?;
+        Ok(())
     }
 
     #[test]
-    fn rml_tc_0000_csv() -> Result<(), Error> {
-        exec("test-resources/rml-testcases/RMLTC0000-CSV")?;
+    fn timeouts() -> Result<(), Error> {
+        struct HangingSource;
+        impl Source for HangingSource {
+            fn fields(&mut self) -> io::Result<Vec<String>> {
+                Ok(vec!["Name".to_string(), "ID".to_string()])
+            }
+            fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+                thread::sleep(Duration::from_secs(10));
+                Ok(None)
+            }
+        }
+        struct HangingSink;
+        impl Sink for HangingSink {
+            fn write(&mut self, _statements: &str) -> io::Result<()> {
+                thread::sleep(Duration::from_secs(10));
+                Ok(())
+            }
+        }
+
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?
+            .replace("\"path\":\"sport.csv\"", "\"source\":\"sports\"");
+        let options = MopperOptionsBuilder::default()
+            .force_to_std_out(true)
+            .working_dir_hint(test_dir)
+            .source(("sports".to_string(), SourceFactory::new(|_source| Ok(Box::new(HangingSource)))))
+            .source_read_timeout(Duration::from_millis(100))
+            .build().unwrap();
+        let error = start(&plan, &options).unwrap_err();
+        assert!(matches!(error, MopperError::Timeout { kind: OperatorKind::Source, .. }), "{error}");
+
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?;
+        let options = MopperOptionsBuilder::default()
+            .working_dir_hint(test_dir)
+            .sink(("StdOut".to_string(), SinkFactory::new(|_target| Ok(Box::new(HangingSink)))))
+            .sink_write_timeout(Duration::from_millis(100))
+            .build().unwrap();
+        let error = start(&plan, &options).unwrap_err();
+        assert!(error.to_string().contains("timed out: Cannot write output: no response within 100ms"), "{error}");
+        assert_eq!(Some(OperatorKind::Sink), error.operator_kind());
         Ok(())
     }
 
     #[test]
-    fn rml_tc_0008b_csv() -> Result<(), Error> {
-        exec("test-resources/rml-testcases/RMLTC0008b-CSV")?;
+    fn atomic_output_file() -> Result<(), Error> {
+        struct FailingSource;
+        impl Source for FailingSource {
+            fn fields(&mut self) -> io::Result<Vec<String>> {
+                Ok(vec!["Name".to_string(), "ID".to_string()])
+            }
+            fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+                Err(io::Error::other("connection lost"))
+            }
+        }
+
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let output_file = std::env::temp_dir().join("mopper-atomic-output-file.nq");
+        fs::write(&output_file, "previous output\n")?;
+
+        // a failed run leaves the previous output as it was
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?
+            .replace("\"path\":\"sport.csv\"", "\"source\":\"sports\"");
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(output_file.to_str().unwrap())
+            .working_dir_hint(test_dir)
+            .source(("sports".to_string(), SourceFactory::new(|_source| Ok(Box::new(FailingSource)))))
+            .error_mode(ErrorMode::Strict)
+            .build().unwrap();
+        assert!(start(&plan, &options).is_err());
+        assert_eq!("previous output\n", fs::read_to_string(&output_file)?);
+
+        // a successful run replaces it
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?;
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(output_file.to_str().unwrap())
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+        start(&plan, &options).unwrap();
+        assert_same_statements(Path::new(test_dir).join("output.nq"), &output_file)?;
+        assert!(!output_file.with_file_name(format!(".mopper-atomic-output-file.nq.mopper-{}.tmp", std::process::id())).exists());
+        fs::remove_file(output_file)?;
         Ok(())
     }
 
     #[test]
-    fn rml_tc_1036_csv() -> Result<(), Error> {
-        exec("test-resources/rml-testcases/RMLTC1036-CSV")?;
+    fn locked_output_file() -> Result<(), Error> {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let output_file = std::env::temp_dir().join("mopper-locked-output-file.nq");
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?;
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(output_file.to_str().unwrap())
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+
+        // as if another run is writing the file
+        let output_lock = OutputLock::acquire(&output_file)?;
+        let error = start(&plan, &options).unwrap_err();
+        assert!(error.to_string().contains("is being written by another run"), "{error}");
+        assert!(!output_file.exists());
+
+        drop(output_lock);
+        start(&plan, &options).unwrap();
+        assert_same_statements(Path::new(test_dir).join("output.nq"), &output_file)?;
+        assert!(!output_file.with_file_name(".mopper-locked-output-file.nq.mopper.lock").exists());
+        fs::remove_file(output_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn external_source() -> Result<(), Error> {
+        // the sports are pushed instead of read from sport.csv
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?
+            .replace("\"path\":\"sport.csv\"", "\"source\":\"external\",\"name\":\"sports\"");
+        let mopper_output_file = std::env::temp_dir().join("mopper-external-source.nq");
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(mopper_output_file.to_str().unwrap())
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+        let engine = start_external(&plan, &options).unwrap();
+        assert_eq!(vec!["sports"], engine.source_names());
+        for (id, name) in [("100", "Tennis"), ("200", "Fietsen"), ("300", "Vissen"), ("400", "De sport die niemand doet")] {
+            engine.push("sports", [("ID", id), ("Name", name)]).unwrap();
+        }
+        assert!(matches!(engine.push("students", [("ID", "10")]), Err(MopperError::Push { .. })));
+        engine.finish().unwrap();
+        assert_same_statements(Path::new(test_dir).join("output.nq"), &mopper_output_file)?;
+        fs::remove_file(mopper_output_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn remote_sources() -> Result<(), Error> {
+        // the mapping is "remote", so its sources are read from the web server it is on
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let base_url = format!("http://{}/RMLTC1036-CSV", listener.local_addr()?);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                BufReader::new(stream.try_clone().unwrap()).read_line(&mut request_line).unwrap();
+                let file_name = request_line.split(' ').nth(1).unwrap().rsplit('/').next().unwrap();
+                let body = fs::read(Path::new(test_dir).join(file_name)).unwrap();
+                write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()).unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?;
+        let mopper_output_file = std::env::temp_dir().join("mopper-remote-sources.nq");
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(mopper_output_file.to_str().unwrap())
+            .working_dir_hint(base_url)
+            .build().unwrap();
+        start(&plan, &options).unwrap();
+        assert_same_statements(Path::new(test_dir).join("output.nq"), &mopper_output_file)?;
+        fs::remove_file(mopper_output_file)?;
+
+        // all places where a missing file is looked for are reported
+        let plan = plan.replace("\"path\":\"sport.csv\"", "\"path\":\"sports.csv\"");
+        let options = MopperOptionsBuilder::default()
+            .force_to_std_out(true)
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+        match start(&plan, &options) {
+            Err(MopperError::Source { msg, .. }) => assert_eq!(format!("File not found: sports.csv, looked for it at sports.csv, {test_dir}/sports.csv"), msg),
+            Err(other) => panic!("unexpected error {other}"),
+            Ok(_) => panic!("missing file not reported")
+        }
         Ok(())
     }
+
+    #[test]
+    fn start_from_path_or_reader() -> Result<(), Error> {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let mopper_output_file = std::env::temp_dir().join("mopper-from-path.nq");
+
+        // the sources are found next to the plan
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(mopper_output_file.to_str().unwrap())
+            .build().unwrap();
+        start_from_path(Path::new(test_dir).join("mapping.json"), &options).unwrap();
+        assert_same_statements(Path::new(test_dir).join("output.nq"), &mopper_output_file)?;
+        assert!(matches!(start_from_path(Path::new(test_dir).join("no-mapping.json"), &options), Err(MopperError::Plan { .. })));
+
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(mopper_output_file.to_str().unwrap())
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+        start_from_reader(File::open(Path::new(test_dir).join("mapping.json"))?, &options).unwrap();
+        assert_same_statements(Path::new(test_dir).join("output.nq"), &mopper_output_file)?;
+        fs::remove_file(mopper_output_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn daemon() -> Result<(), Error> {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?;
+        let mopper_output_file = std::env::temp_dir().join("mopper-daemon.nq");
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(mopper_output_file.to_str().unwrap())
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+        let schedule = Schedule { interval: Some(Duration::from_millis(10)), watch_sources: false };
+        let mut nr_of_runs = 0;
+        run_daemon(&plan, &options, &schedule, |result| {
+            assert_eq!(38, result.unwrap().statements_written());
+            nr_of_runs += 1;
+            if nr_of_runs == 3 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        }).unwrap();
+        assert_eq!(3, nr_of_runs);
+        assert_same_statements(Path::new(test_dir).join("output.nq"), &mopper_output_file)?;
+        fs::remove_file(mopper_output_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn daemon_reloads_mapping() -> Result<(), Error> {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let mapping_file = std::env::temp_dir().join("mopper-daemon-mapping.json");
+        fs::copy(Path::new(test_dir).join("mapping.json"), &mapping_file)?;
+        let mopper_output_file = std::env::temp_dir().join("mopper-daemon-reload.nq");
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(mopper_output_file.to_str().unwrap())
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+        let schedule = Schedule { interval: None, watch_sources: true };
+        let load_plan = || fs::read_to_string(&mapping_file).map_err(|err| MopperError::plan(err.to_string()));
+        let mut nr_of_runs = 0;
+        run_reloading_daemon(std::slice::from_ref(&mapping_file), load_plan, &options, &schedule, |result| {
+            result.unwrap();
+            nr_of_runs += 1;
+            if nr_of_runs == 1 {
+                let changed_mapping = fs::read_to_string(&mapping_file).unwrap().replace("rdf-schema#label", "rdf-schema#comment");
+                fs::write(&mapping_file, changed_mapping).unwrap();
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        }).unwrap();
+        assert_eq!(2, nr_of_runs);
+        let output = read_and_sort(&mopper_output_file)?;
+        assert_eq!(4, output.iter().filter(|statement| statement.contains("rdf-schema#comment")).count());
+        fs::remove_file(mopper_output_file)?;
+        fs::remove_file(mapping_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn default_graph() -> Result<(), Error> {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let plan = fs::read_to_string(Path::new(test_dir).join("mapping.json"))?
+            .replace("http://example.com/graph/sports", "http://www.w3.org/ns/r2rml#defaultGraph");
+        let mopper_output_file = std::env::temp_dir().join("mopper-default-graph.nq");
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(mopper_output_file.to_str().unwrap())
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+        start(&plan, &options).unwrap();
+        let expected_output: HashSet<String> = read_and_sort(Path::new(test_dir).join("output.nq"))?.iter()
+            .map(|statement| statement.replace(" <http://example.com/graph/sports> .", " ."))
+            .collect();
+        assert_eq!(expected_output, read_and_sort(&mopper_output_file)?);
+        fs::remove_file(mopper_output_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn graph_partitioned_output() -> Result<(), Error> {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let output_dir = std::env::temp_dir().join(format!("mopper-graphs-{}", std::process::id()));
+        fs::create_dir_all(&output_dir)?;
+        let mut plan: Value = serde_json::from_str(&fs::read_to_string(Path::new(test_dir).join("mapping.json"))?)?;
+        for node in plan["nodes"].as_array_mut().unwrap() {
+            if node["operator"]["type"] == "TargetOp" {
+                node["operator"]["config"] = json!({"target_type": "File", "data_format": "NQuads", "path": output_dir.join("{graph}.nq").to_str().unwrap()});
+            }
+        }
+        let options = MopperOptionsBuilder::default().working_dir_hint(test_dir).build().unwrap();
+        start(&plan.to_string(), &options).unwrap();
+
+        let expected_output = read_and_sort(Path::new(test_dir).join("output.nq"))?;
+        for graph in ["sports", "students", "practise"] {
+            let graph_file = output_dir.join(format!("http%3A%2F%2Fexample.com%2Fgraph%2F{graph}.nq"));
+            let expected_graph_output: HashSet<String> = expected_output.iter()
+                .filter(|statement| statement.ends_with(&format!(" <http://example.com/graph/{graph}> .")))
+                .cloned()
+                .collect();
+            assert_eq!(expected_graph_output, read_and_sort(&graph_file)?);
+        }
+        // no statements in the default graph, so no file for it
+        let output_files = fs::read_dir(&output_dir)?
+            .filter(|entry| entry.as_ref().is_ok_and(|entry| !entry.file_name().to_string_lossy().starts_with('.')))
+            .count();
+        assert_eq!(3, output_files);
+        fs::remove_dir_all(output_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn direct_mapping() -> Result<(), Error> {
+        let plan = direct_mapping_to_plan("test-resources/rml-testcases/RMLTC1036-CSV/sport.csv", "http://example.com/base/", &CsvDialect::default()).unwrap();
+        let mopper_output_file = std::env::temp_dir().join("mopper-direct-mapping.nq");
+        let options = MopperOptionsBuilder::default()
+            .force_to_file(mopper_output_file.to_str().unwrap())
+            .build().unwrap();
+        start(&plan, &options).unwrap();
+        let mopper_output = read_and_sort(&mopper_output_file)?;
+        // 4 sports, with a type and 2 columns each
+        assert_eq!(12, mopper_output.len());
+        assert!(mopper_output.contains("<http://example.com/base/sport/ID=100;Name=Tennis> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://example.com/base/sport> ."));
+        assert!(mopper_output.contains("<http://example.com/base/sport/ID=100;Name=Tennis> <http://example.com/base/sport#ID> \"100\" ."));
+        assert!(mopper_output.contains("<http://example.com/base/sport/ID=400;Name=De%20sport%20die%20niemand%20doet> <http://example.com/base/sport#Name> \"De sport die niemand doet\" ."));
+        fs::remove_file(mopper_output_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn missing_reference() -> Result<(), Error> {
+        // the source has no field Naam
+        let plan = direct_mapping_to_plan("test-resources/rml-testcases/RMLTC1036-CSV/sport.csv", "http://example.com/base/", &CsvDialect::default()).unwrap();
+        let mut plan: Value = serde_json::from_str(&plan)?;
+        plan["nodes"][1]["operator"]["config"]["projection_attributes"].as_array_mut().unwrap().push(json!("Naam"));
+        plan["nodes"][2]["operator"]["config"]["?o1"]["inner_function"]["value"] = json!("Naam");
+        let plan = plan.to_string();
+
+        let mopper_output_file = std::env::temp_dir().join("mopper-missing-reference.nq");
+        let run = |policy: MissingReferencePolicy| {
+            let options = MopperOptionsBuilder::default()
+                .force_to_file(mopper_output_file.to_str().unwrap())
+                .missing_reference_policy(policy)
+                .build().unwrap();
+            start(&plan, &options)
+        };
+
+        // only the type and ID of every sport
+        run(MissingReferencePolicy::SkipStatement).unwrap();
+        assert_eq!(8, read_and_sort(&mopper_output_file)?.len());
+
+        run(MissingReferencePolicy::Empty).unwrap();
+        let mopper_output = read_and_sort(&mopper_output_file)?;
+        assert_eq!(12, mopper_output.len());
+        assert!(mopper_output.contains("<http://example.com/base/sport/ID=100;Name=Tennis> <http://example.com/base/sport#Name> \"\" ."));
+
+        run(MissingReferencePolicy::SkipRecord).unwrap();
+        assert!(read_and_sort(&mopper_output_file)?.is_empty());
+
+        assert!(run(MissingReferencePolicy::Fail).is_err());
+        fs::remove_file(mopper_output_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_iri() -> Result<(), Error> {
+        // the name of sport 400 has spaces, so it is no valid IRI
+        let plan = direct_mapping_to_plan("test-resources/rml-testcases/RMLTC1036-CSV/sport.csv", "http://example.com/base/", &CsvDialect::default()).unwrap();
+        let mut plan: Value = serde_json::from_str(&plan)?;
+        plan["nodes"][2]["operator"]["config"]["?o1"] = json!({"type": "Iri", "base_iri": "http://example.com/base/", "inner_function": {"type": "Reference", "value": "Name"}});
+        let plan = plan.to_string();
+
+        let mopper_output_file = std::env::temp_dir().join(format!("mopper-invalid-iri-{}.nq", std::process::id()));
+        let run = |policy: InvalidIriPolicy| {
+            let options = MopperOptionsBuilder::default()
+                .force_to_file(mopper_output_file.to_str().unwrap())
+                .invalid_iri_policy(policy)
+                .build().unwrap();
+            start(&plan, &options)
+        };
+
+        // only the name of sport 400 is missing
+        let run_stats = run(InvalidIriPolicy::SkipStatement).unwrap();
+        let mopper_output = read_and_sort(&mopper_output_file)?;
+        assert_eq!(11, mopper_output.len());
+        assert!(mopper_output.contains("<http://example.com/base/sport/ID=100;Name=Tennis> <http://example.com/base/sport#Name> <http://example.com/base/Tennis> ."));
+        assert!(mopper_output.contains("<http://example.com/base/sport/ID=400;Name=De%20sport%20die%20niemand%20doet> <http://example.com/base/sport#ID> \"400\" ."));
+        assert_eq!(1, run_stats.invalid_iris());
+        assert_eq!(0, run_stats.errors());
+
+        // sport 400 is missing altogether
+        let run_stats = run(InvalidIriPolicy::Quarantine).unwrap();
+        let mopper_output = read_and_sort(&mopper_output_file)?;
+        assert_eq!(9, mopper_output.len());
+        assert!(mopper_output.iter().all(|statement| !statement.contains("ID=400")));
+        assert_eq!(1, run_stats.invalid_iris());
+        assert_eq!(1, run_stats.errors());
+
+        let err = run(InvalidIriPolicy::Fail).unwrap_err();
+        assert!(err.to_string().contains("Invalid IRI: http://example.com/base/De sport die niemand doet"), "{err}");
+        fs::remove_file(mopper_output_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn error_mode() -> Result<(), Error> {
+        // the name of sport 400 has spaces, so its record cannot be processed
+        let plan = direct_mapping_to_plan("test-resources/rml-testcases/RMLTC1036-CSV/sport.csv", "http://example.com/base/", &CsvDialect::default()).unwrap();
+        let mut plan: Value = serde_json::from_str(&plan)?;
+        plan["nodes"][2]["operator"]["config"]["?o1"] = json!({"type": "Iri", "base_iri": "http://example.com/base/", "inner_function": {"type": "Reference", "value": "Name"}});
+        let plan = plan.to_string();
+
+        let mopper_output_file = std::env::temp_dir().join(format!("mopper-error-mode-{}.nq", std::process::id()));
+        let run = |error_mode: ErrorMode, policy: InvalidIriPolicy| {
+            let options = MopperOptionsBuilder::default()
+                .force_to_file(mopper_output_file.to_str().unwrap())
+                .error_mode(error_mode)
+                .invalid_iri_policy(policy)
+                .build().unwrap();
+            start(&plan, &options)
+        };
+
+        // the bad record is skipped and counted, the others are written
+        let run_stats = run(ErrorMode::Lenient, InvalidIriPolicy::Quarantine).unwrap();
+        let mopper_output = read_and_sort(&mopper_output_file)?;
+        assert_eq!(9, mopper_output.len());
+        assert!(mopper_output.iter().all(|statement| !statement.contains("ID=400")));
+        assert_eq!(4, run_stats.records_read());
+        assert_eq!(1, run_stats.errors());
+
+        // the bad record stops the run, even if the policy would only skip a statement
+        for policy in [InvalidIriPolicy::Quarantine, InvalidIriPolicy::SkipStatement] {
+            let err = run(ErrorMode::Strict, policy).unwrap_err();
+            assert!(err.to_string().contains("Invalid IRI"), "{err}");
+        }
+        // and leaves the output of the lenient run in place
+        assert_eq!(mopper_output, read_and_sort(&mopper_output_file)?);
+        fs::remove_file(mopper_output_file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn base_iri_of_mapping() -> Result<(), Error> {
+        let mapping = fs::read_to_string("test-resources/rml-testcases/RMLTC1036-CSV/mapping.ttl")?;
+        assert_eq!(Some("http://example.com/base/".to_string()), turtle_base_iri(&mapping));
+        assert_eq!(Some("http://example.org/".to_string()), turtle_base_iri("PREFIX ex: <http://example.com/>\nbase <http://example.org/>"));
+        assert_eq!(None, turtle_base_iri("@prefix based: <http://example.com/based/> ."));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_plan() {
+        let options = MopperOptionsBuilder::default().build().unwrap();
+        let result = start("{ not a plan", &options);
+        assert!(matches!(result, Err(MopperError::Plan { .. })));
+    }
+
+    #[test]
+    fn invalid_node_in_plan() {
+        let options = MopperOptionsBuilder::default().build().unwrap();
+        let plan = r#"{"nodes": [{"id": "Source_0", "operator": 5}], "node_holes": [], "edge_property": "directed", "edges": []}"#;
+        let error = start(plan, &options).unwrap_err();
+        let msg = error.to_string();
+        assert!(msg.contains("line 1"), "{msg}");
+        assert!(msg.contains("nodes[0].operator"), "{msg}");
+        assert!(msg.contains("Source_0"), "{msg}");
+    }
+
+    #[test]
+    fn secrets_of_files_and_commands_not_resolved_by_default() -> Result<(), Error> {
+        let test_dir = "test-resources/rml-testcases/RMLTC1036-CSV";
+        let marker_file = std::env::temp_dir().join(format!("mopper-secret-command-{}", std::process::id()));
+        let mut plan: Value = serde_json::from_str(&fs::read_to_string(Path::new(test_dir).join("mapping.json"))?)?;
+        plan["nodes"][0]["operator"]["config"]["password"] = json!(format!("${{secret:cmd:touch {}}}", marker_file.display()));
+        let options = MopperOptionsBuilder::default()
+            .working_dir_hint(test_dir)
+            .build().unwrap();
+        let Err(MopperError::Plan { msg, .. }) = start(&plan.to_string(), &options) else {
+            panic!("Secrets of commands should not be resolved by default");
+        };
+        assert!(msg.contains("Unknown credentials provider 'cmd'"), "{msg}");
+        assert!(!marker_file.exists());
+
+        plan["nodes"][0]["operator"]["config"]["password"] = json!("${secret:file:/etc/passwd}");
+        let Err(MopperError::Plan { msg, .. }) = start(&plan.to_string(), &options) else {
+            panic!("Secrets of files should not be resolved by default");
+        };
+        assert!(msg.contains("Unknown credentials provider 'file'"), "{msg}");
+        Ok(())
+    }
+
+    /// The test case the source, function and target tests of mopper itself run on: it gives the sports in
+    /// sport.csv a type and their name as label, in node 13, and writes those statements to the target in node 15.
+    mod sports {
+        use std::fs;
+        use std::path::Path;
+        use serde_json::{json, Value};
+        use crate::error::MopperError;
+        use crate::mopper_options::MopperOptionsBuilder;
+        use crate::start;
+        use crate::stats::RunStats;
+        use super::temp_path;
+
+        pub(super) const TEST_DIR: &str = "test-resources/rml-testcases/RMLTC1036-CSV";
+
+        /// The plan of the test case, to change before executing it with [exec_plan].
+        pub(super) fn plan() -> Value {
+            let plan = fs::read_to_string(Path::new(TEST_DIR).join("mapping.json")).unwrap();
+            serde_json::from_str(&plan).unwrap()
+        }
+
+        /// Executes the plan of the test case with the sports read by a source with the given configuration,
+        /// like [exec_plan].
+        pub(super) fn exec(source_config: Value, options_builder: &MopperOptionsBuilder) -> Result<(RunStats, Vec<String>), MopperError> {
+            let mut plan = plan();
+            plan["nodes"][0]["operator"]["config"] = source_config;
+            exec_plan(plan, options_builder)
+        }
+
+        /// Executes a plan of the test case with the given options, and returns the statistics of the run and the
+        /// statements about the sports, in N-Triples and sorted. They are written to a temporary file of their own,
+        /// so tests running at the same time do not mix their outputs.
+        pub(super) fn exec_plan(mut plan: Value, options_builder: &MopperOptionsBuilder) -> Result<(RunStats, Vec<String>), MopperError> {
+            let target_file = temp_path("sports", "nt");
+            plan["nodes"][15]["operator"]["config"] = json!({"target_type": "File", "data_format": "NTriples", "path": target_file.to_str().unwrap()});
+            let options = options_builder.clone()
+                .working_dir_hint(TEST_DIR)
+                .build().unwrap();
+            let run_stats = start(&plan.to_string(), &options)
+                .inspect_err(|_| {
+                    // a failed run may have created the file all the same
+                    let _ = fs::remove_file(&target_file);
+                })?;
+            let mut statements: Vec<String> = fs::read_to_string(&target_file).unwrap().lines()
+                .map(String::from)
+                .collect();
+            statements.sort();
+            fs::remove_file(target_file).unwrap();
+            Ok((run_stats, statements))
+        }
+
+        /// The labels among the statements.
+        pub(super) fn labels(statements: &[String]) -> Vec<&str> {
+            statements.iter()
+                .map(String::as_str)
+                .filter(|statement| statement.contains("rdf-schema#label"))
+                .collect()
+        }
+    }
 }
@@ -0,0 +1,172 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Timeouts on calls that can hang, like reading from or writing to a remote system. A call that does not
+//! return in time fails with [io::ErrorKind::TimedOut], which the operator turns into a
+//! [crate::error::MopperError::Timeout]. The call itself cannot be interrupted: its thread is left behind.
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+use crossbeam_channel::{bounded, RecvTimeoutError, Sender};
+use crate::sink::Sink;
+use crate::source::Source;
+
+/// Runs the function in a thread of its own, and waits at most `timeout` for its result.
+pub(crate) fn with_timeout<R: Send + 'static>(name: String, timeout: Duration, function: impl FnOnce() -> R + Send + 'static) -> io::Result<R> {
+    let (sender, receiver) = bounded(1);
+    thread::Builder::new()
+        .name(name)
+        .spawn(move || {
+            let _ = sender.send(function());
+        })?;
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => Ok(result),
+        Err(RecvTimeoutError::Timeout) => Err(timed_out(timeout)),
+        Err(RecvTimeoutError::Disconnected) => Err(io::Error::other("the call panicked"))
+    }
+}
+
+fn timed_out(timeout: Duration) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, format!("no response within {timeout:?}"))
+}
+
+type Call<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+/// Owns an object in a thread of its own, and makes the calls on it there, waiting at most the
+/// timeout for each. After a call timed out, the object is not called anymore.
+struct TimeLimited<T> {
+    calls: Sender<Call<T>>,
+    timeout: Duration,
+    timed_out: bool
+}
+
+impl<T: Send + 'static> TimeLimited<T> {
+    fn new(name: String, mut object: T, timeout: Duration) -> Self {
+        let (calls, receiver) = bounded::<Call<T>>(1);
+        thread::Builder::new()
+            .name(name)
+            .spawn(move || {
+                for call in receiver {
+                    call(&mut object);
+                }
+            }).unwrap();
+        TimeLimited { calls, timeout, timed_out: false }
+    }
+
+    fn call<R: Send + 'static>(&mut self, function: impl FnOnce(&mut T) -> R + Send + 'static) -> io::Result<R> {
+        if self.timed_out {
+            return Err(timed_out(self.timeout));
+        }
+        let (sender, receiver) = bounded(1);
+        self.calls.send(Box::new(move |object: &mut T| {
+            let _ = sender.send(function(object));
+        })).map_err(|_| io::Error::other("an earlier call panicked"))?;
+        match receiver.recv_timeout(self.timeout) {
+            Ok(result) => Ok(result),
+            Err(RecvTimeoutError::Timeout) => {
+                self.timed_out = true;
+                Err(timed_out(self.timeout))
+            },
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::other("the call panicked"))
+        }
+    }
+}
+
+/// A source of which reading the fields or a record times out.
+pub(crate) struct TimeoutSource(TimeLimited<Box<dyn Source>>);
+
+impl TimeoutSource {
+    pub(crate) fn new(source: Box<dyn Source>, node_id: usize, timeout: Duration) -> Self {
+        TimeoutSource(TimeLimited::new(format!("Source reader {node_id}"), source, timeout))
+    }
+}
+
+impl Source for TimeoutSource {
+    fn fields(&mut self) -> io::Result<Vec<String>> {
+        self.0.call(|source| source.fields())?
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        self.0.call(|source| source.next_record())?
+    }
+}
+
+/// A sink of which writing or finishing times out.
+pub(crate) struct TimeoutSink(TimeLimited<Box<dyn Sink>>);
+
+impl TimeoutSink {
+    pub(crate) fn new(sink: Box<dyn Sink>, node_id: usize, timeout: Duration) -> Self {
+        TimeoutSink(TimeLimited::new(format!("Sink writer {node_id}"), sink, timeout))
+    }
+}
+
+impl Sink for TimeoutSink {
+    fn write(&mut self, statements: &str) -> io::Result<()> {
+        let statements = statements.to_string();
+        self.0.call(move |sink| sink.write(&statements))?
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.0.call(|sink| sink.finish())?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use crate::source::TableSource;
+    use super::*;
+
+    // A source that hangs on its third record
+    struct HangingSource(usize);
+
+    impl Source for HangingSource {
+        fn fields(&mut self) -> io::Result<Vec<String>> {
+            Ok(vec!["value".to_string()])
+        }
+
+        fn next_record(&mut self) -> io::Result<Option<Vec<String>>> {
+            self.0 += 1;
+            if self.0 == 3 {
+                thread::sleep(Duration::from_secs(5));
+            }
+            Ok(Some(vec![self.0.to_string()]))
+        }
+    }
+
+    #[test]
+    fn source_timeout() {
+        let mut source = TimeoutSource::new(Box::new(HangingSource(0)), 1, Duration::from_millis(50));
+        assert_eq!(vec!["value".to_string()], source.fields().unwrap());
+        assert_eq!(Some(vec!["1".to_string()]), source.next_record().unwrap());
+        assert_eq!(Some(vec!["2".to_string()]), source.next_record().unwrap());
+        let started = Instant::now();
+        assert_eq!(io::ErrorKind::TimedOut, source.next_record().unwrap_err().kind());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(io::ErrorKind::TimedOut, source.next_record().unwrap_err().kind());
+
+        let mut source = TimeoutSource::new(Box::new(TableSource::new(Vec::new(), Vec::new())), 2, Duration::from_millis(50));
+        assert_eq!(None, source.next_record().unwrap());
+    }
+
+    #[test]
+    fn call_timeout() {
+        assert_eq!(3, with_timeout("test".to_string(), Duration::from_secs(1), || 1 + 2).unwrap());
+        let result = with_timeout("test".to_string(), Duration::from_millis(50), || thread::sleep(Duration::from_secs(5)));
+        assert_eq!(io::ErrorKind::TimedOut, result.unwrap_err().kind());
+    }
+}
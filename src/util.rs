@@ -14,15 +14,53 @@
  *    limitations under the License.
  */
 
+use crossbeam_channel::Sender;
+use crate::message::Message;
+
+/// Sends `message` to every channel. Returns `false` if a receiving operator is gone, e.g. because it
+/// failed; the sending operator should then stop as well.
+pub fn send_to_all(tx_channels: &[Sender<Message>], message: Message) -> bool {
+    match tx_channels.split_last() {
+        Some((last_tx_chan, tx_channels)) => tx_channels.iter()
+            .all(|tx_chan| tx_chan.send(message.clone()).is_ok()) && last_tx_chan.send(message).is_ok(),
+        None => true
+    }
+}
+
+/// Removes the prefix `<join alias>_` from the variable name, if it has it.
 pub fn remove_join_alias_prefix(variable_name: &str, join_alias: &Option<String>) -> String {
-    match join_alias {
-        Some(alias) => {
-            if variable_name.starts_with(alias) {
-                variable_name[alias.len() + 1..].to_string()
-            } else {
-                variable_name.to_string()
-            }
-        },
-        None => variable_name.to_string()
+    join_alias.as_ref()
+        .and_then(|alias| variable_name.strip_prefix(alias.as_str()))
+        .and_then(|rest| rest.strip_prefix('_'))
+        .unwrap_or(variable_name)
+        .to_string()
+}
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::unbounded;
+    use super::*;
+
+    #[test]
+    fn message_sent_to_all() {
+        let (tx_1, rx_1) = unbounded();
+        let (tx_2, rx_2) = unbounded();
+        let message = Message::Data(vec!["a".to_string()]);
+        assert!(send_to_all(&[tx_1.clone(), tx_2], message.clone()));
+        assert_eq!(message, rx_1.recv().unwrap());
+        assert_eq!(message, rx_2.recv().unwrap());
+
+        drop(rx_2);
+        let (tx_2, _) = unbounded();
+        assert!(!send_to_all(&[tx_1, tx_2], Message::End));
+        assert_eq!(Message::End, rx_1.recv().unwrap());
+    }
+
+    #[test]
+    fn join_alias_prefix_removed() {
+        let join_alias = Some("join_0".to_string());
+        assert_eq!("Sport", remove_join_alias_prefix("join_0_Sport", &join_alias));
+        assert_eq!("join_01_Sport", remove_join_alias_prefix("join_01_Sport", &join_alias));
+        assert_eq!("join_0", remove_join_alias_prefix("join_0", &join_alias));
+        assert_eq!("join_0_Sport", remove_join_alias_prefix("join_0_Sport", &None));
     }
-}
\ No newline at end of file
+}
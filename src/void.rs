@@ -0,0 +1,298 @@
+/*
+ * Copyright 2024 Gerald Haesendonck
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+//! Statistics of the generated dataset, and [VoID](https://www.w3.org/TR/void/) descriptions of it: how many
+//! statements it has, its class and property partitions, and the sources it is generated from, so a
+//! published dataset describes itself.
+//!
+//! The statistics can also be written as a JSON or CSV report, to check quickly how a change to a mapping
+//! changes the generated data.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::io::BufWriter;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::SystemTime;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use serde::Serialize;
+use crate::logging::utc_timestamp;
+use crate::message::Message;
+use crate::rdf::{Statement, Term};
+use crate::test_suite::{turtle_string, MOPPER_IRI};
+
+const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+const RDF_LANG_STRING: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#langString";
+const XSD_STRING: &str = "http://www.w3.org/2001/XMLSchema#string";
+
+/// The statistics of a dataset, as its VoID description gives them.
+/// Statements generated more than once, e.g. by several mapping rules, are counted once.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DatasetStatistics {
+    /// The number of distinct triples or quads
+    pub triples: u64,
+    pub distinct_subjects: u64,
+    pub distinct_objects: u64,
+    /// The number of distinct instances of every class, by class IRI
+    pub classes: BTreeMap<String, u64>,
+    /// The number of triples or quads with an instance of every class as subject, by class IRI
+    pub class_triples: BTreeMap<String, u64>,
+    /// The number of triples or quads with every property, by property IRI
+    pub properties: BTreeMap<String, u64>,
+    /// The number of literals with every datatype, by datatype IRI. Literals without datatype are strings,
+    /// or language-tagged strings if they have a language tag.
+    pub datatypes: BTreeMap<String, u64>
+}
+
+impl DatasetStatistics {
+    pub fn from_statements<'a>(statements: impl IntoIterator<Item = &'a Statement>) -> Self {
+        let mut counter = StatisticsCounter::default();
+        for statement in statements {
+            counter.add(statement);
+        }
+        counter.finish()
+    }
+
+    /// Writes the statistics as a report to the given file: in CSV if its extension is `csv`, otherwise in JSON.
+    /// Every row of a CSV report has a statistic, the IRI of the class, property or datatype it is about, if any,
+    /// and a count.
+    pub fn write_report(&self, path: &str) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        if !path.to_lowercase().ends_with(".csv") {
+            serde_json::to_writer_pretty(writer, self)?;
+            return Ok(());
+        }
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["statistic", "iri", "count"])?;
+        for (statistic, count) in [("triples", self.triples), ("distinct_subjects", self.distinct_subjects), ("distinct_objects", self.distinct_objects)] {
+            writer.write_record([statistic, "", &count.to_string()])?;
+        }
+        let partitions = [("class_entities", &self.classes), ("class_triples", &self.class_triples),
+            ("property_triples", &self.properties), ("datatype_literals", &self.datatypes)];
+        for (statistic, counts) in partitions {
+            for (iri, count) in counts {
+                writer.write_record([statistic, iri, &count.to_string()])?;
+            }
+        }
+        writer.flush()
+    }
+
+    /// The VoID description of the dataset in Turtle, with the given sources and files the dataset
+    /// is written to as IRIs.
+    pub fn to_void(&self, sources: &[String], data_dumps: &[String]) -> String {
+        let mut void = String::from("\
+@prefix void: <http://rdfs.org/ns/void#> .
+@prefix dcterms: <http://purl.org/dc/terms/> .
+@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+
+<#dataset> a void:Dataset ;
+");
+        void.push_str(&format!("    dcterms:created \"{}\"^^xsd:dateTime ;\n", utc_timestamp(SystemTime::now())));
+        void.push_str(&format!("    dcterms:creator <{MOPPER_IRI}> ;\n"));
+        for source in sources {
+            void.push_str(&format!("    dcterms:source {} ;\n", turtle_resource(source)));
+        }
+        for data_dump in data_dumps {
+            void.push_str(&format!("    void:dataDump {} ;\n", turtle_resource(data_dump)));
+        }
+        void.push_str(&format!("    void:triples {} ;\n", self.triples));
+        void.push_str(&format!("    void:distinctSubjects {} ;\n", self.distinct_subjects));
+        void.push_str(&format!("    void:distinctObjects {} ;\n", self.distinct_objects));
+        void.push_str(&format!("    void:classes {} ;\n", self.classes.len()));
+        void.push_str(&format!("    void:properties {}", self.properties.len()));
+        for (class, entities) in &self.classes {
+            let triples = self.class_triples.get(class).copied().unwrap_or_default();
+            void.push_str(&format!(" ;\n    void:classPartition [ void:class <{class}> ; void:entities {entities} ; void:triples {triples} ]"));
+        }
+        for (property, triples) in &self.properties {
+            void.push_str(&format!(" ;\n    void:propertyPartition [ void:property <{property}> ; void:triples {triples} ]"));
+        }
+        void.push_str(" .\n");
+        void
+    }
+}
+
+// An IRI if the value is one, otherwise a literal, e.g. for a source that is not a file
+pub(crate) fn turtle_resource(value: &str) -> String {
+    if value.contains("://") && !value.contains(|c: char| c.is_whitespace() || "<>\"{}|^`\\".contains(c)) {
+        format!("<{value}>")
+    } else {
+        turtle_string(value)
+    }
+}
+
+// Counts the statements, remembering only the hashes of what it has seen. The triples of the instances
+// of a class are summed up at the end, when the classes of every subject are known.
+#[derive(Default)]
+struct StatisticsCounter {
+    statistics: DatasetStatistics,
+    statements: HashSet<u64>,
+    subject_triples: HashMap<u64, u64>,
+    subject_classes: HashMap<u64, Vec<String>>,
+    objects: HashSet<u64>,
+    entities: HashSet<u64>
+}
+
+impl StatisticsCounter {
+    fn add(&mut self, statement: &Statement) {
+        if !self.statements.insert(hash(statement)) {
+            return;
+        }
+        self.statistics.triples += 1;
+        let subject = hash(&statement.subject);
+        *self.subject_triples.entry(subject).or_default() += 1;
+        if self.objects.insert(hash(&statement.object)) {
+            self.statistics.distinct_objects += 1;
+        }
+        if let Term::Literal { datatype, language, .. } = &statement.object {
+            let datatype = match (datatype, language) {
+                (Some(datatype), _) => datatype.as_str(),
+                (None, Some(_)) => RDF_LANG_STRING,
+                (None, None) => XSD_STRING
+            };
+            *self.statistics.datatypes.entry(datatype.to_string()).or_default() += 1;
+        }
+        if let Term::Iri(property) = &statement.predicate {
+            *self.statistics.properties.entry(property.clone()).or_default() += 1;
+            // an instance typed in several graphs is counted once
+            if property == RDF_TYPE {
+                if let Term::Iri(class) = &statement.object {
+                    if self.entities.insert(hash(&(&statement.subject, class))) {
+                        *self.statistics.classes.entry(class.clone()).or_default() += 1;
+                        self.subject_classes.entry(subject).or_default().push(class.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl StatisticsCounter {
+    fn finish(mut self) -> DatasetStatistics {
+        self.statistics.distinct_subjects = self.subject_triples.len() as u64;
+        for (subject, classes) in &self.subject_classes {
+            let triples = self.subject_triples.get(subject).copied().unwrap_or_default();
+            for class in classes {
+                *self.statistics.class_triples.entry(class.clone()).or_default() += triples;
+            }
+        }
+        self.statistics
+    }
+}
+
+fn hash(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Collects the statements of the serializers while the plan executes, and writes the VoID description
+/// and the report of the dataset, as far as they are asked for, when it is done.
+pub(crate) struct DatasetDescription {
+    void_file: Option<String>,
+    report_file: Option<String>,
+    sources: Vec<String>,
+    data_dumps: Vec<String>,
+    sender: Sender<Message>,
+    collector: JoinHandle<DatasetStatistics>
+}
+
+impl DatasetDescription {
+    pub(crate) fn new(void_file: Option<String>, report_file: Option<String>, sources: Vec<String>, data_dumps: Vec<String>, capacity: usize) -> Self {
+        let (sender, receiver) = bounded::<Message>(capacity);
+        let collector = thread::Builder::new()
+            .name("Dataset statistics collector".to_string())
+            .spawn(move || collect(receiver)).unwrap();
+        DatasetDescription { void_file, report_file, sources, data_dumps, sender, collector }
+    }
+
+    /// A channel to send the messages of a serializer to, like to a sink.
+    pub(crate) fn collect_from(&self) -> Sender<Message> {
+        self.sender.clone()
+    }
+
+    /// Waits until all statements are counted, and writes the description and the report.
+    pub(crate) fn write(self) -> io::Result<DatasetStatistics> {
+        let DatasetDescription { void_file, report_file, sources, data_dumps, sender, collector } = self;
+        drop(sender);
+        let statistics = collector.join().map_err(|_| io::Error::other("The dataset statistics collector panicked"))?;
+        if let Some(void_file) = void_file {
+            fs::write(void_file, statistics.to_void(&sources, &data_dumps))?;
+        }
+        if let Some(report_file) = report_file {
+            statistics.write_report(&report_file)?;
+        }
+        Ok(statistics)
+    }
+}
+
+fn collect(receiver: Receiver<Message>) -> DatasetStatistics {
+    let mut counter = StatisticsCounter::default();
+    for message in receiver {
+        if let Message::Data(values) = message {
+            for statement in values.iter().flat_map(|value| value.lines()).filter_map(Statement::from_nquads) {
+                counter.add(&statement);
+            }
+        }
+    }
+    counter.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_counted() {
+        let statements: Vec<Statement> = [
+            "<http://example.com/Venus> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://example.com/Person> .",
+            "<http://example.com/Venus> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://example.com/Person> <http://example.com/g> .",
+            "<http://example.com/Venus> <http://example.com/name> \"Venus\" .",
+            "<http://example.com/Venus> <http://example.com/name> \"Venus\" .",
+            "<http://example.com/Bob> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://example.com/Person> <http://example.com/g> .",
+            "<http://example.com/Bob> <http://example.com/name> \"Venus\" .",
+            "<http://example.com/Bob> <http://example.com/age> \"42\"^^<http://www.w3.org/2001/XMLSchema#integer> .",
+            "<http://example.com/Tennis> <http://example.com/name> \"tennis\"@en ."
+        ].iter().map(|line| Statement::from_nquads(line).unwrap()).collect();
+
+        let statistics = DatasetStatistics::from_statements(&statements);
+        assert_eq!(7, statistics.triples);
+        assert_eq!(3, statistics.distinct_subjects);
+        assert_eq!(4, statistics.distinct_objects);
+        assert_eq!(BTreeMap::from([("http://example.com/Person".to_string(), 2)]), statistics.classes);
+        assert_eq!(BTreeMap::from([("http://example.com/Person".to_string(), 6)]), statistics.class_triples);
+        assert_eq!(BTreeMap::from([
+            ("http://example.com/age".to_string(), 1),
+            ("http://example.com/name".to_string(), 3),
+            (RDF_TYPE.to_string(), 3)
+        ]), statistics.properties);
+        assert_eq!(BTreeMap::from([
+            (RDF_LANG_STRING.to_string(), 1),
+            ("http://www.w3.org/2001/XMLSchema#integer".to_string(), 1),
+            (XSD_STRING.to_string(), 2)
+        ]), statistics.datatypes);
+
+        let void = statistics.to_void(&["file:///data/people.csv".to_string()], &[]);
+        assert!(void.contains("dcterms:source <file:///data/people.csv> ;"));
+        assert!(void.contains("void:triples 7 ;"));
+        assert!(void.contains("void:classPartition [ void:class <http://example.com/Person> ; void:entities 2 ; void:triples 6 ]"));
+        assert!(void.ends_with("void:propertyPartition [ void:property <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> ; void:triples 3 ] .\n"));
+    }
+}